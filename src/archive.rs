@@ -0,0 +1,263 @@
+//! Copying files into and out of the container the Docker backend runs,
+//! through Docker's archive API.
+
+use crate::bitcoind::Bitcoind;
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use crate::logs::chain_dir;
+use bollard::container::{DownloadFromContainerOptions, UploadToContainerOptions};
+use futures_util::stream::TryStreamExt;
+use serde_json::{json, Value};
+use std::path::{Component, Path, PathBuf};
+use tracing::info;
+
+impl Bitcoind<DockerBackend> {
+    /// Copies the files of the loaded wallet `name` (`wallet.dat` or the
+    /// descriptor wallet database) into the host directory `host_path`, e.g.
+    /// to move a funded wallet to another node with [`Bitcoind::copy_in`].
+    ///
+    /// The wallet is unloaded while it is copied, so its files are
+    /// consistent, and loaded again afterwards, even if the copy failed.
+    pub async fn export_wallet(&self, name: &str, host_path: &Path) -> Result<(), BitcoindError> {
+        let chain = chain_dir(&self.node_spec().command_line([]));
+        let path = [
+            self.backend.container.datadir().trim_end_matches('/'),
+            &chain,
+            "wallets",
+            name,
+        ]
+        .iter()
+        .filter(|part| !part.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join("/");
+
+        let client = self.rpc_client();
+        client.call::<Value>("unloadwallet", json!([name])).await?;
+        let archive = self.backend.download(&path).await;
+        client.call::<Value>("loadwallet", json!([name])).await?;
+
+        let archive = archive?;
+        let dest = host_path.to_path_buf();
+        tokio::task::spawn_blocking(move || unpack(&archive, &dest))
+            .await
+            .map_err(|e| BitcoindError::Artifact {
+                path: host_path.to_path_buf(),
+                source: std::io::Error::other(e),
+            })?
+    }
+}
+
+impl DockerBackend {
+    /// Copies the container's datadir into the host directory `host_path`,
+    /// created if missing.
+    pub async fn export_datadir(&self, host_path: &Path) -> Result<(), BitcoindError> {
+        let datadir = self.container.datadir();
+        info!(
+            "Exporting {} of {} to {}",
+            datadir,
+            self.container_name,
+            host_path.display()
+        );
+        let archive = self.download(&datadir).await?;
+        let dest = host_path.to_path_buf();
+        tokio::task::spawn_blocking(move || unpack(&archive, &dest))
+            .await
+            .map_err(|e| BitcoindError::Artifact {
+                path: host_path.to_path_buf(),
+                source: std::io::Error::other(e),
+            })?
+    }
+
+    /// Copies the host file or directory `host_path` to `container_path` in
+    /// the container, replacing files already there. The copies keep their
+    /// host owner and permissions.
+    pub async fn copy_in(
+        &self,
+        host_path: &Path,
+        container_path: &str,
+    ) -> Result<(), BitcoindError> {
+        info!(
+            "Copying {} to {} in {}",
+            host_path.display(),
+            container_path,
+            self.container_name
+        );
+        let (source, target) = (host_path.to_path_buf(), container_path.to_string());
+        let archive = tokio::task::spawn_blocking(move || pack(&source, &target))
+            .await
+            .map_err(|e| BitcoindError::Artifact {
+                path: host_path.to_path_buf(),
+                source: std::io::Error::other(e),
+            })??;
+        self.upload(archive, "/").await
+    }
+
+    /// Extracts a tar archive into the directory `dir` of the container, e.g.
+    /// `/` for one with absolute paths minus the leading `/`. Missing parent
+    /// directories are created.
+    pub(crate) async fn upload(&self, archive: Vec<u8>, dir: &str) -> Result<(), BitcoindError> {
+        self.docker
+            .upload_to_container(
+                &self.container_name,
+                Some(UploadToContainerOptions {
+                    path: dir,
+                    ..Default::default()
+                }),
+                archive.into(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Downloads `path` from the container as a tar archive.
+    pub(crate) async fn download(&self, path: &str) -> Result<Vec<u8>, BitcoindError> {
+        let chunks = self
+            .docker
+            .download_from_container(
+                &self.container_name,
+                Some(DownloadFromContainerOptions { path }),
+            )
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(chunks.concat())
+    }
+}
+
+/// Packs the host file or directory `host_path` to be extracted at the
+/// absolute `container_path` by [`DockerBackend::upload`].
+pub(crate) fn pack(host_path: &Path, container_path: &str) -> Result<Vec<u8>, BitcoindError> {
+    let name = container_path.trim_start_matches('/');
+    let mut archive = tar::Builder::new(Vec::new());
+    archive.follow_symlinks(false);
+    let appended = if host_path.is_dir() {
+        archive.append_dir_all(name, host_path)
+    } else {
+        archive.append_path_with_name(host_path, name)
+    };
+    appended
+        .and_then(|()| archive.into_inner())
+        .map_err(|source| BitcoindError::Artifact {
+            path: host_path.to_path_buf(),
+            source,
+        })
+}
+
+/// Extracts an archive downloaded from the container into `dest`. Docker
+/// puts the downloaded directory's contents under its name, e.g. `data/`,
+/// which is stripped.
+pub(crate) fn unpack(archive: &[u8], dest: &Path) -> Result<(), BitcoindError> {
+    let artifact = |path: &Path| {
+        let path = path.to_path_buf();
+        move |source| BitcoindError::Artifact { path, source }
+    };
+    std::fs::create_dir_all(dest).map_err(artifact(dest))?;
+
+    let mut entries = tar::Archive::new(archive);
+    for entry in entries.entries().map_err(artifact(dest))? {
+        let mut entry = entry.map_err(artifact(dest))?;
+        let path = entry.path().map_err(artifact(dest))?.into_owned();
+        let relative = path
+            .components()
+            .skip(1)
+            .map(|component| match component {
+                Component::Normal(part) => Some(part),
+                _ => None,
+            })
+            .collect::<Option<PathBuf>>();
+        let target = match relative {
+            Some(relative) if !relative.as_os_str().is_empty() => dest.join(relative),
+            _ => continue,
+        };
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).map_err(artifact(parent))?;
+        }
+        entry.unpack(&target).map_err(artifact(&target))?;
+    }
+    Ok(())
+}
+
+/// Writes the single file of an archive downloaded from the container to
+/// `dest`.
+pub(crate) fn unpack_file(archive: &[u8], dest: &Path) -> Result<(), BitcoindError> {
+    let artifact = |source| BitcoindError::Artifact {
+        path: dest.to_path_buf(),
+        source,
+    };
+    let mut entries = tar::Archive::new(archive);
+    let mut entry = entries
+        .entries()
+        .map_err(artifact)?
+        .next()
+        .ok_or_else(|| artifact(std::io::ErrorKind::NotFound.into()))?
+        .map_err(artifact)?;
+    if let Some(parent) = dest.parent() {
+        std::fs::create_dir_all(parent).map_err(artifact)?;
+    }
+    entry.unpack(dest).map_err(artifact)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_pack() -> Result<(), BitcoindError> {
+        let dir = std::env::temp_dir().join("bitcoind-test-copy-in");
+        std::fs::create_dir_all(dir.join("wallets/test")).unwrap();
+        std::fs::write(dir.join("wallets/test/wallet.dat"), "wallet").unwrap();
+        std::fs::write(dir.join("extra.conf"), "txindex=1").unwrap();
+
+        let entries = |archive: Vec<u8>| {
+            tar::Archive::new(archive.as_slice())
+                .entries()
+                .unwrap()
+                .map(|entry| entry.unwrap().path().unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+        let wallets = entries(pack(&dir.join("wallets"), "/data/regtest/wallets")?);
+        let conf = entries(pack(&dir.join("extra.conf"), "/etc/bitcoin/extra.conf")?);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(wallets.contains(&"data/regtest/wallets/test/wallet.dat".to_string()));
+        assert_eq!(conf, ["etc/bitcoin/extra.conf"]);
+        assert!(matches!(
+            pack(Path::new("/nonexistent/wallet.dat"), "/data/wallet.dat"),
+            Err(BitcoindError::Artifact { .. })
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_unpack_strips_datadir() -> Result<(), BitcoindError> {
+        let mut archive = tar::Builder::new(Vec::new());
+        for (path, contents) in [
+            ("data/regtest/blocks/blk00000.dat", "blocks"),
+            ("data/regtest/wallets/test/wallet.dat", "wallet"),
+            ("data/../escape", "escape"),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            // `set_path` refuses `..`, so the name is written as is.
+            header.as_gnu_mut().unwrap().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            archive.append(&header, contents.as_bytes()).unwrap();
+        }
+        let archive = archive.into_inner().unwrap();
+
+        let dest = std::env::temp_dir().join("bitcoind-test-export");
+        unpack(&archive, &dest)?;
+        let blocks = std::fs::read_to_string(dest.join("regtest/blocks/blk00000.dat")).unwrap();
+        let wallet = std::fs::read_to_string(dest.join("regtest/wallets/test/wallet.dat")).unwrap();
+        let escaped = dest.parent().unwrap().join("escape").exists();
+        std::fs::remove_dir_all(&dest).unwrap();
+
+        assert_eq!(blocks, "blocks");
+        assert_eq!(wallet, "wallet");
+        assert!(!escaped);
+        Ok(())
+    }
+}
@@ -0,0 +1,179 @@
+//! Exporting the UTXO set with `dumptxoutset` and loading it on a fresh node
+//! with `loadtxoutset` (assumeutxo).
+
+use crate::archive::unpack_file;
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::builder::random_hex;
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use crate::readiness::ReadyCondition;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::path::Path;
+use std::time::Duration;
+use tracing::info;
+
+/// Result of `dumptxoutset`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoSetDump {
+    pub coins_written: u64,
+    /// Block the UTXO set is taken at.
+    pub base_hash: String,
+    pub base_height: u64,
+    /// Path of the dump in the container, removed after the download.
+    pub path: String,
+    /// Hash of the serialized UTXO set, as in the node's chainparams.
+    pub txoutset_hash: String,
+    /// Transactions in the chain up to the base block.
+    pub nchaintx: u64,
+}
+
+/// Result of `loadtxoutset`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoSetLoad {
+    pub coins_loaded: u64,
+    /// Tip of the chainstate built from the snapshot.
+    pub tip_hash: String,
+    pub base_height: u64,
+    pub path: String,
+}
+
+impl Bitcoind<DockerBackend> {
+    /// Dumps the UTXO set to the host file `host_path` with `dumptxoutset`,
+    /// at the tip or, with `rollback`, at an earlier height. The node stops
+    /// syncing while it rolls back.
+    ///
+    /// Nodes only load snapshots at heights their chainparams list, e.g.
+    /// 110, 200 and 299 on regtest, so dump at one of those to use the file
+    /// with [`Bitcoind::load_utxo_set`]. Requires Bitcoin Core 28 or later.
+    pub async fn dump_utxo_set(
+        &self,
+        host_path: &Path,
+        rollback: Option<u64>,
+    ) -> Result<UtxoSetDump, BitcoindError> {
+        let path = self.utxo_set_path();
+        let params = match rollback {
+            Some(height) => json!([path, "rollback", { "rollback": height }]),
+            None => json!([path, "latest"]),
+        };
+        info!(
+            "Dumping the UTXO set of {} to {}",
+            self.backend.name(),
+            host_path.display()
+        );
+        let dump = self
+            .rpc_client()
+            .call::<UtxoSetDump>("dumptxoutset", params)
+            .await?;
+
+        let archive = self.backend.download(&dump.path).await;
+        self.exec(&["rm", "-f", &dump.path]).await?;
+        let archive = archive?;
+        let dest = host_path.to_path_buf();
+        tokio::task::spawn_blocking(move || unpack_file(&archive, &dest))
+            .await
+            .map_err(|e| BitcoindError::Artifact {
+                path: host_path.to_path_buf(),
+                source: std::io::Error::other(e),
+            })??;
+        Ok(dump)
+    }
+
+    /// Loads the UTXO set dumped to the host file `host_path` with
+    /// `loadtxoutset`, so the node syncs from the snapshot's base block on
+    /// while it validates the blocks below it in the background.
+    ///
+    /// The node must know the header of the base block, e.g. from a peer,
+    /// and must not have synced past it. Wait for the validation with
+    /// [`Bitcoind::wait_for_background_validation`].
+    pub async fn load_utxo_set(&self, host_path: &Path) -> Result<UtxoSetLoad, BitcoindError> {
+        let path = self.utxo_set_path();
+        self.backend.copy_in(host_path, &path).await?;
+        let load = self
+            .rpc_client()
+            .call::<UtxoSetLoad>("loadtxoutset", json!([path]))
+            .await;
+        self.exec(&["rm", "-f", &path]).await?;
+        Ok(load?)
+    }
+
+    /// Path in the datadir to put a UTXO set dump at. `dumptxoutset`
+    /// refuses to overwrite a file, so each dump gets its own.
+    fn utxo_set_path(&self) -> String {
+        format!(
+            "{}/utxo-{}.dat",
+            self.backend.container.datadir().trim_end_matches('/'),
+            random_hex(8)
+        )
+    }
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Waits until a UTXO set loaded with `loadtxoutset` is validated in the
+    /// background and the node drops the background chainstate.
+    ///
+    /// See [`ReadyCondition::SnapshotValidated`] and
+    /// [`Bitcoind::wait_until_ready`] for the errors.
+    pub async fn wait_for_background_validation(
+        &self,
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        self.wait_until_ready(&[ReadyCondition::SnapshotValidated], timeout)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_utxo_set_results() {
+        let dump: UtxoSetDump = serde_json::from_value(json!({
+            "coins_written": 200,
+            "base_hash": "6affe030b7965ab538f820a56ef56c8149b7dc1d1c144af57113be080db7c397",
+            "base_height": 200,
+            "path": "/home/bitcoin/.bitcoin/utxo-0a1b2c3d.dat",
+            "txoutset_hash": "7e3b7780fbd2fa479a01f66950dc8f728dc1b11f03d06d5bf223168520df3a48",
+            "nchaintx": 201
+        }))
+        .unwrap();
+        assert_eq!(dump.base_height, 200);
+        assert_eq!(dump.nchaintx, 201);
+
+        let load: UtxoSetLoad = serde_json::from_value(json!({
+            "coins_loaded": 200,
+            "tip_hash": "6affe030b7965ab538f820a56ef56c8149b7dc1d1c144af57113be080db7c397",
+            "base_height": 200,
+            "path": "/home/bitcoin/.bitcoin/utxo-4e5f6a7b.dat"
+        }))
+        .unwrap();
+        assert_eq!(load.tip_hash, dump.base_hash);
+    }
+
+    #[tokio::test]
+    async fn test_start_dump_utxo_set() -> Result<(), BitcoindError> {
+        let bitcoind = crate::bitcoind::ephemeral_builder().build()?;
+        bitcoind.start_with_premined_chain().await?;
+        bitcoind
+            .rpc_client()
+            .call::<serde_json::Value>(
+                "generatetodescriptor",
+                json!([10, crate::snapshot::premined_descriptor()]),
+            )
+            .await?;
+
+        let path = std::env::temp_dir().join("bitcoind-test-utxo-set.dat");
+        let dump = bitcoind.dump_utxo_set(&path, Some(200)).await;
+        bitcoind.stop().await?;
+
+        let dump = dump?;
+        let written = std::fs::metadata(&path).map(|m| m.len());
+        std::fs::remove_file(&path).ok();
+        assert_eq!(dump.base_height, 200);
+        assert!(written.unwrap() > 0);
+        Ok(())
+    }
+}
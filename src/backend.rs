@@ -0,0 +1,256 @@
+//! Abstraction over where the `bitcoind` process runs.
+//!
+//! [`crate::Bitcoind`] builds the node configuration and handles RPC readiness,
+//! while a [`NodeBackend`] owns the process itself. [`crate::DockerBackend`] is
+//! the default backend.
+
+use crate::error::BitcoindError;
+use crate::logs::{LogLine, LogOptions};
+use crate::version::NodeVersion;
+use async_trait::async_trait;
+use bitcoin::Network;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::path::Path;
+
+/// What a backend needs to launch the node.
+#[derive(Debug, Clone)]
+pub struct NodeSpec {
+    /// Chain the node runs, selected by one of `args`.
+    pub network: Network,
+    /// Port the RPC interface must be reachable on from the host.
+    pub rpc_port: u16,
+    /// Generated `bitcoind` arguments that don't depend on the backend.
+    pub args: Vec<String>,
+    /// User arguments, placed after every generated one so they take precedence.
+    pub extra_args: Vec<String>,
+}
+
+impl NodeSpec {
+    /// Full `bitcoind` command line: the generated arguments, then the
+    /// backend-specific ones and finally the user arguments.
+    pub fn command_line<I>(&self, backend_args: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut args = self.args.clone();
+        args.extend(backend_args);
+        args.extend(self.extra_args.iter().cloned());
+        args
+    }
+}
+
+/// `bitcoind` arguments `bitcoin-cli` needs to reach the node's RPC interface.
+const CLI_ARGS: &[&str] = &[
+    "-regtest",
+    "-testnet",
+    "-testnet4",
+    "-signet",
+    "-signetchallenge",
+    "-chain",
+    "-datadir",
+    "-conf",
+    "-rpcuser",
+    "-rpcpassword",
+    "-rpcport",
+];
+
+/// Keeps the `bitcoind` arguments that `bitcoin-cli` also understands.
+pub(crate) fn cli_args<I>(bitcoind_args: I) -> impl Iterator<Item = String>
+where
+    I: IntoIterator<Item = String>,
+{
+    bitcoind_args.into_iter().filter(|arg| {
+        CLI_ARGS
+            .iter()
+            .any(|prefix| arg.split('=').next() == Some(*prefix))
+    })
+}
+
+/// Where the RPC interface of a started node can be reached.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcAddress {
+    /// Host to connect to. `None` keeps the host of the configured RPC url.
+    pub host: Option<String>,
+    pub port: u16,
+}
+
+/// Result of a command run next to the node.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecOutput {
+    pub exit_code: i64,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// State of a node as reported by its backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeStatus {
+    /// No node exists, e.g. before `start` or after `stop`.
+    NotCreated,
+    /// The node was created but never started.
+    Created,
+    /// Running, but its healthcheck hasn't passed yet.
+    Starting,
+    Running,
+    /// Running, but its healthcheck fails.
+    Unhealthy,
+    /// Frozen by `pause`.
+    Paused,
+    /// The process exited with the given code, e.g. 137 after `kill`.
+    Exited(i64),
+    /// The runtime could not be reached, e.g. the Docker daemon is down.
+    Unreachable,
+}
+
+/// Runtime that hosts the `bitcoind` process.
+#[async_trait]
+pub trait NodeBackend: Send + Sync {
+    /// Name of the node, used in logs and errors.
+    fn name(&self) -> &str;
+
+    /// Prepares a fresh node from `spec`, discarding any previous one with the same name.
+    async fn create(&self, spec: &NodeSpec) -> Result<(), BitcoindError>;
+
+    /// Launches the node prepared by [`NodeBackend::create`].
+    async fn start(&self) -> Result<(), BitcoindError>;
+
+    /// Stops and removes the node. Does nothing if it is not running.
+    async fn stop(&self) -> Result<(), BitcoindError>;
+
+    /// Restarts the running node, keeping its data directory.
+    ///
+    /// The default replaces the node with a fresh one from `spec`, so only
+    /// data `stop` keeps survives. Backends that can restart in place override it.
+    async fn restart(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        self.stop().await?;
+        self.create(spec).await?;
+        self.start().await
+    }
+
+    /// Replaces the node with one running `spec`, keeping its data directory,
+    /// e.g. to change `bitcoind` flags without rebuilding chain state.
+    ///
+    /// The default fails with [`BitcoindError::Unsupported`].
+    async fn recreate(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        let _ = spec;
+        Err(BitcoindError::Unsupported(format!(
+            "{} can't be recreated keeping its data",
+            self.name()
+        )))
+    }
+
+    /// Kills the node process abruptly, leaving its data directory as a
+    /// crashed node would. The default fails with [`BitcoindError::Unsupported`].
+    async fn kill(&self) -> Result<(), BitcoindError> {
+        Err(BitcoindError::Unsupported(format!(
+            "{} can't be killed",
+            self.name()
+        )))
+    }
+
+    /// Freezes the node process without closing its connections, so it stops
+    /// answering as if it hung. The default fails with [`BitcoindError::Unsupported`].
+    async fn pause(&self) -> Result<(), BitcoindError> {
+        Err(BitcoindError::Unsupported(format!(
+            "{} can't be paused",
+            self.name()
+        )))
+    }
+
+    /// Resumes a node frozen by [`NodeBackend::pause`].
+    async fn unpause(&self) -> Result<(), BitcoindError> {
+        Err(BitcoindError::Unsupported(format!(
+            "{} can't be paused",
+            self.name()
+        )))
+    }
+
+    /// Whether the node process is running.
+    async fn is_running(&self) -> Result<bool, BitcoindError>;
+
+    /// Current state of the node. The default only tells running nodes from
+    /// missing ones, based on [`NodeBackend::is_running`].
+    async fn status(&self) -> Result<NodeStatus, BitcoindError> {
+        Ok(if self.is_running().await? {
+            NodeStatus::Running
+        } else {
+            NodeStatus::NotCreated
+        })
+    }
+
+    /// Address the RPC interface is reachable on once the node is started.
+    async fn rpc_address(&self, spec: &NodeSpec) -> Result<RpcAddress, BitcoindError>;
+
+    /// Command line running `bitcoin-cli` with `args` against the node, for
+    /// [`NodeBackend::exec`]. The default fails with [`BitcoindError::Unsupported`].
+    fn cli_command(&self, spec: &NodeSpec, args: &[String]) -> Result<Vec<String>, BitcoindError> {
+        let _ = (spec, args);
+        Err(BitcoindError::Unsupported(format!(
+            "{} can't run bitcoin-cli",
+            self.name()
+        )))
+    }
+
+    /// Data directory of the node as seen by [`NodeBackend::exec`], if known.
+    fn datadir(&self) -> Option<String> {
+        None
+    }
+
+    /// Bitcoin Core version the node will run, if known before it starts,
+    /// so unsupported flags are reported before `bitcoind` rejects them.
+    fn expected_version(&self) -> Option<NodeVersion> {
+        None
+    }
+
+    /// Removes the node, without an async runtime, when the [`crate::Bitcoind`]
+    /// owning it is dropped, e.g. by a test that panicked. With
+    /// `log_artifacts` the node's output is saved there first.
+    ///
+    /// The default does nothing.
+    fn remove_on_drop(&self, log_artifacts: Option<&Path>) {
+        let _ = log_artifacts;
+    }
+
+    /// Runs a command next to the node, e.g. `bitcoin-cli`, and waits for it to finish.
+    async fn exec(&self, cmd: &[String]) -> Result<ExecOutput, BitcoindError>;
+
+    /// Returns what the node printed so far, filtered by `options`.
+    async fn logs(&self, options: &LogOptions) -> Result<String, BitcoindError>;
+
+    /// Streams the node's output from the start, then new lines as they are
+    /// printed. The default yields a single [`BitcoindError::Unsupported`].
+    fn follow_logs(&self) -> BoxStream<'_, Result<LogLine, BitcoindError>> {
+        let error = BitcoindError::Unsupported(format!("{} can't follow logs", self.name()));
+        stream::once(async { Err(error) }).boxed()
+    }
+
+    /// Answers the readiness check in place of the node's RPC interface, with
+    /// the reason it is not ready yet on error.
+    ///
+    /// Backends running a real node keep the default, `None`, and readiness is
+    /// checked with a `getblockchaininfo` call.
+    async fn probe_readiness(&self) -> Option<Result<(), String>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_extra_args_come_last() {
+        let spec = NodeSpec {
+            network: Network::Regtest,
+            rpc_port: 18443,
+            args: vec!["-regtest=1".to_string()],
+            extra_args: vec!["-rpcbind=127.0.0.1".to_string()],
+        };
+
+        assert_eq!(
+            spec.command_line(["-rpcbind=0.0.0.0".to_string()]),
+            vec!["-regtest=1", "-rpcbind=0.0.0.0", "-rpcbind=127.0.0.1"]
+        );
+    }
+}
@@ -1,29 +1,109 @@
+use crate::backend::{ExecOutput, NodeBackend, NodeSpec, NodeStatus, RpcAddress};
+use crate::builder::{random_hex, BitcoindBuilder};
+use crate::cleanup::{OrphanFilter, RemovedOrphans};
+use crate::conf::BitcoinConf;
+use crate::config::BitcoindConfig;
+use crate::docker::{
+    ContainerDetails, DockerBackend, PullPolicy, PullProgressCallback, RetryPolicy,
+};
+use crate::error::BitcoindError;
+use crate::hooks::HookList;
+use crate::image::{ImageProfile, ImageSource};
+use crate::logs::{LogLine, LogOptions};
+use crate::ports::{parse_rpc_port, MappedPorts, PublishedPorts, ZmqEndpoint, P2P_CONTAINER_PORT};
+use crate::readiness::{probe_until, ReadyCondition, NO_ANSWER};
+use crate::registry::RegistryAuth;
+use crate::rpc::{RpcClient, RpcError};
+use crate::stats::ResourceUsage;
+use crate::version::NodeVersion;
+use bitcoin::Network;
+use bitvmx_bitcoin_rpc::bitcoin_client::BitcoinClient;
 use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
-use bollard::container::{Config, CreateContainerOptions, RemoveContainerOptions};
-use bollard::errors::Error;
-use bollard::image::CreateImageOptions;
-use bollard::models::{ContainerCreateResponse, HostConfig};
-use bollard::Docker;
-use futures_util::stream::StreamExt;
+use futures_util::stream::Stream;
+use redact::Secret;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::default::Default;
-use tokio::runtime::Runtime;
-use tracing::{self, info};
-
-pub struct Bitcoind {
-    docker: Docker,
-    container_name: String,
-    image: String,
-    runtime: Runtime,
-    rpc_config: RpcConfig,
-    flags: BitcoindFlags,
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+use tracing::{self, debug, info};
+
+/// A managed `bitcoind` node.
+///
+/// The node runs in a Docker container unless another [`NodeBackend`] is
+/// supplied with [`Bitcoind::with_backend`].
+///
+/// Dropping it removes the Docker container, so a test that panics doesn't
+/// leave it running. Call [`Bitcoind::stop`] for a clean shutdown.
+pub struct Bitcoind<B: NodeBackend = DockerBackend> {
+    pub(crate) backend: B,
+    pub(crate) rpc_config: RpcConfig,
+    pub(crate) rpc_port: u16,
+    pub(crate) effective_rpc_config: Mutex<RpcConfig>,
+    pub(crate) flags: BitcoindFlags,
+    pub(crate) readiness: ReadinessConfig,
+    /// Directory the node's output is saved to when it fails to start.
+    pub(crate) log_artifacts: Option<PathBuf>,
+    pub(crate) hooks: HookList,
+    /// Set by `stop` and `kill` until the next start, so the watchdog leaves
+    /// the node down.
+    pub(crate) stopped: AtomicBool,
+    /// Held while the node is started, stopped or replaced, so the watchdog
+    /// doesn't mistake a node in transition for one that crashed.
+    pub(crate) lifecycle: tokio::sync::Mutex<()>,
+    /// Version the node reported once it was last ready.
+    pub(crate) node_version: Mutex<Option<NodeVersion>>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BitcoindFlags {
     pub min_relay_tx_fee: f64,
     pub block_min_tx_fee: f64,
+    /// `-debug` value used when `debug_categories` is empty: `1` logs every
+    /// category, `0` none.
     pub debug: u8,
     pub fallback_fee: f64,
+    /// Categories to log, e.g. `[net, mempool]`. Replaces `debug` when set.
+    pub debug_categories: Vec<DebugCategory>,
+    /// Categories left out of debug logging, e.g. `[libevent, leveldb]`.
+    pub debug_exclude: Vec<DebugCategory>,
+    /// Keeps a transaction index with `-txindex`, so `getrawtransaction`
+    /// finds any transaction. On by default.
+    pub txindex: bool,
+    /// Builds the BIP 158 block filter index and serves the filters to peers,
+    /// with `-blockfilterindex=1` and `-peerblockfilters=1`.
+    pub block_filter_index: bool,
+    /// Builds the coin statistics index with `-coinstatsindex=1`, so
+    /// `gettxoutsetinfo` answers at any height without scanning the UTXO set.
+    pub coinstats_index: bool,
+    /// `-prune` value: the target size of the block files in MiB, at least
+    /// 550, `1` to prune only with `pruneblockchain`, or `0` not to prune.
+    /// Pruned nodes run without `-txindex`, whatever `txindex` says.
+    pub prune: Option<u64>,
+    /// Runs with `-blocksonly`: the node neither requests nor relays
+    /// transactions from peers.
+    pub blocks_only: bool,
+    /// `-whitelist` entries, `[permissions@]<ip or net>`, e.g.
+    /// `relay,mempool@172.17.0.0/16`.
+    pub whitelist: Vec<String>,
+    /// `-whitelistrelay`: relay transactions of whitelisted peers even with
+    /// `blocks_only`.
+    pub whitelist_relay: Option<bool>,
+    /// `-whitelistforcerelay`: relay transactions of whitelisted peers even
+    /// when they break the mempool policy.
+    pub whitelist_force_relay: Option<bool>,
+    /// Challenge of a custom signet as script hex, see [`CustomSignet`].
+    ///
+    /// [`CustomSignet`]: crate::signet::CustomSignet
+    pub signet_challenge: Option<String>,
+    /// Additional arguments appended verbatim after the generated ones,
+    /// e.g. `-acceptnonstdtxn=1`.
+    pub extra_args: Vec<String>,
 }
 
 impl Default for BitcoindFlags {
@@ -33,279 +113,1916 @@ impl Default for BitcoindFlags {
             block_min_tx_fee: 0.00001,
             debug: 1,
             fallback_fee: 0.0002,
+            debug_categories: Vec::new(),
+            debug_exclude: Vec::new(),
+            txindex: true,
+            block_filter_index: false,
+            coinstats_index: false,
+            prune: None,
+            blocks_only: false,
+            whitelist: Vec::new(),
+            whitelist_relay: None,
+            whitelist_force_relay: None,
+            signet_challenge: None,
+            extra_args: Vec::new(),
+        }
+    }
+}
+
+/// A `bitcoind` debug logging category, as passed to `-debug` and `-debugexclude`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DebugCategory {
+    Net,
+    Tor,
+    Mempool,
+    Http,
+    Bench,
+    Zmq,
+    WalletDb,
+    Rpc,
+    EstimateFee,
+    Addrman,
+    SelectCoins,
+    Reindex,
+    CmpctBlock,
+    Rand,
+    Prune,
+    Proxy,
+    MempoolRej,
+    Libevent,
+    CoinDb,
+    Qt,
+    LevelDb,
+    Validation,
+    I2p,
+    Ipc,
+    Lock,
+    BlockStorage,
+    TxReconciliation,
+    Scan,
+    TxPackages,
+}
+
+impl DebugCategory {
+    /// Name of the category on the `bitcoind` command line.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DebugCategory::Net => "net",
+            DebugCategory::Tor => "tor",
+            DebugCategory::Mempool => "mempool",
+            DebugCategory::Http => "http",
+            DebugCategory::Bench => "bench",
+            DebugCategory::Zmq => "zmq",
+            DebugCategory::WalletDb => "walletdb",
+            DebugCategory::Rpc => "rpc",
+            DebugCategory::EstimateFee => "estimatefee",
+            DebugCategory::Addrman => "addrman",
+            DebugCategory::SelectCoins => "selectcoins",
+            DebugCategory::Reindex => "reindex",
+            DebugCategory::CmpctBlock => "cmpctblock",
+            DebugCategory::Rand => "rand",
+            DebugCategory::Prune => "prune",
+            DebugCategory::Proxy => "proxy",
+            DebugCategory::MempoolRej => "mempoolrej",
+            DebugCategory::Libevent => "libevent",
+            DebugCategory::CoinDb => "coindb",
+            DebugCategory::Qt => "qt",
+            DebugCategory::LevelDb => "leveldb",
+            DebugCategory::Validation => "validation",
+            DebugCategory::I2p => "i2p",
+            DebugCategory::Ipc => "ipc",
+            DebugCategory::Lock => "lock",
+            DebugCategory::BlockStorage => "blockstorage",
+            DebugCategory::TxReconciliation => "txreconciliation",
+            DebugCategory::Scan => "scan",
+            DebugCategory::TxPackages => "txpackages",
+        }
+    }
+}
+
+impl std::fmt::Display for DebugCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Docker-level settings for the `bitcoind` container.
+#[derive(Debug, Clone, Default)]
+pub struct ContainerOptions {
+    /// P2P, ZMQ and REST services to publish alongside RPC.
+    pub publish: PublishedPorts,
+    /// Additional `(container_port, host_port)` pairs to publish.
+    pub ports: Vec<(u16, u16)>,
+    /// Volumes as `(source, target)` pairs. The source may be a host path or a named volume.
+    pub volumes: Vec<(String, String)>,
+    /// Additional environment variables as `(key, value)` pairs. They are merged
+    /// with the defaults (`BITCOIN_DATA=/data`), replacing variables with the same name.
+    pub env: Vec<(String, String)>,
+    /// Entrypoint, datadir and user conventions of the image.
+    pub image_profile: ImageProfile,
+    /// Overrides the entrypoint of the image profile, for images that don't
+    /// launch `bitcoind` directly.
+    pub entrypoint: Option<Vec<String>>,
+    /// Arguments placed before the generated `bitcoind` flags, e.g. `["bitcoind"]`
+    /// for images whose entrypoint expects the binary name. Replaces those of
+    /// the image profile when set.
+    pub cmd_prefix: Vec<String>,
+    /// Lets Docker pick free host ports for every published port instead of the
+    /// configured ones, so several nodes can run side by side.
+    pub ephemeral_ports: bool,
+    /// Runs the container with `network_mode: host` instead of publishing ports.
+    /// The node listens on the configured host ports directly and `ports` is
+    /// ignored. Only supported on Linux.
+    pub host_network: bool,
+    /// Host address the published ports bind to. Defaults to `0.0.0.0`; an IPv6
+    /// address such as `::` also makes the node accept IPv6 RPC clients.
+    pub host_ip: Option<IpAddr>,
+    /// Whether the image is pulled or built. Defaults to pulling it.
+    pub image_source: ImageSource,
+    /// Platform of the image to run as `os/arch[/variant]`, e.g. `linux/arm64`.
+    /// Defaults to the daemon's platform.
+    pub platform: Option<String>,
+    /// When the image is pulled. Defaults to pulling only if it is missing.
+    pub pull_policy: PullPolicy,
+    /// Credentials for pulling the image from a private registry.
+    pub registry_auth: Option<RegistryAuth>,
+    /// `docker save` archive loaded instead of pulling when the image is missing.
+    pub image_tar: Option<PathBuf>,
+    /// Receives the progress of image pulls.
+    pub pull_progress: Option<PullProgressCallback>,
+    /// Retries of Docker operations that fail with transient errors.
+    pub retry: RetryPolicy,
+    /// Keeps a running container created from the same settings instead of
+    /// recreating it, so its chain state survives between test binaries.
+    pub reuse_existing: bool,
+    /// Emits the container's output as `tracing` events while it runs.
+    pub forward_logs: bool,
+    /// Leaves the container running when the `Bitcoind` is dropped, for
+    /// persistent setups. Reused containers are always left running.
+    pub keep_on_drop: bool,
+    /// Docker healthcheck of the container, reported by `status`.
+    pub healthcheck: Option<Healthcheck>,
+    /// CPU, memory and process limits of the container.
+    pub resources: ResourceLimits,
+    /// Size in bytes of a tmpfs mounted as the datadir, keeping the chain in
+    /// memory. The chain state is lost when the container stops.
+    pub tmpfs_datadir: Option<u64>,
+    /// Named volume created if missing and mounted as the datadir, so the
+    /// chain state survives stopping the container and upgrading the image.
+    pub datadir_volume: Option<String>,
+    /// Host directory bind mounted as the datadir, created if missing, to
+    /// inspect or pre-seed the node's files from outside the container.
+    pub datadir_path: Option<PathBuf>,
+    /// Host `bitcoin.conf` mounted read-only into the container and passed
+    /// with `-conf`. Command-line flags take precedence over its settings.
+    pub conf_file: Option<PathBuf>,
+    /// `bitcoin.conf` written into the container when it is created, instead
+    /// of mounting `conf_file`.
+    pub bitcoin_conf: Option<BitcoinConf>,
+    /// Host files or directories copied into the container as `(host_path,
+    /// container_path)` pairs when it is created, before the node starts.
+    pub copy_in: Vec<(PathBuf, String)>,
+}
+
+/// Controls how `start` waits for the node to answer RPC requests.
+#[derive(Debug, Clone)]
+pub struct ReadinessConfig {
+    /// Maximum time to wait for the node to answer `getblockchaininfo`.
+    pub timeout: Duration,
+    /// Delay before the first retry. Doubles after every failed attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for ReadinessConfig {
+    fn default() -> Self {
+        ReadinessConfig {
+            timeout: Duration::from_secs(30),
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+/// How long to wait for the node to start and stop, and how often to check.
+///
+/// In a config file durations are given in seconds:
+/// `timeouts = { start = 120, stop = 30, poll_interval = 0.5 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Timeouts {
+    /// Maximum time `start` waits for the node to answer RPC requests.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub start: Duration,
+    /// Maximum time `stop` waits for the node to shut down cleanly before
+    /// removing it by force.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub stop: Duration,
+    /// Longest delay between checks while waiting for the node to start or stop.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub poll_interval: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            start: Duration::from_secs(30),
+            stop: Duration::from_secs(10),
+            poll_interval: Duration::from_millis(250),
+        }
+    }
+}
+
+/// Docker healthcheck running `bitcoin-cli getblockcount` in the container,
+/// so `docker ps` and orchestrators see whether the node answers RPC.
+///
+/// In a config file durations are given in seconds:
+/// `healthcheck = { interval = 10, retries = 5 }`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct Healthcheck {
+    /// Time between checks.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub interval: Duration,
+    /// Time a check may take before it counts as failed.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub timeout: Duration,
+    /// Time after the start during which failed checks don't count, while the
+    /// node loads its chain state.
+    #[serde(deserialize_with = "deserialize_seconds")]
+    pub start_period: Duration,
+    /// Consecutive failed checks before the node is unhealthy.
+    pub retries: u32,
+}
+
+impl Default for Healthcheck {
+    fn default() -> Self {
+        Healthcheck {
+            interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(5),
+            start_period: Duration::from_secs(30),
+            retries: 3,
         }
     }
 }
 
-impl Bitcoind {
+/// Resource limits of the container, e.g. to check how the stack copes with a
+/// constrained node or to keep it from starving a shared CI host. Unset
+/// limits leave the resource unconstrained.
+///
+/// In a config file: `resources = { cpus = 0.5, memory = 536870912, pids = 256 }`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ResourceLimits {
+    /// Number of CPUs the container may use, e.g. `0.5` for half a core.
+    pub cpus: Option<f64>,
+    /// Memory limit in bytes. Swap is disabled, so the node is killed when it
+    /// exceeds the limit.
+    pub memory: Option<u64>,
+    /// Maximum number of processes and threads in the container.
+    pub pids: Option<i64>,
+}
+
+impl ResourceLimits {
+    /// CPU limit in billionths of a CPU, as Docker expects it.
+    pub(crate) fn nano_cpus(&self) -> Option<i64> {
+        self.cpus.map(|cpus| (cpus * 1e9) as i64)
+    }
+}
+
+fn deserialize_seconds<'de, D>(deserializer: D) -> Result<Duration, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let seconds = f64::deserialize(deserializer)?;
+    Duration::try_from_secs_f64(seconds).map_err(serde::de::Error::custom)
+}
+
+impl Bitcoind<DockerBackend> {
     /// Creates a new `Bitcoind` instance with default flags.
     ///
+    /// # Panics
+    ///
+    /// Panics if the Docker client cannot be created. Use [`Bitcoind::try_new`] instead.
+    ///
     /// # Arguments
     ///
     /// * `container_name` - The name of the Docker container.
     /// * `image` - The Docker image to use.
     /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    #[deprecated(since = "0.5.0", note = "use `Bitcoind::try_new` instead")]
     pub fn new(container_name: &str, image: &str, rpc_config: RpcConfig) -> Self {
-        Self::new_with_flags(container_name, image, rpc_config, BitcoindFlags::default())
+        Self::try_new(container_name, image, rpc_config).expect("failed to create Bitcoind")
     }
 
     /// Creates a new `Bitcoind` instance with specified flags.
     ///
+    /// # Panics
+    ///
+    /// Panics if the Docker client cannot be created. Use
+    /// [`Bitcoind::try_new_with_flags`] instead.
+    ///
     /// # Arguments
     ///
     /// * `container_name` - The name of the Docker container.
     /// * `image` - The Docker image to use.
     /// * `rpc_config` - The RPC configuration for the Bitcoin node.
     /// * `flags` - Custom flags for the Bitcoin node.
+    #[deprecated(since = "0.5.0", note = "use `Bitcoind::try_new_with_flags` instead")]
     pub fn new_with_flags(
         container_name: &str,
         image: &str,
         rpc_config: RpcConfig,
         flags: BitcoindFlags,
     ) -> Self {
-        Self {
-            docker: Docker::connect_with_local_defaults().unwrap(),
-            container_name: container_name.to_string(),
-            image: image.to_string(),
-            runtime: Runtime::new().unwrap(),
-            rpc_config,
-            flags,
-        }
+        Self::try_new_with_flags(container_name, image, rpc_config, flags)
+            .expect("failed to create Bitcoind")
     }
 
-    /// Starts the `bitcoind` Docker container.
+    /// Creates a new `Bitcoind` instance with default flags.
+    ///
+    /// # Arguments
     ///
-    /// This method checks if the Docker daemon is active and then attempts to start
-    /// the `bitcoind` container. If the container image is not found, it will pull
-    /// the image and retry starting the container.
+    /// * `container_name` - The name of the Docker container.
+    /// * `image` - The Docker image to use.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` if the container starts successfully.
-    /// * `Err(Error)` if there is an error starting the container.
-    pub fn start(&self) -> Result<(), Error> {
-        info!("Checking if Docker daemon is active");
-        let ping_result = self.runtime.block_on(async { self.docker.ping().await });
-
-        if ping_result.is_err() {
-            return Err(Error::DockerResponseNotFoundError {
-                message:
-                    "Docker deamon is not running. Make sure to start it before running this test"
-                        .to_string(),
-            });
-        }
+    /// * `Err(BitcoindError::DockerUnavailable)` if the Docker client cannot be created.
+    pub fn try_new(
+        container_name: &str,
+        image: &str,
+        rpc_config: RpcConfig,
+    ) -> Result<Self, BitcoindError> {
+        Self::try_new_with_flags(container_name, image, rpc_config, BitcoindFlags::default())
+    }
 
-        info!("Starting bitcoind container");
-        self.runtime.block_on(async {
-            self.internal_stop().await?;
-
-            let err = self.create_and_start_container().await;
-            if let Err(err) = err {
-                //FIX: For some reason checking the list of images is not working, so I handle the error here and retry.
-                if err.to_string().contains("No such image") {
-                    self.pull_image_if_not_present().await?;
-                    self.create_and_start_container().await?;
-                } else {
-                    return Err(err);
-                }
-            }
+    /// Creates a new `Bitcoind` instance with specified flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_name` - The name of the Docker container.
+    /// * `image` - The Docker image to use.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    /// * `flags` - Custom flags for the Bitcoin node.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::DockerUnavailable)` if the Docker client cannot be created.
+    pub fn try_new_with_flags(
+        container_name: &str,
+        image: &str,
+        rpc_config: RpcConfig,
+        flags: BitcoindFlags,
+    ) -> Result<Self, BitcoindError> {
+        Self::builder()
+            .container_name(container_name)
+            .image(image)
+            .rpc_config(rpc_config)
+            .flags(flags)
+            .build()
+    }
 
-            Ok(())
-        })
+    /// Creates a new `Bitcoind` instance from a [`BitcoindConfig`].
+    ///
+    /// When `config.hash` is set the container runs the image pinned to that digest,
+    /// and when `config.flags` is missing the default flags are used.
+    pub fn from_config(config: &BitcoindConfig) -> Result<Self, BitcoindError> {
+        config.builder().build()
+    }
+
+    /// Returns a [`BitcoindBuilder`] to configure a new `Bitcoind` instance.
+    pub fn builder() -> BitcoindBuilder {
+        BitcoindBuilder::new()
     }
 
-    /// Stops the `bitcoind` Docker container.
+    /// Starts an isolated regtest node for a single test: a unique container
+    /// name, free host ports and random RPC credentials. Returns once the node
+    /// answers RPC.
     ///
-    /// This method stops the running `bitcoind` container by calling the internal
-    /// stop method.
+    /// ```ignore
+    /// let (bitcoind, rpc_config) = Bitcoind::ephemeral().await?;
+    /// ```
+    ///
+    /// The container is removed when the returned `Bitcoind` is dropped.
     ///
     /// # Returns
     ///
-    /// * `Ok(())` if the container stops successfully.
-    /// * `Err(Error)` if there is an error stopping the container.
-    pub fn stop(&self) -> Result<(), Error> {
-        info!("Stopping bitcoind container");
-        self.runtime.block_on(async {
-            self.internal_stop().await?;
-            Ok(())
-        })
+    /// * `Ok((Bitcoind, RpcConfig))` with the RPC config pointing at the published port.
+    /// * `Err(BitcoindError)` if the node can't be started.
+    pub async fn ephemeral() -> Result<(Self, RpcConfig), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        let rpc_config = bitcoind.start().await?;
+        Ok((bitcoind, rpc_config))
     }
 
-    async fn internal_stop(&self) -> Result<(), Error> {
-        if self.is_running().await? {
-            info!("Container was running. Stopping bitcoind container");
-            self.docker
-                .remove_container(
-                    &self.container_name,
-                    Some(RemoveContainerOptions {
-                        force: true,
-                        ..Default::default()
-                    }),
-                )
-                .await?;
-            for _ in 0..10 {
-                if !self.is_running().await? {
-                    break;
-                }
-                tokio::time::sleep(std::time::Duration::from_secs(1)).await;
-                info!("Waiting for bitcoind container to stop");
-            }
-        }
-        Ok(())
+    /// Returns the name of the container, e.g. the one generated with
+    /// [`BitcoindBuilder::unique_name`].
+    pub fn container_name(&self) -> &str {
+        self.backend.container_name()
     }
 
-    async fn is_running(&self) -> Result<bool, Error> {
-        let containers = self
-            .docker
-            .list_containers(None::<bollard::container::ListContainersOptions<String>>)
-            .await?;
-        for container in containers {
-            if let Some(names) = container.names {
-                if names.contains(&format!("/{}", self.container_name)) {
-                    return Ok(true);
-                }
-            }
-        }
-        Ok(false)
+    /// Returns the host ports Docker published for the running container.
+    ///
+    /// This is useful with ephemeral ports, and to debug port binding problems.
+    /// It is empty with host networking, since no port is published.
+    pub async fn mapped_ports(&self) -> Result<MappedPorts, BitcoindError> {
+        self.backend.mapped_ports().await
     }
 
-    async fn pull_image_if_not_present(&self) -> Result<(), Error> {
-        info!("Image not found locally. Pulling image: {}", self.image);
-        let options = Some(CreateImageOptions {
-            from_image: self.image.clone(),
-            ..Default::default()
-        });
-
-        let mut stream = self.docker.create_image(options, None, None);
-        while let Some(result) = stream.next().await {
-            match result {
-                Ok(progress) => {
-                    info!("Progress: {:?}", progress.progress);
-                }
-                Err(error) => {
-                    return Err(error);
-                }
-            }
-        }
+    /// Returns the `tcp://` addresses of the enabled ZMQ notifications, with
+    /// the host ports Docker actually published.
+    pub async fn zmq_endpoints(&self) -> Result<Vec<ZmqEndpoint>, BitcoindError> {
+        self.backend.zmq_endpoints().await
+    }
 
-        Ok(())
+    /// Returns what Docker reports about the container: its id, the image
+    /// digest actually running, mounts, published ports, start time and
+    /// restart count.
+    pub async fn inspect(&self) -> Result<ContainerDetails, BitcoindError> {
+        self.backend.inspect().await
     }
 
-    async fn create_and_start_container(&self) -> Result<(), Error> {
-        info!("Creating and starting bitcoind container");
-
-        let min_relay_tx_fee = format!("-minrelaytxfee={}", self.flags.min_relay_tx_fee);
-        let block_min_tx_fee = format!("-blockmintxfee={}", self.flags.block_min_tx_fee);
-        let debug = format!("-debug={}", self.flags.debug);
-        let fallback_fee = format!("-fallbackfee={}", self.flags.fallback_fee);
-
-        let config = Config {
-            image: Some(self.image.clone()),
-            env: Some(vec!["BITCOIN_DATA=/data".to_string()]),
-            host_config: Some(HostConfig {
-                auto_remove: Some(true),
-                port_bindings: Some(
-                    [(
-                        //TODO: Parse port from url
-                        "18443/tcp".to_string(),
-                        Some(vec![bollard::service::PortBinding {
-                            host_ip: Some("0.0.0.0".to_string()),
-                            host_port: Some("18443".to_string()),
-                        }]),
-                    )]
-                    .iter()
-                    .cloned()
-                    .collect(),
-                ),
-                ..Default::default()
-            }),
-            cmd: Some(vec![
-                "-regtest=1".to_string(),
-                "-printtoconsole".to_string(),
-                "-rpcallowip=0.0.0.0/0".to_string(),
-                "-rpcbind=0.0.0.0".to_string(),
-                format!("-rpcuser={}", self.rpc_config.username.expose_secret()).to_string(),
-                format!("-rpcpassword={}", self.rpc_config.password.expose_secret()).to_string(),
-                "-server=1".to_string(),
-                "-txindex=1".to_string(),
-                debug,
-                min_relay_tx_fee,
-                block_min_tx_fee,
-                fallback_fee,
-            ]),
-            ..Default::default()
-        };
-        let ContainerCreateResponse { id, .. } = self
-            .docker
-            .create_container::<&str, String>(
-                Some(CreateContainerOptions {
-                    name: &self.container_name,
-                }),
-                config,
+    /// Adds the node `peer` runs in another container as a peer with
+    /// `addnode`, reaching it over their Docker network.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if `peer` has no address on a
+    ///   Docker network, e.g. with host networking.
+    pub async fn connect_peer(&self, peer: &Bitcoind) -> Result<(), BitcoindError> {
+        let address = peer.inspect().await?.ip_address.ok_or_else(|| {
+            BitcoindError::InvalidConfig(format!(
+                "{} has no address on a Docker network",
+                peer.backend.name()
+            ))
+        })?;
+        self.rpc_client()
+            .call::<Value>(
+                "addnode",
+                json!([format!("{}:{}", address, P2P_CONTAINER_PORT), "add"]),
             )
             .await?;
-        self.docker.start_container::<String>(&id, None).await?;
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
         Ok(())
     }
-}
 
-#[cfg(test)]
-mod tests {
+    /// Returns a single sample of the container's CPU, memory and IO usage,
+    /// e.g. to fail a stress test that exceeds its resource budget.
+    pub async fn stats(&self) -> Result<ResourceUsage, BitcoindError> {
+        self.backend.stats().await
+    }
 
-    use super::*;
-    use bitcoin::Network;
-    use redact::Secret;
+    /// Streams the container's resource usage, one sample per second, until
+    /// the container stops.
+    pub fn stats_stream(&self) -> impl Stream<Item = Result<ResourceUsage, BitcoindError>> + '_ {
+        self.backend.stats_stream()
+    }
 
-    #[test]
-    fn test_start_stop_bitcoind() -> Result<(), Error> {
-        let rpc_config = RpcConfig {
-            username: Secret::new("foo".to_string()),
-            password: Secret::new("rpcpassword".to_string()),
-            url: Secret::new("http://localhost:18443".to_string()),
-            wallet: "mywallet".to_string(),
-            network: Network::Regtest,
-        };
+    /// Copies the host file or directory `host_path` to `container_path` in
+    /// the running container, e.g. a wallet to load or block files to import.
+    /// Use [`BitcoindBuilder::copy_in`] to copy files before the node starts.
+    pub async fn copy_in(
+        &self,
+        host_path: &Path,
+        container_path: &str,
+    ) -> Result<(), BitcoindError> {
+        self.backend.copy_in(host_path, container_path).await
+    }
 
-        let bitcoind = Bitcoind::new(
-            "bitcoin-regtest",
-            "bitcoin/bitcoin:29.1",
-            rpc_config.clone(),
+    /// Copies the node's datadir (blocks, chainstate, wallets and logs) into
+    /// the host directory `host_path`, e.g. to inspect the chain after a test
+    /// or seed other nodes with it. Pause the node first for a consistent copy.
+    pub async fn export_datadir(&self, host_path: &Path) -> Result<(), BitcoindError> {
+        self.backend.export_datadir(host_path).await
+    }
+
+    /// Removes the containers, volumes and networks earlier runs of this
+    /// crate left behind, e.g. from test binaries that were killed. Every
+    /// container is labeled with the crate version, a run id, the test name
+    /// and its creation time; `filter` selects by them.
+    ///
+    /// Containers kept with `reuse_existing` or `keep_on_drop` are left alone,
+    /// and so are running ones unless `filter.include_running` is set.
+    pub async fn cleanup_orphans(
+        &self,
+        filter: &OrphanFilter,
+    ) -> Result<RemovedOrphans, BitcoindError> {
+        self.backend.cleanup_orphans(filter).await
+    }
+
+    /// Loads the images in a `docker save` archive (optionally gzip-compressed)
+    /// into the daemon, for machines that can't reach a registry.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::ImageLoad)` if the archive can't be read or the
+    ///   daemon rejects it.
+    pub async fn load_image_from_tar<P: AsRef<Path>>(&self, path: P) -> Result<(), BitcoindError> {
+        self.backend.load_image_from_tar(path.as_ref()).await
+    }
+
+    /// Upgrades (or downgrades) the node to `new_image` on the same chain
+    /// state, e.g. to test Bitcoin Core 27 → 29 upgrades, and waits until it
+    /// has reindexed or migrated its data and caught up with its headers.
+    ///
+    /// The node is stopped cleanly and a container of the new image started
+    /// on its datadir volume. `timeout` bounds both the startup, which
+    /// includes any database migration, and the catch-up. The new image is
+    /// kept even if the upgrade fails; it is pulled or loaded like the
+    /// original, and a pinned digest no longer applies.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the datadir is not on a
+    ///   volume or host directory, which the old container would take along.
+    /// * `Err(BitcoindError::ReadinessTimeout)` if the node isn't ready and
+    ///   synced after `timeout`.
+    pub async fn upgrade_image(
+        &mut self,
+        new_image: &str,
+        timeout: Duration,
+    ) -> Result<RpcConfig, BitcoindError> {
+        if !self.backend.container.datadir_mounted() {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "upgrading the image requires a volume mounted at {}",
+                self.backend.container.datadir()
+            )));
+        }
+        info!(
+            "Upgrading bitcoind node {} from {} to {}",
+            self.backend.name(),
+            self.backend.image,
+            new_image
         );
+        self.backend.image = new_image.to_string();
+        self.backend.image_hash = None;
+        *self
+            .node_version
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = None;
+        if matches!(
+            self.backend.container.image_source,
+            ImageSource::Build { .. }
+        ) {
+            self.backend.container.image_source = ImageSource::Registry;
+        }
 
-        bitcoind.start()?;
-        bitcoind.stop()?;
+        let started = Instant::now();
+        let readiness = self.readiness.clone();
+        self.readiness.timeout = timeout;
+        let rpc_config = self.restart(false).await;
+        self.readiness = readiness;
+        let rpc_config = rpc_config?;
+        self.wait_until_ready(
+            &[ReadyCondition::Synced],
+            timeout.saturating_sub(started.elapsed()),
+        )
+        .await?;
+        Ok(rpc_config)
+    }
+}
 
-        Ok(())
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Creates a new `Bitcoind` instance that runs the node on a custom backend.
+    ///
+    /// # Arguments
+    ///
+    /// * `backend` - The runtime hosting the node.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    /// * `flags` - Custom flags for the Bitcoin node.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the RPC port can't be derived from the url.
+    pub fn with_backend(
+        backend: B,
+        rpc_config: RpcConfig,
+        flags: BitcoindFlags,
+    ) -> Result<Self, BitcoindError> {
+        let rpc_port = parse_rpc_port(rpc_config.url.expose_secret(), rpc_config.network)?;
+        Ok(Bitcoind {
+            backend,
+            effective_rpc_config: Mutex::new(rpc_config.clone()),
+            node_version: Mutex::new(None),
+            rpc_config,
+            rpc_port,
+            flags,
+            readiness: ReadinessConfig::default(),
+            log_artifacts: None,
+            hooks: HookList::default(),
+            stopped: AtomicBool::new(true),
+            lifecycle: tokio::sync::Mutex::new(()),
+        })
     }
 
-    #[test]
-    fn test_start_stop_bitcoind_with_flags() -> Result<(), Error> {
-        let rpc_config = RpcConfig {
-            username: Secret::new("foo".to_string()),
-            password: Secret::new("rpcpassword".to_string()),
-            url: Secret::new("http://localhost:18443".to_string()),
-            wallet: "mywallet".to_string(),
-            network: Network::Regtest,
+    /// Returns the backend running the node.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Sets how long `start` waits for the node to answer RPC requests.
+    pub fn with_readiness_config(mut self, readiness: ReadinessConfig) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    /// Saves the node's full output to `dir` whenever `start`, `restart` or
+    /// `recreate_with_flags` fails, so CI failures can be debugged afterwards.
+    pub fn with_log_artifacts<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.log_artifacts = Some(dir.into());
+        self
+    }
+
+    /// Starts the `bitcoind` node.
+    ///
+    /// With the Docker backend this checks if the Docker daemon is active and then
+    /// attempts to start the `bitcoind` container. If the container image is not
+    /// found, it will pull the image and retry. It only returns once the node
+    /// answers RPC requests.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RpcConfig)` if the node starts and is ready. The returned config
+    ///   points at the host port actually published by Docker.
+    /// * `Err(BitcoindError)` if there is an error starting the node or it
+    ///   does not become ready within the configured timeout.
+    pub async fn start(&self) -> Result<RpcConfig, BitcoindError> {
+        let _lifecycle = self.lifecycle.lock().await;
+        self.start_node().await
+    }
+
+    /// `start` for a caller already holding the lifecycle lock.
+    pub(crate) async fn start_node(&self) -> Result<RpcConfig, BitcoindError> {
+        let spec = self.node_spec();
+        self.check_flags(&spec)?;
+        self.stopped.store(false, Ordering::SeqCst);
+        self.run_on_starting().await;
+        let started = async {
+            self.backend.create(&spec).await?;
+            self.backend.start().await?;
+            self.await_started(&spec).await
         };
+        self.save_logs_on_failure(started.await).await
+    }
 
-        let flags = BitcoindFlags {
-            min_relay_tx_fee: 0.00001,
-            block_min_tx_fee: 0.00001,
-            debug: 1,
-            fallback_fee: 0.0002,
+    /// Restarts the node and waits until it answers RPC again.
+    ///
+    /// With `preserve_data` the node restarts in place and keeps its chain
+    /// state and wallets; with the Docker backend the container is restarted
+    /// rather than recreated. Otherwise it is stopped and started afresh, like
+    /// `stop` followed by `start`.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(RpcConfig)` once the node is ready. The host port may differ from
+    ///   the previous one with ephemeral ports.
+    /// * `Err(BitcoindError)` if the node fails to restart or become ready.
+    pub async fn restart(&self, preserve_data: bool) -> Result<RpcConfig, BitcoindError> {
+        let _lifecycle = self.lifecycle.lock().await;
+        self.restart_node(preserve_data).await
+    }
+
+    /// `restart` for a caller already holding the lifecycle lock.
+    pub(crate) async fn restart_node(
+        &self,
+        preserve_data: bool,
+    ) -> Result<RpcConfig, BitcoindError> {
+        info!("Restarting bitcoind node {}", self.backend.name());
+        let spec = self.node_spec();
+        self.check_flags(&spec)?;
+        self.stopped.store(false, Ordering::SeqCst);
+        self.run_on_starting().await;
+        let restarted = async {
+            if preserve_data {
+                self.backend.restart(&spec).await?;
+            } else {
+                self.backend.stop().await?;
+                self.backend.create(&spec).await?;
+                self.backend.start().await?;
+            }
+            self.await_started(&spec).await
         };
+        self.save_logs_on_failure(restarted.await).await
+    }
 
-        let bitcoind = Bitcoind::new_with_flags(
-            "bitcoin-regtest",
-            "bitcoin/bitcoin:29.1",
-            rpc_config.clone(),
-            flags,
+    /// Returns the current state of the node, e.g. to tell a paused node from
+    /// one that exited.
+    pub async fn status(&self) -> Result<NodeStatus, BitcoindError> {
+        self.backend.status().await
+    }
+
+    /// Returns the node's output, e.g. to find out why it failed to start.
+    ///
+    /// ```ignore
+    /// let logs = bitcoind.logs(LogOptions::tail(50)).await?;
+    /// ```
+    pub async fn logs(&self, options: LogOptions) -> Result<String, BitcoindError> {
+        self.backend.logs(&options).await
+    }
+
+    /// Streams the node's output line by line, from the start and then live,
+    /// e.g. to mirror it in a test harness. The stream ends when the node stops.
+    pub fn follow_logs(&self) -> impl Stream<Item = Result<LogLine, BitcoindError>> + '_ {
+        self.backend.follow_logs()
+    }
+
+    /// Runs a command next to the node and waits for it to finish, e.g.
+    /// `&["cat", "/data/regtest/.cookie"]`. With the Docker backend it runs
+    /// inside the container.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(ExecOutput)` with the command's stdout, stderr and exit code, also
+    ///   when it exits with a non-zero code.
+    /// * `Err(BitcoindError)` if the command can't be run.
+    pub async fn exec(&self, cmd: &[&str]) -> Result<ExecOutput, BitcoindError> {
+        let cmd = cmd.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        self.backend.exec(&cmd).await
+    }
+
+    /// Runs `bitcoin-cli` with `args` next to the node and parses its output,
+    /// e.g. `cli::<u64>(&["getblockcount"])`. The network and credential
+    /// flags are added, so this works even when the RPC port isn't reachable
+    /// from the host. Output that isn't JSON, such as an address, is parsed as
+    /// a string.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Cli)` if `bitcoin-cli` exits with a non-zero code.
+    /// * `Err(BitcoindError::Unsupported)` if the backend can't run `bitcoin-cli`.
+    pub async fn cli<T: DeserializeOwned>(&self, args: &[&str]) -> Result<T, BitcoindError> {
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let cmd = self.backend.cli_command(&self.node_spec(), &args)?;
+        let output = self.backend.exec(&cmd).await?;
+        if output.exit_code != 0 {
+            return Err(BitcoindError::Cli {
+                exit_code: output.exit_code,
+                stderr: output.stderr.trim().to_string(),
+            });
+        }
+        parse_cli_output(&output.stdout)
+    }
+
+    /// Kills the node with `SIGKILL`, without a clean shutdown, to test crash
+    /// recovery.
+    ///
+    /// With the Docker backend the killed container is removed like a stopped
+    /// one, so keep the datadir on a volume and call `start` to bring up a node
+    /// on the crashed chain state. The native backend keeps its datadir and can
+    /// be brought back with `restart(true)`.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Unsupported)` if the backend can't kill the node.
+    pub async fn kill(&self) -> Result<(), BitcoindError> {
+        let _lifecycle = self.lifecycle.lock().await;
+        info!("Killing bitcoind node {}", self.backend.name());
+        self.stopped.store(true, Ordering::SeqCst);
+        self.backend.kill().await?;
+        self.run_on_stopped().await;
+        Ok(())
+    }
+
+    /// Freezes the node without closing its connections, to test clients
+    /// against a node that stops responding. With the Docker backend the
+    /// container is paused.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Unsupported)` if the backend can't pause the node.
+    pub async fn pause(&self) -> Result<(), BitcoindError> {
+        info!("Pausing bitcoind node {}", self.backend.name());
+        self.backend.pause().await
+    }
+
+    /// Resumes a node frozen by [`Bitcoind::pause`].
+    pub async fn unpause(&self) -> Result<(), BitcoindError> {
+        info!("Unpausing bitcoind node {}", self.backend.name());
+        self.backend.unpause().await
+    }
+
+    /// Replaces the node with one running `flags`, keeping its chain state,
+    /// and waits until it answers RPC, e.g. to enable `-blockfilterindex`
+    /// without rebuilding a long chain.
+    ///
+    /// The Docker backend needs a volume mounted at the container's datadir
+    /// (`/data` unless `BITCOIN_DATA` is overridden). The new flags are kept
+    /// even if recreating fails.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the datadir is not on a volume.
+    /// * `Err(BitcoindError::Unsupported)` if the backend can't keep the data.
+    pub async fn recreate_with_flags(
+        &mut self,
+        flags: BitcoindFlags,
+    ) -> Result<RpcConfig, BitcoindError> {
+        info!(
+            "Recreating bitcoind node {} with new flags",
+            self.backend.name()
         );
+        self.flags = flags;
+        let spec = self.node_spec();
+        self.check_flags(&spec)?;
+        self.stopped.store(false, Ordering::SeqCst);
+        self.run_on_starting().await;
+        let recreated = async {
+            self.backend.recreate(&spec).await?;
+            self.await_started(&spec).await
+        };
+        self.save_logs_on_failure(recreated.await).await
+    }
+
+    /// Returns the RPC configuration to reach the node.
+    ///
+    /// After `start` this points at the host port actually published by Docker,
+    /// which differs from the configured one when ephemeral ports are enabled.
+    pub fn rpc_config(&self) -> RpcConfig {
+        self.effective_rpc_config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns a `bitvmx_bitcoin_rpc` client for the node, built from
+    /// [`Bitcoind::rpc_config`]. Get a new one after a restart with ephemeral
+    /// ports, which changes the url.
+    ///
+    /// ```ignore
+    /// let client = bitcoind.rpc()?;
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::RpcClient)` if the client can't be created.
+    pub fn rpc(&self) -> Result<BitcoinClient, BitcoindError> {
+        BitcoinClient::new_from_config(&self.rpc_config())
+            .map_err(|e| BitcoindError::RpcClient(e.to_string()))
+    }
+
+    /// Internal async client for the node, see [`Bitcoind::rpc_config`].
+    pub(crate) fn rpc_client(&self) -> RpcClient {
+        RpcClient::new(&self.rpc_config())
+    }
+
+    /// Stops the `bitcoind` node.
+    ///
+    /// With the Docker backend `bitcoin-cli stop` shuts the node down cleanly
+    /// first; the container is force-removed if that fails or takes longer
+    /// than the stop timeout.
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the node stops successfully.
+    /// * `Err(BitcoindError)` if there is an error stopping the node.
+    pub async fn stop(&self) -> Result<(), BitcoindError> {
+        let _lifecycle = self.lifecycle.lock().await;
+        info!("Stopping bitcoind node {}", self.backend.name());
+        self.stopped.store(true, Ordering::SeqCst);
+        self.backend.stop().await?;
+        self.run_on_stopped().await;
+        Ok(())
+    }
+
+    /// Backend-independent part of the node configuration.
+    pub(crate) fn node_spec(&self) -> NodeSpec {
+        node_spec(&self.rpc_config, self.rpc_port, &self.flags)
+    }
+
+    /// Resolves where the started node answers RPC and waits until it does.
+    pub(crate) async fn await_started(&self, spec: &NodeSpec) -> Result<RpcConfig, BitcoindError> {
+        let rpc_config = self.resolve_rpc_config(spec).await?;
+        *self
+            .effective_rpc_config
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = rpc_config.clone();
+
+        self.wait_for_rpc(&rpc_config).await?;
+        self.detect_version(spec, &rpc_config).await;
+        self.run_on_ready(&rpc_config).await;
+        Ok(rpc_config)
+    }
+
+    /// Returns the configured RPC config pointing at the address the backend
+    /// exposes RPC on, e.g. the host port Docker published or a remote host.
+    async fn resolve_rpc_config(&self, spec: &NodeSpec) -> Result<RpcConfig, BitcoindError> {
+        let address = self.backend.rpc_address(spec).await?;
+        rpc_config_at(&self.rpc_config, &address)
+    }
+
+    async fn wait_for_rpc(&self, rpc_config: &RpcConfig) -> Result<(), BitcoindError> {
+        info!("Waiting for bitcoind RPC to be ready");
+        let client = RpcClient::new(rpc_config);
+        let deadline = Instant::now() + self.readiness.timeout;
+        let mut backoff = self.readiness.initial_backoff;
+
+        loop {
+            let probe = async {
+                match self.backend.probe_readiness().await {
+                    Some(probe) => Ok(probe),
+                    None => match client.call::<Value>("getblockchaininfo", json!([])).await {
+                        Ok(_) => Ok(Ok(())),
+                        Err(RpcError::Http { status: 401, .. }) => {
+                            Err(BitcoindError::ReadinessFailed(
+                                "RPC credentials were rejected by the node".to_string(),
+                            ))
+                        }
+                        Err(err) => Ok(Err(err.to_string())),
+                    },
+                }
+            };
+            let probe = probe_until(deadline, probe)
+                .await
+                .unwrap_or_else(|| Ok(Err(NO_ANSWER.to_string())))?;
+            let last_error = match probe {
+                Ok(()) => {
+                    info!("bitcoind RPC is ready");
+                    return Ok(());
+                }
+                Err(err) => err,
+            };
+
+            if !self.backend.is_running().await? {
+                return Err(BitcoindError::ReadinessFailed(format!(
+                    "node {} exited before answering RPC. Last error: {}",
+                    self.backend.name(),
+                    last_error
+                )));
+            }
+
+            if Instant::now() + backoff > deadline {
+                return Err(BitcoindError::StartupTimeout {
+                    timeout: self.readiness.timeout,
+                    last_error,
+                });
+            }
+
+            debug!("bitcoind RPC not ready yet: {}", last_error);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.readiness.max_backoff);
+        }
+    }
+}
+
+impl<B: NodeBackend> Drop for Bitcoind<B> {
+    fn drop(&mut self) {
+        // After a panic the node's output is the best clue to what went wrong.
+        let log_artifacts = self
+            .log_artifacts
+            .as_deref()
+            .filter(|_| std::thread::panicking());
+        self.backend.remove_on_drop(log_artifacts);
+    }
+}
+
+/// Builder of the node started by [`Bitcoind::ephemeral`].
+pub(crate) fn ephemeral_builder() -> BitcoindBuilder {
+    Bitcoind::builder()
+        .container_name("bitcoin-ephemeral")
+        .unique_name(true)
+        .rpc_config(RpcConfig {
+            username: Secret::new(format!("user-{}", random_hex(8))),
+            password: Secret::new(random_hex(32)),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "test".to_string(),
+            network: Network::Regtest,
+        })
+}
+
+/// Backend-independent part of the configuration of a node with the given
+/// RPC settings and flags.
+pub(crate) fn node_spec(rpc_config: &RpcConfig, rpc_port: u16, flags: &BitcoindFlags) -> NodeSpec {
+    let mut args: Vec<String> = chain_arg(rpc_config.network).into_iter().collect();
+    args.extend([
+        "-printtoconsole".to_string(),
+        format!("-rpcuser={}", rpc_config.username.expose_secret()),
+        format!("-rpcpassword={}", rpc_config.password.expose_secret()),
+        "-server=1".to_string(),
+    ]);
+    if flags.txindex && flags.prune.unwrap_or(0) == 0 {
+        args.push("-txindex=1".to_string());
+    }
+    if flags.block_filter_index {
+        args.extend([
+            "-blockfilterindex=1".to_string(),
+            "-peerblockfilters=1".to_string(),
+        ]);
+    }
+    if flags.coinstats_index {
+        args.push("-coinstatsindex=1".to_string());
+    }
+    if let Some(prune) = flags.prune {
+        args.push(format!("-prune={}", prune));
+    }
+    if flags.debug_categories.is_empty() {
+        args.push(format!("-debug={}", flags.debug));
+    } else {
+        args.extend(
+            flags
+                .debug_categories
+                .iter()
+                .map(|category| format!("-debug={}", category)),
+        );
+    }
+    args.extend(
+        flags
+            .debug_exclude
+            .iter()
+            .map(|category| format!("-debugexclude={}", category)),
+    );
+    args.extend([
+        format!("-minrelaytxfee={}", flags.min_relay_tx_fee),
+        format!("-blockmintxfee={}", flags.block_min_tx_fee),
+        format!("-fallbackfee={}", flags.fallback_fee),
+    ]);
+    if flags.blocks_only {
+        args.push("-blocksonly=1".to_string());
+    }
+    args.extend(
+        flags
+            .whitelist
+            .iter()
+            .map(|entry| format!("-whitelist={}", entry)),
+    );
+    if let Some(relay) = flags.whitelist_relay {
+        args.push(format!("-whitelistrelay={}", u8::from(relay)));
+    }
+    if let Some(force_relay) = flags.whitelist_force_relay {
+        args.push(format!("-whitelistforcerelay={}", u8::from(force_relay)));
+    }
+    if let Some(challenge) = &flags.signet_challenge {
+        args.push(format!("-signetchallenge={}", challenge));
+    }
+
+    NodeSpec {
+        network: rpc_config.network,
+        rpc_port,
+        args,
+        extra_args: flags.extra_args.clone(),
+    }
+}
+
+/// Flag selecting `network`, none for mainnet, the default. The network flags
+/// rather than `-chain` are used, since older releases only know those.
+fn chain_arg(network: Network) -> Option<String> {
+    match network {
+        Network::Bitcoin => None,
+        Network::Testnet => Some("-testnet=1".to_string()),
+        network => Some(format!("-{}=1", network.to_core_arg())),
+    }
+}
+
+/// Parses `bitcoin-cli` output, which is JSON except for plain strings.
+fn parse_cli_output<T: DeserializeOwned>(stdout: &str) -> Result<T, BitcoindError> {
+    let stdout = stdout.trim();
+    let value = if stdout.is_empty() {
+        Value::Null
+    } else {
+        serde_json::from_str(stdout).unwrap_or_else(|_| Value::String(stdout.to_string()))
+    };
+    serde_json::from_value(value)
+        .map_err(|e| BitcoindError::Rpc(RpcError::InvalidResponse(e.to_string())))
+}
+
+/// Returns `rpc_config` with its url pointing at `address`.
+pub(crate) fn rpc_config_at(
+    rpc_config: &RpcConfig,
+    address: &RpcAddress,
+) -> Result<RpcConfig, BitcoindError> {
+    let mut url = reqwest::Url::parse(rpc_config.url.expose_secret())
+        .map_err(|e| BitcoindError::InvalidConfig(format!("invalid RPC url: {}", e)))?;
+    url.set_port(Some(address.port))
+        .map_err(|_| BitcoindError::InvalidConfig("RPC url cannot have a port".to_string()))?;
+    if let Some(host) = &address.host {
+        let host = if host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            host.clone()
+        };
+        url.set_host(Some(&host)).map_err(|e| {
+            BitcoindError::InvalidConfig(format!("invalid remote host {}: {}", host, e))
+        })?;
+    }
+
+    let mut rpc_config = rpc_config.clone();
+    rpc_config.url = Secret::new(url.to_string());
+    Ok(rpc_config)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::ports::{ZmqTopic, RPC_CONTAINER_PORT, ZMQ_CONTAINER_PORT};
+    use crate::snapshot::PREMINED_BLOCKS;
+    use bitcoin::Network;
+
+    #[tokio::test]
+    async fn test_start_stop_bitcoind() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::try_new(
+            "bitcoin-regtest",
+            "bitcoin/bitcoin:29.1",
+            rpc_config.clone(),
+        )?;
+
+        bitcoind.start().await?;
+        let client = bitcoind.rpc();
+        bitcoind.stop().await?;
+        client?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_stop_bitcoind_with_flags() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let flags = BitcoindFlags {
+            min_relay_tx_fee: 0.00001,
+            block_min_tx_fee: 0.00001,
+            debug: 1,
+            fallback_fee: 0.0002,
+            ..Default::default()
+        };
+
+        let bitcoind = Bitcoind::try_new_with_flags(
+            "bitcoin-regtest",
+            "bitcoin/bitcoin:29.1",
+            rpc_config.clone(),
+            flags,
+        )?;
+
+        bitcoind.start().await?;
+        bitcoind.stop().await?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_extra_args_are_appended() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let flags = BitcoindFlags {
+            extra_args: vec!["-acceptnonstdtxn=1".to_string()],
+            ..Default::default()
+        };
+
+        let bitcoind = Bitcoind::try_new_with_flags(
+            "bitcoin-regtest",
+            "bitcoin/bitcoin:29.1",
+            rpc_config,
+            flags,
+        )?;
+
+        let args = bitcoind
+            .backend
+            .container
+            .bitcoind_args(&bitcoind.node_spec());
+        assert_eq!(args.last().unwrap(), "-acceptnonstdtxn=1");
+        assert!(args.contains(&"-fallbackfee=0.0002".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_debug_categories() {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let args = node_spec(&rpc_config, 18443, &BitcoindFlags::default()).args;
+        assert!(args.contains(&"-debug=1".to_string()));
+
+        let flags: BitcoindFlags = serde_json::from_str(
+            r#"{ "debug_categories": ["net", "mempool"], "debug_exclude": ["leveldb"] }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            flags.debug_categories,
+            vec![DebugCategory::Net, DebugCategory::Mempool]
+        );
+
+        let args = node_spec(&rpc_config, 18443, &flags).args;
+        assert!(!args.contains(&"-debug=1".to_string()));
+        assert!(args.contains(&"-debug=net".to_string()));
+        assert!(args.contains(&"-debug=mempool".to_string()));
+        assert!(args.contains(&"-debugexclude=leveldb".to_string()));
+    }
+
+    #[test]
+    fn test_index_flags() {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+        let args = node_spec(&rpc_config, 18443, &BitcoindFlags::default()).args;
+        assert!(args.contains(&"-txindex=1".to_string()));
+
+        let flags = BitcoindFlags {
+            prune: Some(550),
+            ..Default::default()
+        };
+        let args = node_spec(&rpc_config, 18443, &flags).args;
+        assert!(args.contains(&"-prune=550".to_string()));
+        assert!(!args.iter().any(|arg| arg.starts_with("-txindex")));
+
+        let flags = BitcoindFlags {
+            txindex: false,
+            ..Default::default()
+        };
+        let args = node_spec(&rpc_config, 18443, &flags).args;
+        assert!(!args.iter().any(|arg| arg.starts_with("-txindex")));
+
+        let flags = BitcoindFlags {
+            block_filter_index: true,
+            coinstats_index: true,
+            ..Default::default()
+        };
+        let args = node_spec(&rpc_config, 18443, &flags).args;
+        for arg in [
+            "-blockfilterindex=1",
+            "-peerblockfilters=1",
+            "-coinstatsindex=1",
+        ] {
+            assert!(args.contains(&arg.to_string()), "{}", arg);
+        }
+    }
+
+    #[test]
+    fn test_relay_policy_flags() {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+        let flags: BitcoindFlags = serde_json::from_str(
+            r#"{
+                "blocks_only": true,
+                "whitelist": ["relay@172.17.0.0/16"],
+                "whitelist_relay": true,
+                "whitelist_force_relay": false
+            }"#,
+        )
+        .unwrap();
+        let args = node_spec(&rpc_config, 18443, &flags).args;
+        for arg in [
+            "-blocksonly=1",
+            "-whitelist=relay@172.17.0.0/16",
+            "-whitelistrelay=1",
+            "-whitelistforcerelay=0",
+        ] {
+            assert!(args.contains(&arg.to_string()), "{}", arg);
+        }
+
+        let args = node_spec(&rpc_config, 18443, &BitcoindFlags::default()).args;
+        assert!(!args
+            .iter()
+            .any(|arg| arg.starts_with("-blocksonly") || arg.starts_with("-whitelist")));
+    }
+
+    #[test]
+    fn test_env_overrides_defaults() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .env("BITCOIN_DATA", "/home/bitcoin/.bitcoin")
+            .env("UID", "1000")
+            .build()?;
+
+        assert_eq!(
+            bitcoind.backend.container.container_env(),
+            vec![
+                "BITCOIN_DATA=/home/bitcoin/.bitcoin".to_string(),
+                "UID=1000".to_string()
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmd_prefix_precedes_args() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .entrypoint(Some(vec!["/entrypoint.sh".to_string()]))
+            .cmd_prefix(vec!["bitcoind".to_string()])
+            .build()?;
+
+        let spec = bitcoind.node_spec();
+        let cmd = bitcoind.backend.container.container_cmd(&spec);
+        assert_eq!(cmd[0], "bitcoind");
+        assert_eq!(
+            cmd[1..],
+            bitcoind.backend.container.bitcoind_args(&spec)[..]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_network_selects_chain() -> Result<(), BitcoindError> {
+        for (network, chain_flag, chain) in [
+            (Network::Signet, Some("-signet=1"), "signet"),
+            (Network::Testnet4, Some("-testnet4=1"), "testnet4"),
+            (Network::Testnet, Some("-testnet=1"), "testnet3"),
+            (Network::Bitcoin, None, ""),
+        ] {
+            let bitcoind = Bitcoind::builder()
+                .rpc_config(RpcConfig {
+                    username: Secret::new("foo".to_string()),
+                    password: Secret::new("rpcpassword".to_string()),
+                    url: Secret::new("http://localhost".to_string()),
+                    wallet: "mywallet".to_string(),
+                    network,
+                })
+                .build()?;
+            let spec = bitcoind.node_spec();
+            assert_eq!(spec.rpc_port, crate::ports::default_rpc_port(network));
+
+            let args = bitcoind.backend.container.bitcoind_args(&spec);
+            assert_eq!(
+                args.iter().filter(|arg| arg.contains("-regtest")).count(),
+                0
+            );
+            // Mainnet is the default, selected without a flag.
+            assert!(args.iter().all(|arg| !arg.starts_with("-chain=")));
+            if let Some(chain_flag) = chain_flag {
+                assert!(args.contains(&chain_flag.to_string()));
+            }
+            assert!(args.contains(&format!("-rpcport={}", RPC_CONTAINER_PORT)));
+            assert!(args.contains(&format!("-port={}", P2P_CONTAINER_PORT)));
+            assert_eq!(crate::logs::chain_dir(&args), chain);
+
+            let cli = bitcoind.backend.container.cli_command(&spec, &[]);
+            if let Some(chain_flag) = chain_flag {
+                assert!(cli.contains(&chain_flag.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_profiles() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let btcpay = Bitcoind::builder()
+            .rpc_config(rpc_config.clone())
+            .image("btcpayserver/bitcoin:29.1")
+            .build()?;
+        let container = &btcpay.backend.container;
+        assert_eq!(container.image_profile, ImageProfile::btcpayserver());
+        assert_eq!(container.container_cmd(&btcpay.node_spec())[0], "bitcoind");
+        assert_eq!(container.container_env(), ["BITCOIN_DATA=/data"]);
+
+        let knots = Bitcoind::builder()
+            .rpc_config(rpc_config.clone())
+            .image("bitcoinknots/bitcoin:28.1")
+            .volume("knots-data", "/data")
+            .build()?;
+        let container = &knots.backend.container;
+        assert_eq!(container.effective_entrypoint().unwrap(), ["bitcoind"]);
+        assert!(container.container_env().is_empty());
+        assert_eq!(container.working_dir().as_deref(), Some("/data"));
+        assert!(container
+            .bitcoind_args(&knots.node_spec())
+            .contains(&"-datadir=/data".to_string()));
+        assert!(container.datadir_mounted());
+
+        // An explicit profile outlives a later image change.
+        let custom = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .image_profile(Some(ImageProfile {
+                datadir: "/home/bitcoin/.bitcoin".to_string(),
+                user: Some("1000:1000".to_string()),
+                ..ImageProfile::bitcoin_core()
+            }))
+            .image("registry.local:5000/btcpayserver/bitcoin:29.1")
+            .build()?;
+        assert_eq!(custom.backend.container.datadir(), "/home/bitcoin/.bitcoin");
+        assert!(custom.backend.container.image_profile.cmd_prefix.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_published_services() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .p2p_port(28444)
+            .zmq_port(38332)
+            .rest(true)
+            .build()?;
+
+        assert_eq!(
+            bitcoind
+                .backend
+                .container
+                .published_ports(&bitcoind.node_spec()),
+            vec![
+                (RPC_CONTAINER_PORT, 18443),
+                (P2P_CONTAINER_PORT, 28444),
+                (ZMQ_CONTAINER_PORT, 38332)
+            ]
+        );
+        let args = bitcoind
+            .backend
+            .container
+            .bitcoind_args(&bitcoind.node_spec());
+        assert!(args.contains(&"-rest=1".to_string()));
+        assert!(args.contains(&"-zmqpubrawblock=tcp://0.0.0.0:28332".to_string()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_zmq_topics() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .zmq_topic(ZmqTopic::HashBlock, 38334)
+            .zmq_topic(ZmqTopic::Sequence, 38338)
+            .build()?;
+
+        assert_eq!(
+            bitcoind
+                .backend
+                .container
+                .published_ports(&bitcoind.node_spec())[1..],
+            [
+                (ZmqTopic::HashBlock.container_port(), 38334),
+                (ZmqTopic::Sequence.container_port(), 38338)
+            ]
+        );
+        let args = bitcoind
+            .backend
+            .container
+            .bitcoind_args(&bitcoind.node_spec());
+        assert!(args.contains(&"-zmqpubhashblock=tcp://0.0.0.0:28334".to_string()));
+        assert!(args.contains(&"-zmqpubsequence=tcp://0.0.0.0:28338".to_string()));
+        assert!(!args.iter().any(|arg| arg.starts_with("-zmqpubrawtx")));
+
+        let endpoints = bitcoind.zmq_endpoints().await?;
+        assert_eq!(
+            endpoints,
+            [
+                ZmqEndpoint {
+                    topic: ZmqTopic::HashBlock,
+                    address: "tcp://localhost:38334".to_string()
+                },
+                ZmqEndpoint {
+                    topic: ZmqTopic::Sequence,
+                    address: "tcp://localhost:38338".to_string()
+                }
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_network_listens_on_host_ports() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:28443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .host_network(true)
+            .p2p_port(28444)
+            .zmq_port(38332)
+            .build()?;
+
+        let args = bitcoind
+            .backend
+            .container
+            .bitcoind_args(&bitcoind.node_spec());
+        assert!(args.contains(&"-rpcport=28443".to_string()));
+        assert!(args.contains(&"-port=28444".to_string()));
+        assert!(args.contains(&"-zmqpubrawtx=tcp://0.0.0.0:38332".to_string()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ipv6_host_ip_binds_dual_stack() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://[::1]:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .host_ip(Some("::".parse().unwrap()))
+            .build()?;
+
+        let args = bitcoind
+            .backend
+            .container
+            .bitcoind_args(&bitcoind.node_spec());
+        for arg in ["-rpcbind=0.0.0.0", "-rpcbind=::", "-rpcallowip=::/0"] {
+            assert!(args.contains(&arg.to_string()), "missing {}", arg);
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_image_from_missing_tar() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let path = std::env::temp_dir().join("bitcoind-test-missing-image.tar");
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .image_tar(Some(path.clone()))
+            .build()?;
+        assert_eq!(bitcoind.backend.container.image_tar, Some(path.clone()));
+
+        // The archive is opened before the daemon is contacted.
+        let err = bitcoind.load_image_from_tar(&path).await.unwrap_err();
+        assert!(matches!(err, BitcoindError::ImageLoad { path: ref p, .. } if *p == path));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recreate_requires_datadir_volume() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let mut bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config.clone())
+            .volume("bitcoin-data", "/home/bitcoin/.bitcoin")
+            .build()?;
+        let flags = BitcoindFlags {
+            extra_args: vec!["-blockfilterindex=1".to_string()],
+            ..Default::default()
+        };
+        assert!(matches!(
+            bitcoind.recreate_with_flags(flags.clone()).await,
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        assert_eq!(bitcoind.flags.extra_args, flags.extra_args);
+        assert!(matches!(
+            bitcoind
+                .upgrade_image("bitcoin/bitcoin:29.0", Duration::from_secs(1))
+                .await,
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .env("BITCOIN_DATA", "/home/bitcoin/.bitcoin")
+            .build()?;
+        assert_eq!(
+            bitcoind.backend.container.datadir(),
+            "/home/bitcoin/.bitcoin"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_upgrade_image() -> Result<(), BitcoindError> {
+        let volume = format!("bitcoind-test-upgrade-{}", random_hex(8));
+        let mut bitcoind = ephemeral_builder()
+            .image("bitcoin/bitcoin:27.2")
+            .datadir_volume(Some(&volume))
+            .build()?;
+        bitcoind.start().await?;
+        bitcoind
+            .cli::<Value>(&[
+                "generatetodescriptor",
+                "10",
+                &crate::snapshot::premined_descriptor(),
+            ])
+            .await?;
+
+        let upgraded = bitcoind
+            .upgrade_image("bitcoin/bitcoin:29.0", Duration::from_secs(120))
+            .await;
+        let height = bitcoind.cli::<u64>(&["getblockcount"]).await;
+        let version = bitcoind.cli::<Value>(&["getnetworkinfo"]).await;
+        bitcoind.stop().await?;
+        bitcoind.backend.docker.remove_volume(&volume, None).await?;
+
+        upgraded?;
+        assert_eq!(height?, 10);
+        assert_eq!(version?["version"].as_u64().unwrap() / 10000, 29);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_with_ephemeral_ports() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::builder()
+            .container_name("bitcoin-regtest-ephemeral")
+            .rpc_config(rpc_config)
+            .ephemeral_ports(true)
+            .build()?;
+
+        let effective = bitcoind.start().await?;
+        let port = crate::ports::parse_rpc_port(effective.url.expose_secret(), effective.network)?;
+        assert_ne!(port, 0);
+        assert_eq!(
+            bitcoind.rpc_config().url.expose_secret(),
+            effective.url.expose_secret()
+        );
+
+        bitcoind.stop().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_drop_removes_container() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+        let builder = Bitcoind::builder()
+            .container_name("bitcoin-regtest-drop")
+            .rpc_config(rpc_config)
+            .ephemeral_ports(true);
+
+        let bitcoind = builder.clone().build()?;
+        bitcoind.start().await?;
+        drop(bitcoind);
+        assert_eq!(builder.build()?.status().await?, NodeStatus::NotCreated);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_stop_removes_created_container() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        // Like a start that failed after creating the container.
+        bitcoind.backend().create(&bitcoind.node_spec()).await?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::Created);
+
+        bitcoind.stop().await?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::NotCreated);
+        bitcoind.start().await?;
+        bitcoind.stop().await?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_ephemeral() -> Result<(), BitcoindError> {
+        let (a, a_rpc) = Bitcoind::ephemeral().await?;
+        let (b, b_rpc) = Bitcoind::ephemeral().await?;
+
+        assert_ne!(a.container_name(), b.container_name());
+        assert_ne!(a_rpc.url.expose_secret(), b_rpc.url.expose_secret());
+        assert_ne!(
+            a_rpc.password.expose_secret(),
+            b_rpc.password.expose_secret()
+        );
+        assert_eq!(a.cli::<u64>(&["getblockcount"]).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_block_filter_index() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().block_filter_index(true).build()?;
+        let rpc_config = bitcoind.start().await?;
+        assert!(bitcoind
+            .node_spec()
+            .args
+            .contains(&"-peerblockfilters=1".to_string()));
+        let filter = async {
+            let client = RpcClient::new(&rpc_config);
+            client
+                .call::<Value>(
+                    "generatetodescriptor",
+                    json!([10, crate::snapshot::premined_descriptor()]),
+                )
+                .await?;
+            bitcoind
+                .wait_until_ready(
+                    &[ReadyCondition::block_filter_index_synced()],
+                    Duration::from_secs(30),
+                )
+                .await?;
+            let tip = client.call::<String>("getbestblockhash", json!([])).await?;
+            Ok::<_, BitcoindError>(
+                client
+                    .call::<Value>("getblockfilter", json!([tip, "basic"]))
+                    .await?,
+            )
+        };
+        let filter = filter.await;
+        bitcoind.stop().await?;
+        assert!(filter?["filter"].is_string());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_wait_for_tx_confirmed() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        bitcoind.start().await?;
+        let waited = async {
+            crate::events::tests::fund_wallet(&bitcoind).await?;
+            let address = bitcoind.cli::<String>(&["getnewaddress"]).await?;
+            let txid = bitcoind
+                .cli::<bitcoin::Txid>(&["sendtoaddress", &address, "1"])
+                .await?;
+            let unconfirmed = bitcoind
+                .wait_for_tx_confirmed(txid, 1, Duration::from_millis(500))
+                .await;
+            bitcoind
+                .cli::<Value>(&["generatetoaddress", "2", &address])
+                .await?;
+            bitcoind
+                .wait_for_tx_confirmed(txid, 2, Duration::from_secs(10))
+                .await?;
+            Ok::<_, BitcoindError>(unconfirmed)
+        };
+        let waited = waited.await;
+        bitcoind.stop().await?;
+
+        match waited? {
+            Err(BitcoindError::ReadinessTimeout { last_error, .. }) => {
+                assert!(last_error.ends_with("in the mempool"), "{}", last_error)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_wait_for_mempool_tx() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        bitcoind.start().await?;
+        let waited = async {
+            crate::events::tests::fund_wallet(&bitcoind).await?;
+            let address = bitcoind.cli::<String>(&["getnewaddress"]).await?;
+            let txid = bitcoind
+                .cli::<bitcoin::Txid>(&["sendtoaddress", &address, "1"])
+                .await?;
+            bitcoind
+                .wait_for_mempool_tx(txid, Duration::from_secs(10))
+                .await?;
+            // Once mined it can never enter the mempool again.
+            bitcoind
+                .cli::<Value>(&["generatetoaddress", "1", &address])
+                .await?;
+            Ok::<_, BitcoindError>(
+                bitcoind
+                    .wait_for_mempool_tx(txid, Duration::from_secs(10))
+                    .await,
+            )
+        };
+        let waited = waited.await;
+        bitcoind.stop().await?;
+
+        assert!(matches!(waited?, Err(BitcoindError::TxRejected { .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_snapshot_restore() -> Result<(), BitcoindError> {
+        let (bitcoind, _) = Bitcoind::ephemeral().await?;
+        bitcoind.cli::<Value>(&["createwallet", "funded"]).await?;
+        let address = bitcoind.cli::<String>(&["getnewaddress"]).await?;
+        let name = bitcoind.container_name().to_string();
+
+        bitcoind
+            .cli::<Value>(&["generatetoaddress", "101", &address])
+            .await?;
+        let snapshot = bitcoind.snapshot(&name).await?;
+        bitcoind
+            .cli::<Value>(&["generatetoaddress", "10", &address])
+            .await?;
+        bitcoind.restore(&name).await?;
+        std::fs::remove_file(snapshot).unwrap();
+
+        assert_eq!(bitcoind.cli::<u64>(&["getblockcount"]).await?, 101);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_premined_chain() -> Result<(), BitcoindError> {
+        for _ in 0..2 {
+            let bitcoind = ephemeral_builder().build()?;
+            bitcoind.start_with_premined_chain().await?;
+            let height = bitcoind.cli::<u64>(&["getblockcount"]).await?;
+            assert_eq!(height, PREMINED_BLOCKS);
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_export_wallet() -> Result<(), BitcoindError> {
+        let (bitcoind, _) = Bitcoind::ephemeral().await?;
+        bitcoind.cli::<Value>(&["createwallet", "funded"]).await?;
 
-        bitcoind.start()?;
-        bitcoind.stop()?;
+        let dest = std::env::temp_dir().join(bitcoind.container_name());
+        bitcoind.export_wallet("funded", &dest).await?;
+        let exported = std::fs::read_dir(&dest).unwrap().count();
+        std::fs::remove_dir_all(&dest).unwrap();
 
+        assert!(exported > 0);
+        // The wallet is loaded again.
+        let wallets = bitcoind.cli::<Vec<String>>(&["listwallets"]).await?;
+        assert!(wallets.contains(&"funded".to_string()));
         Ok(())
     }
 }
@@ -0,0 +1,616 @@
+//! Blocking wrapper around the async [`crate::bitcoind::Bitcoind`].
+//!
+//! Every call is driven to completion on either a caller-provided runtime
+//! handle or a runtime owned by the wrapper, so these methods must not be
+//! called from inside an async context.
+
+use crate::assumeutxo::{UtxoSetDump, UtxoSetLoad};
+use crate::backend::{ExecOutput, NodeStatus};
+use crate::bitcoind::{self, BitcoindFlags, ReadinessConfig};
+use crate::cleanup::{OrphanFilter, RemovedOrphans};
+use crate::coinstats::TxOutSetInfo;
+use crate::docker::ContainerDetails;
+use crate::error::BitcoindError;
+use crate::logs::{LogLine, LogOptions};
+use crate::ports::{MappedPorts, ZmqEndpoint};
+use crate::prune::PruneInfo;
+use crate::readiness::ReadyCondition;
+use crate::signet::CustomSignet;
+use crate::stats::ResourceUsage;
+use crate::version::NodeVersion;
+use bitcoin::{Address, BlockHash, Script, Txid};
+use bitvmx_bitcoin_rpc::bitcoin_client::BitcoinClient;
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use serde::de::DeserializeOwned;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::runtime::{Handle, Runtime};
+
+enum Executor {
+    Owned(Runtime),
+    Shared(Handle),
+}
+
+impl Executor {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        match self {
+            Executor::Owned(runtime) => runtime.block_on(future),
+            Executor::Shared(handle) => handle.block_on(future),
+        }
+    }
+}
+
+pub struct Bitcoind {
+    inner: bitcoind::Bitcoind,
+    executor: Executor,
+}
+
+impl Bitcoind {
+    /// Creates a new blocking `Bitcoind` instance with default flags.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Docker client or the runtime cannot be created. Use
+    /// [`Bitcoind::try_new`] instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_name` - The name of the Docker container.
+    /// * `image` - The Docker image to use.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    #[deprecated(since = "0.5.0", note = "use `Bitcoind::try_new` instead")]
+    pub fn new(container_name: &str, image: &str, rpc_config: RpcConfig) -> Self {
+        Self::try_new(container_name, image, rpc_config).expect("failed to create Bitcoind")
+    }
+
+    /// Creates a new blocking `Bitcoind` instance with specified flags.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the Docker client or the runtime cannot be created. Use
+    /// [`Bitcoind::try_new_with_flags`] instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_name` - The name of the Docker container.
+    /// * `image` - The Docker image to use.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    /// * `flags` - Custom flags for the Bitcoin node.
+    #[deprecated(since = "0.5.0", note = "use `Bitcoind::try_new_with_flags` instead")]
+    pub fn new_with_flags(
+        container_name: &str,
+        image: &str,
+        rpc_config: RpcConfig,
+        flags: BitcoindFlags,
+    ) -> Self {
+        Self::try_new_with_flags(container_name, image, rpc_config, flags)
+            .expect("failed to create Bitcoind")
+    }
+
+    /// Creates a new blocking `Bitcoind` instance with default flags.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_name` - The name of the Docker container.
+    /// * `image` - The Docker image to use.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    pub fn try_new(
+        container_name: &str,
+        image: &str,
+        rpc_config: RpcConfig,
+    ) -> Result<Self, BitcoindError> {
+        Self::try_new_with_flags(container_name, image, rpc_config, BitcoindFlags::default())
+    }
+
+    /// Creates a new blocking `Bitcoind` instance with specified flags.
+    ///
+    /// The runtime is chosen as described in [`Bitcoind::try_from_async`].
+    ///
+    /// # Arguments
+    ///
+    /// * `container_name` - The name of the Docker container.
+    /// * `image` - The Docker image to use.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    /// * `flags` - Custom flags for the Bitcoin node.
+    pub fn try_new_with_flags(
+        container_name: &str,
+        image: &str,
+        rpc_config: RpcConfig,
+        flags: BitcoindFlags,
+    ) -> Result<Self, BitcoindError> {
+        Self::try_from_async(bitcoind::Bitcoind::try_new_with_flags(
+            container_name,
+            image,
+            rpc_config,
+            flags,
+        )?)
+    }
+
+    /// Wraps an async `Bitcoind` instance, for example one created with
+    /// [`bitcoind::Bitcoind::builder`].
+    ///
+    /// If the calling thread is inside a tokio runtime context its handle is
+    /// reused, otherwise a dedicated runtime is created.
+    pub fn try_from_async(inner: bitcoind::Bitcoind) -> Result<Self, BitcoindError> {
+        let executor = match Handle::try_current() {
+            Ok(handle) => Executor::Shared(handle),
+            Err(_) => Executor::Owned(Runtime::new().map_err(BitcoindError::Runtime)?),
+        };
+
+        Ok(Self { inner, executor })
+    }
+
+    /// Creates a new blocking `Bitcoind` instance that runs every Docker
+    /// operation on the given runtime instead of creating its own.
+    ///
+    /// The handle should belong to a multi-thread runtime, since a
+    /// current-thread runtime only makes progress inside its own `block_on`.
+    ///
+    /// # Arguments
+    ///
+    /// * `container_name` - The name of the Docker container.
+    /// * `image` - The Docker image to use.
+    /// * `rpc_config` - The RPC configuration for the Bitcoin node.
+    /// * `flags` - Custom flags for the Bitcoin node.
+    /// * `handle` - Handle of the runtime used to drive all operations.
+    pub fn try_new_with_handle(
+        container_name: &str,
+        image: &str,
+        rpc_config: RpcConfig,
+        flags: BitcoindFlags,
+        handle: Handle,
+    ) -> Result<Self, BitcoindError> {
+        Ok(Self {
+            inner: bitcoind::Bitcoind::try_new_with_flags(
+                container_name,
+                image,
+                rpc_config,
+                flags,
+            )?,
+            executor: Executor::Shared(handle),
+        })
+    }
+
+    /// Sets how long `start` waits for the node to answer RPC requests.
+    pub fn with_readiness_config(mut self, readiness: ReadinessConfig) -> Self {
+        self.inner = self.inner.with_readiness_config(readiness);
+        self
+    }
+
+    /// Returns the async instance driven by this wrapper.
+    pub fn inner(&self) -> &bitcoind::Bitcoind {
+        &self.inner
+    }
+
+    /// Starts the `bitcoind` Docker container and waits until it answers RPC.
+    ///
+    /// See [`bitcoind::Bitcoind::start`].
+    pub fn start(&self) -> Result<RpcConfig, BitcoindError> {
+        self.executor.block_on(self.inner.start())
+    }
+
+    /// Restarts the node and waits until it answers RPC again.
+    ///
+    /// See [`bitcoind::Bitcoind::restart`].
+    pub fn restart(&self, preserve_data: bool) -> Result<RpcConfig, BitcoindError> {
+        self.executor.block_on(self.inner.restart(preserve_data))
+    }
+
+    /// Returns the current state of the node.
+    ///
+    /// See [`bitcoind::Bitcoind::status`].
+    pub fn status(&self) -> Result<NodeStatus, BitcoindError> {
+        self.executor.block_on(self.inner.status())
+    }
+
+    /// Waits until the started node meets every condition.
+    ///
+    /// See [`bitcoind::Bitcoind::wait_until_ready`].
+    pub fn wait_until_ready(
+        &self,
+        conditions: &[ReadyCondition],
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        self.executor
+            .block_on(self.inner.wait_until_ready(conditions, timeout))
+    }
+
+    /// Waits until the tip of the node reaches `height`.
+    ///
+    /// See [`bitcoind::Bitcoind::wait_for_block_height`].
+    pub fn wait_for_block_height(
+        &self,
+        height: u64,
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        self.executor
+            .block_on(self.inner.wait_for_block_height(height, timeout))
+    }
+
+    /// Waits until a transaction has at least `confirmations` confirmations.
+    ///
+    /// See [`bitcoind::Bitcoind::wait_for_tx_confirmed`].
+    pub fn wait_for_tx_confirmed(
+        &self,
+        txid: Txid,
+        confirmations: u64,
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        self.executor.block_on(
+            self.inner
+                .wait_for_tx_confirmed(txid, confirmations, timeout),
+        )
+    }
+
+    /// Mines `n` blocks and returns their hashes.
+    ///
+    /// See [`bitcoind::Bitcoind::mine_blocks`].
+    pub fn mine_blocks(&self, n: u64) -> Result<Vec<BlockHash>, BitcoindError> {
+        self.executor.block_on(self.inner.mine_blocks(n))
+    }
+
+    /// Mines `n` blocks paying to `address` and returns their hashes and the
+    /// height at which the last coinbase matures.
+    ///
+    /// See [`bitcoind::Bitcoind::mine_to_address`].
+    pub fn mine_to_address(
+        &self,
+        n: u64,
+        address: &Address,
+    ) -> Result<(Vec<BlockHash>, u64), BitcoindError> {
+        self.executor
+            .block_on(self.inner.mine_to_address(n, address))
+    }
+
+    /// Waits until a transaction is in the node's mempool.
+    ///
+    /// See [`bitcoind::Bitcoind::wait_for_mempool_tx`].
+    pub fn wait_for_mempool_tx(&self, txid: Txid, timeout: Duration) -> Result<(), BitcoindError> {
+        self.executor
+            .block_on(self.inner.wait_for_mempool_tx(txid, timeout))
+    }
+
+    /// Returns the node's output.
+    ///
+    /// See [`bitcoind::Bitcoind::logs`].
+    pub fn logs(&self, options: LogOptions) -> Result<String, BitcoindError> {
+        self.executor.block_on(self.inner.logs(options))
+    }
+
+    /// Waits until the node prints a line containing `pattern`.
+    ///
+    /// See [`bitcoind::Bitcoind::wait_for_log`].
+    pub fn wait_for_log(&self, pattern: &str, timeout: Duration) -> Result<LogLine, BitcoindError> {
+        self.executor
+            .block_on(self.inner.wait_for_log(pattern, timeout))
+    }
+
+    /// Reads `debug.log` from the datadir, or its last `tail` lines.
+    ///
+    /// See [`bitcoind::Bitcoind::debug_log`].
+    pub fn debug_log(&self, tail: Option<usize>) -> Result<String, BitcoindError> {
+        self.executor.block_on(self.inner.debug_log(tail))
+    }
+
+    /// Runs a command next to the node and waits for it to finish.
+    ///
+    /// See [`bitcoind::Bitcoind::exec`].
+    pub fn exec(&self, cmd: &[&str]) -> Result<ExecOutput, BitcoindError> {
+        self.executor.block_on(self.inner.exec(cmd))
+    }
+
+    /// Runs `bitcoin-cli` with `args` next to the node and parses its output.
+    ///
+    /// See [`bitcoind::Bitcoind::cli`].
+    pub fn cli<T: DeserializeOwned>(&self, args: &[&str]) -> Result<T, BitcoindError> {
+        self.executor.block_on(self.inner.cli(args))
+    }
+
+    /// Kills the node without a clean shutdown.
+    ///
+    /// See [`bitcoind::Bitcoind::kill`].
+    pub fn kill(&self) -> Result<(), BitcoindError> {
+        self.executor.block_on(self.inner.kill())
+    }
+
+    /// Freezes the node without closing its connections.
+    ///
+    /// See [`bitcoind::Bitcoind::pause`].
+    pub fn pause(&self) -> Result<(), BitcoindError> {
+        self.executor.block_on(self.inner.pause())
+    }
+
+    /// Resumes a paused node.
+    ///
+    /// See [`bitcoind::Bitcoind::unpause`].
+    pub fn unpause(&self) -> Result<(), BitcoindError> {
+        self.executor.block_on(self.inner.unpause())
+    }
+
+    /// Replaces the node with one running `flags`, keeping its chain state.
+    ///
+    /// See [`bitcoind::Bitcoind::recreate_with_flags`].
+    pub fn recreate_with_flags(
+        &mut self,
+        flags: BitcoindFlags,
+    ) -> Result<RpcConfig, BitcoindError> {
+        self.executor
+            .block_on(self.inner.recreate_with_flags(flags))
+    }
+
+    /// Moves the node to `new_image` on the same chain state and waits until it is synced.
+    ///
+    /// See [`bitcoind::Bitcoind::upgrade_image`].
+    pub fn upgrade_image(
+        &mut self,
+        new_image: &str,
+        timeout: Duration,
+    ) -> Result<RpcConfig, BitcoindError> {
+        self.executor
+            .block_on(self.inner.upgrade_image(new_image, timeout))
+    }
+
+    /// Returns the RPC configuration to reach the node.
+    ///
+    /// See [`bitcoind::Bitcoind::rpc_config`].
+    pub fn rpc_config(&self) -> RpcConfig {
+        self.inner.rpc_config()
+    }
+
+    /// Returns a `bitvmx_bitcoin_rpc` client for the node.
+    ///
+    /// See [`bitcoind::Bitcoind::rpc`].
+    pub fn rpc(&self) -> Result<BitcoinClient, BitcoindError> {
+        self.inner.rpc()
+    }
+
+    /// Returns the base url of the REST interface when it is enabled.
+    ///
+    /// See [`bitcoind::Bitcoind::rest_url`].
+    pub fn rest_url(&self) -> Option<String> {
+        self.inner.rest_url()
+    }
+
+    /// Returns the Bitcoin Core version the node reported once it was last ready.
+    ///
+    /// See [`bitcoind::Bitcoind::node_version`].
+    pub fn node_version(&self) -> Option<NodeVersion> {
+        self.inner.node_version()
+    }
+
+    /// Returns what Docker reports about the container.
+    ///
+    /// See [`bitcoind::Bitcoind::inspect`].
+    pub fn inspect(&self) -> Result<ContainerDetails, BitcoindError> {
+        self.executor.block_on(self.inner.inspect())
+    }
+
+    /// Adds the node `peer` runs in another container as a peer.
+    ///
+    /// See [`bitcoind::Bitcoind::connect_peer`].
+    pub fn connect_peer(&self, peer: &Bitcoind) -> Result<(), BitcoindError> {
+        self.executor.block_on(self.inner.connect_peer(&peer.inner))
+    }
+
+    /// Copies a host file or directory into the running container.
+    ///
+    /// See [`bitcoind::Bitcoind::copy_in`].
+    pub fn copy_in(&self, host_path: &Path, container_path: &str) -> Result<(), BitcoindError> {
+        self.executor
+            .block_on(self.inner.copy_in(host_path, container_path))
+    }
+
+    /// Saves the node's datadir as the snapshot `name`.
+    ///
+    /// See [`bitcoind::Bitcoind::snapshot`].
+    pub fn snapshot(&self, name: &str) -> Result<PathBuf, BitcoindError> {
+        self.executor.block_on(self.inner.snapshot(name))
+    }
+
+    /// Starts a regtest node on a cached chain with mature coins.
+    ///
+    /// See [`bitcoind::Bitcoind::start_with_premined_chain`].
+    pub fn start_with_premined_chain(&self) -> Result<RpcConfig, BitcoindError> {
+        self.executor
+            .block_on(self.inner.start_with_premined_chain())
+    }
+
+    /// Restarts the node from the snapshot `name`.
+    ///
+    /// See [`bitcoind::Bitcoind::restore`].
+    pub fn restore(&self, name: &str) -> Result<RpcConfig, BitcoindError> {
+        self.executor.block_on(self.inner.restore(name))
+    }
+
+    /// Dumps the UTXO set to the host file `host_path`.
+    ///
+    /// See [`bitcoind::Bitcoind::dump_utxo_set`].
+    pub fn dump_utxo_set(
+        &self,
+        host_path: &Path,
+        rollback: Option<u64>,
+    ) -> Result<UtxoSetDump, BitcoindError> {
+        self.executor
+            .block_on(self.inner.dump_utxo_set(host_path, rollback))
+    }
+
+    /// Loads the UTXO set dumped to the host file `host_path`.
+    ///
+    /// See [`bitcoind::Bitcoind::load_utxo_set`].
+    pub fn load_utxo_set(&self, host_path: &Path) -> Result<UtxoSetLoad, BitcoindError> {
+        self.executor.block_on(self.inner.load_utxo_set(host_path))
+    }
+
+    /// Waits until a loaded UTXO set is validated in the background.
+    ///
+    /// See [`bitcoind::Bitcoind::wait_for_background_validation`].
+    pub fn wait_for_background_validation(&self, timeout: Duration) -> Result<(), BitcoindError> {
+        self.executor
+            .block_on(self.inner.wait_for_background_validation(timeout))
+    }
+
+    /// Returns whether and how far the node has pruned its blocks.
+    ///
+    /// See [`bitcoind::Bitcoind::prune_info`].
+    pub fn prune_info(&self) -> Result<PruneInfo, BitcoindError> {
+        self.executor.block_on(self.inner.prune_info())
+    }
+
+    /// Prunes the blocks up to `height`.
+    ///
+    /// See [`bitcoind::Bitcoind::prune_blockchain`].
+    pub fn prune_blockchain(&self, height: u64) -> Result<u64, BitcoindError> {
+        self.executor.block_on(self.inner.prune_blockchain(height))
+    }
+
+    /// Returns whether the node still stores the block at `height`.
+    ///
+    /// See [`bitcoind::Bitcoind::has_block`].
+    pub fn has_block(&self, height: u64) -> Result<bool, BitcoindError> {
+        self.executor.block_on(self.inner.has_block(height))
+    }
+
+    /// Returns the statistics of the UTXO set at the tip or at `height`.
+    ///
+    /// See [`bitcoind::Bitcoind::txout_set_info`].
+    pub fn txout_set_info(&self, height: Option<u64>) -> Result<TxOutSetInfo, BitcoindError> {
+        self.executor.block_on(self.inner.txout_set_info(height))
+    }
+
+    /// Mines a signed block on a custom signet, paying to `payout`.
+    ///
+    /// See [`bitcoind::Bitcoind::mine_signet_block`].
+    pub fn mine_signet_block(
+        &self,
+        signet: &CustomSignet,
+        payout: &Script,
+    ) -> Result<BlockHash, BitcoindError> {
+        self.executor
+            .block_on(self.inner.mine_signet_block(signet, payout))
+    }
+
+    /// Copies the files of the loaded wallet `name` into the host directory `host_path`.
+    ///
+    /// See [`bitcoind::Bitcoind::export_wallet`].
+    pub fn export_wallet(&self, name: &str, host_path: &Path) -> Result<(), BitcoindError> {
+        self.executor
+            .block_on(self.inner.export_wallet(name, host_path))
+    }
+
+    /// Copies the node's datadir into the host directory `host_path`.
+    ///
+    /// See [`bitcoind::Bitcoind::export_datadir`].
+    pub fn export_datadir(&self, host_path: &Path) -> Result<(), BitcoindError> {
+        self.executor.block_on(self.inner.export_datadir(host_path))
+    }
+
+    /// Removes the containers, volumes and networks earlier runs left behind.
+    ///
+    /// See [`bitcoind::Bitcoind::cleanup_orphans`].
+    pub fn cleanup_orphans(&self, filter: &OrphanFilter) -> Result<RemovedOrphans, BitcoindError> {
+        self.executor.block_on(self.inner.cleanup_orphans(filter))
+    }
+
+    /// Returns a single sample of the container's resource usage.
+    ///
+    /// See [`bitcoind::Bitcoind::stats`].
+    pub fn stats(&self) -> Result<ResourceUsage, BitcoindError> {
+        self.executor.block_on(self.inner.stats())
+    }
+
+    /// Starts an isolated regtest node for a single test.
+    ///
+    /// See [`bitcoind::Bitcoind::ephemeral`].
+    pub fn ephemeral() -> Result<(Self, RpcConfig), BitcoindError> {
+        let bitcoind = Self::try_from_async(bitcoind::ephemeral_builder().build()?)?;
+        let rpc_config = bitcoind.start()?;
+        Ok((bitcoind, rpc_config))
+    }
+
+    /// Returns the name of the container.
+    ///
+    /// See [`bitcoind::Bitcoind::container_name`].
+    pub fn container_name(&self) -> &str {
+        self.inner.container_name()
+    }
+
+    /// Returns the host ports Docker published for the running container.
+    ///
+    /// See [`bitcoind::Bitcoind::mapped_ports`].
+    pub fn mapped_ports(&self) -> Result<MappedPorts, BitcoindError> {
+        self.executor.block_on(self.inner.mapped_ports())
+    }
+
+    /// Returns the addresses of the enabled ZMQ notifications.
+    ///
+    /// See [`bitcoind::Bitcoind::zmq_endpoints`].
+    pub fn zmq_endpoints(&self) -> Result<Vec<ZmqEndpoint>, BitcoindError> {
+        self.executor.block_on(self.inner.zmq_endpoints())
+    }
+
+    /// Loads the images in a `docker save` archive into the daemon.
+    ///
+    /// See [`bitcoind::Bitcoind::load_image_from_tar`].
+    pub fn load_image_from_tar<P: AsRef<Path>>(&self, path: P) -> Result<(), BitcoindError> {
+        self.executor.block_on(self.inner.load_image_from_tar(path))
+    }
+
+    /// Stops the `bitcoind` Docker container.
+    ///
+    /// See [`bitcoind::Bitcoind::stop`].
+    pub fn stop(&self) -> Result<(), BitcoindError> {
+        self.executor.block_on(self.inner.stop())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use bitcoin::Network;
+    use redact::Secret;
+
+    #[test]
+    fn test_start_stop_blocking_bitcoind() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let bitcoind = Bitcoind::try_new("bitcoin-regtest", "bitcoin/bitcoin:29.1", rpc_config)?;
+
+        bitcoind.start()?;
+        bitcoind.stop()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_stop_blocking_bitcoind_with_handle() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+
+        let runtime = Runtime::new().unwrap();
+        let bitcoind = Bitcoind::try_new_with_handle(
+            "bitcoin-regtest",
+            "bitcoin/bitcoin:29.1",
+            rpc_config,
+            BitcoindFlags::default(),
+            runtime.handle().clone(),
+        )?;
+
+        bitcoind.start()?;
+        bitcoind.stop()?;
+
+        Ok(())
+    }
+}
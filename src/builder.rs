@@ -0,0 +1,939 @@
+use crate::bitcoind::{
+    Bitcoind, BitcoindFlags, ContainerOptions, Healthcheck, ReadinessConfig, ResourceLimits,
+    Timeouts,
+};
+use crate::conf::BitcoinConf;
+use crate::docker::{
+    normalize_digest, DockerBackend, DockerConnection, PullPolicy, PullProgress,
+    PullProgressCallback, RetryPolicy,
+};
+use crate::error::BitcoindError;
+use crate::hooks::{HookList, LifecycleHooks};
+use crate::image::{ImageProfile, ImageSource};
+use crate::ports::{parse_rpc_port, PublishedPorts, ZmqTopic};
+use crate::registry::RegistryAuth;
+use crate::signet::CustomSignet;
+use bitcoin::{Network, ScriptBuf};
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+pub const DEFAULT_CONTAINER_NAME: &str = "bitcoin-regtest";
+pub const DEFAULT_IMAGE: &str = "bitcoin/bitcoin:29.1";
+
+/// Random lowercase hex string of `len` characters, from the randomly seeded
+/// hasher of the standard library.
+pub(crate) fn random_hex(len: usize) -> String {
+    let mut hex = String::new();
+    while hex.len() < len {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        hex.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    hex.truncate(len);
+    hex
+}
+
+/// Fluent builder for [`Bitcoind`].
+///
+/// Only the RPC configuration is mandatory; everything else falls back to the
+/// same defaults used by [`Bitcoind::try_new`].
+#[derive(Debug, Clone)]
+pub struct BitcoindBuilder {
+    pub(crate) container_name: String,
+    pub(crate) unique_name: bool,
+    pub(crate) image: String,
+    pub(crate) image_hash: Option<String>,
+    /// Whether the image profile was set rather than picked from the image name.
+    pub(crate) image_profile_set: bool,
+    pub(crate) rpc_config: Option<RpcConfig>,
+    pub(crate) flags: BitcoindFlags,
+    pub(crate) container: ContainerOptions,
+    pub(crate) readiness: ReadinessConfig,
+    pub(crate) log_artifacts: Option<PathBuf>,
+    pub(crate) hooks: HookList,
+    pub(crate) stop_timeout: Duration,
+    pub(crate) poll_interval: Duration,
+    pub(crate) docker: DockerConnection,
+}
+
+impl Default for BitcoindBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BitcoindBuilder {
+    pub fn new() -> Self {
+        Self {
+            container_name: DEFAULT_CONTAINER_NAME.to_string(),
+            unique_name: false,
+            image: DEFAULT_IMAGE.to_string(),
+            image_hash: None,
+            image_profile_set: false,
+            rpc_config: None,
+            flags: BitcoindFlags::default(),
+            container: ContainerOptions {
+                image_profile: ImageProfile::for_image(DEFAULT_IMAGE),
+                ..Default::default()
+            },
+            readiness: ReadinessConfig::default(),
+            log_artifacts: None,
+            hooks: HookList::default(),
+            stop_timeout: Timeouts::default().stop,
+            poll_interval: Timeouts::default().poll_interval,
+            docker: DockerConnection::default(),
+        }
+    }
+
+    /// Sets the name of the Docker container.
+    pub fn container_name(mut self, container_name: &str) -> Self {
+        self.container_name = container_name.to_string();
+        self
+    }
+
+    /// Appends a random suffix to the container name and publishes every port
+    /// on a free host port, so tests can run nodes in parallel. The generated
+    /// name is returned by [`Bitcoind::container_name`].
+    pub fn unique_name(mut self, unique_name: bool) -> Self {
+        self.unique_name = unique_name;
+        self
+    }
+
+    /// Sets the Docker image to use, and its image profile unless one was set.
+    pub fn image(mut self, image: &str) -> Self {
+        self.image = image.to_string();
+        if !self.image_profile_set {
+            self.container.image_profile = ImageProfile::for_image(image);
+        }
+        self
+    }
+
+    /// Pins the image to a digest (`sha256:...`). `start` fails with
+    /// [`BitcoindError::ImageHashMismatch`] if the image the container runs
+    /// has a different digest.
+    pub fn image_hash(mut self, hash: Option<&str>) -> Self {
+        self.image_hash = hash.map(str::to_string);
+        self
+    }
+
+    /// Sets whether the image is pulled from its registry or built from a
+    /// Dockerfile. Defaults to [`ImageSource::Registry`].
+    pub fn image_source(mut self, image_source: ImageSource) -> Self {
+        self.container.image_source = image_source;
+        self
+    }
+
+    /// Sets the platform of the image to pull, build and run, as
+    /// `os/arch[/variant]`, e.g. `linux/amd64` to run an amd64-only image under
+    /// emulation on an arm64 host. Defaults to the daemon's platform.
+    pub fn platform(mut self, platform: Option<&str>) -> Self {
+        self.container.platform = platform.map(str::to_string);
+        self
+    }
+
+    /// Sets when the image is pulled. Defaults to [`PullPolicy::IfNotPresent`].
+    pub fn pull_policy(mut self, pull_policy: PullPolicy) -> Self {
+        self.container.pull_policy = pull_policy;
+        self
+    }
+
+    /// Sets a `docker save` archive to load when the image is not present
+    /// locally, instead of pulling it from the registry.
+    pub fn image_tar(mut self, image_tar: Option<PathBuf>) -> Self {
+        self.container.image_tar = image_tar;
+        self
+    }
+
+    /// Attaches to an already running container when it was created from the
+    /// same image and settings, instead of removing it and starting afresh.
+    /// Chain state then carries over between runs.
+    pub fn reuse_existing(mut self, reuse_existing: bool) -> Self {
+        self.container.reuse_existing = reuse_existing;
+        self
+    }
+
+    /// Adds a Docker healthcheck running `bitcoin-cli getblockcount`, so
+    /// `docker ps` shows whether the node answers RPC and `status` reports
+    /// [`crate::NodeStatus::Starting`] and [`crate::NodeStatus::Unhealthy`].
+    pub fn healthcheck(mut self, healthcheck: Option<Healthcheck>) -> Self {
+        self.container.healthcheck = healthcheck;
+        self
+    }
+
+    /// Keeps the datadir in the named volume `volume`, created if missing, so
+    /// the chain state survives stopping the node, `recreate_with_flags` and
+    /// image upgrades. The volume is never removed by this crate.
+    pub fn datadir_volume(mut self, volume: Option<&str>) -> Self {
+        self.container.datadir_volume = volume.map(str::to_string);
+        self
+    }
+
+    /// Bind mounts the host directory `path` as the datadir, created if
+    /// missing, so `blocks/`, wallets and `debug.log` can be inspected from
+    /// the host and a pre-seeded datadir can be used. A relative path is
+    /// resolved against the current directory.
+    pub fn datadir_path(mut self, path: Option<PathBuf>) -> Self {
+        self.container.datadir_path = path;
+        self
+    }
+
+    /// Mounts the host file `path` read-only as the node's `bitcoin.conf`, for
+    /// settings easier to keep in a file, e.g. `rpcauth` lines. A relative
+    /// path is resolved against the current directory.
+    pub fn conf_file(mut self, path: Option<PathBuf>) -> Self {
+        self.container.conf_file = path;
+        self
+    }
+
+    /// Writes `conf` into the container as its `bitcoin.conf` when it is
+    /// created and passes `-conf`. The generated flags take precedence over it.
+    pub fn bitcoin_conf(mut self, conf: Option<BitcoinConf>) -> Self {
+        self.container.bitcoin_conf = conf;
+        self
+    }
+
+    /// Copies the host file or directory `host_path` to `container_path` when
+    /// the container is created, before the node starts, e.g. a pre-built
+    /// wallet or block files. Files in the datadir end up owned by the node.
+    pub fn copy_in(mut self, host_path: &Path, container_path: &str) -> Self {
+        self.container
+            .copy_in
+            .push((host_path.to_path_buf(), container_path.to_string()));
+        self
+    }
+
+    /// Mounts a tmpfs of `size` bytes as the datadir, so block generation and
+    /// syncing don't wait on the disk. The chain state is lost on stop.
+    pub fn tmpfs_datadir(mut self, size: Option<u64>) -> Self {
+        self.container.tmpfs_datadir = size;
+        self
+    }
+
+    /// Limits the CPUs, memory and processes available to the container.
+    pub fn resource_limits(mut self, resources: ResourceLimits) -> Self {
+        self.container.resources = resources;
+        self
+    }
+
+    /// Leaves the container running when the `Bitcoind` is dropped instead of
+    /// removing it, for setups that outlive the process.
+    pub fn keep_on_drop(mut self, keep_on_drop: bool) -> Self {
+        self.container.keep_on_drop = keep_on_drop;
+        self
+    }
+
+    /// Saves the container's full output to `<dir>/<container name>.log` when
+    /// the node fails to start, so CI failures can be debugged afterwards.
+    pub fn log_artifacts(mut self, dir: Option<PathBuf>) -> Self {
+        self.log_artifacts = dir;
+        self
+    }
+
+    /// Registers `hooks` to run when the node starts, becomes ready and stops.
+    /// Several hooks can be registered; they run in registration order.
+    pub fn lifecycle_hooks<H: LifecycleHooks + 'static>(mut self, hooks: H) -> Self {
+        self.hooks.push(Arc::new(hooks));
+        self
+    }
+
+    /// Emits every line the container prints as a `tracing` event with target
+    /// `bitcoind::node`, tagged with the container name: stdout at `INFO` and
+    /// stderr at `WARN`. `RUST_LOG=bitcoind::node=off` silences them.
+    pub fn forward_logs(mut self, forward_logs: bool) -> Self {
+        self.container.forward_logs = forward_logs;
+        self
+    }
+
+    /// Sets how Docker operations failing with transient errors are retried.
+    /// Use [`RetryPolicy::none`] to fail on the first error.
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.container.retry = retry;
+        self
+    }
+
+    /// Sets the credentials used to pull the image from a private registry.
+    pub fn registry_auth(mut self, registry_auth: Option<RegistryAuth>) -> Self {
+        self.container.registry_auth = registry_auth;
+        self
+    }
+
+    /// Calls `callback` with every progress event while the image is pulled,
+    /// e.g. to render progress bars or detect stalled pulls.
+    pub fn on_pull_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&PullProgress) + Send + Sync + 'static,
+    {
+        self.container.pull_progress = Some(PullProgressCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Sets the RPC configuration for the Bitcoin node.
+    pub fn rpc_config(mut self, rpc_config: RpcConfig) -> Self {
+        self.rpc_config = Some(rpc_config);
+        self
+    }
+
+    /// Sets the flags passed to `bitcoind`.
+    pub fn flags(mut self, flags: BitcoindFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Appends an extra argument to the `bitcoind` command line.
+    ///
+    /// Call after [`BitcoindBuilder::flags`], which replaces any previous arguments.
+    pub fn arg(mut self, arg: &str) -> Self {
+        self.flags.extra_args.push(arg.to_string());
+        self
+    }
+
+    /// Runs the node on the custom signet `signet` by passing its challenge
+    /// with `-signetchallenge`. The RPC config must select signet.
+    pub fn custom_signet(mut self, signet: &CustomSignet) -> Self {
+        self.flags.signet_challenge = Some(signet.challenge().to_hex_string());
+        self
+    }
+
+    /// Keeps a transaction index with `-txindex`, on by default. Turn it off
+    /// to test against nodes where `getrawtransaction` only finds mempool
+    /// and wallet transactions.
+    pub fn txindex(mut self, txindex: bool) -> Self {
+        self.flags.txindex = txindex;
+        self
+    }
+
+    /// Builds the BIP 158 block filter index and serves the filters to peers.
+    /// See [`BitcoindFlags::block_filter_index`].
+    pub fn block_filter_index(mut self, block_filter_index: bool) -> Self {
+        self.flags.block_filter_index = block_filter_index;
+        self
+    }
+
+    /// Builds the coin statistics index. See [`BitcoindFlags::coinstats_index`].
+    pub fn coinstats_index(mut self, coinstats_index: bool) -> Self {
+        self.flags.coinstats_index = coinstats_index;
+        self
+    }
+
+    /// Prunes the block files down to `prune` MiB, at least 550, or with
+    /// `Some(1)` only when `pruneblockchain` is called. See
+    /// [`BitcoindFlags::prune`].
+    pub fn prune(mut self, prune: Option<u64>) -> Self {
+        self.flags.prune = prune;
+        self
+    }
+
+    /// Runs the node with `-blocksonly`, as a peer that doesn't relay
+    /// transactions. See [`BitcoindFlags::blocks_only`].
+    pub fn blocks_only(mut self, blocks_only: bool) -> Self {
+        self.flags.blocks_only = blocks_only;
+        self
+    }
+
+    /// Publishes the P2P port on the given host port.
+    pub fn p2p_port(mut self, host_port: u16) -> Self {
+        self.container.publish.p2p = Some(host_port);
+        self
+    }
+
+    /// Enables ZMQ block and transaction notifications and publishes them on
+    /// the given host port.
+    pub fn zmq_port(mut self, host_port: u16) -> Self {
+        self.container.publish.zmq = Some(host_port);
+        self
+    }
+
+    /// Publishes the ZMQ `topic` alone on the given host port, e.g. the
+    /// `sequence` notifications the shared ZMQ port doesn't carry.
+    pub fn zmq_topic(mut self, topic: ZmqTopic, host_port: u16) -> Self {
+        self.container
+            .publish
+            .zmq_topics
+            .set(topic, Some(host_port));
+        self
+    }
+
+    /// Enables the REST interface, served on the RPC port.
+    pub fn rest(mut self, rest: bool) -> Self {
+        self.container.publish.rest = rest;
+        self
+    }
+
+    /// Sets the P2P, ZMQ and REST services to publish at once.
+    pub fn publish(mut self, publish: PublishedPorts) -> Self {
+        self.container.publish = publish;
+        self
+    }
+
+    /// Publishes an additional container port on the given host port.
+    pub fn port(mut self, container_port: u16, host_port: u16) -> Self {
+        self.container.ports.push((container_port, host_port));
+        self
+    }
+
+    /// Lets Docker pick free host ports for every published port. The actual RPC
+    /// url is returned by [`Bitcoind::start`] and [`Bitcoind::rpc_config`].
+    pub fn ephemeral_ports(mut self, ephemeral_ports: bool) -> Self {
+        self.container.ephemeral_ports = ephemeral_ports;
+        self
+    }
+
+    /// Runs the container on the host network instead of publishing ports.
+    /// Avoids the NAT hop on Linux CI runners; not available on Docker Desktop.
+    pub fn host_network(mut self, host_network: bool) -> Self {
+        self.container.host_network = host_network;
+        self
+    }
+
+    /// Sets the host address published ports bind to, e.g. `::` on IPv6-only
+    /// hosts. `None` binds to `0.0.0.0`.
+    pub fn host_ip(mut self, host_ip: Option<IpAddr>) -> Self {
+        self.container.host_ip = host_ip;
+        self
+    }
+
+    /// Mounts a host path or named volume at `target` inside the container.
+    pub fn volume(mut self, source: &str, target: &str) -> Self {
+        self.container
+            .volumes
+            .push((source.to_string(), target.to_string()));
+        self
+    }
+
+    /// Adds an environment variable to the container, replacing the default
+    /// value when the name matches one (e.g. `BITCOIN_DATA`).
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.container
+            .env
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Adds several environment variables to the container. See [`BitcoindBuilder::env`].
+    pub fn envs<I, K, V>(mut self, vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<String>,
+        V: Into<String>,
+    {
+        self.container.env.extend(
+            vars.into_iter()
+                .map(|(key, value)| (key.into(), value.into())),
+        );
+        self
+    }
+
+    /// Sets the entrypoint, datadir and user conventions of the image, e.g.
+    /// [`ImageProfile::knots`]. `None` picks them from the image name with
+    /// [`ImageProfile::for_image`].
+    pub fn image_profile(mut self, image_profile: Option<ImageProfile>) -> Self {
+        self.image_profile_set = image_profile.is_some();
+        self.container.image_profile =
+            image_profile.unwrap_or_else(|| ImageProfile::for_image(&self.image));
+        self
+    }
+
+    /// Overrides the entrypoint of the image profile. `None` keeps it.
+    pub fn entrypoint(mut self, entrypoint: Option<Vec<String>>) -> Self {
+        self.container.entrypoint = entrypoint;
+        self
+    }
+
+    /// Sets the arguments placed before the generated `bitcoind` flags.
+    pub fn cmd_prefix(mut self, cmd_prefix: Vec<String>) -> Self {
+        self.container.cmd_prefix = cmd_prefix;
+        self
+    }
+
+    /// Sets how `start` waits for the node to answer RPC requests.
+    pub fn readiness(mut self, readiness: ReadinessConfig) -> Self {
+        self.readiness = readiness;
+        self
+    }
+
+    /// Sets the maximum time `start` waits for the node to answer RPC requests.
+    pub fn startup_timeout(mut self, timeout: Duration) -> Self {
+        self.readiness.timeout = timeout;
+        self
+    }
+
+    /// Sets the start and stop timeouts and the polling interval at once.
+    /// Readiness checks back off up to `poll_interval` instead of 2 seconds.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.readiness.timeout = timeouts.start;
+        self.readiness.max_backoff = timeouts.poll_interval;
+        self.readiness.initial_backoff = self.readiness.initial_backoff.min(timeouts.poll_interval);
+        self.stop_timeout = timeouts.stop;
+        self.poll_interval = timeouts.poll_interval;
+        self
+    }
+
+    /// Sets how long `stop` waits for `bitcoind` to shut down cleanly after
+    /// `bitcoin-cli stop` before removing the container by force. Defaults to
+    /// 10 seconds; zero skips the clean shutdown.
+    pub fn stop_timeout(mut self, timeout: Duration) -> Self {
+        self.stop_timeout = timeout;
+        self
+    }
+
+    /// Sets how to reach the Docker daemon. Defaults to `DOCKER_HOST` or the
+    /// platform default socket.
+    pub fn docker_connection(mut self, docker: DockerConnection) -> Self {
+        self.docker = docker;
+        self
+    }
+
+    /// Validates the configuration and creates the [`Bitcoind`] instance.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if any setting is invalid, including
+    ///   an RPC url the published port can't be derived from.
+    /// * `Err(BitcoindError::DockerUnavailable)` if the Docker client cannot be created,
+    ///   e.g. the local socket does not exist. The daemon itself is first contacted by `start`.
+    pub fn build(mut self) -> Result<Bitcoind, BitcoindError> {
+        self.validate()?;
+        if self.unique_name {
+            self.container_name = format!("{}-{}", self.container_name, random_hex(8));
+            // With host networking the node uses the host's ports as they are.
+            self.container.ephemeral_ports = !self.container.host_network;
+        }
+
+        if let Some(path) = &mut self.container.datadir_path {
+            *path = std::path::absolute(&*path).map_err(|e| {
+                BitcoindError::InvalidConfig(format!("invalid datadir path {:?}: {}", path, e))
+            })?;
+        }
+        if let Some(path) = &mut self.container.conf_file {
+            *path = std::path::absolute(&*path).map_err(|e| {
+                BitcoindError::InvalidConfig(format!("invalid bitcoin.conf path {:?}: {}", path, e))
+            })?;
+            // Docker would mount a missing file as an empty directory.
+            if self.docker.remote_host().is_none() && !path.is_file() {
+                return Err(BitcoindError::InvalidConfig(format!(
+                    "bitcoin.conf not found: {}",
+                    path.display()
+                )));
+            }
+        }
+
+        let rpc_config = self
+            .rpc_config
+            .ok_or_else(|| BitcoindError::InvalidConfig("rpc_config is required".to_string()))?;
+        let rpc_port = parse_rpc_port(rpc_config.url.expose_secret(), rpc_config.network)?;
+        let (docker, ssh_tunnel) = self.docker.connect()?;
+
+        let backend = DockerBackend {
+            docker,
+            container_name: self.container_name,
+            image: self.image,
+            image_hash: self.image_hash.as_deref().and_then(normalize_digest),
+            container: self.container,
+            remote_host: self.docker.remote_host(),
+            podman: self.docker.is_podman(),
+            stop_timeout: self.stop_timeout,
+            poll_interval: self.poll_interval,
+            spec: Mutex::new(None),
+            created: AtomicBool::new(false),
+            connection: self.docker.clone(),
+            log_forwarder: Mutex::new(None),
+            _ssh_tunnel: ssh_tunnel,
+        };
+
+        Ok(Bitcoind {
+            backend,
+            effective_rpc_config: Mutex::new(rpc_config.clone()),
+            node_version: Mutex::new(None),
+            rpc_config,
+            rpc_port,
+            flags: self.flags,
+            readiness: self.readiness,
+            log_artifacts: self.log_artifacts,
+            hooks: self.hooks,
+            stopped: AtomicBool::new(true),
+            lifecycle: tokio::sync::Mutex::new(()),
+        })
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), BitcoindError> {
+        let valid_name = self
+            .container_name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphanumeric())
+            && self
+                .container_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'));
+        if !valid_name {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid container name: {:?}",
+                self.container_name
+            )));
+        }
+
+        if self.image.is_empty() || self.image.contains(char::is_whitespace) {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid image: {:?}",
+                self.image
+            )));
+        }
+
+        if let Some(hash) = self
+            .image_hash
+            .as_ref()
+            .filter(|hash| normalize_digest(hash).is_none())
+        {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid image hash: {:?}",
+                hash
+            )));
+        }
+
+        if let Some(platform) = self.container.platform.as_ref().filter(|platform| {
+            let parts = platform.split('/').collect::<Vec<_>>();
+            !(2..=3).contains(&parts.len()) || parts.iter().any(|part| part.is_empty())
+        }) {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid platform: {:?}",
+                platform
+            )));
+        }
+
+        if matches!(self.container.image_source, ImageSource::Build { .. })
+            && (self.image_hash.is_some() || self.image.contains('@'))
+        {
+            return Err(BitcoindError::InvalidConfig(
+                "a built image is tagged by name and can't be pinned to a digest".to_string(),
+            ));
+        }
+
+        if let Some(arg) = self
+            .flags
+            .extra_args
+            .iter()
+            .find(|arg| !arg.starts_with('-'))
+        {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid bitcoind argument: {:?}",
+                arg
+            )));
+        }
+
+        if let Some(prune) = self.flags.prune {
+            if (2..550).contains(&prune) {
+                return Err(BitcoindError::InvalidConfig(format!(
+                    "prune target must be 0, 1 or at least 550 MiB, got {}",
+                    prune
+                )));
+            }
+            let txindex = self.flags.extra_args.iter().any(|arg| {
+                arg == "-txindex" || (arg.starts_with("-txindex=") && arg != "-txindex=0")
+            }) || self
+                .container
+                .bitcoin_conf
+                .as_ref()
+                .is_some_and(|conf| conf.txindex == Some(true));
+            if prune > 0 && txindex {
+                return Err(BitcoindError::InvalidConfig(
+                    "a pruned node can't keep a transaction index".to_string(),
+                ));
+            }
+        }
+
+        if let Some(challenge) = &self.flags.signet_challenge {
+            if ScriptBuf::from_hex(challenge).is_err() {
+                return Err(BitcoindError::InvalidConfig(format!(
+                    "invalid signet challenge: {:?}",
+                    challenge
+                )));
+            }
+            if self
+                .rpc_config
+                .as_ref()
+                .is_none_or(|rpc_config| rpc_config.network != Network::Signet)
+            {
+                return Err(BitcoindError::InvalidConfig(
+                    "a signet challenge needs the signet network".to_string(),
+                ));
+            }
+        }
+
+        if let Some((container_port, _)) = self.container.ports.iter().find(|(port, _)| *port == 0)
+        {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid container port: {}",
+                container_port
+            )));
+        }
+
+        if self.container.host_network && self.container.ephemeral_ports {
+            return Err(BitcoindError::InvalidConfig(
+                "ephemeral ports cannot be used with host networking".to_string(),
+            ));
+        }
+
+        if let Some((_, target)) = self
+            .container
+            .volumes
+            .iter()
+            .find(|(source, target)| source.is_empty() || !target.starts_with('/'))
+        {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid volume target: {:?}",
+                target
+            )));
+        }
+
+        if let Some((host_path, container_path)) =
+            self.container
+                .copy_in
+                .iter()
+                .find(|(host_path, container_path)| {
+                    !container_path.starts_with('/') || !host_path.exists()
+                })
+        {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "can't copy {} to {:?}",
+                host_path.display(),
+                container_path
+            )));
+        }
+
+        if self.container.conf_file.is_some() && self.container.bitcoin_conf.is_some() {
+            return Err(BitcoindError::InvalidConfig(
+                "conf_file and bitcoin_conf can't be used together".to_string(),
+            ));
+        }
+
+        let datadir = self.container.datadir();
+        let datadir_mounts = self
+            .container
+            .mounts()
+            .iter()
+            .filter(|(_, target)| *target == datadir)
+            .count()
+            + usize::from(self.container.tmpfs_datadir.is_some());
+        if datadir_mounts > 1 {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "more than one volume, directory or tmpfs is mounted at {}",
+                datadir
+            )));
+        }
+
+        if let Some((key, _)) = self
+            .container
+            .env
+            .iter()
+            .find(|(key, _)| !is_valid_env_name(key))
+        {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid environment variable name: {:?}",
+                key
+            )));
+        }
+
+        if self
+            .container
+            .effective_entrypoint()
+            .is_some_and(|entrypoint| entrypoint.is_empty())
+        {
+            return Err(BitcoindError::InvalidConfig(
+                "entrypoint must not be empty".to_string(),
+            ));
+        }
+        if self.container.image_profile.datadir.is_empty() {
+            return Err(BitcoindError::InvalidConfig(
+                "image profile datadir must not be empty".to_string(),
+            ));
+        }
+
+        if self.container.retry.max_attempts == 0 {
+            return Err(BitcoindError::InvalidConfig(
+                "retry policy must allow at least one attempt".to_string(),
+            ));
+        }
+
+        if self.readiness.timeout.is_zero() {
+            return Err(BitcoindError::InvalidConfig(
+                "startup timeout must be greater than zero".to_string(),
+            ));
+        }
+
+        if self.poll_interval.is_zero() {
+            return Err(BitcoindError::InvalidConfig(
+                "poll interval must be greater than zero".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn is_valid_env_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use redact::Secret;
+
+    fn rpc_config() -> RpcConfig {
+        RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        }
+    }
+
+    #[test]
+    fn test_unique_name() -> Result<(), BitcoindError> {
+        let builder = Bitcoind::builder()
+            .container_name("bitcoin-parallel")
+            .rpc_config(rpc_config())
+            .unique_name(true);
+        let a = builder.clone().build()?;
+        let b = builder.build()?;
+
+        assert!(a.container_name().starts_with("bitcoin-parallel-"));
+        assert_eq!(a.container_name().len(), "bitcoin-parallel-".len() + 8);
+        assert_ne!(a.container_name(), b.container_name());
+        assert!(a.backend().container.ephemeral_ports);
+        Ok(())
+    }
+
+    #[test]
+    fn test_datadir_path() -> Result<(), BitcoindError> {
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .datadir_path(Some(PathBuf::from("bitcoin-data")))
+            .build()?;
+
+        let mounts = bitcoind.backend().container.mounts();
+        let (source, target) = &mounts[0];
+        assert!(PathBuf::from(source).is_absolute());
+        assert!(source.ends_with("bitcoin-data"));
+        assert_eq!(target, "/data");
+        Ok(())
+    }
+
+    #[test]
+    fn test_conf_file() -> Result<(), BitcoindError> {
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .conf_file(Some(PathBuf::from("/nonexistent/bitcoin.conf")))
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let path = std::env::temp_dir().join(format!("bitcoin-{}.conf", random_hex(8)));
+        std::fs::write(&path, "rpcauth=foo:abc$def\n").unwrap();
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .conf_file(Some(path.clone()))
+            .build()?;
+        std::fs::remove_file(&path).unwrap();
+
+        let container = &bitcoind.backend().container;
+        assert_eq!(
+            container.binds(),
+            [format!("{}:/etc/bitcoin/bitcoin.conf:ro", path.display())]
+        );
+        let spec = bitcoind.node_spec();
+        assert!(container
+            .container_cmd(&spec)
+            .contains(&"-conf=/etc/bitcoin/bitcoin.conf".to_string()));
+        assert!(container
+            .cli_command(&spec, &[])
+            .contains(&"-conf=/etc/bitcoin/bitcoin.conf".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_builder_requires_rpc_config() {
+        let result = BitcoindBuilder::new().build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_settings() {
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .container_name("/bad name")
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .volume("bitcoin-data", "relative/path")
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .volume("bitcoin-data", "/data")
+            .tmpfs_datadir(Some(1 << 30))
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .datadir_volume(Some("bitcoin-data"))
+            .datadir_path(Some(PathBuf::from("/tmp/bitcoin-data")))
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .env("BAD=KEY", "value")
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .image_hash(Some("sha256:not-a-digest"))
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .prune(Some(100))
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .prune(Some(550))
+            .arg("-txindex=1")
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+
+        let result = Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .platform(Some("arm64"))
+            .build();
+        assert!(matches!(result, Err(BitcoindError::InvalidConfig(_))));
+        assert!(Bitcoind::builder()
+            .rpc_config(rpc_config())
+            .platform(Some("linux/arm/v7"))
+            .build()
+            .is_ok());
+    }
+}
@@ -0,0 +1,440 @@
+//! Removal of the containers this crate creates: a process-wide registry so
+//! an aborted test binary can remove its own, and labels to find the ones
+//! earlier runs left behind.
+
+use crate::docker::{DockerBackend, DockerConnection};
+use crate::error::BitcoindError;
+use bollard::container::{ListContainersOptions, RemoveContainerOptions};
+use bollard::errors::Error;
+use bollard::network::ListNetworksOptions;
+use bollard::volume::ListVolumesOptions;
+use bollard::Docker;
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Mutex, Once, OnceLock, PoisonError};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+/// Label on every container this crate creates, holding the crate version.
+pub const MANAGED_LABEL: &str = "bitcoind.managed";
+/// Label holding the [`run_id`] of the process that created the container.
+pub const RUN_ID_LABEL: &str = "bitcoind.run-id";
+/// Label holding the name of the test that created the container, when known.
+pub const TEST_LABEL: &str = "bitcoind.test";
+/// Label holding the UNIX time the container was created at, in seconds.
+pub const CREATED_LABEL: &str = "bitcoind.created";
+/// Label on containers kept with `reuse_existing` or `keep_on_drop`, which
+/// [`Bitcoind::cleanup_orphans`](crate::Bitcoind::cleanup_orphans) leaves alone.
+pub const PERSISTENT_LABEL: &str = "bitcoind.persistent";
+
+/// Variable overriding the run id, e.g. with the CI job id.
+const RUN_ID_ENV: &str = "BITCOIND_RUN_ID";
+
+/// Containers created and not removed yet, with the daemon running them.
+static REGISTRY: Mutex<Vec<(String, DockerConnection)>> = Mutex::new(Vec::new());
+
+pub(crate) fn register(container_name: &str, connection: &DockerConnection) {
+    let mut registry = REGISTRY.lock().unwrap_or_else(PoisonError::into_inner);
+    if !registry
+        .iter()
+        .any(|(name, conn)| name == container_name && conn == connection)
+    {
+        registry.push((container_name.to_string(), connection.clone()));
+    }
+}
+
+pub(crate) fn unregister(container_name: &str, connection: &DockerConnection) {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .retain(|(name, conn)| name != container_name || conn != connection);
+}
+
+/// Names of the containers created by this process that haven't been removed yet.
+pub fn registered() -> Vec<String> {
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .iter()
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Force-removes every container this process created and hasn't removed yet,
+/// returning how many were removed. Failures are logged and the containers
+/// stay registered.
+///
+/// Containers kept with `reuse_existing` or `keep_on_drop` are not registered.
+/// It blocks, but can be called from within an async runtime.
+pub fn cleanup_all() -> usize {
+    let entries = std::mem::take(&mut *REGISTRY.lock().unwrap_or_else(PoisonError::into_inner));
+    if entries.is_empty() {
+        return 0;
+    }
+
+    let mut removed = 0;
+    let mut failed = Vec::new();
+    let cleaned = block_on_own_runtime(async {
+        for (name, connection) in entries.iter() {
+            let result = match connection.connect() {
+                Ok((docker, _ssh_tunnel)) => force_remove(&docker, name)
+                    .await
+                    .map_err(BitcoindError::from),
+                Err(err) => Err(err),
+            };
+            match result {
+                Ok(()) => {
+                    info!("Removed bitcoind container {}", name);
+                    removed += 1;
+                }
+                Err(err) => {
+                    warn!("Could not remove bitcoind container {}: {}", name, err);
+                    failed.push((name.clone(), connection.clone()));
+                }
+            }
+        }
+    });
+    if let Err(err) = cleaned {
+        warn!("Could not remove the bitcoind containers: {}", err);
+        failed = entries;
+    }
+
+    REGISTRY
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .extend(failed);
+    removed
+}
+
+/// Runs [`cleanup_all`] and exits with status 130 when the process receives
+/// Ctrl-C. The handler runs on a thread of its own, so no async runtime is
+/// needed; calling it again does nothing.
+pub fn cleanup_on_ctrl_c() {
+    static INSTALLED: Once = Once::new();
+    INSTALLED.call_once(|| {
+        std::thread::spawn(|| {
+            let interrupted = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .and_then(|runtime| runtime.block_on(tokio::signal::ctrl_c()));
+            match interrupted {
+                Ok(()) => {
+                    info!("Interrupted, removing the bitcoind containers");
+                    cleanup_all();
+                    std::process::exit(130);
+                }
+                Err(err) => warn!("Could not listen for Ctrl-C: {}", err),
+            }
+        });
+    });
+}
+
+/// Identifies this process in the labels of the containers it creates.
+/// `BITCOIND_RUN_ID` when set, otherwise the process id and start time.
+pub fn run_id() -> &'static str {
+    static RUN_ID: OnceLock<String> = OnceLock::new();
+    RUN_ID.get_or_init(|| {
+        std::env::var(RUN_ID_ENV)
+            .unwrap_or_else(|_| format!("{}-{}", std::process::id(), unix_time().as_millis()))
+    })
+}
+
+pub(crate) fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Labels identifying a container created now by this process. The test name
+/// is the name of the current thread, which the test harness sets.
+pub(crate) fn labels(persistent: bool) -> HashMap<String, String> {
+    let mut labels = HashMap::from([
+        (
+            MANAGED_LABEL.to_string(),
+            env!("CARGO_PKG_VERSION").to_string(),
+        ),
+        (RUN_ID_LABEL.to_string(), run_id().to_string()),
+        (CREATED_LABEL.to_string(), unix_time().as_secs().to_string()),
+    ]);
+    if let Some(test) = std::thread::current().name().filter(|name| *name != "main") {
+        labels.insert(TEST_LABEL.to_string(), test.to_string());
+    }
+    if persistent {
+        labels.insert(PERSISTENT_LABEL.to_string(), "true".to_string());
+    }
+    labels
+}
+
+/// Selects the leftovers [`Bitcoind::cleanup_orphans`](crate::Bitcoind::cleanup_orphans)
+/// removes. By default everything labeled by other runs of this crate, except
+/// containers still running, which another process may be using.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OrphanFilter {
+    /// Only resources created by this run, instead of every run but the current one.
+    pub run_id: Option<String>,
+    /// Only resources created by this test.
+    pub test: Option<String>,
+    /// Only resources at least this old, e.g. to spare concurrent CI jobs.
+    pub older_than: Option<Duration>,
+    /// Also running containers, such as the nodes a killed run left up. Off by
+    /// default since a running node may belong to a concurrent process.
+    pub include_running: bool,
+}
+
+impl OrphanFilter {
+    /// Whether a resource with `labels` is an orphan this filter selects.
+    fn matches(&self, labels: &HashMap<String, String>, running: bool, now: Duration) -> bool {
+        if !labels.contains_key(MANAGED_LABEL) || labels.contains_key(PERSISTENT_LABEL) {
+            return false;
+        }
+        if running && !self.include_running {
+            return false;
+        }
+        let run_id = labels.get(RUN_ID_LABEL).map(String::as_str);
+        let run_matches = match &self.run_id {
+            Some(wanted) => run_id == Some(wanted.as_str()),
+            None => run_id != Some(crate::cleanup::run_id()),
+        };
+        let test_matches = self
+            .test
+            .as_ref()
+            .is_none_or(|wanted| labels.get(TEST_LABEL) == Some(wanted));
+        let old_enough = self.older_than.is_none_or(|older_than| {
+            labels
+                .get(CREATED_LABEL)
+                .and_then(|created| created.parse::<u64>().ok())
+                .is_some_and(|created| {
+                    now.saturating_sub(Duration::from_secs(created)) >= older_than
+                })
+        });
+        run_matches && test_matches && old_enough
+    }
+}
+
+/// Resources removed by [`Bitcoind::cleanup_orphans`](crate::Bitcoind::cleanup_orphans).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RemovedOrphans {
+    pub containers: Vec<String>,
+    pub volumes: Vec<String>,
+    pub networks: Vec<String>,
+}
+
+impl DockerBackend {
+    /// Removes the containers, volumes and networks labeled by this crate
+    /// that `filter` selects. Volumes and networks still in use are skipped.
+    pub async fn cleanup_orphans(
+        &self,
+        filter: &OrphanFilter,
+    ) -> Result<RemovedOrphans, BitcoindError> {
+        let now = unix_time();
+        let label_filter = HashMap::from([("label", vec![MANAGED_LABEL])]);
+        let mut removed = RemovedOrphans::default();
+
+        let containers = self
+            .docker
+            .list_containers(Some(ListContainersOptions {
+                all: true,
+                filters: label_filter.clone(),
+                ..Default::default()
+            }))
+            .await?;
+        for container in containers {
+            let (Some(id), Some(labels)) = (container.id, container.labels) else {
+                continue;
+            };
+            let running = matches!(
+                container.state.as_deref(),
+                Some("running" | "paused" | "restarting")
+            );
+            if !filter.matches(&labels, running, now) {
+                continue;
+            }
+            let name = container
+                .names
+                .and_then(|names| names.into_iter().next())
+                .map_or(id.clone(), |name| name.trim_start_matches('/').to_string());
+            info!("Removing orphaned bitcoind container {}", name);
+            let options = RemoveContainerOptions {
+                force: true,
+                // Anonymous volumes declared by the image would outlive it.
+                v: true,
+                ..Default::default()
+            };
+            match self.docker.remove_container(&id, Some(options)).await {
+                Ok(())
+                | Err(Error::DockerResponseServerError {
+                    status_code: 404, ..
+                }) => removed.containers.push(name),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let volumes = self
+            .docker
+            .list_volumes(Some(ListVolumesOptions {
+                filters: label_filter.clone(),
+            }))
+            .await?;
+        for volume in volumes.volumes.unwrap_or_default() {
+            if !filter.matches(&volume.labels, false, now) {
+                continue;
+            }
+            match self.docker.remove_volume(&volume.name, None).await {
+                Ok(()) => {
+                    info!("Removed orphaned volume {}", volume.name);
+                    removed.volumes.push(volume.name);
+                }
+                Err(Error::DockerResponseServerError {
+                    status_code: 409, ..
+                }) => debug!("Volume {} is in use, not removing it", volume.name),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        let networks = self
+            .docker
+            .list_networks(Some(ListNetworksOptions {
+                filters: label_filter,
+            }))
+            .await?;
+        for network in networks {
+            let (Some(name), Some(labels)) = (network.name, network.labels) else {
+                continue;
+            };
+            if !filter.matches(&labels, false, now) {
+                continue;
+            }
+            match self.docker.remove_network(&name).await {
+                Ok(()) => {
+                    info!("Removed orphaned network {}", name);
+                    removed.networks.push(name);
+                }
+                Err(Error::DockerResponseServerError {
+                    status_code: 403 | 409,
+                    ..
+                }) => debug!("Network {} is in use, not removing it", name),
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        Ok(removed)
+    }
+}
+
+/// Runs `future` to completion on a new runtime in a thread of its own.
+///
+/// Needed to talk to Docker from synchronous code that may itself run on an
+/// async runtime, e.g. a destructor: a client's pooled connections are driven
+/// by the runtime that opened them, which may be blocked on that code, so
+/// only clients created inside `future` can be used.
+pub(crate) fn block_on_own_runtime<F>(future: F) -> std::io::Result<F::Output>
+where
+    F: Future + Send,
+    F::Output: Send,
+{
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map(|runtime| runtime.block_on(future))
+            })
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+/// Removes `container_name` even if it is running. A container that is
+/// already gone counts as removed.
+pub(crate) async fn force_remove(docker: &Docker, container_name: &str) -> Result<(), Error> {
+    let removed = docker
+        .remove_container(
+            container_name,
+            Some(RemoveContainerOptions {
+                force: true,
+                ..Default::default()
+            }),
+        )
+        .await;
+    match removed {
+        Ok(())
+        | Err(Error::DockerResponseServerError {
+            status_code: 404, ..
+        }) => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let local = DockerConnection::default();
+        let tcp = DockerConnection::Tcp {
+            address: "tcp://10.0.0.5:2375".to_string(),
+        };
+        register("bitcoin-registry-a", &local);
+        register("bitcoin-registry-a", &local);
+        register("bitcoin-registry-a", &tcp);
+        register("bitcoin-registry-b", &local);
+        let count = |name: &str| registered().iter().filter(|n| *n == name).count();
+        assert_eq!(count("bitcoin-registry-a"), 2);
+
+        unregister("bitcoin-registry-a", &local);
+        unregister("bitcoin-registry-a", &tcp);
+        assert_eq!(count("bitcoin-registry-a"), 0);
+        assert_eq!(count("bitcoin-registry-b"), 1);
+        unregister("bitcoin-registry-b", &local);
+    }
+
+    #[test]
+    fn test_orphan_filter() {
+        let now = unix_time();
+        let labels = labels(false);
+        assert_eq!(labels[RUN_ID_LABEL], run_id());
+        assert_eq!(
+            labels.get(TEST_LABEL).map(String::as_str),
+            Some("cleanup::tests::test_orphan_filter")
+        );
+        // Resources of the current run are not orphans.
+        assert!(!OrphanFilter::default().matches(&labels, false, now));
+
+        let mut earlier = labels.clone();
+        earlier.insert(RUN_ID_LABEL.to_string(), "earlier".to_string());
+        assert!(OrphanFilter::default().matches(&earlier, false, now));
+        assert!(OrphanFilter {
+            run_id: Some("earlier".to_string()),
+            ..Default::default()
+        }
+        .matches(&earlier, false, now));
+        assert!(!OrphanFilter {
+            test: Some("other".to_string()),
+            ..Default::default()
+        }
+        .matches(&earlier, false, now));
+
+        // A running container of another run may belong to a concurrent process.
+        assert!(!OrphanFilter::default().matches(&earlier, true, now));
+        assert!(OrphanFilter {
+            include_running: true,
+            ..Default::default()
+        }
+        .matches(&earlier, true, now));
+
+        let older_than = OrphanFilter {
+            older_than: Some(Duration::from_secs(3600)),
+            ..Default::default()
+        };
+        assert!(!older_than.matches(&earlier, false, now));
+        assert!(older_than.matches(&earlier, false, now + Duration::from_secs(3600)));
+
+        let mut persistent = earlier.clone();
+        persistent.insert(PERSISTENT_LABEL.to_string(), "true".to_string());
+        assert!(!OrphanFilter::default().matches(&persistent, false, now));
+        assert!(!OrphanFilter::default().matches(&HashMap::new(), false, now));
+    }
+}
@@ -0,0 +1,75 @@
+//! Statistics of the UTXO set from `gettxoutsetinfo`, cheap at any height
+//! with the coin statistics index (`-coinstatsindex`).
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Result of `gettxoutsetinfo` with the MuHash of the UTXO set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TxOutSetInfo {
+    pub height: u64,
+    #[serde(rename = "bestblock")]
+    pub best_block: String,
+    /// Unspent outputs in the set.
+    pub txouts: u64,
+    /// Estimate of the serialized size of the set.
+    pub bogosize: u64,
+    /// MuHash3072 of the set, which is independent of the order the coins
+    /// were added in.
+    pub muhash: String,
+    /// Total value of the unspent outputs, in BTC.
+    pub total_amount: f64,
+    /// Value that can never be spent, e.g. `OP_RETURN` outputs and unclaimed
+    /// subsidy, in BTC. Only reported with the coin statistics index.
+    pub total_unspendable_amount: Option<f64>,
+    /// Transactions with unspent outputs. Not reported with the index.
+    pub transactions: Option<u64>,
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Returns the statistics of the UTXO set at the tip or, with `height`,
+    /// at an earlier block.
+    ///
+    /// With [`BitcoindFlags::coinstats_index`] the answer comes from the
+    /// index; otherwise the node scans the whole set, and only at the tip.
+    /// Wait for `ReadyCondition::IndexSynced("coinstatsindex")` after start.
+    ///
+    /// [`BitcoindFlags::coinstats_index`]: crate::bitcoind::BitcoindFlags::coinstats_index
+    pub async fn txout_set_info(&self, height: Option<u64>) -> Result<TxOutSetInfo, BitcoindError> {
+        let params = match height {
+            Some(height) => json!(["muhash", height]),
+            None => json!(["muhash"]),
+        };
+        Ok(self
+            .rpc_client()
+            .call::<TxOutSetInfo>("gettxoutsetinfo", params)
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_txout_set_info() {
+        let info: TxOutSetInfo = serde_json::from_value(json!({
+            "height": 200,
+            "bestblock": "6affe030b7965ab538f820a56ef56c8149b7dc1d1c144af57113be080db7c397",
+            "txouts": 200,
+            "bogosize": 14600,
+            "muhash": "dd5ad2a105c2d29495f577245c357409002329b9f4d6182c0af3dc2f462555c8",
+            "total_amount": 8725.0,
+            "total_unspendable_amount": 50.0,
+            "block_info": { "prevout_spent": 0 }
+        }))
+        .unwrap();
+        assert_eq!(info.best_block.len(), 64);
+        assert_eq!(info.total_unspendable_amount, Some(50.0));
+        assert_eq!(info.transactions, None);
+    }
+}
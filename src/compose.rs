@@ -0,0 +1,395 @@
+//! Docker Compose export of the container the Docker backend creates, to run
+//! the same node outside Rust, e.g. while debugging.
+
+use crate::bitcoind::node_spec;
+use crate::builder::BitcoindBuilder;
+use crate::config::BitcoindConfig;
+use crate::docker::CONF_PATH;
+use crate::error::BitcoindError;
+use crate::image::ImageSource;
+use crate::ports::parse_rpc_port;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Serialize)]
+struct ComposeFile {
+    services: BTreeMap<String, ComposeService>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    volumes: BTreeMap<String, ComposeVolume>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    configs: BTreeMap<String, ComposeConfig>,
+}
+
+/// File created from inline content, for a typed `bitcoin.conf`.
+#[derive(Debug, Serialize)]
+struct ComposeConfig {
+    content: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ComposeConfigMount {
+    source: String,
+    target: &'static str,
+}
+
+/// Named volume, kept under its own name instead of one prefixed with the project.
+#[derive(Debug, Serialize)]
+struct ComposeVolume {
+    name: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ComposeService {
+    image: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    build: Option<ComposeBuild>,
+    container_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entrypoint: Option<Vec<String>>,
+    command: Vec<String>,
+    environment: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    working_dir: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    ports: Vec<ComposePort>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    volumes: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tmpfs: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    configs: Vec<ComposeConfigMount>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    network_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    healthcheck: Option<ComposeHealthcheck>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpus: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mem_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    memswap_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pids_limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+struct ComposeHealthcheck {
+    test: Vec<String>,
+    interval: String,
+    timeout: String,
+    start_period: String,
+    retries: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct ComposeBuild {
+    context: PathBuf,
+    dockerfile: PathBuf,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    args: BTreeMap<String, String>,
+}
+
+/// Long port syntax, which also handles IPv6 host addresses.
+#[derive(Debug, Serialize)]
+struct ComposePort {
+    target: u16,
+    /// Omitted with ephemeral ports, so Compose picks a free one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    published: Option<String>,
+    host_ip: String,
+    protocol: &'static str,
+}
+
+impl BitcoindConfig {
+    /// Returns a `docker-compose.yml` with a single service running the same
+    /// container `Bitcoind::from_config` starts.
+    ///
+    /// The RPC credentials appear in the service command, with `$` escaped
+    /// as `$$` so Compose does not interpolate them.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the configuration is invalid.
+    pub fn to_compose_yaml(&self) -> Result<String, BitcoindError> {
+        Self::cluster_to_compose_yaml(std::slice::from_ref(self))
+    }
+
+    /// Returns a `docker-compose.yml` with one service per node, named after
+    /// its container.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if a configuration is invalid or two
+    ///   nodes share a container name.
+    pub fn cluster_to_compose_yaml(configs: &[BitcoindConfig]) -> Result<String, BitcoindError> {
+        let mut services = BTreeMap::new();
+        let mut volumes = BTreeMap::new();
+        let mut conf_files = BTreeMap::new();
+        for config in configs {
+            if let Some(conf) = &config.bitcoin_conf {
+                conf_files.insert(
+                    conf_name(&config.container_name),
+                    ComposeConfig {
+                        content: escape(&conf.render()),
+                    },
+                );
+            }
+            if let Some(volume) = &config.datadir_volume {
+                volumes.insert(
+                    volume.clone(),
+                    ComposeVolume {
+                        name: volume.clone(),
+                    },
+                );
+            }
+            let service = config.builder().compose_service()?;
+            if services
+                .insert(config.container_name.clone(), service)
+                .is_some()
+            {
+                return Err(BitcoindError::InvalidConfig(format!(
+                    "duplicate container name {}",
+                    config.container_name
+                )));
+            }
+        }
+
+        serde_yaml::to_string(&ComposeFile {
+            services,
+            volumes,
+            configs: conf_files,
+        })
+        .map_err(|e| BitcoindError::InvalidConfig(e.to_string()))
+    }
+}
+
+impl BitcoindBuilder {
+    fn compose_service(&self) -> Result<ComposeService, BitcoindError> {
+        self.validate()?;
+
+        let rpc_config = self
+            .rpc_config
+            .as_ref()
+            .ok_or_else(|| BitcoindError::InvalidConfig("rpc_config is required".to_string()))?;
+        let spec = node_spec(
+            rpc_config,
+            parse_rpc_port(rpc_config.url.expose_secret(), rpc_config.network)?,
+            &self.flags,
+        );
+        let container = &self.container;
+
+        let ports = if container.host_network {
+            Vec::new()
+        } else {
+            container
+                .published_ports(&spec)
+                .into_iter()
+                .map(|(container_port, host_port)| ComposePort {
+                    target: container_port,
+                    published: (!container.ephemeral_ports).then(|| host_port.to_string()),
+                    host_ip: container.bind_ip().to_string(),
+                    protocol: "tcp",
+                })
+                .collect()
+        };
+
+        Ok(ComposeService {
+            image: self.image.clone(),
+            build: match &container.image_source {
+                ImageSource::Build {
+                    dockerfile,
+                    context,
+                    build_args,
+                } => Some(ComposeBuild {
+                    context: context.clone(),
+                    dockerfile: dockerfile.clone(),
+                    args: build_args.clone(),
+                }),
+                ImageSource::Registry => None,
+            },
+            container_name: self.container_name.clone(),
+            platform: container.platform.clone(),
+            entrypoint: container.effective_entrypoint().map(escape_all),
+            command: escape_all(container.container_cmd(&spec)),
+            environment: escape_all(container.container_env()),
+            user: container.image_profile.user.clone(),
+            working_dir: container.working_dir(),
+            ports,
+            volumes: container.binds(),
+            tmpfs: container
+                .tmpfs_datadir
+                .map(|size| format!("{}:size={}", container.datadir(), size))
+                .into_iter()
+                .collect(),
+            configs: container
+                .bitcoin_conf
+                .iter()
+                .map(|_| ComposeConfigMount {
+                    source: conf_name(&self.container_name),
+                    target: CONF_PATH,
+                })
+                .collect(),
+            network_mode: container.host_network.then(|| "host".to_string()),
+            healthcheck: container.healthcheck.map(|healthcheck| {
+                let duration = |duration: Duration| format!("{}ms", duration.as_millis());
+                ComposeHealthcheck {
+                    test: escape_all(container.healthcheck_test(&spec)),
+                    interval: duration(healthcheck.interval),
+                    timeout: duration(healthcheck.timeout),
+                    start_period: duration(healthcheck.start_period),
+                    retries: healthcheck.retries,
+                }
+            }),
+            cpus: container.resources.cpus,
+            mem_limit: container.resources.memory,
+            memswap_limit: container.resources.memory,
+            pids_limit: container.resources.pids,
+        })
+    }
+}
+
+/// Escapes `s` from Compose's variable interpolation, which would otherwise
+/// mangle e.g. the `$` separating an `rpcauth` salt from its hash.
+fn escape(s: &str) -> String {
+    s.replace('$', "$$")
+}
+
+fn escape_all(strings: Vec<String>) -> Vec<String> {
+    strings.iter().map(|s| escape(s)).collect()
+}
+
+/// Name of the Compose config holding a node's `bitcoin.conf`.
+fn conf_name(container_name: &str) -> String {
+    format!("{}-bitcoin-conf", container_name)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::conf::BitcoinConf;
+    use redact::Secret;
+    use serde_yaml::Value;
+
+    const CONFIG: &str = r#"
+container_name: node-a
+image: bitcoin/bitcoin:29.1
+hash: "sha256:abababababababababababababababababababababababababababababababab"
+rpc:
+  network: regtest
+  url: http://localhost:18443
+  username: foo
+  password: rpcpassword
+  wallet: mywallet
+publish:
+  p2p: 18444
+host_ip: "::"
+env:
+  UID: "1000"
+healthcheck:
+  interval: 2
+resources:
+  cpus: 0.5
+  memory: 536870912
+tmpfs_datadir: 1073741824
+"#;
+
+    #[test]
+    fn test_compose_yaml_matches_container() -> Result<(), BitcoindError> {
+        let config: BitcoindConfig = serde_yaml::from_str(CONFIG).unwrap();
+        let mut node_b = config.clone();
+        node_b.container_name = "node-b".to_string();
+        node_b.host_network = true;
+        node_b.hash = None;
+        node_b.image_source = ImageSource::build("./bitcoin");
+        node_b.tmpfs_datadir = None;
+        node_b.datadir_volume = Some("node-b-data".to_string());
+        node_b.bitcoin_conf = Some(BitcoinConf {
+            rpcauth: vec!["foo:abc$def".to_string()],
+            ..Default::default()
+        });
+
+        let yaml = BitcoindConfig::cluster_to_compose_yaml(&[config.clone(), node_b])?;
+        let compose: Value = serde_yaml::from_str(&yaml).unwrap();
+
+        let node_a = &compose["services"]["node-a"];
+        assert!(node_a["image"]
+            .as_str()
+            .unwrap()
+            .starts_with("bitcoin/bitcoin:29.1@sha256:abab"));
+        assert_eq!(node_a["container_name"], "node-a");
+        assert_eq!(node_a["command"][0], "-regtest=1");
+        assert!(node_a["command"]
+            .as_sequence()
+            .unwrap()
+            .contains(&Value::from("-rpcbind=::")));
+        assert_eq!(node_a["environment"][1], "UID=1000");
+        assert_eq!(node_a["ports"][0]["target"], 18443);
+        assert_eq!(node_a["ports"][0]["published"], "18443");
+        assert_eq!(node_a["ports"][0]["host_ip"], "::");
+        assert_eq!(node_a["ports"][1]["target"], 18444);
+        assert_eq!(node_a["healthcheck"]["test"][0], "CMD");
+        assert_eq!(node_a["healthcheck"]["interval"], "2000ms");
+        assert_eq!(node_a["healthcheck"]["retries"], 3);
+        assert_eq!(node_a["cpus"], 0.5);
+        assert_eq!(node_a["mem_limit"], 536870912);
+        assert!(node_a.get("pids_limit").is_none());
+        assert_eq!(node_a["tmpfs"][0], "/data:size=1073741824");
+
+        let node_b = &compose["services"]["node-b"];
+        assert_eq!(node_b["network_mode"], "host");
+        assert_eq!(node_b["volumes"][0], "node-b-data:/data");
+        assert_eq!(compose["volumes"]["node-b-data"]["name"], "node-b-data");
+        assert_eq!(node_b["configs"][0]["source"], "node-b-bitcoin-conf");
+        assert_eq!(node_b["configs"][0]["target"], "/etc/bitcoin/bitcoin.conf");
+        assert_eq!(
+            compose["configs"]["node-b-bitcoin-conf"]["content"],
+            "rpcauth=foo:abc$$def\n"
+        );
+        assert!(node_b["command"]
+            .as_sequence()
+            .unwrap()
+            .contains(&Value::from("-conf=/etc/bitcoin/bitcoin.conf")));
+        assert!(node_b.get("ports").is_none());
+        assert_eq!(node_b["build"]["context"], "./bitcoin");
+        assert_eq!(node_b["build"]["dockerfile"], "Dockerfile");
+        assert!(node_a.get("build").is_none());
+
+        assert!(matches!(
+            BitcoindConfig::cluster_to_compose_yaml(&[config.clone(), config]),
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_compose_escapes_dollar_signs() -> Result<(), BitcoindError> {
+        let mut config: BitcoindConfig = serde_yaml::from_str(CONFIG).unwrap();
+        config.rpc.password = Secret::new("pa$word".to_string());
+        config.env.insert("SECRET".to_string(), "a$b".to_string());
+
+        let yaml = config.to_compose_yaml()?;
+        let compose: Value = serde_yaml::from_str(&yaml).unwrap();
+        let node = &compose["services"]["node-a"];
+        let contains = |key: &str, value: &str| {
+            node[key]
+                .as_sequence()
+                .unwrap()
+                .contains(&Value::from(value))
+        };
+        assert!(contains("command", "-rpcpassword=pa$$word"));
+        assert!(contains("environment", "SECRET=a$$b"));
+        assert!(node["healthcheck"]["test"]
+            .as_sequence()
+            .unwrap()
+            .contains(&Value::from("-rpcpassword=pa$$word")));
+        assert!(!yaml.contains("pa$word"));
+        Ok(())
+    }
+}
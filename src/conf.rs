@@ -0,0 +1,152 @@
+//! Typed `bitcoin.conf` written into the container when it is created.
+
+use bitcoin::Network;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+/// Settings rendered as a `bitcoin.conf`, an alternative to flags and to a
+/// hand-written file mounted with `conf_file`.
+///
+/// Options `bitcoind` only reads from the section of the active chain, such
+/// as `rpcport` or `addnode`, are written under `[regtest]`, `[signet]` and so
+/// on; the others go at the top of the file. `extra` holds options without a
+/// field, e.g. `("maxconnections", "8")`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BitcoinConf {
+    /// Chain the file selects with `chain=`, and the section for per-chain options.
+    pub chain: Option<Network>,
+    pub server: Option<bool>,
+    pub txindex: Option<bool>,
+    /// Target size of the block files in MiB, or `1` to prune manually.
+    pub prune: Option<u64>,
+    /// Database cache in MiB.
+    pub dbcache: Option<u64>,
+    /// Mempool size in MB.
+    pub maxmempool: Option<u64>,
+    pub fallbackfee: Option<f64>,
+    /// `rpcauth` lines, `<user>:<salt>$<hash>`.
+    pub rpcauth: Vec<String>,
+    pub rpcallowip: Vec<String>,
+    pub zmqpubrawblock: Vec<String>,
+    pub zmqpubrawtx: Vec<String>,
+    pub zmqpubhashblock: Vec<String>,
+    pub zmqpubhashtx: Vec<String>,
+    pub rpcport: Option<u16>,
+    pub port: Option<u16>,
+    pub rpcbind: Vec<String>,
+    pub bind: Vec<String>,
+    pub addnode: Vec<String>,
+    pub connect: Vec<String>,
+    /// Wallets loaded at startup.
+    pub wallet: Vec<String>,
+    /// Further top-level options as `(name, value)` pairs.
+    pub extra: Vec<(String, String)>,
+}
+
+impl BitcoinConf {
+    /// Renders the file, one `name=value` line per setting.
+    pub fn render(&self) -> String {
+        let mut global = Lines::default();
+        if let Some(chain) = self.chain {
+            global.push("chain", chain.to_core_arg());
+        }
+        global.push_opt("server", self.server.map(u8::from));
+        global.push_opt("txindex", self.txindex.map(u8::from));
+        global.push_opt("prune", self.prune);
+        global.push_opt("dbcache", self.dbcache);
+        global.push_opt("maxmempool", self.maxmempool);
+        global.push_opt("fallbackfee", self.fallbackfee);
+        global.push_all("rpcauth", &self.rpcauth);
+        global.push_all("rpcallowip", &self.rpcallowip);
+        global.push_all("zmqpubrawblock", &self.zmqpubrawblock);
+        global.push_all("zmqpubrawtx", &self.zmqpubrawtx);
+        global.push_all("zmqpubhashblock", &self.zmqpubhashblock);
+        global.push_all("zmqpubhashtx", &self.zmqpubhashtx);
+        for (name, value) in &self.extra {
+            global.push(name, value);
+        }
+
+        let mut chain = Lines::default();
+        chain.push_opt("rpcport", self.rpcport);
+        chain.push_opt("port", self.port);
+        chain.push_all("rpcbind", &self.rpcbind);
+        chain.push_all("bind", &self.bind);
+        chain.push_all("addnode", &self.addnode);
+        chain.push_all("connect", &self.connect);
+        chain.push_all("wallet", &self.wallet);
+
+        let mut conf = global.0;
+        match self.chain {
+            // Mainnet reads the options from the top of the file.
+            None | Some(Network::Bitcoin) => conf.push_str(&chain.0),
+            Some(network) if !chain.0.is_empty() => {
+                conf.push_str(&format!("\n[{}]\n", network.to_core_arg()));
+                conf.push_str(&chain.0);
+            }
+            Some(_) => {}
+        }
+        conf
+    }
+}
+
+#[derive(Default)]
+struct Lines(String);
+
+impl Lines {
+    fn push(&mut self, name: &str, value: impl Display) {
+        self.0.push_str(&format!("{}={}\n", name, value));
+    }
+
+    fn push_opt(&mut self, name: &str, value: Option<impl Display>) {
+        if let Some(value) = value {
+            self.push(name, value);
+        }
+    }
+
+    fn push_all(&mut self, name: &str, values: &[String]) {
+        for value in values {
+            self.push(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_render_bitcoin_conf() {
+        let conf = BitcoinConf {
+            chain: Some(Network::Regtest),
+            txindex: Some(true),
+            rpcauth: vec!["foo:abc$def".to_string()],
+            zmqpubrawblock: vec!["tcp://0.0.0.0:28332".to_string()],
+            zmqpubrawtx: vec!["tcp://0.0.0.0:28333".to_string()],
+            rpcport: Some(18443),
+            addnode: vec!["node-b:18444".to_string()],
+            extra: vec![("maxconnections".to_string(), "8".to_string())],
+            ..Default::default()
+        };
+        assert_eq!(
+            conf.render(),
+            "chain=regtest\n\
+             txindex=1\n\
+             rpcauth=foo:abc$def\n\
+             zmqpubrawblock=tcp://0.0.0.0:28332\n\
+             zmqpubrawtx=tcp://0.0.0.0:28333\n\
+             maxconnections=8\n\
+             \n\
+             [regtest]\n\
+             rpcport=18443\n\
+             addnode=node-b:18444\n"
+        );
+
+        let mainnet = BitcoinConf {
+            rpcport: Some(8332),
+            ..Default::default()
+        };
+        assert_eq!(mainnet.render(), "rpcport=8332\n");
+    }
+}
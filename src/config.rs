@@ -0,0 +1,519 @@
+use crate::bitcoind::{BitcoindFlags, Healthcheck, ResourceLimits, Timeouts};
+use crate::builder::{BitcoindBuilder, DEFAULT_CONTAINER_NAME, DEFAULT_IMAGE};
+use crate::conf::BitcoinConf;
+use crate::docker::{DockerConnection, PullPolicy};
+#[cfg(feature = "download")]
+use crate::download::BitcoinCoreRelease;
+use crate::error::BitcoindError;
+use crate::image::{ImageProfile, ImageSource};
+use crate::ports::PublishedPorts;
+use crate::registry::RegistryAuth;
+use bitcoin::Network;
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use redact::Secret;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+pub const ENV_CONTAINER_NAME: &str = "BITCOIND_CONTAINER_NAME";
+pub const ENV_IMAGE: &str = "BITCOIND_IMAGE";
+pub const ENV_HASH: &str = "BITCOIND_HASH";
+pub const ENV_NETWORK: &str = "BITCOIND_NETWORK";
+pub const ENV_RPC_URL: &str = "BITCOIND_RPC_URL";
+pub const ENV_RPC_USER: &str = "BITCOIND_RPC_USER";
+pub const ENV_RPC_PASSWORD: &str = "BITCOIND_RPC_PASSWORD";
+pub const ENV_RPC_WALLET: &str = "BITCOIND_RPC_WALLET";
+
+const DEFAULT_WALLET: &str = "default";
+
+/// Deserializable description of a managed `bitcoind` node.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BitcoindConfig {
+    /// The name of the Docker container.
+    #[serde(default = "default_container_name")]
+    pub container_name: String,
+    /// The Docker image to use, e.g. `bitcoin/bitcoin:29.1`.
+    #[serde(default = "default_image")]
+    pub image: String,
+    /// Optional image digest (`sha256:...`). When set, the image is pinned to it.
+    pub hash: Option<String>,
+    /// Whether the image is pulled (default) or built from a Dockerfile.
+    #[serde(default)]
+    pub image_source: ImageSource,
+    /// Platform of the image, e.g. `linux/arm64`. Defaults to the daemon's platform.
+    pub platform: Option<String>,
+    /// When the image is pulled: `always`, `if_not_present` (default) or `never`.
+    #[serde(default)]
+    pub pull_policy: PullPolicy,
+    /// Credentials for pulling the image from a private registry.
+    pub registry_auth: Option<RegistryAuth>,
+    /// `docker save` archive loaded instead of pulling when the image is missing.
+    pub image_tar: Option<PathBuf>,
+    /// The RPC configuration for the Bitcoin node.
+    pub rpc: RpcConfig,
+    /// Flags passed to `bitcoind`. Missing fields take their default value.
+    pub flags: Option<BitcoindFlags>,
+    /// Extra container environment variables, merged with the defaults.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    /// Entrypoint, datadir and user conventions of the image. Picked from
+    /// the image name when missing.
+    pub image_profile: Option<ImageProfile>,
+    /// Overrides the entrypoint of the image profile.
+    pub entrypoint: Option<Vec<String>>,
+    /// Arguments placed before the generated `bitcoind` flags.
+    #[serde(default)]
+    pub cmd_prefix: Vec<String>,
+    /// P2P, ZMQ and REST services to publish alongside RPC.
+    #[serde(default)]
+    pub publish: PublishedPorts,
+    /// Appends a random suffix to `container_name` and publishes every port on
+    /// a free host port, so nodes can run in parallel.
+    #[serde(default)]
+    pub unique_name: bool,
+    /// Keeps a running container created from the same settings instead of recreating it.
+    #[serde(default)]
+    pub reuse_existing: bool,
+    /// Emits the container's output as `tracing` events.
+    #[serde(default)]
+    pub forward_logs: bool,
+    /// Leaves the container running when the `Bitcoind` is dropped.
+    #[serde(default)]
+    pub keep_on_drop: bool,
+    /// Docker healthcheck running `bitcoin-cli getblockcount`, in seconds.
+    pub healthcheck: Option<Healthcheck>,
+    /// CPU, memory (in bytes) and process limits of the container.
+    #[serde(default)]
+    pub resources: ResourceLimits,
+    /// Size in bytes of a tmpfs mounted as the datadir.
+    pub tmpfs_datadir: Option<u64>,
+    /// Named volume holding the datadir across restarts and image upgrades.
+    pub datadir_volume: Option<String>,
+    /// Host directory bind mounted as the datadir.
+    pub datadir_path: Option<PathBuf>,
+    /// Host `bitcoin.conf` mounted into the container and passed with `-conf`.
+    pub conf_file: Option<PathBuf>,
+    /// `bitcoin.conf` settings written into the container.
+    pub bitcoin_conf: Option<BitcoinConf>,
+    /// Host files or directories copied into the container before the node
+    /// starts, as `[host_path, container_path]` pairs.
+    #[serde(default)]
+    pub copy_in: Vec<(PathBuf, String)>,
+    /// Directory the container's output is saved to when the node fails to start.
+    pub log_artifacts: Option<PathBuf>,
+    /// Runs the container on the host network instead of publishing ports.
+    #[serde(default)]
+    pub host_network: bool,
+    /// Host address the published ports bind to, e.g. `::`. Defaults to `0.0.0.0`.
+    pub host_ip: Option<IpAddr>,
+    /// Start and stop timeouts and the polling interval, in seconds.
+    pub timeouts: Option<Timeouts>,
+    /// How to reach the Docker daemon. Defaults to `DOCKER_HOST` or the local socket.
+    #[serde(default)]
+    pub docker: DockerConnection,
+    /// Bitcoin Core release to run without Docker, see [`crate::NativeBackend::download`].
+    #[cfg(feature = "download")]
+    pub release: Option<BitcoinCoreRelease>,
+}
+
+fn default_container_name() -> String {
+    DEFAULT_CONTAINER_NAME.to_string()
+}
+
+fn default_image() -> String {
+    DEFAULT_IMAGE.to_string()
+}
+
+impl BitcoindConfig {
+    /// Loads the configuration from a TOML (`.toml`) or YAML (`.yaml`, `.yml`) file.
+    ///
+    /// `${VAR}` and `${VAR:-default}` placeholders are replaced with environment
+    /// variables before parsing, so RPC credentials don't need to live in the file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, BitcoindError> {
+        let path = path.as_ref();
+        let contents =
+            std::fs::read_to_string(path).map_err(|source| BitcoindError::ConfigFile {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        let contents = interpolate_env(&contents)?;
+
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => toml::from_str(&contents)
+                .map_err(|e| BitcoindError::InvalidConfig(format!("{}: {}", path.display(), e))),
+            Some("yaml") | Some("yml") => serde_yaml::from_str(&contents)
+                .map_err(|e| BitcoindError::InvalidConfig(format!("{}: {}", path.display(), e))),
+            _ => Err(BitcoindError::InvalidConfig(format!(
+                "unsupported config file format: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Builds the configuration from `BITCOIND_*` environment variables only.
+    ///
+    /// `BITCOIND_RPC_URL`, `BITCOIND_RPC_USER` and `BITCOIND_RPC_PASSWORD` are
+    /// required. The network defaults to regtest and the wallet to `default`.
+    pub fn from_env() -> Result<Self, BitcoindError> {
+        let required = |name: &str| {
+            env_var(name).ok_or_else(|| {
+                BitcoindError::InvalidConfig(format!("environment variable {} is not set", name))
+            })
+        };
+
+        let rpc = RpcConfig {
+            network: Network::Regtest,
+            url: Secret::new(required(ENV_RPC_URL)?),
+            username: Secret::new(required(ENV_RPC_USER)?),
+            password: Secret::new(required(ENV_RPC_PASSWORD)?),
+            wallet: DEFAULT_WALLET.to_string(),
+        };
+
+        Self {
+            container_name: default_container_name(),
+            image: default_image(),
+            hash: None,
+            image_source: ImageSource::default(),
+            platform: None,
+            pull_policy: PullPolicy::default(),
+            registry_auth: None,
+            image_tar: None,
+            rpc,
+            flags: None,
+            env: BTreeMap::new(),
+            image_profile: None,
+            entrypoint: None,
+            cmd_prefix: Vec::new(),
+            publish: PublishedPorts::default(),
+            unique_name: false,
+            reuse_existing: false,
+            forward_logs: false,
+            keep_on_drop: false,
+            healthcheck: None,
+            resources: ResourceLimits::default(),
+            tmpfs_datadir: None,
+            datadir_volume: None,
+            datadir_path: None,
+            conf_file: None,
+            bitcoin_conf: None,
+            copy_in: Vec::new(),
+            log_artifacts: None,
+            host_network: false,
+            host_ip: None,
+            timeouts: None,
+            docker: DockerConnection::default(),
+            #[cfg(feature = "download")]
+            release: None,
+        }
+        .with_env_overrides()
+    }
+
+    /// Overrides fields with any `BITCOIND_*` environment variables that are set.
+    ///
+    /// Combine with [`BitcoindConfig::from_file`] so the environment wins over the file:
+    /// `BitcoindConfig::from_file(path)?.with_env_overrides()?`.
+    pub fn with_env_overrides(mut self) -> Result<Self, BitcoindError> {
+        if let Some(container_name) = env_var(ENV_CONTAINER_NAME) {
+            self.container_name = container_name;
+        }
+        if let Some(image) = env_var(ENV_IMAGE) {
+            self.image = image;
+        }
+        if let Some(hash) = env_var(ENV_HASH) {
+            self.hash = Some(hash);
+        }
+        if let Some(network) = env_var(ENV_NETWORK) {
+            self.rpc.network = network.parse().map_err(|_| {
+                BitcoindError::InvalidConfig(format!("invalid {}: {}", ENV_NETWORK, network))
+            })?;
+        }
+        if let Some(url) = env_var(ENV_RPC_URL) {
+            self.rpc.url = Secret::new(url);
+        }
+        if let Some(username) = env_var(ENV_RPC_USER) {
+            self.rpc.username = Secret::new(username);
+        }
+        if let Some(password) = env_var(ENV_RPC_PASSWORD) {
+            self.rpc.password = Secret::new(password);
+        }
+        if let Some(wallet) = env_var(ENV_RPC_WALLET) {
+            self.rpc.wallet = wallet;
+        }
+        Ok(self)
+    }
+
+    /// Returns a [`BitcoindBuilder`] preset from this configuration, to adjust
+    /// settings that have no config field before building.
+    pub fn builder(&self) -> BitcoindBuilder {
+        let builder = match self.timeouts {
+            Some(timeouts) => BitcoindBuilder::new().timeouts(timeouts),
+            None => BitcoindBuilder::new(),
+        };
+        let builder = self
+            .copy_in
+            .iter()
+            .fold(builder, |builder, (host_path, container_path)| {
+                builder.copy_in(host_path, container_path)
+            });
+        builder
+            .container_name(&self.container_name)
+            .image(&self.image_reference())
+            .image_hash(self.hash.as_deref())
+            .image_source(self.image_source.clone())
+            .platform(self.platform.as_deref())
+            .pull_policy(self.pull_policy)
+            .registry_auth(self.registry_auth.clone())
+            .image_tar(self.image_tar.clone())
+            .rpc_config(self.rpc.clone())
+            .flags(self.flags.clone().unwrap_or_default())
+            .envs(self.env.clone())
+            .image_profile(self.image_profile.clone())
+            .entrypoint(self.entrypoint.clone())
+            .cmd_prefix(self.cmd_prefix.clone())
+            .publish(self.publish.clone())
+            .unique_name(self.unique_name)
+            .reuse_existing(self.reuse_existing)
+            .forward_logs(self.forward_logs)
+            .keep_on_drop(self.keep_on_drop)
+            .healthcheck(self.healthcheck)
+            .resource_limits(self.resources)
+            .tmpfs_datadir(self.tmpfs_datadir)
+            .datadir_volume(self.datadir_volume.as_deref())
+            .datadir_path(self.datadir_path.clone())
+            .conf_file(self.conf_file.clone())
+            .bitcoin_conf(self.bitcoin_conf.clone())
+            .log_artifacts(self.log_artifacts.clone())
+            .host_network(self.host_network)
+            .host_ip(self.host_ip)
+            .docker_connection(self.docker.clone())
+    }
+
+    /// Returns the image reference to run, pinned to `hash` when present.
+    pub fn image_reference(&self) -> String {
+        match &self.hash {
+            Some(hash) if hash.starts_with("sha256:") => format!("{}@{}", self.image, hash),
+            Some(hash) => format!("{}@sha256:{}", self.image, hash),
+            None => self.image.clone(),
+        }
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn interpolate_env(input: &str) -> Result<String, BitcoindError> {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+
+    while let Some(start) = rest.find("${") {
+        output.push_str(&rest[..start]);
+        let placeholder = &rest[start + 2..];
+        let end = placeholder.find('}').ok_or_else(|| {
+            BitcoindError::InvalidConfig("unterminated ${ placeholder in config".to_string())
+        })?;
+
+        let (name, default) = match placeholder[..end].split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (&placeholder[..end], None),
+        };
+        let value = match (std::env::var(name), default) {
+            (Ok(value), _) => value,
+            (Err(_), Some(default)) => default.to_string(),
+            (Err(_), None) => {
+                return Err(BitcoindError::InvalidConfig(format!(
+                    "environment variable {} is not set",
+                    name
+                )))
+            }
+        };
+
+        output.push_str(&value);
+        rest = &placeholder[end + 1..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Serializes the tests that set environment variables, which other tests
+/// would otherwise read half-set.
+#[cfg(test)]
+pub(crate) fn env_lock() -> std::sync::MutexGuard<'static, ()> {
+    static ENV: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    ENV.lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_image_reference_is_pinned_to_hash() {
+        let json = r#"{
+            "container_name": "bitcoin-regtest",
+            "image": "bitcoin/bitcoin:29.1",
+            "hash": "0123abcd",
+            "rpc": {
+                "network": "regtest",
+                "url": "http://localhost:18443",
+                "username": "foo",
+                "password": "rpcpassword",
+                "wallet": "mywallet"
+            }
+        }"#;
+
+        let mut config: BitcoindConfig = serde_json::from_str(json).unwrap();
+        assert_eq!(
+            config.image_reference(),
+            "bitcoin/bitcoin:29.1@sha256:0123abcd"
+        );
+
+        config.hash = None;
+        assert_eq!(config.image_reference(), "bitcoin/bitcoin:29.1");
+    }
+
+    #[test]
+    fn test_partial_flags_use_defaults() {
+        let json = r#"{
+            "container_name": "bitcoin-regtest",
+            "image": "bitcoin/bitcoin:29.1",
+            "rpc": {
+                "network": "regtest",
+                "url": "http://localhost:18443",
+                "username": "foo",
+                "password": "rpcpassword",
+                "wallet": "mywallet"
+            },
+            "flags": { "debug": 0, "fallback_fee": 0.0003 }
+        }"#;
+
+        let config: BitcoindConfig = serde_json::from_str(json).unwrap();
+        let flags = config.flags.unwrap();
+        let defaults = BitcoindFlags::default();
+
+        assert_eq!(flags.debug, 0);
+        assert_eq!(flags.fallback_fee, 0.0003);
+        assert_eq!(flags.min_relay_tx_fee, defaults.min_relay_tx_fee);
+        assert_eq!(flags.block_min_tx_fee, defaults.block_min_tx_fee);
+    }
+
+    #[test]
+    fn test_from_file_interpolates_env() {
+        let _env = env_lock();
+        std::env::set_var("BITCOIND_TEST_RPC_USER", "envuser");
+        let path = std::env::temp_dir().join("bitcoind-test-config.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [rpc]
+            network = "regtest"
+            url = "http://localhost:18443"
+            username = "${BITCOIND_TEST_RPC_USER}"
+            password = "${BITCOIND_TEST_RPC_PASSWORD:-fallback}"
+            wallet = "mywallet"
+            "#,
+        )
+        .unwrap();
+
+        let config = BitcoindConfig::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        std::env::remove_var("BITCOIND_TEST_RPC_USER");
+
+        assert_eq!(config.container_name, DEFAULT_CONTAINER_NAME);
+        assert_eq!(config.image, DEFAULT_IMAGE);
+        assert_eq!(config.rpc.username.expose_secret(), "envuser");
+        assert_eq!(config.rpc.password.expose_secret(), "fallback");
+    }
+
+    #[test]
+    fn test_from_file_yaml() {
+        let path = std::env::temp_dir().join("bitcoind-test-config.yaml");
+        std::fs::write(
+            &path,
+            "image: bitcoin/bitcoin:28.0\n\
+             pull_policy: never\n\
+             forward_logs: true\n\
+             timeouts:\n  start: 120\n  poll_interval: 0.5\n\
+             rpc:\n  network: regtest\n  url: http://localhost:18443\n  \
+             username: foo\n  password: bar\n  wallet: mywallet\n",
+        )
+        .unwrap();
+
+        let config = BitcoindConfig::from_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.image, "bitcoin/bitcoin:28.0");
+        assert_eq!(config.pull_policy, PullPolicy::Never);
+        assert!(config.forward_logs);
+        assert_eq!(
+            config.timeouts,
+            Some(Timeouts {
+                start: std::time::Duration::from_secs(120),
+                poll_interval: std::time::Duration::from_millis(500),
+                ..Default::default()
+            })
+        );
+        assert_eq!(config.rpc.username.expose_secret(), "foo");
+    }
+
+    #[test]
+    fn test_env_overrides_win() {
+        let _env = env_lock();
+        std::env::set_var(ENV_RPC_URL, "http://localhost:28443");
+        std::env::set_var(ENV_RPC_USER, "envuser");
+        std::env::set_var(ENV_RPC_PASSWORD, "envpassword");
+        std::env::set_var(ENV_IMAGE, "bitcoin/bitcoin:28.0");
+
+        let config = BitcoindConfig::from_env().unwrap();
+
+        for name in [ENV_RPC_URL, ENV_RPC_USER, ENV_RPC_PASSWORD, ENV_IMAGE] {
+            std::env::remove_var(name);
+        }
+
+        assert_eq!(config.image, "bitcoin/bitcoin:28.0");
+        assert_eq!(config.container_name, DEFAULT_CONTAINER_NAME);
+        assert_eq!(config.rpc.url.expose_secret(), "http://localhost:28443");
+        assert_eq!(config.rpc.username.expose_secret(), "envuser");
+        assert_eq!(config.rpc.network, Network::Regtest);
+    }
+
+    #[test]
+    fn test_env_overrides_file() {
+        let _env = env_lock();
+        let path = std::env::temp_dir().join("bitcoind-test-config-overrides.toml");
+        std::fs::write(
+            &path,
+            r#"
+            image = "bitcoin/bitcoin:27.2"
+            container_name = "bitcoind-from-file"
+
+            [rpc]
+            network = "regtest"
+            url = "http://localhost:18443"
+            username = "fileuser"
+            password = "filepassword"
+            wallet = "mywallet"
+            "#,
+        )
+        .unwrap();
+        std::env::set_var(ENV_IMAGE, "bitcoin/bitcoin:28.0");
+        std::env::set_var(ENV_RPC_USER, "envuser");
+
+        let config =
+            BitcoindConfig::from_file(&path).and_then(|config| config.with_env_overrides());
+        std::fs::remove_file(&path).unwrap();
+        for name in [ENV_IMAGE, ENV_RPC_USER] {
+            std::env::remove_var(name);
+        }
+
+        let config = config.unwrap();
+        assert_eq!(config.image, "bitcoin/bitcoin:28.0");
+        assert_eq!(config.rpc.username.expose_secret(), "envuser");
+        assert_eq!(config.container_name, "bitcoind-from-file");
+        assert_eq!(config.rpc.password.expose_secret(), "filepassword");
+    }
+}
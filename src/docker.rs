@@ -0,0 +1,2248 @@
+use crate::backend::{cli_args, ExecOutput, NodeBackend, NodeSpec, NodeStatus, RpcAddress};
+use crate::bitcoind::{ContainerOptions, Healthcheck};
+use crate::cleanup::{block_on_own_runtime, force_remove, labels, register, unix_time, unregister};
+use crate::conf::BitcoinConf;
+use crate::error::BitcoindError;
+use crate::image::ImageSource;
+use crate::logs::{write_artifact, LogLine, LogOptions, LogStream};
+use crate::ports::{
+    MappedPorts, ZmqEndpoint, ZmqTopic, P2P_CONTAINER_PORT, RPC_CONTAINER_PORT, ZMQ_CONTAINER_PORT,
+};
+use crate::version::NodeVersion;
+use async_trait::async_trait;
+use bitcoin::Network;
+use bollard::container::{
+    Config, CreateContainerOptions, InspectContainerOptions, KillContainerOptions,
+    ListContainersOptions, LogOutput, LogsOptions, RemoveContainerOptions, RestartContainerOptions,
+    StartContainerOptions,
+};
+use bollard::errors::Error;
+use bollard::exec::{CreateExecOptions, StartExecResults};
+use bollard::image::{CreateImageOptions, ImportImageOptions};
+use bollard::models::{
+    ContainerState, ContainerStateStatusEnum, CreateImageInfo, HealthConfig, HealthStatusEnum,
+    HostConfig, PortBinding,
+};
+use bollard::volume::CreateVolumeOptions;
+use bollard::{Docker, API_DEFAULT_VERSION};
+use bytes::BytesMut;
+use futures_util::stream::{BoxStream, StreamExt};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::io::AsyncReadExt;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Seconds to wait for a Docker API request, same as bollard's default.
+const DOCKER_TIMEOUT: u64 = 120;
+/// Docker socket on the remote machine when none is configured.
+const DEFAULT_REMOTE_SOCKET: &str = "/var/run/docker.sock";
+/// Maximum time to wait for `ssh` to open the forwarded socket.
+const SSH_TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+/// Seconds Docker waits for `bitcoind` to shut down before killing it on restart.
+const RESTART_TIMEOUT_SECS: isize = 30;
+/// Where a `bitcoin.conf` supplied with `conf_file` or `bitcoin_conf` is placed.
+pub(crate) const CONF_PATH: &str = "/etc/bitcoin/bitcoin.conf";
+/// Container label holding the fingerprint of the settings it was created from.
+const FINGERPRINT_LABEL: &str = "bitcoind.fingerprint";
+/// Bytes read from an image archive per chunk sent to the daemon.
+const TAR_CHUNK_SIZE: usize = 64 * 1024;
+/// Socket of a rootful Podman service, tried after the rootless one.
+const PODMAN_ROOTFUL_SOCKET: &str = "/run/podman/podman.sock";
+
+/// How to reach the Docker daemon.
+///
+/// In a config file the variant is selected with `type`, e.g.
+/// `{ type = "tcp", address = "tcp://10.0.0.5:2375" }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum DockerConnection {
+    /// Uses `DOCKER_HOST` when set, otherwise the platform default socket.
+    /// An `ssh://` `DOCKER_HOST` is handled like [`DockerConnection::Ssh`].
+    #[default]
+    LocalDefaults,
+    /// Unix socket at the given path, e.g. `/run/user/1000/docker.sock`.
+    Unix { path: PathBuf },
+    /// Plain HTTP to a daemon listening on `tcp://host:port`.
+    Tcp { address: String },
+    /// TLS to a daemon listening on `tcp://host:port`, authenticated with client
+    /// certificates. Requires the `tls` feature.
+    Tls {
+        address: String,
+        key: PathBuf,
+        cert: PathBuf,
+        ca: PathBuf,
+    },
+    /// Windows named pipe, e.g. `//./pipe/docker_engine`.
+    NamedPipe { path: String },
+    /// Podman's Docker-compatible API. Without a socket, the rootless socket at
+    /// `$XDG_RUNTIME_DIR/podman/podman.sock` is used, falling back to the
+    /// rootful `/run/podman/podman.sock`. Unix only.
+    Podman {
+        #[serde(default)]
+        socket: Option<PathBuf>,
+    },
+    /// Remote daemon reached through the system `ssh` client, e.g.
+    /// `ssh://ci@build-server`. The remote socket is forwarded to a local one,
+    /// so key-based authentication must work without prompting. Unix only.
+    ///
+    /// Containers run on the remote machine, so the RPC url returned by `start`
+    /// points at the remote host.
+    Ssh {
+        destination: String,
+        /// Docker socket on the remote machine. Defaults to `/var/run/docker.sock`.
+        #[serde(default)]
+        socket: Option<String>,
+    },
+}
+
+/// When the image is pulled before the container is created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PullPolicy {
+    /// Pulls on every `start`, to pick up moving tags.
+    Always,
+    /// Pulls only when the image is not present locally.
+    #[default]
+    IfNotPresent,
+    /// Never pulls. `start` fails if the image is not present locally.
+    Never,
+}
+
+/// Retries of Docker API calls that fail with transient errors, such as a
+/// dropped socket connection or a 5xx response from the daemon. Applies to
+/// pulling the image and listing, creating and starting containers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts per operation, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    /// Delay before the first retry. Doubles after every failed attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between attempts.
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy that runs every operation once.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Runs `operation`, retrying it while it fails with a transient error.
+    pub(crate) async fn run<T, E, F, Fut>(&self, name: &str, mut operation: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Transient + std::fmt::Display,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(err) if attempt < self.max_attempts && err.is_transient() => {
+                    warn!(
+                        "{} failed (attempt {}/{}), retrying in {:?}: {}",
+                        name, attempt, self.max_attempts, backoff, err
+                    );
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.max_backoff);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Classifies errors that may succeed when the operation is retried.
+pub(crate) trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::DockerResponseServerError { status_code, .. } => *status_code >= 500,
+            Error::RequestTimeoutError
+            | Error::IOError { .. }
+            | Error::HyperResponseError { .. }
+            | Error::HyperLegacyError { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl Transient for BitcoindError {
+    fn is_transient(&self) -> bool {
+        match self {
+            BitcoindError::Docker(source) | BitcoindError::ImagePull { source, .. } => {
+                source.is_transient()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Progress of an image pull, as reported by the daemon.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PullProgress {
+    /// Layer the event refers to. `None` for events about the whole image.
+    pub layer_id: Option<String>,
+    /// Status such as `Downloading`, `Extracting` or `Pull complete`.
+    pub status: String,
+    /// Bytes of the layer processed so far.
+    pub current: Option<u64>,
+    /// Size of the layer in bytes.
+    pub total: Option<u64>,
+}
+
+impl From<CreateImageInfo> for PullProgress {
+    fn from(info: CreateImageInfo) -> Self {
+        let detail = info.progress_detail.unwrap_or_default();
+        Self {
+            layer_id: info.id,
+            status: info.status.unwrap_or_default(),
+            current: detail.current.and_then(|current| current.try_into().ok()),
+            total: detail.total.and_then(|total| total.try_into().ok()),
+        }
+    }
+}
+
+/// Collects the output of `container_name` selected by `options`.
+async fn container_logs(
+    docker: &Docker,
+    container_name: &str,
+    options: &LogOptions,
+) -> Result<String, BitcoindError> {
+    let mut stream = docker.logs(
+        container_name,
+        Some(LogsOptions::<String> {
+            stdout: options.stdout,
+            stderr: options.stderr,
+            since: options.since_secs(),
+            tail: options
+                .tail
+                .map_or_else(|| "all".to_string(), |tail| tail.to_string()),
+            ..Default::default()
+        }),
+    );
+
+    let mut logs = String::new();
+    while let Some(chunk) = stream.next().await {
+        logs.push_str(&chunk?.to_string());
+    }
+    Ok(logs)
+}
+
+impl From<LogOutput> for LogLine {
+    fn from(output: LogOutput) -> Self {
+        let (stream, message) = match output {
+            LogOutput::StdErr { message } => (LogStream::Stderr, message),
+            LogOutput::StdOut { message }
+            | LogOutput::StdIn { message }
+            | LogOutput::Console { message } => (LogStream::Stdout, message),
+        };
+        // With `timestamps` Docker prefixes every line with its RFC 3339 time.
+        LogLine::parse(stream, &String::from_utf8_lossy(&message))
+    }
+}
+
+/// Callback receiving every [`PullProgress`] event, set with
+/// [`crate::BitcoindBuilder::on_pull_progress`].
+#[derive(Clone)]
+pub struct PullProgressCallback(pub(crate) Arc<dyn Fn(&PullProgress) + Send + Sync>);
+
+impl std::fmt::Debug for PullProgressCallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PullProgressCallback")
+    }
+}
+
+impl DockerConnection {
+    /// Creates the Docker client. No request is sent to the daemon yet, but an
+    /// SSH connection opens its tunnel here.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::DockerUnavailable)` if the client cannot be created,
+    ///   e.g. the unix socket does not exist.
+    /// * `Err(BitcoindError::SshTunnel)` if the SSH tunnel cannot be opened.
+    /// * `Err(BitcoindError::InvalidConfig)` if the connection type is not
+    ///   supported on this platform or build.
+    pub(crate) fn connect(&self) -> Result<(Docker, Option<SshTunnel>), BitcoindError> {
+        if let Some((destination, socket)) = self.ssh_destination() {
+            return connect_with_ssh(&destination, socket.as_deref());
+        }
+
+        let docker = match self {
+            DockerConnection::LocalDefaults => Docker::connect_with_local_defaults(),
+            #[cfg(unix)]
+            DockerConnection::Unix { path } => Docker::connect_with_unix(
+                &path.to_string_lossy(),
+                DOCKER_TIMEOUT,
+                API_DEFAULT_VERSION,
+            ),
+            DockerConnection::Tcp { address } => {
+                Docker::connect_with_http(address, DOCKER_TIMEOUT, API_DEFAULT_VERSION)
+            }
+            #[cfg(unix)]
+            DockerConnection::Podman { socket } => {
+                let socket = match socket {
+                    Some(socket) => socket.clone(),
+                    None => podman_socket().ok_or_else(|| {
+                        BitcoindError::InvalidConfig(
+                            "no Podman socket found. Start it with `systemctl --user start podman.socket`"
+                                .to_string(),
+                        )
+                    })?,
+                };
+                Docker::connect_with_unix(
+                    &socket.to_string_lossy(),
+                    DOCKER_TIMEOUT,
+                    API_DEFAULT_VERSION,
+                )
+            }
+            #[cfg(feature = "tls")]
+            DockerConnection::Tls {
+                address,
+                key,
+                cert,
+                ca,
+            } => Docker::connect_with_ssl(
+                address,
+                key,
+                cert,
+                ca,
+                DOCKER_TIMEOUT,
+                API_DEFAULT_VERSION,
+            ),
+            #[cfg(windows)]
+            DockerConnection::NamedPipe { path } => {
+                Docker::connect_with_named_pipe(path, DOCKER_TIMEOUT, API_DEFAULT_VERSION)
+            }
+            #[allow(unreachable_patterns)]
+            unsupported => {
+                return Err(BitcoindError::InvalidConfig(format!(
+                    "unsupported Docker connection on this platform or build: {:?}",
+                    unsupported
+                )))
+            }
+        };
+
+        Ok((docker.map_err(BitcoindError::DockerUnavailable)?, None))
+    }
+
+    /// Whether the daemon is Podman, which needs a few workarounds.
+    pub(crate) fn is_podman(&self) -> bool {
+        matches!(self, DockerConnection::Podman { .. })
+    }
+
+    /// Host the containers run on when it is not the local machine. Published
+    /// ports are reached through it.
+    pub(crate) fn remote_host(&self) -> Option<String> {
+        self.ssh_destination()
+            .map(|(destination, _)| SshDestination::parse(&destination).host)
+    }
+
+    fn ssh_destination(&self) -> Option<(String, Option<String>)> {
+        match self {
+            DockerConnection::Ssh {
+                destination,
+                socket,
+            } => Some((destination.clone(), socket.clone())),
+            DockerConnection::LocalDefaults => std::env::var("DOCKER_HOST")
+                .ok()
+                .filter(|host| host.starts_with("ssh://"))
+                .map(|host| (host, None)),
+            _ => None,
+        }
+    }
+}
+
+/// Runs the node in a Docker (or Podman) container. This is the default backend,
+/// configured through [`crate::BitcoindBuilder`].
+pub struct DockerBackend {
+    pub(crate) docker: Docker,
+    pub(crate) container_name: String,
+    pub(crate) image: String,
+    /// Digest the image must have, as `sha256:<hex>`.
+    pub(crate) image_hash: Option<String>,
+    pub(crate) container: ContainerOptions,
+    /// Host running the containers when the daemon is remote.
+    pub(crate) remote_host: Option<String>,
+    /// Whether the daemon is Podman, which runs containers without `auto_remove`.
+    pub(crate) podman: bool,
+    /// How long `stop` waits for `bitcoind` to exit after `bitcoin-cli stop`
+    /// before removing the container. Zero removes it right away.
+    pub(crate) stop_timeout: Duration,
+    /// Delay between checks that the container stopped.
+    pub(crate) poll_interval: Duration,
+    /// Spec of the last created container, for `bitcoin-cli` credentials.
+    pub(crate) spec: Mutex<Option<NodeSpec>>,
+    /// Whether a container may exist that `stop` hasn't removed yet.
+    pub(crate) created: AtomicBool,
+    /// How to reach the daemon, to remove the container on drop.
+    pub(crate) connection: DockerConnection,
+    /// Task emitting the container output as `tracing` events.
+    pub(crate) log_forwarder: Mutex<Option<JoinHandle<()>>>,
+    /// Keeps the SSH tunnel to a remote daemon open while the backend lives.
+    pub(crate) _ssh_tunnel: Option<SshTunnel>,
+}
+
+impl DockerBackend {
+    /// Returns the name of the container.
+    pub fn container_name(&self) -> &str {
+        &self.container_name
+    }
+
+    /// Returns the image the container runs.
+    pub fn image(&self) -> &str {
+        &self.image
+    }
+
+    /// Returns the Docker client, for operations this crate doesn't wrap.
+    pub fn docker(&self) -> &Docker {
+        &self.docker
+    }
+
+    /// Returns the host ports Docker published for the running container.
+    pub async fn mapped_ports(&self) -> Result<MappedPorts, BitcoindError> {
+        let response = self
+            .docker
+            .inspect_container(&self.container_name, None::<InspectContainerOptions>)
+            .await?;
+
+        let ports = response
+            .network_settings
+            .and_then(|settings| settings.ports)
+            .unwrap_or_default();
+
+        Ok(MappedPorts::from_port_map(&ports))
+    }
+
+    /// Returns the addresses of the enabled ZMQ notifications on the host,
+    /// those of the shared ZMQ port first.
+    pub async fn zmq_endpoints(&self) -> Result<Vec<ZmqEndpoint>, BitcoindError> {
+        let publish = &self.container.publish;
+        let mut ports: Vec<(ZmqTopic, u16, u16)> = Vec::new();
+        if let Some(host_port) = publish.zmq {
+            for topic in [
+                ZmqTopic::RawBlock,
+                ZmqTopic::RawTx,
+                ZmqTopic::HashBlock,
+                ZmqTopic::HashTx,
+            ] {
+                ports.push((topic, ZMQ_CONTAINER_PORT, host_port));
+            }
+        }
+        ports.extend(
+            publish
+                .zmq_topics
+                .iter()
+                .map(|(topic, host_port)| (topic, topic.container_port(), host_port)),
+        );
+        if ports.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mapped = if self.container.ephemeral_ports && !self.container.host_network {
+            Some(self.mapped_ports().await?)
+        } else {
+            None
+        };
+        let host = self.remote_host.as_deref().unwrap_or("localhost");
+        ports
+            .into_iter()
+            .map(|(topic, container_port, host_port)| {
+                let port = match &mapped {
+                    Some(mapped) => mapped.get(container_port).ok_or_else(|| {
+                        BitcoindError::InvalidConfig(format!(
+                            "ZMQ {} port {} was not published",
+                            topic, container_port
+                        ))
+                    })?,
+                    None => host_port,
+                };
+                Ok(ZmqEndpoint {
+                    topic,
+                    address: format!("tcp://{}:{}", host, port),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns what Docker reports about the container.
+    pub async fn inspect(&self) -> Result<ContainerDetails, BitcoindError> {
+        let container = self
+            .docker
+            .inspect_container(&self.container_name, None::<InspectContainerOptions>)
+            .await?;
+
+        let image_id = container.image.clone().unwrap_or_default();
+        let image = self.docker.inspect_image(&image_id).await?;
+        let image_digest = image
+            .repo_digests
+            .and_then(|digests| digests.into_iter().next())
+            .unwrap_or(image_id);
+
+        let state = container.state.clone();
+        let settings = container.network_settings;
+        Ok(ContainerDetails {
+            id: container.id.unwrap_or_default(),
+            image: container
+                .config
+                .and_then(|config| config.image)
+                .unwrap_or_else(|| self.image.clone()),
+            image_digest,
+            mounts: container
+                .mounts
+                .unwrap_or_default()
+                .into_iter()
+                .map(|mount| ContainerMount {
+                    source: mount.name.or(mount.source).unwrap_or_default(),
+                    destination: mount.destination.unwrap_or_default(),
+                    read_only: !mount.rw.unwrap_or(true),
+                })
+                .collect(),
+            ports: MappedPorts::from_port_map(
+                &settings
+                    .as_ref()
+                    .and_then(|settings| settings.ports.clone())
+                    .unwrap_or_default(),
+            ),
+            ip_address: settings.and_then(|settings| {
+                let networks = settings.networks.unwrap_or_default();
+                std::iter::once(settings.ip_address)
+                    .chain(networks.into_values().map(|network| network.ip_address))
+                    .flatten()
+                    .find(|address| !address.is_empty())
+            }),
+            // Docker reports the zero time for containers that never started.
+            started_at: state
+                .as_ref()
+                .and_then(|state| state.started_at.clone())
+                .filter(|started_at| !started_at.starts_with("0001-")),
+            restart_count: container.restart_count.unwrap_or_default(),
+            status: node_status(state),
+        })
+    }
+
+    /// Emits the container output printed from `since` (Unix seconds) on as
+    /// `tracing` events, if enabled, replacing any previous forwarder.
+    fn forward_logs(&self, since: i64) {
+        if !self.container.forward_logs {
+            return;
+        }
+        self.stop_forwarding_logs();
+
+        let container = self.container_name.clone();
+        let mut stream = self.docker.logs(
+            &self.container_name,
+            Some(LogsOptions::<String> {
+                follow: true,
+                stdout: true,
+                stderr: true,
+                since,
+                ..Default::default()
+            }),
+        );
+        let task = tokio::spawn(async move {
+            while let Some(output) = stream.next().await {
+                match output.map(LogLine::from) {
+                    Ok(line) if line.stream == LogStream::Stderr => {
+                        warn!(target: "bitcoind::node", container = %container, "{}", line.message)
+                    }
+                    Ok(line) => {
+                        info!(target: "bitcoind::node", container = %container, "{}", line.message)
+                    }
+                    Err(err) => {
+                        debug!("Stopped forwarding the logs of {}: {}", container, err);
+                        break;
+                    }
+                }
+            }
+        });
+        *self
+            .log_forwarder
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(task);
+    }
+
+    /// Whether the container outlives this backend, so it is neither removed
+    /// on drop nor by [`crate::cleanup::cleanup_all`].
+    fn keeps_container(&self) -> bool {
+        self.container.keep_on_drop || self.container.reuse_existing
+    }
+
+    /// Saves the output of the dropped node to `log_artifacts` and force-removes
+    /// its container, with a new client.
+    async fn remove_dropped(&self, log_artifacts: Option<&Path>) -> Result<(), BitcoindError> {
+        let (docker, _ssh_tunnel) = self.connection.connect()?;
+        if let Some(dir) = log_artifacts {
+            let path = dir.join(format!("{}.log", self.container_name));
+            let saved = container_logs(&docker, &self.container_name, &LogOptions::default())
+                .await
+                .and_then(|logs| write_artifact(dir, &path, &logs));
+            match saved {
+                Ok(()) => info!(
+                    "Saved the logs of {} to {}",
+                    self.container_name,
+                    path.display()
+                ),
+                Err(err) => warn!(
+                    "Could not save the logs of {}: {}",
+                    self.container_name, err
+                ),
+            }
+        }
+
+        info!(
+            "Removing dropped bitcoind container {}",
+            self.container_name
+        );
+        force_remove(&docker, &self.container_name).await?;
+        unregister(&self.container_name, &self.connection);
+        Ok(())
+    }
+
+    fn stop_forwarding_logs(&self) {
+        if let Some(task) = self
+            .log_forwarder
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            task.abort();
+        }
+    }
+
+    async fn remove_container(&self) -> Result<(), Error> {
+        // A container left created by a failed start holds the name as well,
+        // so it is removed whatever its state.
+        info!("Removing bitcoind container");
+        self.force_remove_container().await?;
+        let deadline = Instant::now() + self.stop_timeout;
+        while self.running().await? && Instant::now() < deadline {
+            tokio::time::sleep(self.poll_interval).await;
+            info!("Waiting for bitcoind container to stop");
+        }
+        Ok(())
+    }
+
+    /// Asks `bitcoind` to shut down with `bitcoin-cli stop`, so the datadir is
+    /// flushed, and waits up to the stop timeout for the container to exit.
+    /// Returns whether it exited; otherwise the caller removes it by force.
+    async fn graceful_stop(&self) -> bool {
+        let spec = self
+            .spec
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let Some(spec) = spec.filter(|_| !self.stop_timeout.is_zero()) else {
+            return false;
+        };
+
+        info!("Stopping bitcoind with bitcoin-cli");
+        match self
+            .exec(&self.container.cli_command(&spec, &["stop".to_string()]))
+            .await
+        {
+            Ok(output) if output.exit_code == 0 => {}
+            Ok(output) => {
+                warn!("bitcoin-cli stop failed: {}", output.stderr.trim());
+                return false;
+            }
+            Err(err) => {
+                warn!("bitcoin-cli stop failed: {}", err);
+                return false;
+            }
+        }
+
+        let deadline = Instant::now() + self.stop_timeout;
+        while Instant::now() < deadline {
+            match self.running().await {
+                Ok(true) => tokio::time::sleep(self.poll_interval).await,
+                Ok(false) => return true,
+                Err(_) => return false,
+            }
+        }
+        warn!(
+            "bitcoind did not exit within {:?}, removing the container",
+            self.stop_timeout
+        );
+        false
+    }
+
+    /// Force-removes the container if it exists, e.g. after it was created
+    /// and never started. A container the daemon is already removing, like an
+    /// auto-removed one that just exited, counts as removed.
+    async fn force_remove_container(&self) -> Result<(), Error> {
+        match self
+            .docker
+            .remove_container(
+                &self.container_name,
+                Some(RemoveContainerOptions {
+                    force: true,
+                    ..Default::default()
+                }),
+            )
+            .await
+        {
+            Ok(())
+            | Err(Error::DockerResponseServerError {
+                status_code: 404 | 409,
+                ..
+            }) => Ok(()),
+            Err(error) => Err(error),
+        }
+    }
+
+    async fn running(&self) -> Result<bool, Error> {
+        let containers = self
+            .container
+            .retry
+            .run("Listing containers", || {
+                self.docker
+                    .list_containers(None::<ListContainersOptions<String>>)
+            })
+            .await?;
+        for container in containers {
+            if let Some(names) = container.names {
+                if names.contains(&format!("/{}", self.container_name)) {
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    async fn pull_image(&self) -> Result<(), BitcoindError> {
+        info!("Pulling image: {}", self.image);
+        let (repository, tag) = split_image_reference(&self.image);
+        let options = Some(CreateImageOptions {
+            from_image: repository,
+            tag,
+            platform: self.container.platform.clone().unwrap_or_default(),
+            ..Default::default()
+        });
+
+        let credentials = self
+            .container
+            .registry_auth
+            .as_ref()
+            .map(|auth| auth.credentials(&self.image))
+            .transpose()?;
+
+        let mut stream = self.docker.create_image(options, None, credentials);
+        while let Some(result) = stream.next().await {
+            match result {
+                Ok(info) => {
+                    let progress = PullProgress::from(info);
+                    debug!("Pull progress: {:?}", progress);
+                    if let Some(callback) = &self.container.pull_progress {
+                        (callback.0)(&progress);
+                    }
+                }
+                Err(source) if is_platform_mismatch(&source) => {
+                    return Err(self.platform_unavailable().await);
+                }
+                Err(source) => {
+                    return Err(BitcoindError::ImagePull {
+                        image: self.image.clone(),
+                        source,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the images in a `docker save` archive, streaming it to the daemon.
+    pub async fn load_image_from_tar(&self, path: &Path) -> Result<(), BitcoindError> {
+        info!("Loading image from {}", path.display());
+        let load_error = |message: String| BitcoindError::ImageLoad {
+            path: path.to_path_buf(),
+            message,
+        };
+
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| load_error(e.to_string()))?;
+        let chunks = futures_util::stream::unfold(file, |mut file| async move {
+            let mut chunk = BytesMut::with_capacity(TAR_CHUNK_SIZE);
+            match file.read_buf(&mut chunk).await {
+                Ok(0) => None,
+                Ok(_) => Some((chunk.freeze(), file)),
+                Err(err) => {
+                    // Ending the stream early makes the daemon reject the archive.
+                    warn!("Failed to read image archive: {}", err);
+                    None
+                }
+            }
+        });
+
+        let mut stream =
+            self.docker
+                .import_image_stream(ImportImageOptions { quiet: true }, chunks, None);
+        while let Some(result) = stream.next().await {
+            let info = result.map_err(|e| load_error(e.to_string()))?;
+            if let Some(message) = info.stream {
+                info!("{}", message.trim_end());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the daemon's platform as `os/arch`, e.g. `linux/arm64`.
+    pub async fn daemon_platform(&self) -> Result<String, BitcoindError> {
+        let version = self.docker.version().await?;
+        Ok(format!(
+            "{}/{}",
+            version.os.as_deref().unwrap_or("linux"),
+            version.arch.unwrap_or_default()
+        ))
+    }
+
+    /// Error for an image without a variant for the requested platform.
+    async fn platform_unavailable(&self) -> BitcoindError {
+        let platform = match &self.container.platform {
+            Some(platform) => platform.clone(),
+            None => self
+                .daemon_platform()
+                .await
+                .unwrap_or_else(|_| "the daemon's platform".to_string()),
+        };
+        BitcoindError::PlatformUnavailable {
+            image: self.image.clone(),
+            platform,
+        }
+    }
+
+    /// Warns when the container's image was built for another architecture
+    /// than the daemon's, since it then runs under slow emulation.
+    async fn warn_on_emulation(&self) -> Result<(), BitcoindError> {
+        let container = self
+            .docker
+            .inspect_container(&self.container_name, None::<InspectContainerOptions>)
+            .await?;
+        let image = self
+            .docker
+            .inspect_image(&container.image.unwrap_or_default())
+            .await?;
+        let daemon_platform = self.daemon_platform().await?;
+        if let Some(architecture) = image.architecture {
+            if !daemon_platform.ends_with(&format!("/{}", architecture)) {
+                warn!(
+                    "Image {} is built for {} but the daemon runs on {}; it will run under emulation",
+                    self.image, architecture, daemon_platform
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the image of the created container has the pinned digest.
+    async fn verify_image_hash(&self) -> Result<(), BitcoindError> {
+        let Some(expected) = &self.image_hash else {
+            return Ok(());
+        };
+
+        let container = self
+            .docker
+            .inspect_container(&self.container_name, None::<InspectContainerOptions>)
+            .await?;
+        let image_id = container.image.unwrap_or_default();
+        let image = self.docker.inspect_image(&image_id).await?;
+        let repo_digests = image.repo_digests.unwrap_or_default();
+
+        let digests = repo_digests
+            .iter()
+            .filter_map(|digest| digest.rsplit_once('@').map(|(_, digest)| digest))
+            .collect::<Vec<_>>();
+        if image_id == *expected || digests.contains(&expected.as_str()) {
+            return Ok(());
+        }
+
+        Err(BitcoindError::ImageHashMismatch {
+            expected: expected.clone(),
+            actual: digests
+                .first()
+                .map_or(image_id.clone(), |digest| digest.to_string()),
+        })
+    }
+
+    /// Hash of everything that shapes the container, stored as a label so a
+    /// running container can be reused only when nothing changed.
+    fn fingerprint(&self, spec: &NodeSpec) -> String {
+        let container = &self.container;
+        let mut hasher = StableHasher(Sha256::new());
+        (
+            &self.image,
+            &self.image_hash,
+            &container.platform,
+            container.container_env(),
+            container.effective_entrypoint(),
+            container.container_cmd(spec),
+            &container.image_profile.user,
+            container.published_ports(spec),
+            (
+                container.ephemeral_ports,
+                container.host_network,
+                container.host_ip,
+            ),
+            (
+                container.binds(),
+                container.tmpfs_datadir,
+                // A changed `bitcoin.conf` changes the node as much as its flags.
+                container
+                    .conf_file
+                    .as_ref()
+                    .and_then(|path| std::fs::read(path).ok()),
+                container.bitcoin_conf.as_ref().map(BitcoinConf::render),
+                // Copied files are hashed by content, as the conf file is.
+                container
+                    .copy_in
+                    .iter()
+                    .map(|(host_path, container_path)| (tree_contents(host_path), container_path))
+                    .collect::<Vec<_>>(),
+            ),
+            &container.healthcheck,
+            (
+                container.resources.nano_cpus(),
+                container.resources.memory,
+                container.resources.pids,
+            ),
+        )
+            .hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    /// Whether the container is running and was created from the same settings.
+    async fn is_reusable(&self, spec: &NodeSpec) -> Result<bool, Error> {
+        let container = match self
+            .docker
+            .inspect_container(&self.container_name, None::<InspectContainerOptions>)
+            .await
+        {
+            Ok(container) => container,
+            Err(Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => return Ok(false),
+            Err(error) => return Err(error),
+        };
+
+        let running = container
+            .state
+            .and_then(|state| state.running)
+            .unwrap_or(false);
+        let fingerprint = container
+            .config
+            .and_then(|config| config.labels)
+            .and_then(|mut labels| labels.remove(FINGERPRINT_LABEL));
+        Ok(running && fingerprint == Some(self.fingerprint(spec)))
+    }
+
+    /// Writes `conf` to the created container at [`CONF_PATH`].
+    async fn upload_conf(&self, conf: &BitcoinConf) -> Result<(), BitcoindError> {
+        let path = Path::new(CONF_PATH);
+        let contents = conf.render();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(unix_time().as_secs());
+        let mut archive = tar::Builder::new(Vec::new());
+        let archive = archive
+            .append_data(
+                &mut header,
+                path.strip_prefix("/").unwrap_or(path),
+                contents.as_bytes(),
+            )
+            .and_then(|()| archive.into_inner())
+            .map_err(|source| BitcoindError::Artifact {
+                path: path.to_path_buf(),
+                source,
+            })?;
+        self.upload(archive, "/").await
+    }
+
+    /// Creates the named volume holding the datadir. An existing volume is
+    /// kept as it is, with its chain state.
+    async fn create_datadir_volume(&self, volume: &str) -> Result<(), Error> {
+        info!("Using volume {} for the datadir", volume);
+        self.docker
+            .create_volume(CreateVolumeOptions {
+                name: volume.to_string(),
+                driver: "local".to_string(),
+                driver_opts: HashMap::new(),
+                labels: labels(true),
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn create_container(&self, spec: &NodeSpec) -> Result<(), Error> {
+        info!("Creating bitcoind container");
+
+        let ports = if self.container.host_network {
+            Vec::new()
+        } else {
+            self.container.published_ports(spec)
+        };
+        let host_ip = self.container.bind_ip().to_string();
+        let exposed_ports: HashMap<String, HashMap<(), ()>> = ports
+            .iter()
+            .map(|(container_port, _)| (format!("{}/tcp", container_port), HashMap::new()))
+            .collect();
+        let port_bindings = ports
+            .iter()
+            .map(|(container_port, host_port)| {
+                (
+                    format!("{}/tcp", container_port),
+                    Some(vec![PortBinding {
+                        host_ip: Some(host_ip.clone()),
+                        // An empty host port makes Docker pick a free one.
+                        host_port: Some(if self.container.ephemeral_ports {
+                            String::new()
+                        } else {
+                            host_port.to_string()
+                        }),
+                    }]),
+                )
+            })
+            .collect();
+
+        let binds = self.container.binds();
+
+        let config = Config {
+            image: Some(self.image.clone()),
+            env: Some(self.container.container_env()),
+            exposed_ports: Some(exposed_ports),
+            host_config: Some(HostConfig {
+                // Podman can race removing an auto-removed container with a
+                // forced removal, so its containers are removed by `stop`.
+                auto_remove: Some(!self.podman),
+                port_bindings: Some(port_bindings),
+                network_mode: self.container.host_network.then(|| "host".to_string()),
+                binds: (!binds.is_empty()).then_some(binds),
+                tmpfs: self.container.tmpfs_datadir.map(|size| {
+                    HashMap::from([(self.container.datadir(), format!("size={}", size))])
+                }),
+                nano_cpus: self.container.resources.nano_cpus(),
+                memory: self.container.resources.memory.map(|memory| memory as i64),
+                // Without swap the memory limit is a hard one.
+                memory_swap: self.container.resources.memory.map(|memory| memory as i64),
+                pids_limit: self.container.resources.pids,
+                ..Default::default()
+            }),
+            entrypoint: self.container.effective_entrypoint(),
+            cmd: Some(self.container.container_cmd(spec)),
+            user: self.container.image_profile.user.clone(),
+            working_dir: self.container.working_dir(),
+            healthcheck: self
+                .container
+                .healthcheck
+                .map(|healthcheck| self.container.health_config(&healthcheck, spec)),
+            labels: Some({
+                let mut labels = labels(self.keeps_container());
+                labels.insert(FINGERPRINT_LABEL.to_string(), self.fingerprint(spec));
+                labels
+            }),
+            ..Default::default()
+        };
+        self.docker
+            .create_container::<&str, String>(
+                Some(CreateContainerOptions {
+                    name: self.container_name.as_str(),
+                    platform: self.container.platform.as_deref(),
+                }),
+                config,
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+impl ContainerOptions {
+    /// `(container_port, host_port)` pairs to publish: RPC first, then the
+    /// enabled services and finally any additional ports.
+    pub(crate) fn published_ports(&self, spec: &NodeSpec) -> Vec<(u16, u16)> {
+        let publish = &self.publish;
+        let mut ports = vec![(RPC_CONTAINER_PORT, spec.rpc_port)];
+        ports.extend(publish.p2p.map(|host_port| (P2P_CONTAINER_PORT, host_port)));
+        ports.extend(publish.zmq.map(|host_port| (ZMQ_CONTAINER_PORT, host_port)));
+        ports.extend(
+            publish
+                .zmq_topics
+                .iter()
+                .map(|(topic, host_port)| (topic.container_port(), host_port)),
+        );
+        ports.extend(self.ports.iter().cloned());
+        ports
+    }
+
+    /// `(source, target)` pairs to mount: the volumes followed by the datadir volume.
+    pub(crate) fn mounts(&self) -> Vec<(String, String)> {
+        let mut mounts = self.volumes.clone();
+        mounts.extend(
+            self.datadir_volume
+                .iter()
+                .map(|volume| (volume.clone(), self.datadir())),
+        );
+        mounts.extend(
+            self.datadir_path
+                .iter()
+                .map(|path| (path.display().to_string(), self.datadir())),
+        );
+        mounts
+    }
+
+    /// Whether a volume or host directory is mounted at the datadir, so the
+    /// chain state outlives the container.
+    pub(crate) fn datadir_mounted(&self) -> bool {
+        let datadir = self.datadir();
+        self.mounts()
+            .iter()
+            .any(|(_, target)| target.trim_end_matches('/') == datadir.trim_end_matches('/'))
+    }
+
+    /// Docker bind specifications of the mounts and the `bitcoin.conf`.
+    pub(crate) fn binds(&self) -> Vec<String> {
+        let mut binds = self
+            .mounts()
+            .iter()
+            .map(|(source, target)| format!("{}:{}", source, target))
+            .collect::<Vec<_>>();
+        binds.extend(
+            self.conf_file
+                .iter()
+                .map(|path| format!("{}:{}:ro", path.display(), CONF_PATH)),
+        );
+        binds
+    }
+
+    /// Default container environment with the user-provided variables merged
+    /// in. A user variable with the same name replaces the default.
+    pub(crate) fn container_env(&self) -> Vec<String> {
+        let profile = &self.image_profile;
+        let mut env = profile
+            .datadir_env
+            .iter()
+            .map(|key| (key.clone(), profile.datadir.clone()))
+            .collect::<Vec<_>>();
+        for (key, value) in &self.env {
+            match env.iter_mut().find(|(existing, _)| existing == key) {
+                Some(entry) => entry.1 = value.clone(),
+                None => env.push((key.clone(), value.clone())),
+            }
+        }
+
+        env.into_iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect()
+    }
+
+    /// Datadir of `bitcoind` inside the container.
+    pub(crate) fn datadir(&self) -> String {
+        let profile = &self.image_profile;
+        self.env
+            .iter()
+            .rev()
+            .find(|(key, _)| Some(key) == profile.datadir_env.as_ref())
+            .map_or(profile.datadir.clone(), |(_, value)| value.clone())
+    }
+
+    /// Working directory of the container. Docker creates a missing one, so a
+    /// datadir passed with `-datadir` exists before `bitcoind` looks for it.
+    pub(crate) fn working_dir(&self) -> Option<String> {
+        self.image_profile
+            .datadir_env
+            .is_none()
+            .then(|| self.datadir())
+    }
+
+    /// Entrypoint of the container: the configured one, else the image profile's.
+    pub(crate) fn effective_entrypoint(&self) -> Option<Vec<String>> {
+        self.entrypoint
+            .clone()
+            .or_else(|| self.image_profile.entrypoint.clone())
+    }
+
+    pub(crate) fn container_cmd(&self, spec: &NodeSpec) -> Vec<String> {
+        let mut cmd = if self.cmd_prefix.is_empty() {
+            self.image_profile.cmd_prefix.clone()
+        } else {
+            self.cmd_prefix.clone()
+        };
+        cmd.extend(self.bitcoind_args(spec));
+        cmd
+    }
+
+    pub(crate) fn bitcoind_args(&self, spec: &NodeSpec) -> Vec<String> {
+        let mut args = vec![
+            "-rpcallowip=0.0.0.0/0".to_string(),
+            "-rpcbind=0.0.0.0".to_string(),
+        ];
+        if self.ipv6() {
+            args.push("-rpcallowip=::/0".to_string());
+            args.push("-rpcbind=::".to_string());
+        }
+        if self.image_profile.datadir_env.is_none() {
+            args.push(format!("-datadir={}", self.datadir()));
+        }
+        if self.conf_file.is_some() || self.bitcoin_conf.is_some() {
+            args.push(format!("-conf={}", CONF_PATH));
+        }
+        if self.publish.rest {
+            args.push("-rest=1".to_string());
+        }
+        // With host networking nothing is remapped, so the node listens on
+        // the host ports directly.
+        let zmq_host = if self.ipv6() { "[::]" } else { "0.0.0.0" };
+        let zmq_port = |host_port, container_port| {
+            if self.host_network {
+                host_port
+            } else {
+                container_port
+            }
+        };
+        if let Some(host_port) = self.publish.zmq {
+            let port = zmq_port(host_port, ZMQ_CONTAINER_PORT);
+            for topic in ["rawblock", "rawtx", "hashblock", "hashtx"] {
+                args.push(format!("-zmqpub{}=tcp://{}:{}", topic, zmq_host, port));
+            }
+        }
+        for (topic, host_port) in self.publish.zmq_topics.iter() {
+            let port = zmq_port(host_port, topic.container_port());
+            args.push(format!("-zmqpub{}=tcp://{}:{}", topic, zmq_host, port));
+        }
+        if self.host_network {
+            args.push(format!("-rpcport={}", spec.rpc_port));
+            if let Some(p2p_port) = self.publish.p2p {
+                args.push(format!("-port={}", p2p_port));
+            }
+        } else if spec.network != Network::Regtest {
+            // The container ports are the regtest ones on every network.
+            args.push(format!("-rpcport={}", RPC_CONTAINER_PORT));
+            args.push(format!("-port={}", P2P_CONTAINER_PORT));
+        }
+        spec.command_line(args)
+    }
+
+    /// `bitcoin-cli` running `args` with the network and RPC settings the node runs with.
+    pub(crate) fn cli_command(&self, spec: &NodeSpec, args: &[String]) -> Vec<String> {
+        let mut cmd = vec!["bitcoin-cli".to_string()];
+        cmd.extend(cli_args(self.bitcoind_args(spec)));
+        cmd.extend(args.iter().cloned());
+        cmd
+    }
+
+    /// Command of the healthcheck, in the `CMD` form Docker and Compose expect.
+    pub(crate) fn healthcheck_test(&self, spec: &NodeSpec) -> Vec<String> {
+        let mut test = vec!["CMD".to_string()];
+        test.extend(self.cli_command(spec, &["getblockcount".to_string()]));
+        test
+    }
+
+    fn health_config(&self, healthcheck: &Healthcheck, spec: &NodeSpec) -> HealthConfig {
+        let nanos = |duration: Duration| Some(duration.as_nanos() as i64);
+        HealthConfig {
+            test: Some(self.healthcheck_test(spec)),
+            interval: nanos(healthcheck.interval),
+            timeout: nanos(healthcheck.timeout),
+            start_period: nanos(healthcheck.start_period),
+            retries: Some(healthcheck.retries.into()),
+            ..Default::default()
+        }
+    }
+
+    /// Host address the published ports bind to.
+    pub(crate) fn bind_ip(&self) -> IpAddr {
+        self.host_ip.unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+
+    /// Whether the published ports bind to an IPv6 address, in which case the
+    /// node also listens for IPv6 clients.
+    fn ipv6(&self) -> bool {
+        self.host_ip.is_some_and(|ip| ip.is_ipv6())
+    }
+}
+
+#[async_trait]
+impl NodeBackend for DockerBackend {
+    fn name(&self) -> &str {
+        &self.container_name
+    }
+
+    async fn create(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        info!("Checking if Docker daemon is active");
+        self.docker
+            .ping()
+            .await
+            .map_err(BitcoindError::DockerUnavailable)?;
+        *self.spec.lock().unwrap_or_else(PoisonError::into_inner) = Some(spec.clone());
+        self.created.store(true, Ordering::SeqCst);
+        if !self.keeps_container() {
+            register(&self.container_name, &self.connection);
+        }
+
+        if self.container.reuse_existing && self.is_reusable(spec).await? {
+            // Starting a running container is a no-op, so `start` needs no change.
+            info!("Reusing running container {}", self.container_name);
+            return Ok(());
+        }
+
+        self.remove_container().await?;
+        if let Some(volume) = &self.container.datadir_volume {
+            self.create_datadir_volume(volume).await?;
+        }
+        // A remote daemon's host paths can't be created from here.
+        if let Some(path) = self
+            .container
+            .datadir_path
+            .as_ref()
+            .filter(|_| self.remote_host.is_none())
+        {
+            tokio::fs::create_dir_all(path)
+                .await
+                .map_err(|source| BitcoindError::Artifact {
+                    path: path.clone(),
+                    source,
+                })?;
+        }
+
+        let retry = &self.container.retry;
+        match &self.container.image_source {
+            ImageSource::Build {
+                dockerfile,
+                context,
+                build_args,
+            } => self.build_image(dockerfile, context, build_args).await?,
+            ImageSource::Registry if self.container.pull_policy == PullPolicy::Always => {
+                retry.run("Pulling the image", || self.pull_image()).await?
+            }
+            ImageSource::Registry => {}
+        }
+
+        let err = retry
+            .run("Creating the container", || self.create_container(spec))
+            .await;
+        if let Err(err) = err {
+            if is_platform_mismatch(&err) {
+                return Err(self.platform_unavailable().await);
+            }
+            //FIX: For some reason checking the list of images is not working, so I handle the error here and retry.
+            if !err.to_string().contains("No such image") {
+                return Err(err.into());
+            }
+            if let Some(image_tar) = &self.container.image_tar {
+                self.load_image_from_tar(image_tar).await?;
+            } else if self.container.pull_policy == PullPolicy::Never {
+                return Err(BitcoindError::ImageNotPresent(self.image.clone()));
+            } else {
+                retry.run("Pulling the image", || self.pull_image()).await?;
+            }
+            retry
+                .run("Creating the container", || self.create_container(spec))
+                .await?;
+        }
+
+        if let Err(err) = self.verify_image_hash().await {
+            self.force_remove_container().await?;
+            return Err(err);
+        }
+        self.warn_on_emulation().await?;
+        if let Some(conf) = &self.container.bitcoin_conf {
+            self.upload_conf(conf).await?;
+        }
+        for (host_path, container_path) in &self.container.copy_in {
+            self.copy_in(host_path, container_path).await?;
+        }
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<(), BitcoindError> {
+        info!("Starting bitcoind container");
+        self.container
+            .retry
+            .run("Starting the container", || {
+                self.docker
+                    .start_container(&self.container_name, None::<StartContainerOptions<String>>)
+            })
+            .await?;
+        self.forward_logs(0);
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<(), BitcoindError> {
+        self.stop_forwarding_logs();
+        if self.running().await? {
+            self.graceful_stop().await;
+        }
+        self.remove_container().await?;
+        self.created.store(false, Ordering::SeqCst);
+        unregister(&self.container_name, &self.connection);
+        Ok(())
+    }
+
+    async fn restart(&self, _spec: &NodeSpec) -> Result<(), BitcoindError> {
+        // The daemon keeps auto-removed containers while restarting them.
+        info!("Restarting bitcoind container");
+        let restarted_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as i64;
+        self.docker
+            .restart_container(
+                &self.container_name,
+                Some(RestartContainerOptions {
+                    t: RESTART_TIMEOUT_SECS,
+                }),
+            )
+            .await?;
+        // The old forwarder ended with the old process; skip its output.
+        self.forward_logs(restarted_at);
+        Ok(())
+    }
+
+    async fn kill(&self) -> Result<(), BitcoindError> {
+        self.docker
+            .kill_container(
+                &self.container_name,
+                Some(KillContainerOptions { signal: "SIGKILL" }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn pause(&self) -> Result<(), BitcoindError> {
+        self.docker.pause_container(&self.container_name).await?;
+        Ok(())
+    }
+
+    async fn unpause(&self) -> Result<(), BitcoindError> {
+        self.docker.unpause_container(&self.container_name).await?;
+        Ok(())
+    }
+
+    async fn recreate(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        if !self.container.datadir_mounted() {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "keeping chain data requires a volume mounted at {}",
+                self.container.datadir()
+            )));
+        }
+
+        self.create(spec).await?;
+        self.start().await
+    }
+
+    async fn is_running(&self) -> Result<bool, BitcoindError> {
+        Ok(self.running().await?)
+    }
+
+    async fn status(&self) -> Result<NodeStatus, BitcoindError> {
+        match self
+            .docker
+            .inspect_container(&self.container_name, None::<InspectContainerOptions>)
+            .await
+        {
+            Ok(container) => Ok(node_status(container.state)),
+            Err(Error::DockerResponseServerError {
+                status_code: 404, ..
+            }) => Ok(NodeStatus::NotCreated),
+            Err(err @ Error::DockerResponseServerError { .. }) => Err(err.into()),
+            Err(err) => {
+                debug!("Docker daemon unreachable: {}", err);
+                Ok(NodeStatus::Unreachable)
+            }
+        }
+    }
+
+    async fn rpc_address(&self, spec: &NodeSpec) -> Result<RpcAddress, BitcoindError> {
+        let port = if self.container.ephemeral_ports {
+            self.mapped_ports().await?.rpc().ok_or_else(|| {
+                BitcoindError::ReadinessFailed("RPC port was not published".to_string())
+            })?
+        } else {
+            spec.rpc_port
+        };
+
+        Ok(RpcAddress {
+            host: self.remote_host.clone(),
+            port,
+        })
+    }
+
+    fn cli_command(&self, spec: &NodeSpec, args: &[String]) -> Result<Vec<String>, BitcoindError> {
+        Ok(self.container.cli_command(spec, args))
+    }
+
+    fn datadir(&self) -> Option<String> {
+        Some(self.container.datadir())
+    }
+
+    fn expected_version(&self) -> Option<NodeVersion> {
+        NodeVersion::from_image(&self.image)
+    }
+
+    fn remove_on_drop(&self, log_artifacts: Option<&Path>) {
+        self.stop_forwarding_logs();
+        if !self.created.load(Ordering::SeqCst) || self.keeps_container() {
+            return;
+        }
+
+        let failure = match block_on_own_runtime(self.remove_dropped(log_artifacts)) {
+            Ok(Ok(())) => return,
+            Ok(Err(err)) => err.to_string(),
+            Err(err) => err.to_string(),
+        };
+        warn!(
+            "Could not remove bitcoind container {} on drop: {}",
+            self.container_name, failure
+        );
+    }
+
+    async fn exec(&self, cmd: &[String]) -> Result<ExecOutput, BitcoindError> {
+        let exec = self
+            .docker
+            .create_exec(
+                &self.container_name,
+                CreateExecOptions {
+                    cmd: Some(cmd.to_vec()),
+                    attach_stdout: Some(true),
+                    attach_stderr: Some(true),
+                    ..Default::default()
+                },
+            )
+            .await?;
+
+        let mut result = ExecOutput::default();
+        if let StartExecResults::Attached { mut output, .. } =
+            self.docker.start_exec(&exec.id, None).await?
+        {
+            while let Some(chunk) = output.next().await {
+                match chunk? {
+                    LogOutput::StdErr { message } => {
+                        result.stderr.push_str(&String::from_utf8_lossy(&message))
+                    }
+                    LogOutput::StdOut { message } | LogOutput::Console { message } => {
+                        result.stdout.push_str(&String::from_utf8_lossy(&message))
+                    }
+                    LogOutput::StdIn { .. } => {}
+                }
+            }
+        }
+
+        result.exit_code = self
+            .docker
+            .inspect_exec(&exec.id)
+            .await?
+            .exit_code
+            .unwrap_or_default();
+        Ok(result)
+    }
+
+    fn follow_logs(&self) -> BoxStream<'_, Result<LogLine, BitcoindError>> {
+        self.docker
+            .logs(
+                &self.container_name,
+                Some(LogsOptions::<String> {
+                    follow: true,
+                    stdout: true,
+                    stderr: true,
+                    timestamps: true,
+                    tail: "all".to_string(),
+                    ..Default::default()
+                }),
+            )
+            .map(|output| Ok(LogLine::from(output?)))
+            .boxed()
+    }
+
+    async fn logs(&self, options: &LogOptions) -> Result<String, BitcoindError> {
+        container_logs(&self.docker, &self.container_name, options).await
+    }
+}
+
+/// What Docker reports about the node's container, returned by
+/// [`DockerBackend::inspect`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerDetails {
+    pub id: String,
+    /// Image reference the container was created from.
+    pub image: String,
+    /// Digest of the image actually running, e.g. `bitcoin/bitcoin@sha256:...`.
+    /// The image id for images without a registry digest, e.g. built ones.
+    pub image_digest: String,
+    pub mounts: Vec<ContainerMount>,
+    pub ports: MappedPorts,
+    /// Address of the container on its Docker network, which other
+    /// containers reach it at. `None` with host networking.
+    pub ip_address: Option<String>,
+    /// When the container last started, in RFC 3339. `None` if it never started.
+    pub started_at: Option<String>,
+    pub restart_count: i64,
+    pub status: NodeStatus,
+}
+
+/// A volume or bind mount of the container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerMount {
+    /// Volume name, or host path of a bind mount.
+    pub source: String,
+    /// Path inside the container.
+    pub destination: String,
+    pub read_only: bool,
+}
+
+/// Maps the state of an inspected container to a [`NodeStatus`].
+fn node_status(state: Option<ContainerState>) -> NodeStatus {
+    let state = state.unwrap_or_default();
+    match state.status {
+        Some(ContainerStateStatusEnum::CREATED) => NodeStatus::Created,
+        Some(ContainerStateStatusEnum::RUNNING | ContainerStateStatusEnum::RESTARTING) => {
+            match state.health.and_then(|health| health.status) {
+                Some(HealthStatusEnum::STARTING) => NodeStatus::Starting,
+                Some(HealthStatusEnum::UNHEALTHY) => NodeStatus::Unhealthy,
+                _ => NodeStatus::Running,
+            }
+        }
+        Some(ContainerStateStatusEnum::PAUSED) => NodeStatus::Paused,
+        Some(
+            ContainerStateStatusEnum::EXITED
+            | ContainerStateStatusEnum::DEAD
+            | ContainerStateStatusEnum::REMOVING,
+        ) => NodeStatus::Exited(state.exit_code.unwrap_or_default()),
+        Some(ContainerStateStatusEnum::EMPTY) | None => NodeStatus::NotCreated,
+    }
+}
+
+/// Whether the daemon rejected the image because it has no variant for the
+/// requested platform, when pulling or when creating the container.
+fn is_platform_mismatch(error: &Error) -> bool {
+    let message = match error {
+        Error::DockerStreamError { error } => error,
+        Error::DockerResponseServerError { message, .. } => message,
+        _ => return false,
+    };
+    message.contains("no matching manifest for")
+        || message.contains("does not match the specified platform")
+}
+
+/// Splits an image reference into the repository and the tag or digest to
+/// pull, defaulting to `latest` like the Docker CLI.
+pub(crate) fn split_image_reference(image: &str) -> (String, String) {
+    if let Some((repository, digest)) = image.split_once('@') {
+        // A tag next to the digest is ignored, the digest wins.
+        let repository = match repository.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => name,
+            _ => repository,
+        };
+        return (repository.to_string(), digest.to_string());
+    }
+    match image.rsplit_once(':') {
+        Some((repository, tag)) if !tag.contains('/') => (repository.to_string(), tag.to_string()),
+        _ => (image.to_string(), "latest".to_string()),
+    }
+}
+
+/// Hasher feeding into SHA-256, so fingerprints don't change with the Rust
+/// release the way `DefaultHasher` output may.
+struct StableHasher(Sha256);
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finish(&self) -> u64 {
+        let digest = self.0.clone().finalize();
+        u64::from_be_bytes(
+            digest[..8]
+                .try_into()
+                .expect("SHA-256 digests are 32 bytes"),
+        )
+    }
+}
+
+/// Files under `path`, or `path` itself, with their contents, in a stable
+/// order. Unreadable entries are skipped.
+fn tree_contents(path: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .collect::<Vec<_>>();
+        entries.sort();
+        entries
+            .iter()
+            .flat_map(|entry| tree_contents(entry))
+            .collect()
+    } else {
+        std::fs::read(path)
+            .map(|contents| vec![(path.to_path_buf(), contents)])
+            .unwrap_or_default()
+    }
+}
+
+/// Normalizes an image digest to `sha256:<lowercase hex>`, accepting it with
+/// or without the algorithm prefix. Returns `None` if it is not a SHA-256 digest.
+pub(crate) fn normalize_digest(hash: &str) -> Option<String> {
+    let hex = hash.strip_prefix("sha256:").unwrap_or(hash);
+    (hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .then(|| format!("sha256:{}", hex.to_ascii_lowercase()))
+}
+
+/// Finds the Podman socket, preferring the rootless one.
+fn podman_socket() -> Option<PathBuf> {
+    let rootless = std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("podman").join("podman.sock"));
+    rootless
+        .into_iter()
+        .chain(std::iter::once(PathBuf::from(PODMAN_ROOTFUL_SOCKET)))
+        .find(|socket| socket.exists())
+}
+
+/// `ssh` process forwarding the remote Docker socket to a local one. The
+/// process is killed when the tunnel is dropped.
+#[derive(Debug)]
+pub(crate) struct SshTunnel {
+    child: Child,
+    socket: PathBuf,
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SshDestination {
+    /// `[user@]host` as passed to `ssh`.
+    target: String,
+    host: String,
+    port: Option<u16>,
+}
+
+impl SshDestination {
+    /// Parses `ssh://[user@]host[:port]` or `[user@]host`.
+    fn parse(destination: &str) -> Self {
+        let destination = destination
+            .trim_start_matches("ssh://")
+            .trim_end_matches('/');
+        let (user, host_port) = match destination.rsplit_once('@') {
+            Some((user, host_port)) => (Some(user), host_port),
+            None => (None, destination),
+        };
+
+        let (host, port) = match host_port.strip_prefix('[') {
+            Some(rest) => match rest.split_once(']') {
+                Some((host, port)) => (host, port.strip_prefix(':')),
+                None => (rest, None),
+            },
+            None => match host_port.rsplit_once(':') {
+                Some((host, port)) if !host.contains(':') => (host, Some(port)),
+                _ => (host_port, None),
+            },
+        };
+
+        Self {
+            target: match user {
+                Some(user) => format!("{}@{}", user, host),
+                None => host.to_string(),
+            },
+            host: host.to_string(),
+            port: port.and_then(|port| port.parse().ok()),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn connect_with_ssh(
+    destination: &str,
+    remote_socket: Option<&str>,
+) -> Result<(Docker, Option<SshTunnel>), BitcoindError> {
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Instant;
+
+    static TUNNELS: AtomicUsize = AtomicUsize::new(0);
+
+    let destination = SshDestination::parse(destination);
+    let socket = std::env::temp_dir().join(format!(
+        "bitcoind-ssh-{}-{}.sock",
+        std::process::id(),
+        TUNNELS.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_file(&socket);
+
+    let mut command = Command::new("ssh");
+    command
+        .args([
+            "-nNT",
+            "-o",
+            "ExitOnForwardFailure=yes",
+            "-o",
+            "BatchMode=yes",
+        ])
+        .arg("-L")
+        .arg(format!(
+            "{}:{}",
+            socket.display(),
+            remote_socket.unwrap_or(DEFAULT_REMOTE_SOCKET)
+        ));
+    if let Some(port) = destination.port {
+        command.arg("-p").arg(port.to_string());
+    }
+    command
+        .arg(&destination.target)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+
+    let child = command
+        .spawn()
+        .map_err(|e| BitcoindError::SshTunnel(format!("failed to run ssh: {}", e)))?;
+    let mut tunnel = SshTunnel { child, socket };
+
+    let deadline = Instant::now() + SSH_TUNNEL_TIMEOUT;
+    while !tunnel.socket.exists() {
+        if let Ok(Some(status)) = tunnel.child.try_wait() {
+            return Err(BitcoindError::SshTunnel(format!(
+                "ssh to {} exited with {}",
+                destination.target, status
+            )));
+        }
+        if Instant::now() >= deadline {
+            return Err(BitcoindError::SshTunnel(format!(
+                "timed out connecting to {}",
+                destination.target
+            )));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let docker = Docker::connect_with_unix(
+        &tunnel.socket.to_string_lossy(),
+        DOCKER_TIMEOUT,
+        API_DEFAULT_VERSION,
+    )
+    .map_err(BitcoindError::DockerUnavailable)?;
+
+    Ok((docker, Some(tunnel)))
+}
+
+#[cfg(not(unix))]
+fn connect_with_ssh(
+    _destination: &str,
+    _remote_socket: Option<&str>,
+) -> Result<(Docker, Option<SshTunnel>), BitcoindError> {
+    Err(BitcoindError::InvalidConfig(
+        "Docker over SSH is only supported on Unix".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::Bitcoind;
+    use bitcoin::Network;
+    use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+    use bollard::models::Health;
+    use redact::Secret;
+
+    #[test]
+    fn test_docker_connection_from_config() {
+        let connection: DockerConnection =
+            toml::from_str("type = \"tcp\"\naddress = \"tcp://10.0.0.5:2375\"").unwrap();
+        assert_eq!(
+            connection,
+            DockerConnection::Tcp {
+                address: "tcp://10.0.0.5:2375".to_string()
+            }
+        );
+
+        let connection: DockerConnection =
+            toml::from_str("type = \"unix\"\npath = \"/run/user/1000/docker.sock\"").unwrap();
+        assert!(matches!(connection, DockerConnection::Unix { .. }));
+    }
+
+    #[test]
+    fn test_connect_does_not_contact_daemon() {
+        let connection = DockerConnection::Tcp {
+            address: "tcp://127.0.0.1:1".to_string(),
+        };
+        assert!(connection.connect().is_ok());
+    }
+
+    #[test]
+    fn test_podman_socket_discovery() {
+        let _env = crate::config::env_lock();
+        let runtime_dir = std::env::temp_dir().join("bitcoind-test-xdg-runtime");
+        std::fs::create_dir_all(runtime_dir.join("podman")).unwrap();
+        std::fs::write(runtime_dir.join("podman").join("podman.sock"), "").unwrap();
+        let previous = std::env::var_os("XDG_RUNTIME_DIR");
+        std::env::set_var("XDG_RUNTIME_DIR", &runtime_dir);
+
+        let socket = podman_socket();
+        match previous {
+            Some(previous) => std::env::set_var("XDG_RUNTIME_DIR", previous),
+            None => std::env::remove_var("XDG_RUNTIME_DIR"),
+        }
+        std::fs::remove_dir_all(&runtime_dir).unwrap();
+
+        assert_eq!(socket, Some(runtime_dir.join("podman").join("podman.sock")));
+        assert!(DockerConnection::Podman { socket: None }.is_podman());
+    }
+
+    #[test]
+    fn test_split_image_reference() {
+        let split = |image: &str| {
+            let (repository, tag) = split_image_reference(image);
+            format!("{} {}", repository, tag)
+        };
+        assert_eq!(split("bitcoin/bitcoin:29.1"), "bitcoin/bitcoin 29.1");
+        assert_eq!(split("bitcoin/bitcoin"), "bitcoin/bitcoin latest");
+        assert_eq!(
+            split("localhost:5000/bitcoin"),
+            "localhost:5000/bitcoin latest"
+        );
+        assert_eq!(
+            split("localhost:5000/bitcoin:29.1@sha256:ab"),
+            "localhost:5000/bitcoin sha256:ab"
+        );
+    }
+
+    #[test]
+    fn test_fingerprint_tracks_settings() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+        let fingerprint = |builder: crate::BitcoindBuilder| -> Result<String, BitcoindError> {
+            let bitcoind = builder.rpc_config(rpc_config.clone()).build()?;
+            Ok(bitcoind.backend().fingerprint(&bitcoind.node_spec()))
+        };
+
+        let base = fingerprint(Bitcoind::builder().reuse_existing(true))?;
+        assert_eq!(base, fingerprint(Bitcoind::builder().reuse_existing(true))?);
+        assert_ne!(
+            base,
+            fingerprint(Bitcoind::builder().arg("-acceptnonstdtxn=1"))?
+        );
+        assert_ne!(
+            base,
+            fingerprint(Bitcoind::builder().image("bitcoin/bitcoin:28.0"))?
+        );
+        assert_ne!(base, fingerprint(Bitcoind::builder().env("UID", "1000"))?);
+
+        // Copied files count by content, not just by path.
+        let dir = std::env::temp_dir().join(format!("bitcoind-fingerprint-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("wallet.dat"), "a").unwrap();
+        let copying = || Bitcoind::builder().copy_in(&dir, "/data/wallets");
+        let before = fingerprint(copying())?;
+        assert_eq!(before, fingerprint(copying())?);
+        std::fs::write(dir.join("wallet.dat"), "b").unwrap();
+        let after = fingerprint(copying())?;
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_ne!(before, after);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cli_command() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .host_network(true)
+            .arg("-rpcport=28443")
+            .build()?;
+
+        assert_eq!(
+            bitcoind
+                .backend()
+                .container
+                .cli_command(&bitcoind.node_spec(), &["stop".to_string()]),
+            vec![
+                "bitcoin-cli",
+                "-regtest=1",
+                "-rpcuser=foo",
+                "-rpcpassword=rpcpassword",
+                "-rpcport=18443",
+                "-rpcport=28443",
+                "stop"
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_health_config() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+        let bitcoind = Bitcoind::builder()
+            .rpc_config(rpc_config)
+            .healthcheck(Some(Healthcheck {
+                interval: Duration::from_secs(2),
+                ..Default::default()
+            }))
+            .build()?;
+        let container = &bitcoind.backend().container;
+
+        let config =
+            container.health_config(&container.healthcheck.unwrap(), &bitcoind.node_spec());
+        let test = config.test.unwrap();
+        assert_eq!(test[..2], ["CMD", "bitcoin-cli"]);
+        assert_eq!(test.last().unwrap(), "getblockcount");
+        assert_eq!(config.interval, Some(2_000_000_000));
+        assert_eq!(config.start_period, Some(30_000_000_000));
+        assert_eq!(config.retries, Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_node_status_from_container_state() {
+        let state = |status, exit_code| {
+            Some(ContainerState {
+                status: Some(status),
+                exit_code: Some(exit_code),
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            node_status(state(ContainerStateStatusEnum::RUNNING, 0)),
+            NodeStatus::Running
+        );
+        assert_eq!(
+            node_status(state(ContainerStateStatusEnum::PAUSED, 0)),
+            NodeStatus::Paused
+        );
+        assert_eq!(
+            node_status(state(ContainerStateStatusEnum::EXITED, 137)),
+            NodeStatus::Exited(137)
+        );
+        assert_eq!(
+            node_status(state(ContainerStateStatusEnum::CREATED, 0)),
+            NodeStatus::Created
+        );
+        assert_eq!(node_status(None), NodeStatus::NotCreated);
+
+        let health = |status| {
+            Some(ContainerState {
+                status: Some(ContainerStateStatusEnum::RUNNING),
+                health: Some(Health {
+                    status: Some(status),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })
+        };
+        assert_eq!(
+            node_status(health(HealthStatusEnum::STARTING)),
+            NodeStatus::Starting
+        );
+        assert_eq!(
+            node_status(health(HealthStatusEnum::HEALTHY)),
+            NodeStatus::Running
+        );
+        assert_eq!(
+            node_status(health(HealthStatusEnum::UNHEALTHY)),
+            NodeStatus::Unhealthy
+        );
+    }
+
+    #[test]
+    fn test_log_line_from_output() {
+        let line = LogLine::from(LogOutput::StdOut {
+            message: "2025-01-01T12:00:00.123456789Z 2025-01-01T12:00:00Z UpdateTip: new best\n"
+                .into(),
+        });
+        assert_eq!(
+            line,
+            LogLine {
+                timestamp: Some("2025-01-01T12:00:00.123456789Z".to_string()),
+                stream: LogStream::Stdout,
+                message: "2025-01-01T12:00:00Z UpdateTip: new best".to_string(),
+            }
+        );
+
+        let line = LogLine::from(LogOutput::StdErr {
+            message: "Error: Cannot obtain a lock\n".into(),
+        });
+        assert_eq!(line.timestamp, None);
+        assert_eq!(line.stream, LogStream::Stderr);
+        assert_eq!(line.message, "Error: Cannot obtain a lock");
+    }
+
+    #[test]
+    fn test_platform_mismatch_errors() {
+        assert!(is_platform_mismatch(&Error::DockerStreamError {
+            error: "no matching manifest for linux/arm64/v8 in the manifest list entries"
+                .to_string(),
+        }));
+        assert!(is_platform_mismatch(&Error::DockerResponseServerError {
+            status_code: 500,
+            message: "image with reference bitcoin/bitcoin:29.1 was found but does not match the specified platform: wanted linux/arm64, actual: linux/amd64".to_string(),
+        }));
+        assert!(!is_platform_mismatch(&Error::DockerResponseServerError {
+            status_code: 404,
+            message: "No such image: bitcoin/bitcoin:29.1".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_normalize_digest() {
+        let hex = "AB".repeat(32);
+        let expected = Some(format!("sha256:{}", hex.to_lowercase()));
+        assert_eq!(normalize_digest(&hex), expected);
+        assert_eq!(normalize_digest(&format!("sha256:{}", hex)), expected);
+        assert_eq!(normalize_digest("sha256:0123abcd"), None);
+    }
+
+    #[test]
+    fn test_pull_progress_from_create_image_info() {
+        let progress = PullProgress::from(CreateImageInfo {
+            id: Some("a1b2c3".to_string()),
+            status: Some("Downloading".to_string()),
+            progress_detail: Some(bollard::models::ProgressDetail {
+                current: Some(1024),
+                total: Some(4096),
+            }),
+            ..Default::default()
+        });
+        assert_eq!(
+            progress,
+            PullProgress {
+                layer_id: Some("a1b2c3".to_string()),
+                status: "Downloading".to_string(),
+                current: Some(1024),
+                total: Some(4096),
+            }
+        );
+
+        let progress = PullProgress::from(CreateImageInfo {
+            status: Some("Pulling from bitcoin/bitcoin".to_string()),
+            progress_detail: Some(bollard::models::ProgressDetail {
+                current: Some(-1),
+                total: None,
+            }),
+            ..Default::default()
+        });
+        assert_eq!(progress.layer_id, None);
+        assert_eq!(progress.current, None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_policy_retries_transient_errors() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        };
+        let server_error = |status_code| Error::DockerResponseServerError {
+            status_code,
+            message: "error".to_string(),
+        };
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result = policy
+            .run("Test", || async {
+                match attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) {
+                    0 => Err(server_error(503)),
+                    _ => Ok(()),
+                }
+            })
+            .await;
+        assert!(result.is_ok());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result: Result<(), Error> = policy
+            .run("Test", || async {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Err(server_error(500))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let result: Result<(), Error> = policy
+            .run("Test", || async {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                Err(server_error(404))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_parse_ssh_destination() {
+        assert_eq!(
+            SshDestination::parse("ssh://ci@build-server:2222"),
+            SshDestination {
+                target: "ci@build-server".to_string(),
+                host: "build-server".to_string(),
+                port: Some(2222),
+            }
+        );
+        assert_eq!(
+            SshDestination::parse("build-server"),
+            SshDestination {
+                target: "build-server".to_string(),
+                host: "build-server".to_string(),
+                port: None,
+            }
+        );
+        assert_eq!(
+            SshDestination::parse("ssh://ci@[2001:db8::1]:22").host,
+            "2001:db8::1"
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Downloads and caches official Bitcoin Core releases for [`NativeBackend`].
+//! Enabled with the `download` feature.
+
+use crate::error::BitcoindError;
+use crate::native::NativeBackend;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// Environment variable overriding the cache directory for downloaded releases.
+pub const ENV_CACHE_DIR: &str = "BITCOIND_CACHE_DIR";
+
+const RELEASE_BASE_URL: &str = "https://bitcoincore.org/bin";
+
+/// An official Bitcoin Core release, pinned to the checksum of its archive.
+///
+/// The checksum is the SHA-256 listed for the current platform's
+/// `bitcoin-<version>-<target>.tar.gz` in the release's `SHA256SUMS` file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BitcoinCoreRelease {
+    /// Release version, e.g. `29.1`.
+    pub version: String,
+    /// Hex SHA-256 of the archive for this platform.
+    pub sha256: String,
+    /// Directory releases are cached in. Defaults to `$BITCOIND_CACHE_DIR`,
+    /// then `$XDG_CACHE_HOME/bitcoind`, then `~/.cache/bitcoind`.
+    #[serde(default)]
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl BitcoinCoreRelease {
+    pub fn new(version: &str, sha256: &str) -> Self {
+        Self {
+            version: version.to_string(),
+            sha256: sha256.to_lowercase(),
+            cache_dir: None,
+        }
+    }
+
+    /// Archive name for the current platform.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Download)` on platforms without a `.tar.gz` release.
+    pub fn archive_name(&self) -> Result<String, BitcoindError> {
+        let target = match (std::env::consts::OS, std::env::consts::ARCH) {
+            ("linux", "x86_64") => "x86_64-linux-gnu",
+            ("linux", "aarch64") => "aarch64-linux-gnu",
+            ("linux", "arm") => "arm-linux-gnueabihf",
+            ("linux", "riscv64") => "riscv64-linux-gnu",
+            ("macos", "x86_64") => "x86_64-apple-darwin",
+            ("macos", "aarch64") => "arm64-apple-darwin",
+            (os, arch) => {
+                return Err(BitcoindError::Download(format!(
+                    "no Bitcoin Core release archive for {}-{}",
+                    arch, os
+                )))
+            }
+        };
+        Ok(format!("bitcoin-{}-{}.tar.gz", self.version, target))
+    }
+
+    /// URL of the archive for the current platform.
+    pub fn url(&self) -> Result<String, BitcoindError> {
+        Ok(format!(
+            "{}/bitcoin-core-{}/{}",
+            RELEASE_BASE_URL,
+            self.version,
+            self.archive_name()?
+        ))
+    }
+
+    /// Downloads, verifies and unpacks the release unless it is already cached,
+    /// and returns the path of its `bitcoind` binary.
+    ///
+    /// Releases are cached by the checksum of their archive, so a changed
+    /// `sha256` is downloaded and verified again rather than served from the
+    /// cache.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Download)` if the download fails, the checksum does
+    ///   not match or the archive cannot be unpacked.
+    pub async fn install(&self) -> Result<PathBuf, BitcoindError> {
+        let install_dir = self.install_dir();
+        let binary = self.binary();
+        if binary.is_file() {
+            return Ok(binary);
+        }
+
+        let url = self.url()?;
+        info!("Downloading Bitcoin Core {} from {}", self.version, url);
+        let response = reqwest::get(&url)
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| BitcoindError::Download(format!("{}: {}", url, e)))?;
+        let archive = response
+            .bytes()
+            .await
+            .map_err(|e| BitcoindError::Download(format!("{}: {}", url, e)))?;
+
+        let actual = to_hex(&Sha256::digest(&archive));
+        if actual != self.sha256.to_lowercase() {
+            return Err(BitcoindError::Download(format!(
+                "checksum mismatch for {}: expected {}, found {}",
+                url, self.sha256, actual
+            )));
+        }
+
+        let target = install_dir.clone();
+        tokio::task::spawn_blocking(move || unpack(&archive, &target))
+            .await
+            .map_err(|e| BitcoindError::Download(e.to_string()))??;
+
+        if !binary.is_file() {
+            return Err(BitcoindError::Download(format!(
+                "{} not found in {}",
+                binary.display(),
+                url
+            )));
+        }
+        Ok(binary)
+    }
+
+    /// Directory the release verified against `sha256` is unpacked into.
+    fn install_dir(&self) -> PathBuf {
+        self.cache_dir()
+            .join(&self.version)
+            .join(self.sha256.to_lowercase())
+    }
+
+    fn binary(&self) -> PathBuf {
+        self.install_dir()
+            .join(format!("bitcoin-{}", self.version))
+            .join("bin")
+            .join("bitcoind")
+    }
+
+    fn cache_dir(&self) -> PathBuf {
+        if let Some(cache_dir) = &self.cache_dir {
+            return cache_dir.clone();
+        }
+        if let Some(cache_dir) = std::env::var_os(ENV_CACHE_DIR) {
+            return PathBuf::from(cache_dir);
+        }
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .unwrap_or_else(std::env::temp_dir)
+            .join("bitcoind")
+    }
+}
+
+impl NativeBackend {
+    /// Creates a backend running the given release, downloading it first
+    /// unless it is already cached.
+    pub async fn download(release: &BitcoinCoreRelease) -> Result<Self, BitcoindError> {
+        Ok(Self::new().binary(release.install().await?))
+    }
+}
+
+/// Unpacks into a sibling directory first, so an interrupted unpack never
+/// leaves a partial release in the cache.
+fn unpack(archive: &[u8], target: &Path) -> Result<(), BitcoindError> {
+    let error = |e: std::io::Error| BitcoindError::Download(format!("unpacking release: {}", e));
+    let staging = target.with_extension(format!("partial-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::create_dir_all(&staging).map_err(error)?;
+
+    tar::Archive::new(flate2::read::GzDecoder::new(archive))
+        .unpack(&staging)
+        .map_err(error)?;
+
+    let _ = std::fs::remove_dir_all(target);
+    std::fs::rename(&staging, target).map_err(error)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_release_url() {
+        let release = BitcoinCoreRelease::new("29.1", "ABCDEF");
+        assert_eq!(release.sha256, "abcdef");
+
+        if let Ok(url) = release.url() {
+            assert!(url.starts_with("https://bitcoincore.org/bin/bitcoin-core-29.1/bitcoin-29.1-"));
+            assert!(url.ends_with(".tar.gz"));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_install_uses_cache() -> Result<(), BitcoindError> {
+        let cache_dir = std::env::temp_dir().join("bitcoind-test-release-cache");
+        let binary = cache_dir.join("29.1/00/bitcoin-29.1/bin/bitcoind");
+        std::fs::create_dir_all(binary.parent().unwrap()).unwrap();
+        std::fs::write(&binary, "").unwrap();
+
+        let release = BitcoinCoreRelease {
+            cache_dir: Some(cache_dir.clone()),
+            ..BitcoinCoreRelease::new("29.1", "00")
+        };
+        let installed = release.install().await;
+        // A release pinned to another checksum doesn't reuse the binary.
+        let repinned = BitcoinCoreRelease {
+            cache_dir: Some(cache_dir.clone()),
+            ..BitcoinCoreRelease::new("29.1", "11")
+        };
+        let repinned_cached = repinned.binary().is_file();
+        std::fs::remove_dir_all(&cache_dir).unwrap();
+
+        assert_eq!(installed?, binary);
+        assert!(!repinned_cached);
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::rpc::RpcError;
+use bitcoin::Txid;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, thiserror::Error)]
+pub enum BitcoindError {
+    #[error("Docker daemon is not available. Make sure it is running: {0}")]
+    DockerUnavailable(#[source] bollard::errors::Error),
+
+    #[error("Failed to open SSH tunnel to the Docker host: {0}")]
+    SshTunnel(String),
+
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[error("Failed to read config file {}: {source}", path.display())]
+    ConfigFile {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to create the tokio runtime: {0}")]
+    Runtime(#[source] std::io::Error),
+
+    #[error("Failed to write {}: {source}", path.display())]
+    Artifact {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+
+    #[error("Failed to run the bitcoind process: {0}")]
+    Process(#[source] std::io::Error),
+
+    #[error("Not supported by this backend: {0}")]
+    Unsupported(String),
+
+    #[error("Kubernetes error: {0}")]
+    Kubernetes(String),
+
+    #[error("ZMQ error: {0}")]
+    Zmq(String),
+
+    #[error("Failed to download Bitcoin Core: {0}")]
+    Download(String),
+
+    #[error("Failed to pull image {image}: {source}")]
+    ImagePull {
+        image: String,
+        #[source]
+        source: bollard::errors::Error,
+    },
+
+    #[error("Failed to build image: {0}")]
+    ImageBuild(String),
+
+    #[error("Failed to load image from {}: {message}", path.display())]
+    ImageLoad { path: PathBuf, message: String },
+
+    #[error("Image {image} has no variant for platform {platform}")]
+    PlatformUnavailable { image: String, platform: String },
+
+    #[error("Image {0} is not present locally and the pull policy is `never`")]
+    ImageNotPresent(String),
+
+    #[error("Image digest mismatch: expected {expected}, found {actual}")]
+    ImageHashMismatch { expected: String, actual: String },
+
+    #[error("{command} exited with code {exit_code}: {stderr}")]
+    Exec {
+        command: String,
+        exit_code: i64,
+        stderr: String,
+    },
+
+    #[error("bitcoin-cli exited with code {exit_code}: {stderr}")]
+    Cli { exit_code: i64, stderr: String },
+
+    #[error("bitcoind did not answer RPC within {timeout:?}. Last error: {last_error}")]
+    StartupTimeout {
+        timeout: Duration,
+        last_error: String,
+    },
+
+    #[error("bitcoind did not become ready within {timeout:?}. Last error: {last_error}")]
+    ReadinessTimeout {
+        timeout: Duration,
+        last_error: String,
+    },
+
+    #[error("{pattern:?} did not appear in the node's output within {timeout:?}")]
+    LogTimeout { pattern: String, timeout: Duration },
+
+    #[error("The node's output ended before {0:?} appeared")]
+    LogEnded(String),
+
+    #[error("The node rejected the block: {0}")]
+    BlockRejected(String),
+
+    #[error("The node rejected transaction {txid}: {reason}")]
+    TxRejected { txid: Txid, reason: String },
+
+    #[error("bitcoind failed to become ready: {0}")]
+    ReadinessFailed(String),
+
+    #[cfg(feature = "testcontainers")]
+    #[error("Testcontainers error: {0}")]
+    Testcontainers(#[from] testcontainers::TestcontainersError),
+
+    #[error("Docker error: {0}")]
+    Docker(#[from] bollard::errors::Error),
+
+    #[error(transparent)]
+    Rpc(#[from] RpcError),
+
+    #[error("Failed to create the RPC client: {0}")]
+    RpcClient(String),
+}
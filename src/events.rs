@@ -0,0 +1,434 @@
+//! Streams of chain events obtained by polling RPC, for nodes whose ZMQ
+//! notifications can't be reached.
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use crate::rpc::RpcClient;
+use bitcoin::consensus::encode::deserialize_hex;
+use bitcoin::{BlockHash, OutPoint, Transaction, Txid};
+use futures_util::stream::{self, BoxStream, Stream, StreamExt};
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::Duration;
+
+/// A block the node connected to its active chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockEvent {
+    pub hash: BlockHash,
+    pub height: u64,
+}
+
+/// A change to the node's mempool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MempoolEvent {
+    /// The transaction entered the mempool.
+    Added(Txid),
+    /// The transaction left the mempool without being replaced: it was
+    /// mined, evicted or expired.
+    Removed(Txid),
+    /// `replaced` left the mempool for `by`, which spends some of the same
+    /// outputs, e.g. after an RBF fee bump.
+    Replaced { replaced: Txid, by: Txid },
+}
+
+/// Outputs spent by each transaction known to be in the mempool, to tell
+/// replacements from other removals.
+#[derive(Default)]
+pub(crate) struct MempoolTracker {
+    spent: HashMap<Txid, Vec<OutPoint>>,
+}
+
+impl MempoolTracker {
+    /// Records `txid` as in the mempool. Its inputs can't be looked up once
+    /// it has left again, in which case it never counts as replaced.
+    pub(crate) async fn insert(&mut self, client: &RpcClient, txid: Txid) {
+        let spent = client
+            .call::<String>("getrawtransaction", json!([txid]))
+            .await
+            .ok()
+            .and_then(|hex| deserialize_hex::<Transaction>(&hex).ok())
+            .map(|tx| tx.input.iter().map(|input| input.previous_output).collect())
+            .unwrap_or_default();
+        self.spent.insert(txid, spent);
+    }
+
+    pub(crate) fn contains(&self, txid: &Txid) -> bool {
+        self.spent.contains_key(txid)
+    }
+
+    /// Events for the transactions `added` to the mempool, already inserted,
+    /// and those `removed` from it, which are forgotten.
+    pub(crate) fn changes(&mut self, added: &[Txid], removed: &[Txid]) -> Vec<MempoolEvent> {
+        let mut events: Vec<_> = added.iter().copied().map(MempoolEvent::Added).collect();
+        for txid in removed {
+            let spent = self.spent.remove(txid).unwrap_or_default();
+            let by = added.iter().find(|by| {
+                self.spent
+                    .get(*by)
+                    .is_some_and(|outputs| outputs.iter().any(|output| spent.contains(output)))
+            });
+            events.push(match by {
+                Some(by) => MempoolEvent::Replaced {
+                    replaced: *txid,
+                    by: *by,
+                },
+                None => MempoolEvent::Removed(*txid),
+            });
+        }
+        events
+    }
+}
+
+struct MempoolPoller {
+    client: RpcClient,
+    interval: Duration,
+    tracker: MempoolTracker,
+    pending: VecDeque<MempoolEvent>,
+    polled: bool,
+}
+
+impl MempoolPoller {
+    async fn next_event(&mut self) -> Result<MempoolEvent, BitcoindError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            let first = !self.polled;
+            if self.polled {
+                tokio::time::sleep(self.interval).await;
+            }
+            self.polled = true;
+            let txids = self
+                .client
+                .call::<Vec<Txid>>("getrawmempool", json!([]))
+                .await?;
+            let current: HashSet<_> = txids.iter().collect();
+            let removed: Vec<_> = self
+                .tracker
+                .spent
+                .keys()
+                .filter(|txid| !current.contains(txid))
+                .copied()
+                .collect();
+            let added: Vec<_> = txids
+                .iter()
+                .filter(|txid| !self.tracker.contains(txid))
+                .copied()
+                .collect();
+            for txid in &added {
+                self.tracker.insert(&self.client, *txid).await;
+            }
+            if !first {
+                // The transactions already in the mempool aren't new.
+                self.pending.extend(self.tracker.changes(&added, &removed));
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct HeaderHeight {
+    height: u64,
+}
+
+/// Returns the height of block `hash`.
+pub(crate) async fn block_height(
+    client: &RpcClient,
+    hash: BlockHash,
+) -> Result<u64, BitcoindError> {
+    let header = client
+        .call::<HeaderHeight>("getblockheader", json!([hash, true]))
+        .await?;
+    Ok(header.height)
+}
+
+struct BlockPoller {
+    client: RpcClient,
+    interval: Duration,
+    tip: Option<BlockEvent>,
+    pending: VecDeque<BlockEvent>,
+    polled: bool,
+}
+
+impl BlockPoller {
+    async fn next_event(&mut self) -> Result<BlockEvent, BitcoindError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            if self.polled {
+                tokio::time::sleep(self.interval).await;
+            }
+            self.polled = true;
+            let hash = self
+                .client
+                .call::<BlockHash>("getbestblockhash", json!([]))
+                .await?;
+            let previous = match self.tip {
+                Some(tip) if tip.hash == hash => continue,
+                previous => previous,
+            };
+            let height = block_height(&self.client, hash).await?;
+            let tip = BlockEvent { hash, height };
+            self.tip = Some(tip);
+            let Some(previous) = previous else {
+                // The blocks before the stream was first polled aren't new.
+                continue;
+            };
+            for height in new_heights(previous.height, height) {
+                let hash = self
+                    .client
+                    .call::<BlockHash>("getblockhash", json!([height]))
+                    .await?;
+                self.pending.push_back(BlockEvent { hash, height });
+            }
+            if self.pending.back() != Some(&tip) {
+                self.pending.push_back(tip);
+            }
+        }
+    }
+}
+
+/// Heights connected between a tip at `previous` and one at `current`. After
+/// a reorg to a chain no longer than the previous one only the new tip is
+/// reported.
+fn new_heights(previous: u64, current: u64) -> std::ops::Range<u64> {
+    previous.saturating_add(1).min(current)..current
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Streams the blocks the node connects, polling `getbestblockhash`
+    /// every `interval`. Blocks mined between two polls are reported one by
+    /// one, in height order.
+    ///
+    /// Items are the same as those of `subscribe_block_events` with the `zmq`
+    /// feature, for environments where the ZMQ ports can't be
+    /// published. RPC failures are yielded and polling carries on.
+    pub fn block_events(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<BlockEvent, BitcoindError>> {
+        let poller = BlockPoller {
+            client: self.rpc_client(),
+            interval,
+            tip: None,
+            pending: VecDeque::new(),
+            polled: false,
+        };
+        stream::unfold(poller, |mut poller| async move {
+            let event = poller.next_event().await;
+            Some((event, poller))
+        })
+    }
+
+    /// Streams the transactions added to, removed from and replaced in the
+    /// mempool, diffing `getrawmempool` every `interval`. Transactions that
+    /// come and go between two polls are missed.
+    pub fn poll_mempool_events(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<MempoolEvent, BitcoindError>> {
+        let poller = MempoolPoller {
+            client: self.rpc_client(),
+            interval,
+            tracker: MempoolTracker::default(),
+            pending: VecDeque::new(),
+            polled: false,
+        };
+        stream::unfold(poller, |mut poller| async move {
+            let event = poller.next_event().await;
+            Some((event, poller))
+        })
+    }
+}
+
+impl Bitcoind<DockerBackend> {
+    /// Streams the transactions added to, removed from and replaced in the
+    /// mempool, from the ZMQ `sequence` notifications when the `zmq` feature
+    /// is enabled and the topic is published, and with
+    /// [`Bitcoind::poll_mempool_events`] every `interval` otherwise.
+    pub async fn mempool_events(
+        &self,
+        interval: Duration,
+    ) -> Result<BoxStream<'static, Result<MempoolEvent, BitcoindError>>, BitcoindError> {
+        #[cfg(feature = "zmq")]
+        match self
+            .zmq_subscription(crate::ports::ZmqTopic::Sequence)
+            .await
+        {
+            Ok(subscription) => {
+                let client = self.rpc_client();
+                return Ok(crate::zmq::mempool_sequence(subscription, client).boxed());
+            }
+            Err(BitcoindError::InvalidConfig(_)) => {}
+            Err(e) => return Err(e),
+        }
+        Ok(self.poll_mempool_events(interval).boxed())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+
+    use super::*;
+    use crate::bitcoind::ephemeral_builder;
+    use crate::snapshot::premined_descriptor;
+    use futures_util::StreamExt;
+
+    #[test]
+    fn test_new_heights() {
+        assert_eq!(new_heights(10, 13), 11..13);
+        assert_eq!(new_heights(10, 11), 11..11);
+        // Reorg to a chain of the same height.
+        assert_eq!(new_heights(10, 10), 10..10);
+        assert_eq!(new_heights(10, 8), 8..8);
+    }
+
+    #[test]
+    fn test_mempool_changes() {
+        let txid = |byte| <Txid as bitcoin::hashes::Hash>::from_byte_array([byte; 32]);
+        let outpoint = |vout| OutPoint::new(txid(0), vout);
+        let mut tracker = MempoolTracker::default();
+        tracker
+            .spent
+            .insert(txid(1), vec![outpoint(0), outpoint(1)]);
+        tracker.spent.insert(txid(2), vec![outpoint(2)]);
+        tracker.spent.insert(txid(3), vec![outpoint(1)]);
+
+        assert_eq!(
+            tracker.changes(&[txid(3)], &[txid(1), txid(2)]),
+            [
+                MempoolEvent::Added(txid(3)),
+                MempoolEvent::Replaced {
+                    replaced: txid(1),
+                    by: txid(3)
+                },
+                MempoolEvent::Removed(txid(2)),
+            ]
+        );
+        assert!(!tracker.contains(&txid(1)));
+        assert!(tracker.contains(&txid(3)));
+    }
+
+    /// Sends a transaction, bumps its fee and mines the replacement, returning
+    /// both txids.
+    pub(crate) async fn replace_and_mine(
+        bitcoind: &Bitcoind,
+    ) -> Result<(Txid, Txid), BitcoindError> {
+        let address = bitcoind.cli::<String>(&["getnewaddress"]).await?;
+        let txid = bitcoind
+            .cli::<Txid>(&[
+                "-named",
+                "sendtoaddress",
+                &address,
+                "amount=1",
+                "replaceable=true",
+            ])
+            .await?;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let bumped = bitcoind
+            .cli::<serde_json::Value>(&["bumpfee", &txid.to_string()])
+            .await?;
+        let by: Txid = serde_json::from_value(bumped["txid"].clone())
+            .map_err(|e| crate::rpc::RpcError::InvalidResponse(e.to_string()))?;
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        bitcoind
+            .cli::<serde_json::Value>(&["generatetoaddress", "1", &address])
+            .await?;
+        Ok((txid, by))
+    }
+
+    /// Funds a wallet on `bitcoind`, for [`replace_and_mine`].
+    pub(crate) async fn fund_wallet(bitcoind: &Bitcoind) -> Result<(), BitcoindError> {
+        bitcoind
+            .cli::<serde_json::Value>(&["createwallet", "funded"])
+            .await?;
+        let address = bitcoind.cli::<String>(&["getnewaddress"]).await?;
+        bitcoind
+            .cli::<serde_json::Value>(&["generatetoaddress", "101", &address])
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_poll_mempool_events() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        bitcoind.start().await?;
+        let received = async {
+            fund_wallet(&bitcoind).await?;
+            let mut events = Box::pin(bitcoind.poll_mempool_events(Duration::from_millis(100)));
+            let (events, sent) = tokio::join!(
+                tokio::time::timeout(
+                    Duration::from_secs(30),
+                    events.by_ref().take(4).collect::<Vec<_>>()
+                ),
+                async {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    replace_and_mine(&bitcoind).await
+                }
+            );
+            let events = events
+                .expect("no mempool events within 30s")
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok::<_, BitcoindError>((events, sent?))
+        };
+        let received = received.await;
+        bitcoind.stop().await?;
+
+        let (events, (txid, by)) = received?;
+        assert_eq!(
+            events,
+            [
+                MempoolEvent::Added(txid),
+                MempoolEvent::Added(by),
+                MempoolEvent::Replaced { replaced: txid, by },
+                MempoolEvent::Removed(by),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_block_events() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        let rpc_config = bitcoind.start().await?;
+        let received = async {
+            let mut events = Box::pin(bitcoind.block_events(Duration::from_millis(100)));
+            let (events, mined) = tokio::join!(
+                tokio::time::timeout(
+                    Duration::from_secs(30),
+                    events.by_ref().take(3).collect::<Vec<_>>()
+                ),
+                async {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    RpcClient::new(&rpc_config)
+                        .call::<Vec<BlockHash>>(
+                            "generatetodescriptor",
+                            json!([3, premined_descriptor()]),
+                        )
+                        .await
+                }
+            );
+            let events = events
+                .expect("no block events within 30s")
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok::<_, BitcoindError>((events, mined?))
+        };
+        let received = received.await;
+        bitcoind.stop().await?;
+
+        let (events, mined) = received?;
+        assert_eq!(
+            events.iter().map(|event| event.hash).collect::<Vec<_>>(),
+            mined
+        );
+        assert_eq!(events[0].height, 1);
+        assert_eq!(events[2].height, 3);
+        Ok(())
+    }
+}
@@ -0,0 +1,97 @@
+//! Callbacks run around the node's lifecycle, e.g. to register it with a
+//! service registry once it is ready.
+
+use crate::backend::{NodeBackend, NodeStatus};
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use async_trait::async_trait;
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use std::sync::Arc;
+
+/// Callbacks registered with [`Bitcoind::with_lifecycle_hooks`] or
+/// [`crate::BitcoindBuilder::lifecycle_hooks`].
+///
+/// Every method does nothing by default. The hooks can't fail the node's
+/// lifecycle, so they handle their own errors.
+#[async_trait]
+pub trait LifecycleHooks: Send + Sync {
+    /// Called before the node is started, restarted or recreated.
+    async fn on_starting(&self, name: &str) {
+        let _ = name;
+    }
+
+    /// Called once the node answers RPC, with the config to reach it.
+    async fn on_ready(&self, name: &str, rpc_config: &RpcConfig) {
+        let _ = (name, rpc_config);
+    }
+
+    /// Called after the node is stopped or killed.
+    async fn on_stopped(&self, name: &str) {
+        let _ = name;
+    }
+
+    /// Called when the [`Bitcoind::watchdog`] finds the node down without
+    /// `stop` or `kill`, before it restarts it.
+    async fn on_crashed(&self, name: &str, status: NodeStatus) {
+        let _ = (name, status);
+    }
+
+    /// Called when the [`Bitcoind::watchdog`] gives up restarting the node.
+    async fn on_gave_up(&self, name: &str, error: &BitcoindError) {
+        let _ = (name, error);
+    }
+}
+
+/// The hooks registered with a node, run in registration order.
+#[derive(Clone, Default)]
+pub(crate) struct HookList(Vec<Arc<dyn LifecycleHooks>>);
+
+impl std::fmt::Debug for HookList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HookList({})", self.0.len())
+    }
+}
+
+impl HookList {
+    pub(crate) fn push(&mut self, hooks: Arc<dyn LifecycleHooks>) {
+        self.0.push(hooks);
+    }
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Registers `hooks` to run when the node starts, becomes ready and stops.
+    pub fn with_lifecycle_hooks<H: LifecycleHooks + 'static>(mut self, hooks: H) -> Self {
+        self.hooks.push(Arc::new(hooks));
+        self
+    }
+
+    pub(crate) async fn run_on_starting(&self) {
+        for hooks in &self.hooks.0 {
+            hooks.on_starting(self.backend.name()).await;
+        }
+    }
+
+    pub(crate) async fn run_on_ready(&self, rpc_config: &RpcConfig) {
+        for hooks in &self.hooks.0 {
+            hooks.on_ready(self.backend.name(), rpc_config).await;
+        }
+    }
+
+    pub(crate) async fn run_on_stopped(&self) {
+        for hooks in &self.hooks.0 {
+            hooks.on_stopped(self.backend.name()).await;
+        }
+    }
+
+    pub(crate) async fn run_on_crashed(&self, status: NodeStatus) {
+        for hooks in &self.hooks.0 {
+            hooks.on_crashed(self.backend.name(), status).await;
+        }
+    }
+
+    pub(crate) async fn run_on_gave_up(&self, error: &BitcoindError) {
+        for hooks in &self.hooks.0 {
+            hooks.on_gave_up(self.backend.name(), error).await;
+        }
+    }
+}
@@ -0,0 +1,231 @@
+//! Where the image the Docker backend runs comes from.
+
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use bollard::image::BuildImageOptions;
+use futures_util::stream::StreamExt;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+/// How the image is obtained before the container is created.
+///
+/// In a config file the variant is selected with `type`, e.g.
+/// `{ type = "build", context = "./bitcoin", build_args = { BRANCH = "fix-mempool" } }`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImageSource {
+    /// Pulled from its registry, following the pull policy.
+    #[default]
+    Registry,
+    /// Built from a Dockerfile on every `start` and tagged with the configured
+    /// image name. Docker's layer cache keeps rebuilds of an unchanged context fast.
+    Build {
+        /// Path of the Dockerfile, relative to `context`.
+        #[serde(default = "default_dockerfile")]
+        dockerfile: PathBuf,
+        /// Directory sent to the daemon as the build context. `.dockerignore`
+        /// is not applied, so keep it small.
+        context: PathBuf,
+        /// Values for the Dockerfile's `ARG`s.
+        #[serde(default)]
+        build_args: BTreeMap<String, String>,
+    },
+}
+
+impl ImageSource {
+    /// Builds from `Dockerfile` at the root of `context`, without build args.
+    pub fn build<P: Into<PathBuf>>(context: P) -> Self {
+        ImageSource::Build {
+            dockerfile: default_dockerfile(),
+            context: context.into(),
+            build_args: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_dockerfile() -> PathBuf {
+    PathBuf::from("Dockerfile")
+}
+
+/// How an image launches `bitcoind`: its entrypoint, where it keeps the
+/// datadir and which user runs the node. The container's command, datadir
+/// mounts and environment are generated to match.
+///
+/// Unless one is set with [`crate::BitcoindBuilder::image_profile`], it is
+/// picked from the image name with [`ImageProfile::for_image`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct ImageProfile {
+    /// Entrypoint run instead of the image's. `None` keeps the image's.
+    pub entrypoint: Option<Vec<String>>,
+    /// Arguments placed before the generated flags, e.g. `["bitcoind"]` for
+    /// entrypoints that expect the binary name.
+    pub cmd_prefix: Vec<String>,
+    /// Variable the entrypoint reads the datadir from. `None` passes
+    /// `-datadir` to `bitcoind` instead.
+    pub datadir_env: Option<String>,
+    /// Datadir inside the container.
+    pub datadir: String,
+    /// User the container runs as, as `name`, `uid` or `uid:gid`. `None`
+    /// keeps the image's.
+    pub user: Option<String>,
+}
+
+impl Default for ImageProfile {
+    fn default() -> Self {
+        ImageProfile::bitcoin_core()
+    }
+}
+
+impl ImageProfile {
+    /// `bitcoin/bitcoin` and `ruimarinho/bitcoin-core`: the entrypoint
+    /// prepends `bitcoind` to flags, creates the datadir named by
+    /// `BITCOIN_DATA` and drops to the `bitcoin` user.
+    pub fn bitcoin_core() -> Self {
+        ImageProfile {
+            entrypoint: None,
+            cmd_prefix: Vec::new(),
+            datadir_env: Some("BITCOIN_DATA".to_string()),
+            datadir: "/data".to_string(),
+            user: None,
+        }
+    }
+
+    /// `btcpayserver/bitcoin`: like [`ImageProfile::bitcoin_core`], but the
+    /// entrypoint only starts the node for a command beginning with `bitcoind`.
+    pub fn btcpayserver() -> Self {
+        ImageProfile {
+            cmd_prefix: vec!["bitcoind".to_string()],
+            ..ImageProfile::bitcoin_core()
+        }
+    }
+
+    /// Bitcoin Knots images, and any other image with `bitcoind` on its
+    /// `PATH`: the binary is run directly, with `-datadir`.
+    pub fn knots() -> Self {
+        ImageProfile {
+            entrypoint: Some(vec!["bitcoind".to_string()]),
+            cmd_prefix: Vec::new(),
+            datadir_env: None,
+            datadir: "/data".to_string(),
+            user: None,
+        }
+    }
+
+    /// Preset matching the repository of `image`, e.g. `btcpayserver/bitcoin:29.1`.
+    /// Images that aren't recognized get [`ImageProfile::bitcoin_core`].
+    pub fn for_image(image: &str) -> Self {
+        let repository = image.split('@').next().unwrap_or(image);
+        let repository = match repository.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => name,
+            _ => repository,
+        };
+        if repository.ends_with("btcpayserver/bitcoin") {
+            ImageProfile::btcpayserver()
+        } else if repository.contains("knots") {
+            ImageProfile::knots()
+        } else {
+            ImageProfile::bitcoin_core()
+        }
+    }
+}
+
+impl DockerBackend {
+    /// Builds the image from `dockerfile` in `context`, tagging it with the
+    /// configured image name and logging the build output.
+    pub(crate) async fn build_image(
+        &self,
+        dockerfile: &Path,
+        context: &Path,
+        build_args: &BTreeMap<String, String>,
+    ) -> Result<(), BitcoindError> {
+        info!("Building image {} from {}", self.image, context.display());
+        let archive = context_archive(context)?;
+
+        let options = BuildImageOptions {
+            dockerfile: dockerfile.to_string_lossy().into_owned(),
+            t: self.image.clone(),
+            buildargs: build_args
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            rm: true,
+            platform: self.container.platform.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        let mut stream = self.docker.build_image(options, None, Some(archive.into()));
+        while let Some(result) = stream.next().await {
+            let output = result.map_err(|e| BitcoindError::ImageBuild(e.to_string()))?;
+            if let Some(line) = output.stream {
+                let line = line.trim_end();
+                if !line.is_empty() {
+                    info!("{}", line);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Packs `context` into the tar archive the build API expects.
+fn context_archive(context: &Path) -> Result<Vec<u8>, BitcoindError> {
+    let mut archive = tar::Builder::new(Vec::new());
+    archive.follow_symlinks(false);
+    archive
+        .append_dir_all(".", context)
+        .and_then(|()| archive.into_inner())
+        .map_err(|e| {
+            BitcoindError::ImageBuild(format!(
+                "failed to read build context {}: {}",
+                context.display(),
+                e
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_context_archive() -> Result<(), BitcoindError> {
+        let context = std::env::temp_dir().join("bitcoind-test-build-context");
+        std::fs::create_dir_all(context.join("patches")).unwrap();
+        std::fs::write(context.join("Dockerfile"), "FROM bitcoin/bitcoin:29.1\n").unwrap();
+        std::fs::write(context.join("patches/fix.patch"), "diff\n").unwrap();
+
+        let archive = context_archive(&context)?;
+        std::fs::remove_dir_all(&context).unwrap();
+
+        let entries = tar::Archive::new(archive.as_slice())
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect::<Vec<_>>();
+        assert!(entries.contains(&PathBuf::from("Dockerfile")));
+        assert!(entries.contains(&PathBuf::from("patches/fix.patch")));
+
+        assert!(matches!(
+            context_archive(Path::new("/nonexistent/bitcoind-context")),
+            Err(BitcoindError::ImageBuild(_))
+        ));
+
+        let source: ImageSource =
+            serde_yaml::from_str("type: build\ncontext: ./bitcoin\nbuild_args:\n  BRANCH: fix\n")
+                .unwrap();
+        assert_eq!(
+            source,
+            ImageSource::Build {
+                dockerfile: PathBuf::from("Dockerfile"),
+                context: PathBuf::from("./bitcoin"),
+                build_args: BTreeMap::from([("BRANCH".to_string(), "fix".to_string())]),
+            }
+        );
+        Ok(())
+    }
+}
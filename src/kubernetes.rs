@@ -0,0 +1,503 @@
+//! Backend that runs the node in a Kubernetes namespace through `kubectl`.
+//! Enabled with the `kubernetes` feature.
+//!
+//! The node runs as a single-replica Deployment with a Service in front of it.
+//! RPC is reached through `kubectl port-forward`, so the cluster doesn't need
+//! to expose anything.
+
+use crate::backend::{ExecOutput, NodeBackend, NodeSpec, RpcAddress};
+use crate::bitcoind::{node_spec, ContainerOptions};
+use crate::builder::DEFAULT_IMAGE;
+use crate::config::BitcoindConfig;
+use crate::error::BitcoindError;
+use crate::logs::LogOptions;
+use crate::ports::{
+    parse_rpc_port, ZmqTopic, P2P_CONTAINER_PORT, RPC_CONTAINER_PORT, ZMQ_CONTAINER_PORT,
+};
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, Command};
+use tracing::{debug, info};
+
+/// Maximum time to wait for the Deployment to become available.
+const ROLLOUT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Maximum time to wait for `kubectl port-forward` to start listening.
+const PORT_FORWARD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Runs `bitcoind` as a Deployment and Service in a Kubernetes namespace.
+///
+/// Every call runs the `kubectl` binary, so the current kubeconfig (or the
+/// configured context) decides which cluster is used.
+pub struct KubernetesBackend {
+    name: String,
+    namespace: String,
+    image: String,
+    context: Option<String>,
+    kubectl: PathBuf,
+    container: ContainerOptions,
+    port_forward: Mutex<Option<PortForward>>,
+}
+
+struct PortForward {
+    child: Child,
+    local_port: u16,
+}
+
+impl KubernetesBackend {
+    /// Creates a backend running the default image as `name` in `namespace`.
+    pub fn new(name: &str, namespace: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            namespace: namespace.to_string(),
+            image: DEFAULT_IMAGE.to_string(),
+            context: None,
+            kubectl: PathBuf::from("kubectl"),
+            container: ContainerOptions::default(),
+            port_forward: Mutex::new(None),
+        }
+    }
+
+    /// Creates a backend running the node described by `config` in `namespace`.
+    /// Docker-only settings (`host_network`, `host_ip`, `docker`) are ignored.
+    pub fn from_config(config: &BitcoindConfig, namespace: &str) -> Self {
+        let builder = config.builder();
+        Self {
+            image: builder.image,
+            container: builder.container,
+            ..Self::new(&builder.container_name, namespace)
+        }
+    }
+
+    /// Sets the image the node runs.
+    pub fn image(mut self, image: &str) -> Self {
+        self.image = image.to_string();
+        self
+    }
+
+    /// Adds an environment variable to the node container.
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.container
+            .env
+            .push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Uses the given kubeconfig context instead of the current one.
+    pub fn context(mut self, context: &str) -> Self {
+        self.context = Some(context.to_string());
+        self
+    }
+
+    /// Sets the path of the `kubectl` binary.
+    pub fn kubectl<P: Into<PathBuf>>(mut self, kubectl: P) -> Self {
+        self.kubectl = kubectl.into();
+        self
+    }
+
+    /// Returns the namespace the node runs in.
+    pub fn namespace(&self) -> &str {
+        &self.namespace
+    }
+
+    /// Renders the Deployment and Service for `spec` as a multi-document YAML.
+    pub fn manifests_yaml(&self, spec: &NodeSpec) -> Result<String, BitcoindError> {
+        manifests_yaml(&self.manifests(spec))
+    }
+
+    fn manifests(&self, spec: &NodeSpec) -> Vec<Value> {
+        let labels = json!({
+            "app.kubernetes.io/name": "bitcoind",
+            "app.kubernetes.io/instance": self.name,
+        });
+        let ports = self
+            .container
+            .published_ports(spec)
+            .into_iter()
+            .map(|(container_port, _)| container_port)
+            .collect::<Vec<_>>();
+        let env = self
+            .container
+            .container_env()
+            .into_iter()
+            .filter_map(|var| {
+                var.split_once('=')
+                    .map(|(name, value)| json!({ "name": name, "value": value }))
+            })
+            .collect::<Vec<_>>();
+
+        let mut container = json!({
+            "name": "bitcoind",
+            "image": self.image,
+            "args": self.container.container_cmd(spec),
+            "env": env,
+            "ports": ports
+                .iter()
+                .map(|port| json!({ "name": port_name(*port), "containerPort": port }))
+                .collect::<Vec<_>>(),
+            "readinessProbe": {
+                "tcpSocket": { "port": RPC_CONTAINER_PORT },
+                "periodSeconds": 1,
+            },
+        });
+        if let Some(entrypoint) = self.container.effective_entrypoint() {
+            container["command"] = json!(entrypoint);
+        }
+        if let Some(working_dir) = self.container.working_dir() {
+            container["workingDir"] = json!(working_dir);
+        }
+
+        let deployment = json!({
+            "apiVersion": "apps/v1",
+            "kind": "Deployment",
+            "metadata": { "name": self.name, "namespace": self.namespace, "labels": labels },
+            "spec": {
+                "replicas": 1,
+                "strategy": { "type": "Recreate" },
+                "selector": { "matchLabels": labels },
+                "template": {
+                    "metadata": { "labels": labels },
+                    "spec": { "containers": [container] },
+                },
+            },
+        });
+        let service = json!({
+            "apiVersion": "v1",
+            "kind": "Service",
+            "metadata": { "name": self.name, "namespace": self.namespace, "labels": labels },
+            "spec": {
+                "selector": labels,
+                "ports": ports
+                    .iter()
+                    .map(|port| json!({ "name": port_name(*port), "port": port, "targetPort": port }))
+                    .collect::<Vec<_>>(),
+            },
+        });
+        vec![deployment, service]
+    }
+
+    fn port_forward(&self) -> MutexGuard<'_, Option<PortForward>> {
+        self.port_forward
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.kubectl);
+        if let Some(context) = &self.context {
+            command.arg("--context").arg(context);
+        }
+        command
+            .arg("--namespace")
+            .arg(&self.namespace)
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        command
+    }
+
+    /// Runs `kubectl` with `args`, feeding it `input` on stdin.
+    async fn run(&self, args: &[&str], input: Option<&str>) -> Result<ExecOutput, BitcoindError> {
+        let mut command = self.command();
+        command
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        if input.is_some() {
+            command.stdin(Stdio::piped());
+        }
+
+        let mut child = command.spawn().map_err(BitcoindError::Process)?;
+        if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+            stdin
+                .write_all(input.as_bytes())
+                .await
+                .map_err(BitcoindError::Process)?;
+        }
+        let output = child
+            .wait_with_output()
+            .await
+            .map_err(BitcoindError::Process)?;
+
+        Ok(ExecOutput {
+            exit_code: output.status.code().unwrap_or(-1).into(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    /// Runs `kubectl` with `args` and fails unless it exits successfully.
+    async fn run_checked(
+        &self,
+        args: &[&str],
+        input: Option<&str>,
+    ) -> Result<String, BitcoindError> {
+        let output = self.run(args, input).await?;
+        if output.exit_code != 0 {
+            return Err(BitcoindError::Kubernetes(format!(
+                "kubectl {} failed: {}",
+                args.join(" "),
+                output.stderr.trim()
+            )));
+        }
+        Ok(output.stdout)
+    }
+
+    /// Forwards a free local port to the RPC port of the Service and returns it.
+    async fn open_port_forward(&self) -> Result<u16, BitcoindError> {
+        let mut child = self
+            .command()
+            .arg("port-forward")
+            .arg(format!("service/{}", self.name))
+            .arg(format!(":{}", RPC_CONTAINER_PORT))
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(BitcoindError::Process)?;
+
+        let stdout = child.stdout.take().ok_or_else(|| {
+            BitcoindError::Kubernetes("kubectl port-forward has no stdout".to_string())
+        })?;
+        let mut lines = BufReader::new(stdout).lines();
+
+        // kubectl prints `Forwarding from 127.0.0.1:<port> -> 18443` once listening.
+        let local_port = tokio::time::timeout(PORT_FORWARD_TIMEOUT, async {
+            while let Some(line) = lines.next_line().await.map_err(BitcoindError::Process)? {
+                if let Some(port) = line
+                    .strip_prefix("Forwarding from 127.0.0.1:")
+                    .and_then(|rest| rest.split_whitespace().next())
+                    .and_then(|port| port.parse().ok())
+                {
+                    return Ok(port);
+                }
+            }
+            Err(BitcoindError::Kubernetes(format!(
+                "kubectl port-forward to service/{} exited",
+                self.name
+            )))
+        })
+        .await
+        .map_err(|_| {
+            BitcoindError::Kubernetes(format!(
+                "timed out forwarding a port to service/{}",
+                self.name
+            ))
+        })??;
+
+        // Keep draining the output so kubectl never blocks on a full pipe.
+        tokio::spawn(async move {
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("kubectl port-forward: {}", line);
+            }
+        });
+
+        *self.port_forward() = Some(PortForward { child, local_port });
+        Ok(local_port)
+    }
+
+    async fn close_port_forward(&self) {
+        let port_forward = self.port_forward().take();
+        if let Some(mut port_forward) = port_forward {
+            let _ = port_forward.child.kill().await;
+        }
+    }
+}
+
+#[async_trait]
+impl NodeBackend for KubernetesBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn create(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        self.stop().await?;
+        info!(
+            "Creating bitcoind deployment {} in namespace {}",
+            self.name, self.namespace
+        );
+        let manifests = self.manifests_yaml(spec)?;
+        self.run_checked(&["apply", "-f", "-"], Some(&manifests))
+            .await?;
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<(), BitcoindError> {
+        let deployment = format!("deployment/{}", self.name);
+        let timeout = format!("--timeout={}s", ROLLOUT_TIMEOUT.as_secs());
+        self.run_checked(&["rollout", "status", &deployment, &timeout], None)
+            .await?;
+        self.open_port_forward().await?;
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<(), BitcoindError> {
+        self.close_port_forward().await;
+        let resources = format!("deployment/{0},service/{0}", self.name);
+        self.run_checked(
+            &["delete", &resources, "--ignore-not-found", "--wait"],
+            None,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn is_running(&self) -> Result<bool, BitcoindError> {
+        let deployment = format!("deployment/{}", self.name);
+        let output = self
+            .run(
+                &[
+                    "get",
+                    &deployment,
+                    "--ignore-not-found",
+                    "-o",
+                    "jsonpath={.status.availableReplicas}",
+                ],
+                None,
+            )
+            .await?;
+        Ok(output.exit_code == 0 && output.stdout.trim().parse::<u32>().unwrap_or(0) > 0)
+    }
+
+    async fn rpc_address(&self, _spec: &NodeSpec) -> Result<RpcAddress, BitcoindError> {
+        let local_port = self
+            .port_forward()
+            .as_ref()
+            .map(|forward| forward.local_port);
+        let port = match local_port {
+            Some(port) => port,
+            None => self.open_port_forward().await?,
+        };
+        Ok(RpcAddress {
+            host: Some("127.0.0.1".to_string()),
+            port,
+        })
+    }
+
+    fn cli_command(&self, spec: &NodeSpec, args: &[String]) -> Result<Vec<String>, BitcoindError> {
+        Ok(self.container.cli_command(spec, args))
+    }
+
+    fn datadir(&self) -> Option<String> {
+        Some(self.container.datadir())
+    }
+
+    async fn exec(&self, cmd: &[String]) -> Result<ExecOutput, BitcoindError> {
+        let deployment = format!("deployment/{}", self.name);
+        let mut args = vec!["exec", deployment.as_str(), "--"];
+        args.extend(cmd.iter().map(String::as_str));
+        self.run(&args, None).await
+    }
+
+    async fn logs(&self, options: &LogOptions) -> Result<String, BitcoindError> {
+        if !options.stdout && !options.stderr {
+            return Ok(String::new());
+        }
+        let deployment = format!("deployment/{}", self.name);
+        let mut args = vec!["logs".to_string(), deployment];
+        if let Some(tail) = options.tail {
+            args.push(format!("--tail={}", tail));
+        }
+        if let Some(since) = options.since {
+            let elapsed = since.elapsed().unwrap_or_default();
+            args.push(format!("--since={}s", elapsed.as_secs().max(1)));
+        }
+        let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+        self.run_checked(&args, None).await
+    }
+}
+
+impl BitcoindConfig {
+    /// Renders a Deployment and Service running this node in `namespace`, as a
+    /// multi-document YAML for `kubectl apply -f`.
+    ///
+    /// The RPC credentials appear in the container arguments.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the configuration is invalid.
+    pub fn to_kubernetes_yaml(&self, namespace: &str) -> Result<String, BitcoindError> {
+        self.builder().validate()?;
+        let spec = node_spec(
+            &self.rpc,
+            parse_rpc_port(self.rpc.url.expose_secret(), self.rpc.network)?,
+            &self.flags.clone().unwrap_or_default(),
+        );
+        KubernetesBackend::from_config(self, namespace).manifests_yaml(&spec)
+    }
+}
+
+fn manifests_yaml(manifests: &[Value]) -> Result<String, BitcoindError> {
+    manifests
+        .iter()
+        .map(|manifest| {
+            serde_yaml::to_string(manifest)
+                .map_err(|e| BitcoindError::InvalidConfig(format!("rendering manifest: {}", e)))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map(|documents| documents.join("---\n"))
+}
+
+fn port_name(port: u16) -> String {
+    match port {
+        RPC_CONTAINER_PORT => "rpc".to_string(),
+        P2P_CONTAINER_PORT => "p2p".to_string(),
+        ZMQ_CONTAINER_PORT => "zmq".to_string(),
+        port => ZmqTopic::ALL
+            .into_iter()
+            .find(|topic| topic.container_port() == port)
+            .map(|topic| format!("zmq-{}", topic))
+            .unwrap_or_else(|| format!("port-{}", port)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_kubernetes_manifests() -> Result<(), BitcoindError> {
+        let config: BitcoindConfig = serde_yaml::from_str(
+            r#"
+container_name: regtest-a
+rpc:
+  network: regtest
+  url: http://localhost:18443
+  username: foo
+  password: rpcpassword
+  wallet: mywallet
+publish:
+  zmq: 28332
+env:
+  UID: "1000"
+"#,
+        )
+        .unwrap();
+
+        let yaml = config.to_kubernetes_yaml("staging")?;
+        let documents = yaml.split("---\n").collect::<Vec<_>>();
+        assert_eq!(documents.len(), 2);
+
+        let deployment: Value = serde_yaml::from_str(documents[0]).unwrap();
+        assert_eq!(deployment["kind"], "Deployment");
+        assert_eq!(deployment["metadata"]["namespace"], "staging");
+        let container = &deployment["spec"]["template"]["spec"]["containers"][0];
+        assert_eq!(container["image"], DEFAULT_IMAGE);
+        assert_eq!(container["args"][0], "-regtest=1");
+        assert_eq!(container["env"][1]["value"], "1000");
+        assert_eq!(container["ports"][1]["name"], "zmq");
+        assert!(container.get("command").is_none());
+
+        let service: Value = serde_yaml::from_str(documents[1]).unwrap();
+        assert_eq!(service["kind"], "Service");
+        assert_eq!(
+            service["spec"]["selector"]["app.kubernetes.io/instance"],
+            "regtest-a"
+        );
+        assert_eq!(service["spec"]["ports"][0]["port"], RPC_CONTAINER_PORT);
+        Ok(())
+    }
+}
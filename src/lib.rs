@@ -1 +1,89 @@
+pub mod archive;
+pub mod assumeutxo;
+pub mod backend;
 pub mod bitcoind;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod builder;
+pub mod cleanup;
+pub mod coinstats;
+pub mod compose;
+pub mod conf;
+pub mod config;
+pub mod docker;
+#[cfg(feature = "download")]
+pub mod download;
+pub mod error;
+pub mod events;
+pub mod hooks;
+pub mod image;
+#[cfg(feature = "kubernetes")]
+pub mod kubernetes;
+pub mod logs;
+pub mod mining;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "native")]
+pub mod native;
+pub mod ports;
+pub mod prune;
+pub mod readiness;
+pub mod registry;
+pub mod rest;
+pub mod rpc;
+pub mod signet;
+pub mod snapshot;
+pub mod stats;
+#[cfg(feature = "testcontainers")]
+pub mod testcontainer;
+pub mod version;
+pub mod watchdog;
+#[cfg(feature = "zmq")]
+pub mod zmq;
+
+pub use assumeutxo::{UtxoSetDump, UtxoSetLoad};
+pub use backend::{ExecOutput, NodeBackend, NodeSpec, NodeStatus, RpcAddress};
+pub use bitcoind::{
+    Bitcoind, BitcoindFlags, ContainerOptions, DebugCategory, Healthcheck, ReadinessConfig,
+    ResourceLimits, Timeouts,
+};
+pub use builder::BitcoindBuilder;
+pub use cleanup::{OrphanFilter, RemovedOrphans};
+pub use coinstats::TxOutSetInfo;
+pub use conf::BitcoinConf;
+pub use config::BitcoindConfig;
+pub use docker::{
+    ContainerDetails, ContainerMount, DockerBackend, DockerConnection, PullPolicy, PullProgress,
+    PullProgressCallback, RetryPolicy,
+};
+#[cfg(feature = "download")]
+pub use download::BitcoinCoreRelease;
+pub use error::BitcoindError;
+pub use events::{BlockEvent, MempoolEvent};
+pub use hooks::LifecycleHooks;
+pub use image::{ImageProfile, ImageSource};
+#[cfg(feature = "kubernetes")]
+pub use kubernetes::KubernetesBackend;
+pub use logs::{LogLine, LogOptions, LogStream};
+pub use mining::COINBASE_MATURITY;
+#[cfg(feature = "mock")]
+pub use mock::{MockBackend, MockCall};
+#[cfg(feature = "native")]
+pub use native::NativeBackend;
+pub use ports::{
+    default_p2p_port, default_rpc_port, MappedPorts, PublishedPorts, ZmqEndpoint, ZmqPorts,
+    ZmqTopic,
+};
+pub use prune::PruneInfo;
+pub use readiness::ReadyCondition;
+pub use registry::RegistryAuth;
+pub use rest::{RestChainInfo, RestClient};
+pub use signet::{CustomSignet, SignetCluster};
+pub use snapshot::{premined_descriptor, PREMINED_BLOCKS};
+pub use stats::ResourceUsage;
+#[cfg(feature = "testcontainers")]
+pub use testcontainer::BitcoindImage;
+pub use version::NodeVersion;
+pub use watchdog::{Watchdog, WatchdogConfig};
+#[cfg(feature = "zmq")]
+pub use zmq::subscribe;
@@ -0,0 +1,374 @@
+//! Output of the node, e.g. to find out why it failed to start.
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use futures_util::stream::{self, Stream, StreamExt};
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+/// Delay between reads of `debug.log` while following it.
+const DEBUG_LOG_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Which part of the node's output [`crate::Bitcoind::logs`] returns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogOptions {
+    /// Only output printed at or after this time. Ignored by the native
+    /// backend, whose log file has no timestamps of its own.
+    pub since: Option<SystemTime>,
+    /// Only the last `tail` lines.
+    pub tail: Option<usize>,
+    /// Include standard output, where `bitcoind` prints its log.
+    pub stdout: bool,
+    /// Include standard error. Only the Docker backend keeps it apart from
+    /// standard output; the others return both or neither.
+    pub stderr: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> Self {
+        LogOptions {
+            since: None,
+            tail: None,
+            stdout: true,
+            stderr: true,
+        }
+    }
+}
+
+impl LogOptions {
+    /// Only the last `lines` lines of both streams.
+    pub fn tail(lines: usize) -> Self {
+        LogOptions {
+            tail: Some(lines),
+            ..Default::default()
+        }
+    }
+
+    /// Seconds since the Unix epoch of `since`, as the Docker API takes it.
+    pub(crate) fn since_secs(&self) -> i64 {
+        self.since
+            .and_then(|since| since.duration_since(SystemTime::UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs() as i64)
+    }
+
+    /// Applies `tail` to output read in full, for backends that can't
+    /// limit it at the source.
+    #[cfg(any(feature = "native", feature = "mock", test))]
+    pub(crate) fn apply_tail(&self, logs: &str) -> String {
+        let Some(tail) = self.tail else {
+            return logs.to_string();
+        };
+        let lines = logs.lines().collect::<Vec<_>>();
+        let mut tail = lines[lines.len().saturating_sub(tail)..].join("\n");
+        if !tail.is_empty() && logs.ends_with('\n') {
+            tail.push('\n');
+        }
+        tail
+    }
+}
+
+/// Output stream a [`LogLine`] was printed on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// A line of the node's output, yielded by [`crate::Bitcoind::follow_logs`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    /// When the line was printed, in RFC 3339, if the backend records it.
+    pub timestamp: Option<String>,
+    pub stream: LogStream,
+    /// The line without its trailing newline.
+    pub message: String,
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Writes the node's full output to `<dir>/<name>.log`, creating `dir`,
+    /// and returns the path. `debug.log` is saved next to it as
+    /// `<name>.debug.log` when it can be read.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Artifact)` if a file can't be written.
+    pub async fn save_logs<P: AsRef<Path>>(&self, dir: P) -> Result<PathBuf, BitcoindError> {
+        let dir = dir.as_ref();
+        let name = self.backend.name();
+        let path = dir.join(format!("{}.log", name));
+        write_artifact(dir, &path, &self.logs(LogOptions::default()).await?)?;
+
+        match self.debug_log(None).await {
+            Ok(debug_log) => {
+                write_artifact(dir, &dir.join(format!("{}.debug.log", name)), &debug_log)?
+            }
+            Err(err) => debug!("Not saving debug.log of {}: {}", name, err),
+        }
+        Ok(path)
+    }
+
+    /// Path of `debug.log`, which can hold more than the console output,
+    /// e.g. with `-printtoconsole=0` or categories logged only to the file.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Unsupported)` if the backend has no datadir.
+    pub fn debug_log_path(&self) -> Result<String, BitcoindError> {
+        let datadir = self.backend.datadir().ok_or_else(|| {
+            BitcoindError::Unsupported(format!("{} has no datadir", self.backend.name()))
+        })?;
+        let chain = chain_dir(&self.node_spec().command_line([]));
+        Ok([datadir.trim_end_matches('/'), &chain, "debug.log"]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+
+    /// Reads `debug.log` from the datadir, or its last `tail` lines.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::Exec)` if the file can't be read, e.g. before the
+    ///   node created it.
+    pub async fn debug_log(&self, tail: Option<usize>) -> Result<String, BitcoindError> {
+        let path = self.debug_log_path()?;
+        let cmd = match tail {
+            Some(tail) => vec!["tail".to_string(), "-n".to_string(), tail.to_string(), path],
+            None => vec!["cat".to_string(), path],
+        };
+        let output = self.backend.exec(&cmd).await?;
+        if output.exit_code != 0 {
+            return Err(BitcoindError::Exec {
+                command: cmd.join(" "),
+                exit_code: output.exit_code,
+                stderr: output.stderr.trim().to_string(),
+            });
+        }
+        Ok(output.stdout)
+    }
+
+    /// Streams `debug.log` line by line, from the start and then as it grows,
+    /// checking for new lines twice a second. The stream ends after the first
+    /// error, e.g. when the node is removed.
+    pub fn follow_debug_log(&self) -> impl Stream<Item = Result<LogLine, BitcoindError>> + '_ {
+        struct Follower {
+            path: Option<String>,
+            offset: usize,
+            partial: String,
+            lines: VecDeque<String>,
+            done: bool,
+        }
+
+        let follower = Follower {
+            path: None,
+            offset: 0,
+            partial: String::new(),
+            lines: VecDeque::new(),
+            done: false,
+        };
+        stream::unfold(follower, move |mut follower| async move {
+            loop {
+                if let Some(line) = follower.lines.pop_front() {
+                    return Some((Ok(LogLine::parse(LogStream::Stdout, &line)), follower));
+                }
+                if follower.done {
+                    return None;
+                }
+
+                let path = match &follower.path {
+                    Some(path) => path.clone(),
+                    None => match self.debug_log_path() {
+                        Ok(path) => follower.path.insert(path).clone(),
+                        Err(err) => {
+                            follower.done = true;
+                            return Some((Err(err), follower));
+                        }
+                    },
+                };
+                let cmd = [
+                    "tail".to_string(),
+                    "-c".to_string(),
+                    format!("+{}", follower.offset + 1),
+                    path,
+                ];
+                let output = match self.backend.exec(&cmd).await {
+                    Ok(output) => output,
+                    Err(err) => {
+                        follower.done = true;
+                        return Some((Err(err), follower));
+                    }
+                };
+
+                // A missing file is not an error yet: the node may not have created it.
+                if output.exit_code != 0 || output.stdout.is_empty() {
+                    tokio::time::sleep(DEBUG_LOG_POLL_INTERVAL).await;
+                    continue;
+                }
+                follower.offset += output.stdout.len();
+                follower.partial.push_str(&output.stdout);
+                if let Some(end) = follower.partial.rfind('\n') {
+                    let complete = follower.partial.drain(..=end).collect::<String>();
+                    follower.lines.extend(complete.lines().map(str::to_string));
+                }
+            }
+        })
+    }
+
+    /// Saves the logs to the artifact directory, if set, when `result` failed.
+    pub(crate) async fn save_logs_on_failure<T>(
+        &self,
+        result: Result<T, BitcoindError>,
+    ) -> Result<T, BitcoindError> {
+        if let (Err(_), Some(dir)) = (&result, &self.log_artifacts) {
+            match self.save_logs(dir).await {
+                Ok(path) => info!(
+                    "Saved the logs of {} to {}",
+                    self.backend.name(),
+                    path.display()
+                ),
+                Err(err) => warn!(
+                    "Could not save the logs of {}: {}",
+                    self.backend.name(),
+                    err
+                ),
+            }
+        }
+        result
+    }
+
+    /// Waits until the node prints a line containing `pattern`, e.g.
+    /// `"UpdateTip"`, and returns it. Output printed before the call counts,
+    /// so the line can't be missed by calling this too late.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::LogTimeout)` if no line matches within `timeout`.
+    /// * `Err(BitcoindError::LogEnded)` if the node stops first.
+    pub async fn wait_for_log(
+        &self,
+        pattern: &str,
+        timeout: Duration,
+    ) -> Result<LogLine, BitcoindError> {
+        let mut lines = self.follow_logs();
+        let search = async {
+            while let Some(line) = lines.next().await {
+                let line = line?;
+                if line.message.contains(pattern) {
+                    return Ok(line);
+                }
+            }
+            Err(BitcoindError::LogEnded(pattern.to_string()))
+        };
+
+        tokio::time::timeout(timeout, search)
+            .await
+            .unwrap_or_else(|_| {
+                Err(BitcoindError::LogTimeout {
+                    pattern: pattern.to_string(),
+                    timeout,
+                })
+            })
+    }
+}
+
+impl LogLine {
+    /// Splits the RFC 3339 timestamp Docker and `bitcoind` put in front of a
+    /// line off its message.
+    pub(crate) fn parse(stream: LogStream, line: &str) -> Self {
+        let line = line.trim_end_matches(['\r', '\n']);
+        match line.split_once(' ') {
+            Some((timestamp, message)) if timestamp.contains('T') && timestamp.ends_with('Z') => {
+                LogLine {
+                    timestamp: Some(timestamp.to_string()),
+                    stream,
+                    message: message.to_string(),
+                }
+            }
+            _ => LogLine {
+                timestamp: None,
+                stream,
+                message: line.to_string(),
+            },
+        }
+    }
+}
+
+pub(crate) fn write_artifact(dir: &Path, path: &Path, contents: &str) -> Result<(), BitcoindError> {
+    std::fs::create_dir_all(dir)
+        .and_then(|()| std::fs::write(path, contents))
+        .map_err(|source| BitcoindError::Artifact {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+/// Directory of the chain the node runs inside its datadir, from its arguments.
+pub(crate) fn chain_dir(args: &[String]) -> String {
+    let mut chain = "main".to_string();
+    for arg in args {
+        match arg.split_once('=') {
+            Some(("-regtest" | "-testnet" | "-testnet4" | "-signet", "0")) => {}
+            Some((flag @ ("-regtest" | "-testnet4" | "-signet"), _)) => {
+                chain = flag.trim_start_matches('-').to_string()
+            }
+            Some(("-testnet", _)) => chain = "test".to_string(),
+            Some(("-chain", name)) => chain = name.to_string(),
+            _ => {}
+        }
+    }
+    match chain.as_str() {
+        "main" => String::new(),
+        "test" => "testnet3".to_string(),
+        _ => chain,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_log_options() {
+        let logs = "first\nsecond\nthird\n";
+        assert_eq!(LogOptions::tail(2).apply_tail(logs), "second\nthird\n");
+        assert_eq!(LogOptions::tail(0).apply_tail(logs), "");
+        assert_eq!(
+            LogOptions::tail(5).apply_tail("first\nsecond"),
+            "first\nsecond"
+        );
+        assert_eq!(LogOptions::default().apply_tail(logs), logs);
+
+        let options = LogOptions {
+            since: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            ..Default::default()
+        };
+        assert_eq!(options.since_secs(), 1_700_000_000);
+        assert_eq!(LogOptions::default().since_secs(), 0);
+    }
+
+    #[test]
+    fn test_chain_dir() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(chain_dir(&args(&["-regtest=1", "-txindex=1"])), "regtest");
+        assert_eq!(chain_dir(&args(&["-testnet=1"])), "testnet3");
+        assert_eq!(
+            chain_dir(&args(&["-regtest=1", "-regtest=0", "-chain=signet"])),
+            "signet"
+        );
+        assert_eq!(chain_dir(&args(&["-server=1"])), "");
+
+        let line = LogLine::parse(
+            LogStream::Stdout,
+            "2025-01-01T12:00:00Z UpdateTip: new best\n",
+        );
+        assert_eq!(line.timestamp.as_deref(), Some("2025-01-01T12:00:00Z"));
+        assert_eq!(line.message, "UpdateTip: new best");
+    }
+}
@@ -0,0 +1,112 @@
+//! Block generation on regtest, the most common step of a test.
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use crate::events::block_height;
+use crate::snapshot::premined_descriptor;
+use bitcoin::{Address, BlockHash};
+use serde_json::json;
+
+/// Confirmations after which a coinbase output can be spent.
+pub const COINBASE_MATURITY: u64 = 100;
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Mines `n` blocks and returns their hashes, in height order.
+    ///
+    /// The coinbases pay to [`premined_descriptor`], so tests don't need a
+    /// wallet or an address of their own; import the descriptor to spend
+    /// them.
+    pub async fn mine_blocks(&self, n: u64) -> Result<Vec<BlockHash>, BitcoindError> {
+        Ok(self
+            .rpc_client()
+            .call("generatetodescriptor", json!([n, premined_descriptor()]))
+            .await?)
+    }
+
+    /// Mines `n` blocks paying to `address`, e.g. to fund a wallet under
+    /// test, and returns their hashes along with the height the chain must
+    /// reach for the last coinbase to be spendable.
+    ///
+    /// ```ignore
+    /// let (_, matured_at) = bitcoind.mine_to_address(1, &address).await?;
+    /// // The tip reaches `matured_at` and the coinbase can be spent.
+    /// bitcoind.mine_blocks(COINBASE_MATURITY).await?;
+    /// ```
+    pub async fn mine_to_address(
+        &self,
+        n: u64,
+        address: &Address,
+    ) -> Result<(Vec<BlockHash>, u64), BitcoindError> {
+        let client = self.rpc_client();
+        let hashes: Vec<BlockHash> = client
+            .call("generatetoaddress", json!([n, address.to_string()]))
+            .await?;
+        let matured_at_height = match hashes.last() {
+            Some(last) => block_height(&client, *last).await? + COINBASE_MATURITY,
+            None => client.call("getblockcount", json!([])).await?,
+        };
+        Ok((hashes, matured_at_height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bitcoind::ephemeral_builder;
+    use bitcoin::{Network, PrivateKey};
+
+    #[tokio::test]
+    async fn test_start_mine_blocks() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        bitcoind.start().await?;
+        let mined = async {
+            let hashes = bitcoind.mine_blocks(3).await?;
+            let tip = bitcoind
+                .rpc_client()
+                .call::<BlockHash>("getbestblockhash", json!([]))
+                .await?;
+            Ok::<_, BitcoindError>((hashes, tip))
+        };
+        let mined = mined.await;
+        bitcoind.stop().await?;
+
+        let (hashes, tip) = mined?;
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(hashes[2], tip);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_mine_to_address() -> Result<(), BitcoindError> {
+        let secp = bitcoin::secp256k1::Secp256k1::new();
+        let key = PrivateKey::from_slice(&[2; 32], Network::Regtest).expect("valid secret key");
+        let address = Address::p2wpkh(
+            &bitcoin::CompressedPublicKey::from_private_key(&secp, &key).unwrap(),
+            Network::Regtest,
+        );
+
+        let bitcoind = ephemeral_builder().build()?;
+        bitcoind.start().await?;
+        let mined = async {
+            let (hashes, matured_at_height) = bitcoind.mine_to_address(2, &address).await?;
+            let coinbase = bitcoind
+                .rpc_client()
+                .call::<serde_json::Value>("getblock", json!([hashes[1], 2]))
+                .await?;
+            Ok::<_, BitcoindError>((hashes, matured_at_height, coinbase))
+        };
+        let mined = mined.await;
+        bitcoind.stop().await?;
+
+        let (hashes, matured_at_height, block) = mined?;
+        assert_eq!(hashes.len(), 2);
+        assert_eq!(matured_at_height, 2 + COINBASE_MATURITY);
+        assert_eq!(
+            block["tx"][0]["vout"][0]["scriptPubKey"]["address"],
+            address.to_string()
+        );
+        Ok(())
+    }
+}
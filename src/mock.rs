@@ -0,0 +1,880 @@
+//! Scriptable in-memory backend for unit tests. Enabled with the `mock` feature.
+//!
+//! ```ignore
+//! let backend = MockBackend::new().ready_after(3);
+//! let bitcoind = Bitcoind::with_backend(backend, rpc_config, BitcoindFlags::default())?;
+//! bitcoind.start().await?;
+//!
+//! bitcoind.backend().crash();
+//! assert_eq!(bitcoind.backend().calls(), vec![MockCall::Create, MockCall::Start]);
+//! ```
+
+use crate::backend::{cli_args, ExecOutput, NodeBackend, NodeSpec, NodeStatus, RpcAddress};
+use crate::error::BitcoindError;
+use crate::logs::{LogLine, LogOptions, LogStream};
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream, StreamExt};
+use std::collections::VecDeque;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// A call received by a [`MockBackend`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MockCall {
+    Create,
+    Start,
+    Stop,
+    Restart,
+    Recreate,
+    Kill,
+    Pause,
+    Unpause,
+    Exec(Vec<String>),
+    Logs,
+}
+
+/// Datadir the mock reports, the same as the Docker image's default.
+const MOCK_DATADIR: &str = "/data";
+
+/// Backend that runs nothing and answers from a script.
+///
+/// By default every operation succeeds and the node is ready as soon as it
+/// starts. Scripted errors are returned once, by the next matching call.
+pub struct MockBackend {
+    name: String,
+    state: Mutex<MockState>,
+}
+
+#[derive(Default)]
+struct MockState {
+    created: bool,
+    running: bool,
+    paused: bool,
+    exit_code: Option<i64>,
+    probes: usize,
+    ready_after: usize,
+    crash_after: Option<usize>,
+    create_error: Option<BitcoindError>,
+    start_error: Option<BitcoindError>,
+    stop_error: Option<BitcoindError>,
+    exec_outputs: VecDeque<ExecOutput>,
+    logs: String,
+    spec: Option<NodeSpec>,
+    calls: Vec<MockCall>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self {
+            name: "mock-bitcoind".to_string(),
+            state: Mutex::new(MockState::default()),
+        }
+    }
+
+    /// Sets the node name reported in logs and errors.
+    pub fn name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Makes the next `create` fail with `error`.
+    pub fn fail_create(self, error: BitcoindError) -> Self {
+        self.state().create_error = Some(error);
+        self
+    }
+
+    /// Makes the next `start` fail with `error`.
+    pub fn fail_start(self, error: BitcoindError) -> Self {
+        self.state().start_error = Some(error);
+        self
+    }
+
+    /// Makes the next `stop` fail with `error`.
+    pub fn fail_stop(self, error: BitcoindError) -> Self {
+        self.state().stop_error = Some(error);
+        self
+    }
+
+    /// Reports the node as not ready for the first `probes` readiness checks.
+    pub fn ready_after(self, probes: usize) -> Self {
+        self.state().ready_after = probes;
+        self
+    }
+
+    /// Makes the node exit after `probes` readiness checks, as if it crashed
+    /// during startup.
+    pub fn crash_after(self, probes: usize) -> Self {
+        self.state().crash_after = Some(probes);
+        self
+    }
+
+    /// Queues the output returned by the next `exec`. Without queued outputs
+    /// `exec` succeeds with empty output.
+    pub fn exec_output(self, output: ExecOutput) -> Self {
+        self.state().exec_outputs.push_back(output);
+        self
+    }
+
+    /// Sets what `logs` returns and `follow_logs` yields, line by line.
+    pub fn logs(self, logs: &str) -> Self {
+        self.state().logs = logs.to_string();
+        self
+    }
+
+    /// Makes the running node exit, e.g. to simulate a crash in the middle of a test.
+    pub fn crash(&self) {
+        let mut state = self.state();
+        state.running = false;
+        state.exit_code = Some(1);
+    }
+
+    /// Returns the calls received so far, in order. Readiness checks are not recorded.
+    pub fn calls(&self) -> Vec<MockCall> {
+        self.state().calls.clone()
+    }
+
+    /// Returns the spec passed to the last `create`.
+    pub fn spec(&self) -> Option<NodeSpec> {
+        self.state().spec.clone()
+    }
+
+    fn state(&self) -> MutexGuard<'_, MockState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl NodeBackend for MockBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn create(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Create);
+        if let Some(error) = state.create_error.take() {
+            return Err(error);
+        }
+        state.created = true;
+        state.running = false;
+        state.probes = 0;
+        state.paused = false;
+        state.exit_code = None;
+        state.spec = Some(spec.clone());
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Start);
+        if let Some(error) = state.start_error.take() {
+            return Err(error);
+        }
+        state.running = true;
+        state.exit_code = None;
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Stop);
+        if let Some(error) = state.stop_error.take() {
+            return Err(error);
+        }
+        state.created = false;
+        state.running = false;
+        state.paused = false;
+        Ok(())
+    }
+
+    async fn restart(&self, _spec: &NodeSpec) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Restart);
+        if let Some(error) = state.start_error.take() {
+            return Err(error);
+        }
+        state.running = true;
+        state.probes = 0;
+        state.exit_code = None;
+        Ok(())
+    }
+
+    async fn kill(&self) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Kill);
+        state.running = false;
+        state.paused = false;
+        state.exit_code = Some(137);
+        Ok(())
+    }
+
+    async fn pause(&self) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Pause);
+        state.paused = true;
+        Ok(())
+    }
+
+    async fn unpause(&self) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Unpause);
+        state.paused = false;
+        Ok(())
+    }
+
+    async fn recreate(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Recreate);
+        if let Some(error) = state.create_error.take() {
+            return Err(error);
+        }
+        state.running = true;
+        state.probes = 0;
+        state.exit_code = None;
+        state.spec = Some(spec.clone());
+        Ok(())
+    }
+
+    async fn is_running(&self) -> Result<bool, BitcoindError> {
+        Ok(self.state().running)
+    }
+
+    async fn status(&self) -> Result<NodeStatus, BitcoindError> {
+        let state = self.state();
+        Ok(match state.exit_code {
+            _ if state.paused => NodeStatus::Paused,
+            _ if state.running => NodeStatus::Running,
+            Some(code) => NodeStatus::Exited(code),
+            None if state.created => NodeStatus::Created,
+            None => NodeStatus::NotCreated,
+        })
+    }
+
+    async fn rpc_address(&self, spec: &NodeSpec) -> Result<RpcAddress, BitcoindError> {
+        Ok(RpcAddress {
+            host: None,
+            port: spec.rpc_port,
+        })
+    }
+
+    fn cli_command(&self, spec: &NodeSpec, args: &[String]) -> Result<Vec<String>, BitcoindError> {
+        let mut cmd = vec!["bitcoin-cli".to_string()];
+        cmd.extend(cli_args(spec.command_line([])));
+        cmd.extend(args.iter().cloned());
+        Ok(cmd)
+    }
+
+    fn datadir(&self) -> Option<String> {
+        Some(MOCK_DATADIR.to_string())
+    }
+
+    async fn exec(&self, cmd: &[String]) -> Result<ExecOutput, BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Exec(cmd.to_vec()));
+        Ok(state.exec_outputs.pop_front().unwrap_or_default())
+    }
+
+    fn follow_logs(&self) -> BoxStream<'_, Result<LogLine, BitcoindError>> {
+        let lines = self
+            .state()
+            .logs
+            .lines()
+            .map(|line| {
+                Ok(LogLine {
+                    timestamp: None,
+                    stream: LogStream::Stdout,
+                    message: line.to_string(),
+                })
+            })
+            .collect::<Vec<_>>();
+        stream::iter(lines).boxed()
+    }
+
+    async fn logs(&self, options: &LogOptions) -> Result<String, BitcoindError> {
+        let mut state = self.state();
+        state.calls.push(MockCall::Logs);
+        if !options.stdout && !options.stderr {
+            return Ok(String::new());
+        }
+        Ok(options.apply_tail(&state.logs))
+    }
+
+    async fn probe_readiness(&self) -> Option<Result<(), String>> {
+        let mut state = self.state();
+        if !state.running {
+            return Some(Err("node is not running".to_string()));
+        }
+        if state.paused {
+            return Some(Err("node is paused".to_string()));
+        }
+
+        state.probes += 1;
+        if state
+            .crash_after
+            .is_some_and(|probes| state.probes >= probes)
+        {
+            state.running = false;
+            state.exit_code = Some(1);
+            return Some(Err("node crashed".to_string()));
+        }
+        if state.probes <= state.ready_after {
+            return Some(Err(format!(
+                "not ready ({} of {})",
+                state.probes, state.ready_after
+            )));
+        }
+        Some(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bitcoind::{Bitcoind, BitcoindFlags, ReadinessConfig};
+    use crate::hooks::LifecycleHooks;
+    use crate::readiness::ReadyCondition;
+    use crate::watchdog::WatchdogConfig;
+    use bitcoin::Network;
+    use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+    use redact::Secret;
+    use serde_json::Value;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn rpc_config() -> RpcConfig {
+        RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        }
+    }
+
+    fn readiness() -> ReadinessConfig {
+        ReadinessConfig {
+            timeout: Duration::from_millis(200),
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mock_start_stop() -> Result<(), BitcoindError> {
+        let bitcoind = Bitcoind::with_backend(
+            MockBackend::new().ready_after(2),
+            rpc_config(),
+            BitcoindFlags::default(),
+        )?
+        .with_readiness_config(readiness());
+
+        bitcoind.start().await?;
+        bitcoind.stop().await?;
+
+        assert_eq!(
+            bitcoind.backend().calls(),
+            vec![MockCall::Create, MockCall::Start, MockCall::Stop]
+        );
+        assert_eq!(bitcoind.backend().spec().unwrap().rpc_port, 18443);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_restart_and_recreate() -> Result<(), BitcoindError> {
+        let mut bitcoind = Bitcoind::with_backend(
+            MockBackend::new().ready_after(1),
+            rpc_config(),
+            BitcoindFlags::default(),
+        )?
+        .with_readiness_config(readiness());
+
+        bitcoind.start().await?;
+        bitcoind.restart(true).await?;
+        bitcoind.restart(false).await?;
+        bitcoind
+            .recreate_with_flags(BitcoindFlags {
+                extra_args: vec!["-blockfilterindex=1".to_string()],
+                ..Default::default()
+            })
+            .await?;
+
+        assert_eq!(
+            bitcoind.backend().calls(),
+            vec![
+                MockCall::Create,
+                MockCall::Start,
+                MockCall::Restart,
+                MockCall::Stop,
+                MockCall::Create,
+                MockCall::Start,
+                MockCall::Recreate
+            ]
+        );
+        assert!(bitcoind
+            .backend()
+            .spec()
+            .unwrap()
+            .extra_args
+            .contains(&"-blockfilterindex=1".to_string()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_pause_and_kill() -> Result<(), BitcoindError> {
+        let bitcoind =
+            Bitcoind::with_backend(MockBackend::new(), rpc_config(), BitcoindFlags::default())?;
+        bitcoind.start().await?;
+
+        bitcoind.pause().await?;
+        assert_eq!(
+            bitcoind.backend().probe_readiness().await,
+            Some(Err("node is paused".to_string()))
+        );
+        bitcoind.unpause().await?;
+        assert_eq!(bitcoind.backend().probe_readiness().await, Some(Ok(())));
+
+        bitcoind.kill().await?;
+        assert!(!bitcoind.backend().is_running().await?);
+
+        assert_eq!(
+            bitcoind.backend().calls()[2..],
+            [MockCall::Pause, MockCall::Unpause, MockCall::Kill]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_status() -> Result<(), BitcoindError> {
+        let bitcoind =
+            Bitcoind::with_backend(MockBackend::new(), rpc_config(), BitcoindFlags::default())?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::NotCreated);
+
+        bitcoind.start().await?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::Running);
+        bitcoind.pause().await?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::Paused);
+        bitcoind.kill().await?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::Exited(137));
+
+        bitcoind.backend().create(&bitcoind.node_spec()).await?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::Created);
+        bitcoind.stop().await?;
+        assert_eq!(bitcoind.status().await?, NodeStatus::NotCreated);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_exec() -> Result<(), BitcoindError> {
+        let output = ExecOutput {
+            exit_code: 0,
+            stdout: "__cookie__:secret".to_string(),
+            stderr: String::new(),
+        };
+        let bitcoind = Bitcoind::with_backend(
+            MockBackend::new().exec_output(output.clone()),
+            rpc_config(),
+            BitcoindFlags::default(),
+        )?;
+
+        assert_eq!(
+            bitcoind.exec(&["cat", "/data/regtest/.cookie"]).await?,
+            output
+        );
+        assert_eq!(
+            bitcoind.backend().calls(),
+            [MockCall::Exec(vec![
+                "cat".to_string(),
+                "/data/regtest/.cookie".to_string()
+            ])]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_logs() -> Result<(), BitcoindError> {
+        let bitcoind = Bitcoind::with_backend(
+            MockBackend::new().logs("Bitcoin Core version v29.1\nError: Cannot obtain a lock\n"),
+            rpc_config(),
+            BitcoindFlags::default(),
+        )?;
+
+        assert_eq!(
+            bitcoind.logs(LogOptions::tail(1)).await?,
+            "Error: Cannot obtain a lock\n"
+        );
+        let options = LogOptions {
+            stdout: false,
+            stderr: false,
+            ..Default::default()
+        };
+        assert_eq!(bitcoind.logs(options).await?, "");
+        assert_eq!(bitcoind.backend().calls(), [MockCall::Logs, MockCall::Logs]);
+
+        let lines = bitcoind.follow_logs().collect::<Vec<_>>().await;
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[1].as_ref().unwrap().message,
+            "Error: Cannot obtain a lock"
+        );
+
+        let timeout = Duration::from_secs(1);
+        assert_eq!(
+            bitcoind.wait_for_log("lock", timeout).await?.message,
+            "Error: Cannot obtain a lock"
+        );
+        assert!(matches!(
+            bitcoind.wait_for_log("UpdateTip", timeout).await,
+            Err(BitcoindError::LogEnded(_))
+        ));
+
+        let dir = std::env::temp_dir().join("bitcoind-test-log-artifacts");
+        let bitcoind = Bitcoind::with_backend(
+            MockBackend::new()
+                .logs("Error: Cannot obtain a lock\n")
+                .fail_start(BitcoindError::ReadinessFailed("boom".to_string())),
+            rpc_config(),
+            BitcoindFlags::default(),
+        )?
+        .with_log_artifacts(&dir);
+        assert!(bitcoind.start().await.is_err());
+        let saved = std::fs::read_to_string(dir.join("mock-bitcoind.log")).unwrap();
+        let debug_log = dir.join("mock-bitcoind.debug.log").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(saved, "Error: Cannot obtain a lock\n");
+        assert!(debug_log);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_debug_log() -> Result<(), BitcoindError> {
+        let output = |stdout: &str| ExecOutput {
+            stdout: stdout.to_string(),
+            ..Default::default()
+        };
+        let backend = MockBackend::new()
+            .exec_output(output("2025-01-01T12:00:00Z UpdateTip\n"))
+            .exec_output(output(
+                "2025-01-01T12:00:00Z Bitcoin Core\n2025-01-01T12:00:01Z Upda",
+            ))
+            .exec_output(output("teTip\n"));
+        let bitcoind = Bitcoind::with_backend(backend, rpc_config(), BitcoindFlags::default())?;
+
+        assert_eq!(bitcoind.debug_log_path()?, "/data/regtest/debug.log");
+        assert_eq!(
+            bitcoind.debug_log(Some(1)).await?,
+            "2025-01-01T12:00:00Z UpdateTip\n"
+        );
+        let lines = bitcoind
+            .follow_debug_log()
+            .take(2)
+            .map(|line| line.unwrap().message)
+            .collect::<Vec<_>>()
+            .await;
+        assert_eq!(lines, ["Bitcoin Core", "UpdateTip"]);
+
+        let calls = bitcoind.backend().calls();
+        assert_eq!(
+            calls[0],
+            MockCall::Exec(
+                ["tail", "-n", "1", "/data/regtest/debug.log"]
+                    .map(String::from)
+                    .to_vec()
+            )
+        );
+        assert_eq!(
+            calls[2],
+            MockCall::Exec(
+                ["tail", "-c", "+60", "/data/regtest/debug.log"]
+                    .map(String::from)
+                    .to_vec()
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_cli() -> Result<(), BitcoindError> {
+        let output = |exit_code: i64, stdout: &str| ExecOutput {
+            exit_code,
+            stdout: stdout.to_string(),
+            stderr: if exit_code == 0 { "" } else { "error code: -8" }.to_string(),
+        };
+        let backend = MockBackend::new()
+            .exec_output(output(0, "101\n"))
+            .exec_output(output(0, "bcrt1qaddress\n"))
+            .exec_output(output(1, ""));
+        let bitcoind = Bitcoind::with_backend(backend, rpc_config(), BitcoindFlags::default())?;
+
+        assert_eq!(bitcoind.cli::<u64>(&["getblockcount"]).await?, 101);
+        assert_eq!(
+            bitcoind.cli::<String>(&["getnewaddress"]).await?,
+            "bcrt1qaddress"
+        );
+        assert!(matches!(
+            bitcoind.cli::<Value>(&["getblockhash", "1000"]).await,
+            Err(BitcoindError::Cli { exit_code: 1, .. })
+        ));
+
+        assert_eq!(
+            bitcoind.backend().calls()[0],
+            MockCall::Exec(
+                [
+                    "bitcoin-cli",
+                    "-regtest=1",
+                    "-rpcuser=foo",
+                    "-rpcpassword=rpcpassword",
+                    "getblockcount"
+                ]
+                .map(String::from)
+                .to_vec()
+            )
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_wait_until_ready_conditions() -> Result<(), BitcoindError> {
+        let mut config = rpc_config();
+        // Nothing listens on port 1, so RPC conditions are never met.
+        config.url = Secret::new("http://127.0.0.1:1".to_string());
+        let bitcoind =
+            Bitcoind::with_backend(MockBackend::new(), config, BitcoindFlags::default())?
+                .with_readiness_config(readiness());
+        bitcoind.start().await?;
+
+        bitcoind
+            .wait_until_ready(&[], Duration::from_millis(200))
+            .await?;
+        match bitcoind
+            .wait_until_ready(
+                &[ReadyCondition::MinBlockHeight(101)],
+                Duration::from_millis(200),
+            )
+            .await
+        {
+            Err(BitcoindError::ReadinessTimeout { last_error, .. }) => {
+                assert!(last_error.starts_with("block height 101: "))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        assert!(matches!(
+            bitcoind
+                .wait_for_block_height(5, Duration::from_millis(200))
+                .await,
+            Err(BitcoindError::ReadinessTimeout { .. })
+        ));
+        let txid = <bitcoin::Txid as bitcoin::hashes::Hash>::all_zeros();
+        match bitcoind
+            .wait_for_mempool_tx(txid, Duration::from_millis(200))
+            .await
+        {
+            Err(BitcoindError::ReadinessTimeout { last_error, .. }) => {
+                assert_eq!(last_error, format!("{} is not in the mempool", txid))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        bitcoind.backend().crash();
+        assert!(matches!(
+            bitcoind
+                .wait_until_ready(&[ReadyCondition::ZmqBound], Duration::from_millis(200))
+                .await,
+            Err(BitcoindError::ReadinessFailed(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_waits_bounded_by_silent_node() -> Result<(), BitcoindError> {
+        // Connections are accepted by the kernel but never answered, like a
+        // paused node.
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config = rpc_config();
+        config.url = Secret::new(format!("http://{}", listener.local_addr().unwrap()));
+        let bitcoind =
+            Bitcoind::with_backend(MockBackend::new(), config, BitcoindFlags::default())?
+                .with_readiness_config(readiness());
+        bitcoind.start().await?;
+
+        let timeout = Duration::from_millis(200);
+        let waited = tokio::time::timeout(Duration::from_secs(5), async {
+            let height = bitcoind.wait_for_block_height(5, timeout).await;
+            let txid = <bitcoin::Txid as bitcoin::hashes::Hash>::all_zeros();
+            let mempool = bitcoind.wait_for_mempool_tx(txid, timeout).await;
+            (height, mempool)
+        })
+        .await
+        .expect("waits outlived their timeout");
+        for result in [waited.0, waited.1] {
+            match result {
+                Err(BitcoindError::ReadinessTimeout { last_error, .. }) => {
+                    assert!(last_error.ends_with(crate::readiness::NO_ANSWER))
+                }
+                other => panic!("unexpected result: {:?}", other),
+            }
+        }
+        Ok(())
+    }
+
+    #[derive(Clone, Default)]
+    struct RecordingHooks(Arc<Mutex<Vec<String>>>);
+
+    #[async_trait]
+    impl LifecycleHooks for RecordingHooks {
+        async fn on_starting(&self, name: &str) {
+            self.0.lock().unwrap().push(format!("starting {}", name));
+        }
+
+        async fn on_ready(&self, name: &str, rpc_config: &RpcConfig) {
+            let url = rpc_config.url.expose_secret();
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("ready {} {}", name, url));
+        }
+
+        async fn on_stopped(&self, name: &str) {
+            self.0.lock().unwrap().push(format!("stopped {}", name));
+        }
+
+        async fn on_crashed(&self, name: &str, status: NodeStatus) {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("crashed {} {:?}", name, status));
+        }
+
+        async fn on_gave_up(&self, name: &str, _error: &BitcoindError) {
+            self.0.lock().unwrap().push(format!("gave up {}", name));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle_hooks() -> Result<(), BitcoindError> {
+        let hooks = RecordingHooks::default();
+        let backend = MockBackend::new()
+            .name("node-a")
+            .fail_start(BitcoindError::ReadinessFailed("boom".to_string()));
+        let bitcoind = Bitcoind::with_backend(backend, rpc_config(), BitcoindFlags::default())?
+            .with_lifecycle_hooks(hooks.clone());
+
+        assert!(bitcoind.start().await.is_err());
+        bitcoind.start().await?;
+        bitcoind.restart(true).await?;
+        bitcoind.stop().await?;
+
+        assert_eq!(
+            *hooks.0.lock().unwrap(),
+            vec![
+                "starting node-a",
+                "starting node-a",
+                "ready node-a http://localhost:18443/",
+                "starting node-a",
+                "ready node-a http://localhost:18443/",
+                "stopped node-a",
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_watchdog_restarts_crashed_node() -> Result<(), BitcoindError> {
+        let hooks = RecordingHooks::default();
+        let backend = MockBackend::new().name("node-a");
+        let bitcoind = Arc::new(
+            Bitcoind::with_backend(backend, rpc_config(), BitcoindFlags::default())?
+                .with_lifecycle_hooks(hooks.clone()),
+        );
+        let config = WatchdogConfig {
+            poll_interval: Duration::from_millis(10),
+            max_restarts: 1,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(10),
+        };
+        bitcoind.start().await?;
+        let watchdog = bitcoind.watchdog(config);
+
+        bitcoind.backend().crash();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(watchdog.restarts(), 1);
+        assert!(bitcoind.backend().calls().contains(&MockCall::Restart));
+        assert_eq!(bitcoind.status().await?, NodeStatus::Running);
+
+        // A node being started or restored by another call is left alone.
+        let lifecycle = bitcoind.lifecycle.lock().await;
+        bitcoind.backend().crash();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(watchdog.restarts(), 1);
+        bitcoind.restart_node(true).await?;
+        drop(lifecycle);
+
+        // A stopped node is left alone.
+        bitcoind.stop().await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(watchdog.restarts(), 1);
+        assert_eq!(bitcoind.backend().calls().last(), Some(&MockCall::Stop));
+
+        // Past max_restarts the watchdog gives up.
+        bitcoind.start().await?;
+        bitcoind.backend().crash();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(watchdog.is_finished());
+        let events = hooks.0.lock().unwrap();
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| event.starts_with("crashed") || event.starts_with("gave up"))
+                .collect::<Vec<_>>(),
+            vec![
+                "crashed node-a Exited(1)",
+                "crashed node-a Exited(1)",
+                "gave up node-a"
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mock_scripted_failures() -> Result<(), BitcoindError> {
+        let backend =
+            MockBackend::new().fail_start(BitcoindError::ReadinessFailed("boom".to_string()));
+        let bitcoind = Bitcoind::with_backend(backend, rpc_config(), BitcoindFlags::default())?
+            .with_readiness_config(readiness());
+        assert!(matches!(
+            bitcoind.start().await,
+            Err(BitcoindError::ReadinessFailed(_))
+        ));
+        // The error is returned once.
+        bitcoind.start().await?;
+
+        let bitcoind = Bitcoind::with_backend(
+            MockBackend::new().crash_after(1),
+            rpc_config(),
+            BitcoindFlags::default(),
+        )?
+        .with_readiness_config(readiness());
+        assert!(matches!(
+            bitcoind.start().await,
+            Err(BitcoindError::ReadinessFailed(_))
+        ));
+
+        let bitcoind = Bitcoind::with_backend(
+            MockBackend::new().ready_after(usize::MAX),
+            rpc_config(),
+            BitcoindFlags::default(),
+        )?
+        .with_readiness_config(readiness());
+        assert!(matches!(
+            bitcoind.start().await,
+            Err(BitcoindError::StartupTimeout { .. })
+        ));
+        Ok(())
+    }
+}
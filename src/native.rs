@@ -0,0 +1,439 @@
+//! Backend that runs a locally installed `bitcoind` binary, for environments
+//! without Docker. Enabled with the `native` feature.
+
+use crate::backend::{cli_args, ExecOutput, NodeBackend, NodeSpec, NodeStatus, RpcAddress};
+use crate::bitcoind::Timeouts;
+use crate::error::BitcoindError;
+use crate::logs::LogOptions;
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tokio::process::{Child, Command};
+use tracing::{info, warn};
+
+/// Name of the file the node's console output is written to, inside the datadir.
+const CONSOLE_LOG: &str = "console.log";
+
+/// Runs `bitcoind` as a child process with its own datadir.
+///
+/// Unless a datadir is set, every `start` uses a fresh temporary one that is
+/// deleted by `stop`. `stop` and `restart` shut the node down with `SIGTERM`;
+/// the process is killed when the backend is dropped.
+pub struct NativeBackend {
+    name: String,
+    binary: PathBuf,
+    datadir: Option<PathBuf>,
+    p2p_port: Option<u16>,
+    stop_timeout: Duration,
+    state: Mutex<NativeState>,
+}
+
+#[derive(Default)]
+struct NativeState {
+    child: Option<Child>,
+    paused: bool,
+    args: Vec<String>,
+    datadir: Option<PathBuf>,
+}
+
+impl Default for NativeBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NativeBackend {
+    /// Creates a backend that runs the `bitcoind` found on `PATH`.
+    pub fn new() -> Self {
+        static NODES: AtomicUsize = AtomicUsize::new(0);
+
+        Self {
+            name: format!(
+                "bitcoind-{}-{}",
+                std::process::id(),
+                NODES.fetch_add(1, Ordering::Relaxed)
+            ),
+            binary: PathBuf::from("bitcoind"),
+            datadir: None,
+            p2p_port: None,
+            stop_timeout: Timeouts::default().stop,
+            state: Mutex::new(NativeState::default()),
+        }
+    }
+
+    /// Sets the path of the `bitcoind` binary.
+    pub fn binary<P: Into<PathBuf>>(mut self, binary: P) -> Self {
+        self.binary = binary.into();
+        self
+    }
+
+    /// Uses a fixed datadir, which is kept after `stop`.
+    pub fn datadir<P: Into<PathBuf>>(mut self, datadir: P) -> Self {
+        self.datadir = Some(datadir.into());
+        self
+    }
+
+    /// Listens for P2P connections on the given port. By default P2P is
+    /// disabled so several nodes can run side by side.
+    pub fn p2p_port(mut self, p2p_port: u16) -> Self {
+        self.p2p_port = Some(p2p_port);
+        self
+    }
+
+    /// Sets how long `stop` waits for `bitcoind` to shut down after `SIGTERM`
+    /// before killing it. Defaults to 10 seconds.
+    pub fn stop_timeout(mut self, timeout: Duration) -> Self {
+        self.stop_timeout = timeout;
+        self
+    }
+
+    /// Returns the datadir of the current node, once created.
+    pub fn current_datadir(&self) -> Option<PathBuf> {
+        self.state().datadir.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, NativeState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn bitcoind_args(&self, spec: &NodeSpec, datadir: &Path) -> Vec<String> {
+        let mut args = vec![
+            format!("-datadir={}", datadir.display()),
+            format!("-rpcport={}", spec.rpc_port),
+            "-rpcbind=127.0.0.1".to_string(),
+            "-rpcallowip=127.0.0.1".to_string(),
+        ];
+        match self.p2p_port {
+            Some(port) => args.push(format!("-port={}", port)),
+            None => args.push("-listen=0".to_string()),
+        }
+        spec.command_line(args)
+    }
+
+    async fn kill_process(&self) -> Result<(), BitcoindError> {
+        let child = {
+            let mut state = self.state();
+            state.paused = false;
+            state.child.take()
+        };
+        if let Some(mut child) = child {
+            info!("Killing bitcoind process {}", self.name);
+            child.kill().await.map_err(BitcoindError::Process)?;
+        }
+        Ok(())
+    }
+
+    /// Asks `bitcoind` to shut down with `SIGTERM`, so the datadir is flushed,
+    /// and kills it if it hasn't exited within the stop timeout.
+    async fn stop_process(&self) -> Result<(), BitcoindError> {
+        let paused = self.state().paused;
+        let terminated = async {
+            // A stopped process only handles `SIGTERM` once continued.
+            if paused {
+                self.signal("CONT").await?;
+            }
+            self.signal("TERM").await
+        }
+        .await;
+        let child = {
+            let mut state = self.state();
+            state.paused = false;
+            state.child.take()
+        };
+        let Some(mut child) = child else {
+            return Ok(());
+        };
+
+        info!("Stopping bitcoind process {}", self.name);
+        if terminated.is_ok() {
+            match tokio::time::timeout(self.stop_timeout, child.wait()).await {
+                Ok(exited) => return exited.map(|_| ()).map_err(BitcoindError::Process),
+                Err(_) => warn!(
+                    "bitcoind did not exit within {:?}, killing it",
+                    self.stop_timeout
+                ),
+            }
+        }
+        child.kill().await.map_err(BitcoindError::Process)
+    }
+
+    /// Sends `signal` to the running process with `kill`.
+    async fn signal(&self, signal: &str) -> Result<(), BitcoindError> {
+        let pid = self
+            .state()
+            .child
+            .as_ref()
+            .and_then(Child::id)
+            .ok_or_else(|| BitcoindError::InvalidConfig("node is not running".to_string()))?;
+        let status = Command::new("kill")
+            .arg(format!("-{}", signal))
+            .arg(pid.to_string())
+            .status()
+            .await
+            .map_err(BitcoindError::Process)?;
+        if !status.success() {
+            return Err(BitcoindError::Process(std::io::Error::other(format!(
+                "kill -{} {} failed: {}",
+                signal, pid, status
+            ))));
+        }
+        Ok(())
+    }
+
+    fn remove_temp_datadir(&self) {
+        let datadir = self.state().datadir.take();
+        if let Some(datadir) = datadir.filter(|_| self.datadir.is_none()) {
+            let _ = std::fs::remove_dir_all(datadir);
+        }
+    }
+}
+
+#[async_trait]
+impl NodeBackend for NativeBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn create(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        self.stop_process().await?;
+        self.remove_temp_datadir();
+
+        let datadir = match &self.datadir {
+            Some(datadir) => datadir.clone(),
+            None => std::env::temp_dir().join(&self.name),
+        };
+        std::fs::create_dir_all(&datadir).map_err(BitcoindError::Process)?;
+
+        let mut state = self.state();
+        state.args = self.bitcoind_args(spec, &datadir);
+        state.datadir = Some(datadir);
+        Ok(())
+    }
+
+    async fn start(&self) -> Result<(), BitcoindError> {
+        let mut state = self.state();
+        let datadir = state.datadir.clone().ok_or_else(|| {
+            BitcoindError::InvalidConfig("node must be created before it is started".to_string())
+        })?;
+
+        info!(
+            "Starting {} in {}",
+            self.binary.display(),
+            datadir.display()
+        );
+        let console =
+            std::fs::File::create(datadir.join(CONSOLE_LOG)).map_err(BitcoindError::Process)?;
+        let child = Command::new(&self.binary)
+            .args(&state.args)
+            .stdin(Stdio::null())
+            .stdout(console.try_clone().map_err(BitcoindError::Process)?)
+            .stderr(console)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(BitcoindError::Process)?;
+
+        state.child = Some(child);
+        Ok(())
+    }
+
+    async fn stop(&self) -> Result<(), BitcoindError> {
+        self.stop_process().await?;
+        self.remove_temp_datadir();
+        Ok(())
+    }
+
+    async fn restart(&self, _spec: &NodeSpec) -> Result<(), BitcoindError> {
+        // Keeps the datadir, even a temporary one.
+        self.stop_process().await?;
+        self.start().await
+    }
+
+    async fn kill(&self) -> Result<(), BitcoindError> {
+        // Keeps the datadir, even a temporary one, for `restart`.
+        self.kill_process().await
+    }
+
+    async fn pause(&self) -> Result<(), BitcoindError> {
+        self.signal("STOP").await?;
+        self.state().paused = true;
+        Ok(())
+    }
+
+    async fn unpause(&self) -> Result<(), BitcoindError> {
+        self.signal("CONT").await?;
+        self.state().paused = false;
+        Ok(())
+    }
+
+    async fn recreate(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        self.stop_process().await?;
+        {
+            let mut state = self.state();
+            let datadir = state.datadir.clone().ok_or_else(|| {
+                BitcoindError::InvalidConfig(
+                    "node must be created before it is recreated".to_string(),
+                )
+            })?;
+            state.args = self.bitcoind_args(spec, &datadir);
+        }
+        self.start().await
+    }
+
+    async fn status(&self) -> Result<NodeStatus, BitcoindError> {
+        let mut state = self.state();
+        let paused = state.paused;
+        let created = state.datadir.is_some();
+        Ok(match state.child.as_mut() {
+            Some(child) => match child.try_wait().map_err(BitcoindError::Process)? {
+                // Killed by a signal when there is no exit code.
+                Some(status) => NodeStatus::Exited(status.code().unwrap_or(137).into()),
+                None if paused => NodeStatus::Paused,
+                None => NodeStatus::Running,
+            },
+            None if created => NodeStatus::Created,
+            None => NodeStatus::NotCreated,
+        })
+    }
+
+    async fn is_running(&self) -> Result<bool, BitcoindError> {
+        match self.state().child.as_mut() {
+            Some(child) => Ok(child.try_wait().map_err(BitcoindError::Process)?.is_none()),
+            None => Ok(false),
+        }
+    }
+
+    async fn rpc_address(&self, spec: &NodeSpec) -> Result<RpcAddress, BitcoindError> {
+        Ok(RpcAddress {
+            host: None,
+            port: spec.rpc_port,
+        })
+    }
+
+    fn cli_command(&self, spec: &NodeSpec, args: &[String]) -> Result<Vec<String>, BitcoindError> {
+        let datadir = self.current_datadir().ok_or_else(|| {
+            BitcoindError::InvalidConfig("node must be created before bitcoin-cli runs".to_string())
+        })?;
+        // `bitcoin-cli` is installed next to `bitcoind`.
+        let mut cmd = vec![self
+            .binary
+            .with_file_name("bitcoin-cli")
+            .to_string_lossy()
+            .into_owned()];
+        cmd.extend(cli_args(self.bitcoind_args(spec, &datadir)));
+        cmd.extend(args.iter().cloned());
+        Ok(cmd)
+    }
+
+    fn datadir(&self) -> Option<String> {
+        self.current_datadir()
+            .map(|datadir| datadir.to_string_lossy().into_owned())
+    }
+
+    async fn exec(&self, cmd: &[String]) -> Result<ExecOutput, BitcoindError> {
+        let (program, args) = cmd
+            .split_first()
+            .ok_or_else(|| BitcoindError::InvalidConfig("empty command".to_string()))?;
+        let output = Command::new(program)
+            .args(args)
+            .output()
+            .await
+            .map_err(BitcoindError::Process)?;
+
+        Ok(ExecOutput {
+            exit_code: output.status.code().unwrap_or(-1).into(),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        })
+    }
+
+    async fn logs(&self, options: &LogOptions) -> Result<String, BitcoindError> {
+        match self.current_datadir() {
+            Some(datadir) if options.stdout || options.stderr => {
+                let logs = std::fs::read_to_string(datadir.join(CONSOLE_LOG))
+                    .map_err(BitcoindError::Process)?;
+                Ok(options.apply_tail(&logs))
+            }
+            _ => Ok(String::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use bitcoin::Network;
+
+    #[tokio::test]
+    async fn test_native_backend_args() -> Result<(), BitcoindError> {
+        let backend = NativeBackend::new().binary("/usr/local/bin/bitcoind");
+        let spec = NodeSpec {
+            network: Network::Regtest,
+            rpc_port: 28443,
+            args: vec!["-regtest=1".to_string()],
+            extra_args: vec!["-acceptnonstdtxn=1".to_string()],
+        };
+
+        backend.create(&spec).await?;
+        let datadir = backend.current_datadir().unwrap();
+        assert!(datadir.is_dir());
+        assert!(!backend.is_running().await?);
+
+        let args = backend.state().args.clone();
+        assert_eq!(args.first().unwrap(), "-regtest=1");
+        assert!(args.contains(&format!("-datadir={}", datadir.display())));
+        assert!(args.contains(&"-rpcport=28443".to_string()));
+        assert!(args.contains(&"-listen=0".to_string()));
+        assert_eq!(args.last().unwrap(), "-acceptnonstdtxn=1");
+
+        backend.stop().await?;
+        assert!(!datadir.exists());
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_native_stop_terminates_cleanly() -> Result<(), BitcoindError> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir =
+            std::env::temp_dir().join(format!("bitcoind-test-native-stop-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let marker = dir.join("terminated");
+        // Stands in for bitcoind, recording a clean shutdown on SIGTERM.
+        let binary = dir.join("bitcoind");
+        std::fs::write(
+            &binary,
+            format!(
+                "#!/bin/sh\ntrap 'touch {}; exit 0' TERM\nwhile true; do sleep 0.05; done\n",
+                marker.display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let backend = NativeBackend::new()
+            .binary(&binary)
+            .datadir(dir.join("data"))
+            .stop_timeout(Duration::from_secs(5));
+        let spec = NodeSpec {
+            network: Network::Regtest,
+            rpc_port: 28444,
+            args: vec![],
+            extra_args: vec![],
+        };
+        backend.create(&spec).await?;
+        backend.start().await?;
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        backend.stop().await?;
+
+        let terminated = marker.is_file();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(terminated);
+        Ok(())
+    }
+}
@@ -0,0 +1,293 @@
+use crate::error::BitcoindError;
+use bitcoin::Network;
+use bollard::models::PortMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// RPC port `bitcoind` listens on inside the container, the regtest default,
+/// whatever the network.
+pub const RPC_CONTAINER_PORT: u16 = 18443;
+/// P2P port `bitcoind` listens on inside the container, the regtest default,
+/// whatever the network.
+pub const P2P_CONTAINER_PORT: u16 = 18444;
+/// Port `bitcoind` publishes ZMQ notifications on inside the container.
+pub const ZMQ_CONTAINER_PORT: u16 = 28332;
+
+/// ZMQ notification `bitcoind` can publish, one `-zmqpub<topic>` option each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ZmqTopic {
+    HashBlock,
+    HashTx,
+    RawBlock,
+    RawTx,
+    /// Blocks connected and disconnected and transactions added to and
+    /// removed from the mempool, with their mempool sequence number.
+    Sequence,
+}
+
+impl ZmqTopic {
+    pub const ALL: [ZmqTopic; 5] = [
+        ZmqTopic::HashBlock,
+        ZmqTopic::HashTx,
+        ZmqTopic::RawBlock,
+        ZmqTopic::RawTx,
+        ZmqTopic::Sequence,
+    ];
+
+    /// Topic name, as in `-zmqpub<name>` and the first frame of each message.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ZmqTopic::HashBlock => "hashblock",
+            ZmqTopic::HashTx => "hashtx",
+            ZmqTopic::RawBlock => "rawblock",
+            ZmqTopic::RawTx => "rawtx",
+            ZmqTopic::Sequence => "sequence",
+        }
+    }
+
+    /// Port the topic is published on inside the container when it is
+    /// configured on its own with [`ZmqPorts`].
+    pub fn container_port(&self) -> u16 {
+        match self {
+            ZmqTopic::HashBlock => 28334,
+            ZmqTopic::HashTx => 28335,
+            ZmqTopic::RawBlock => 28336,
+            ZmqTopic::RawTx => 28337,
+            ZmqTopic::Sequence => 28338,
+        }
+    }
+}
+
+impl std::fmt::Display for ZmqTopic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Host ports for ZMQ notifications published one topic per port, so a
+/// subscriber only receives the topic it asked for.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ZmqPorts {
+    pub hashblock: Option<u16>,
+    pub hashtx: Option<u16>,
+    pub rawblock: Option<u16>,
+    pub rawtx: Option<u16>,
+    pub sequence: Option<u16>,
+}
+
+impl ZmqPorts {
+    /// Sets the host port of `topic`.
+    pub fn set(&mut self, topic: ZmqTopic, host_port: Option<u16>) {
+        match topic {
+            ZmqTopic::HashBlock => self.hashblock = host_port,
+            ZmqTopic::HashTx => self.hashtx = host_port,
+            ZmqTopic::RawBlock => self.rawblock = host_port,
+            ZmqTopic::RawTx => self.rawtx = host_port,
+            ZmqTopic::Sequence => self.sequence = host_port,
+        }
+    }
+
+    /// Iterates over the enabled `(topic, host_port)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (ZmqTopic, u16)> {
+        let ports = [
+            self.hashblock,
+            self.hashtx,
+            self.rawblock,
+            self.rawtx,
+            self.sequence,
+        ];
+        ZmqTopic::ALL
+            .into_iter()
+            .zip(ports)
+            .filter_map(|(topic, host_port)| Some((topic, host_port?)))
+    }
+}
+
+/// Address a ZMQ topic can be subscribed to from the host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZmqEndpoint {
+    pub topic: ZmqTopic,
+    /// `tcp://host:port` address for a ZMQ `SUB` socket.
+    pub address: String,
+}
+
+/// Node services published on the host in addition to RPC.
+///
+/// Each service is off by default. Setting a host port publishes the matching
+/// container port there, so tools such as electrs or other nodes can reach it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublishedPorts {
+    /// Host port for P2P connections.
+    pub p2p: Option<u16>,
+    /// Host port for ZMQ `rawblock`, `rawtx`, `hashblock` and `hashtx` notifications.
+    pub zmq: Option<u16>,
+    /// Host ports for ZMQ notifications published one topic per port.
+    pub zmq_topics: ZmqPorts,
+    /// Enables the REST interface. bitcoind serves REST on the RPC port, so no
+    /// extra port is published.
+    pub rest: bool,
+}
+
+/// Host ports published for the container, keyed by container port.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MappedPorts {
+    ports: BTreeMap<u16, u16>,
+}
+
+impl MappedPorts {
+    pub(crate) fn from_port_map(port_map: &PortMap) -> Self {
+        let ports = port_map
+            .iter()
+            .filter_map(|(container_port, bindings)| {
+                let container_port = container_port.strip_suffix("/tcp")?.parse().ok()?;
+                let host_port = bindings
+                    .as_ref()?
+                    .iter()
+                    .find_map(|binding| binding.host_port.as_ref()?.parse().ok())?;
+                Some((container_port, host_port))
+            })
+            .collect();
+
+        Self { ports }
+    }
+
+    /// Host port mapped to the RPC port.
+    pub fn rpc(&self) -> Option<u16> {
+        self.get(RPC_CONTAINER_PORT)
+    }
+
+    /// Host port mapped to the P2P port.
+    pub fn p2p(&self) -> Option<u16> {
+        self.get(P2P_CONTAINER_PORT)
+    }
+
+    /// Host port mapped to the ZMQ port.
+    pub fn zmq(&self) -> Option<u16> {
+        self.get(ZMQ_CONTAINER_PORT)
+    }
+
+    /// Host port mapped to the container port of `topic` published on its own.
+    pub fn zmq_topic(&self, topic: ZmqTopic) -> Option<u16> {
+        self.get(topic.container_port())
+    }
+
+    /// Host port mapped to the given container port.
+    pub fn get(&self, container_port: u16) -> Option<u16> {
+        self.ports.get(&container_port).copied()
+    }
+
+    /// Iterates over `(container_port, host_port)` pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (u16, u16)> + '_ {
+        self.ports
+            .iter()
+            .map(|(container, host)| (*container, *host))
+    }
+}
+
+/// Default RPC port of `network`, e.g. 8332 on mainnet.
+pub fn default_rpc_port(network: Network) -> u16 {
+    match network {
+        Network::Bitcoin => 8332,
+        Network::Testnet => 18332,
+        Network::Testnet4 => 48332,
+        Network::Signet => 38332,
+        _ => 18443,
+    }
+}
+
+/// Default P2P port of `network`, e.g. 8333 on mainnet.
+pub fn default_p2p_port(network: Network) -> u16 {
+    match network {
+        Network::Bitcoin => 8333,
+        Network::Testnet => 18333,
+        Network::Testnet4 => 48333,
+        Network::Signet => 38333,
+        _ => 18444,
+    }
+}
+
+/// Extracts the RPC port from the RPC URL, defaulting to the network's
+/// [`default_rpc_port`] when the URL has no explicit port.
+pub(crate) fn parse_rpc_port(url: &str, network: Network) -> Result<u16, BitcoindError> {
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| BitcoindError::InvalidConfig(format!("invalid RPC url {:?}: {}", url, e)))?;
+
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(BitcoindError::InvalidConfig(format!(
+            "invalid RPC url {:?}: expected http://host:port",
+            url
+        )));
+    }
+
+    Ok(parsed.port().unwrap_or(default_rpc_port(network)))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use bollard::models::PortBinding;
+
+    #[test]
+    fn test_parse_rpc_port() {
+        let regtest = Network::Regtest;
+        assert_eq!(
+            parse_rpc_port("http://localhost:18443", regtest).unwrap(),
+            18443
+        );
+        assert_eq!(
+            parse_rpc_port("http://127.0.0.1:28443/", regtest).unwrap(),
+            28443
+        );
+        assert_eq!(
+            parse_rpc_port("http://localhost", regtest).unwrap(),
+            RPC_CONTAINER_PORT
+        );
+        assert_eq!(
+            parse_rpc_port("http://localhost", Network::Signet).unwrap(),
+            38332
+        );
+        assert!(parse_rpc_port("localhost:18443", regtest).is_err());
+        assert!(parse_rpc_port("not a url", regtest).is_err());
+    }
+
+    #[test]
+    fn test_mapped_ports_from_port_map() {
+        let binding = |port: &str| {
+            Some(vec![PortBinding {
+                host_ip: Some("0.0.0.0".to_string()),
+                host_port: Some(port.to_string()),
+            }])
+        };
+        let port_map: PortMap = [
+            ("18443/tcp".to_string(), binding("49153")),
+            ("18444/tcp".to_string(), None),
+            ("28332/tcp".to_string(), binding("49154")),
+        ]
+        .into_iter()
+        .collect();
+
+        let ports = MappedPorts::from_port_map(&port_map);
+        assert_eq!(ports.rpc(), Some(49153));
+        assert_eq!(ports.p2p(), None);
+        assert_eq!(ports.zmq(), Some(49154));
+    }
+
+    #[test]
+    fn test_zmq_ports() {
+        let mut ports = ZmqPorts::default();
+        ports.set(ZmqTopic::RawBlock, Some(29000));
+        ports.set(ZmqTopic::Sequence, Some(29001));
+        assert_eq!(
+            ports.iter().collect::<Vec<_>>(),
+            [(ZmqTopic::RawBlock, 29000), (ZmqTopic::Sequence, 29001)]
+        );
+
+        let parsed: PublishedPorts = toml::from_str("[zmq_topics]\nhashblock = 29002\n").unwrap();
+        assert_eq!(parsed.zmq_topics.hashblock, Some(29002));
+        assert_eq!(ZmqTopic::HashBlock.to_string(), "hashblock");
+    }
+}
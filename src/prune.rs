@@ -0,0 +1,121 @@
+//! Pruned nodes: what `getblockchaininfo` reports about pruning, and manual
+//! pruning with `pruneblockchain`.
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use crate::rpc::RpcError;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Pruning state of the node, from `getblockchaininfo`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruneInfo {
+    pub pruned: bool,
+    /// Lowest height whose block is still stored. Only set when pruned.
+    #[serde(rename = "pruneheight")]
+    pub prune_height: Option<u64>,
+    /// `false` with `-prune=1`, where only `pruneblockchain` prunes.
+    pub automatic_pruning: Option<bool>,
+    /// Target size of the block files in bytes, with automatic pruning.
+    pub prune_target_size: Option<u64>,
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Returns whether and how far the node has pruned its blocks.
+    pub async fn prune_info(&self) -> Result<PruneInfo, BitcoindError> {
+        Ok(self
+            .rpc_client()
+            .call::<PruneInfo>("getblockchaininfo", json!([]))
+            .await?)
+    }
+
+    /// Prunes the blocks up to `height` with `pruneblockchain` and returns
+    /// the height of the last block pruned. Block files are dropped whole,
+    /// so blocks below `height` may remain.
+    ///
+    /// The node must run with `-prune`, see [`BitcoindFlags::prune`].
+    ///
+    /// [`BitcoindFlags::prune`]: crate::bitcoind::BitcoindFlags::prune
+    pub async fn prune_blockchain(&self, height: u64) -> Result<u64, BitcoindError> {
+        Ok(self
+            .rpc_client()
+            .call::<u64>("pruneblockchain", json!([height]))
+            .await?)
+    }
+
+    /// Returns whether the node still stores the block at `height`, e.g. to
+    /// assert that a pruned peer can no longer serve it.
+    pub async fn has_block(&self, height: u64) -> Result<bool, BitcoindError> {
+        let client = self.rpc_client();
+        let hash = client
+            .call::<String>("getblockhash", json!([height]))
+            .await?;
+        match client.call::<String>("getblock", json!([hash, 0])).await {
+            Ok(_) => Ok(true),
+            Err(RpcError::Server { message, .. }) if message.contains("pruned") => Ok(false),
+            Err(err) => Err(err.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::snapshot::premined_descriptor;
+
+    #[test]
+    fn test_prune_info() {
+        let info: PruneInfo = serde_json::from_value(json!({
+            "chain": "regtest",
+            "blocks": 300,
+            "pruned": true,
+            "pruneheight": 226,
+            "automatic_pruning": false
+        }))
+        .unwrap();
+        assert_eq!(info.prune_height, Some(226));
+        assert_eq!(info.automatic_pruning, Some(false));
+
+        let info: PruneInfo = serde_json::from_value(json!({ "pruned": false })).unwrap();
+        assert!(!info.pruned);
+        assert_eq!(info.prune_height, None);
+    }
+
+    #[tokio::test]
+    async fn test_start_pruned_node() -> Result<(), BitcoindError> {
+        // Small block files, so a short chain spans several of them.
+        let bitcoind = crate::bitcoind::ephemeral_builder()
+            .prune(Some(1))
+            .arg("-fastprune=1")
+            .build()?;
+        bitcoind.start().await?;
+        let pruned = async {
+            bitcoind
+                .rpc_client()
+                .call::<serde_json::Value>(
+                    "generatetodescriptor",
+                    json!([300, premined_descriptor()]),
+                )
+                .await?;
+            let pruned = bitcoind.prune_blockchain(250).await?;
+            Ok::<_, BitcoindError>((
+                pruned,
+                bitcoind.prune_info().await?,
+                bitcoind.has_block(1).await?,
+                bitcoind.has_block(300).await?,
+            ))
+        };
+        let pruned = pruned.await;
+        bitcoind.stop().await?;
+
+        let (pruned, info, first, tip) = pruned?;
+        assert!(pruned > 0);
+        assert!(info.pruned);
+        assert!(info.prune_height.unwrap() > 1);
+        assert!(!first);
+        assert!(tip);
+        Ok(())
+    }
+}
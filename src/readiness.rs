@@ -0,0 +1,392 @@
+//! Conditions a started node must meet before a test uses it, e.g. "answers
+//! RPC with 101 mature blocks".
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use crate::rpc::{RpcClient, RpcError};
+use bitcoin::Txid;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// A condition checked by [`Bitcoind::wait_until_ready`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadyCondition {
+    /// The node answers `getblockchaininfo`.
+    RpcResponding,
+    /// The chain has at least this many blocks, e.g. `101` for a mature coinbase.
+    MinBlockHeight(u64),
+    /// A wallet with this name is loaded.
+    WalletLoaded(String),
+    /// The node publishes at least one ZMQ notification. bitcoind binds the
+    /// ZMQ sockets before it lists them in `getzmqnotifications`.
+    ZmqBound,
+    /// The node left initial block download, e.g. a signet or testnet node
+    /// that caught up with its peers. A fresh regtest node stays in it until
+    /// a block is mined.
+    InitialBlockDownloadDone,
+    /// The node validated every block header it knows of and its optional
+    /// indexes caught up, e.g. after a reindex.
+    Synced,
+    /// The optional index with this name, as `getindexinfo` lists it, is
+    /// built up to the tip, e.g. `txindex` or `basic block filter index`.
+    IndexSynced(String),
+    /// A chainstate loaded with `loadtxoutset` is validated in the background
+    /// and the node runs on a single, fully validated chainstate again.
+    SnapshotValidated,
+    /// The transaction has at least this many confirmations. Confirmed
+    /// transactions are looked up in the transaction index, or in the loaded
+    /// wallet when the node runs without one.
+    TxConfirmed { txid: Txid, confirmations: u64 },
+}
+
+impl fmt::Display for ReadyCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadyCondition::RpcResponding => write!(f, "RPC responding"),
+            ReadyCondition::MinBlockHeight(height) => write!(f, "block height {}", height),
+            ReadyCondition::WalletLoaded(wallet) => write!(f, "wallet {} loaded", wallet),
+            ReadyCondition::ZmqBound => write!(f, "ZMQ bound"),
+            ReadyCondition::InitialBlockDownloadDone => write!(f, "initial block download done"),
+            ReadyCondition::Synced => write!(f, "synced"),
+            ReadyCondition::IndexSynced(index) => write!(f, "{} synced", index),
+            ReadyCondition::SnapshotValidated => write!(f, "UTXO snapshot validated"),
+            ReadyCondition::TxConfirmed {
+                txid,
+                confirmations,
+            } => write!(f, "{} confirmations of {}", confirmations, txid),
+        }
+    }
+}
+
+impl ReadyCondition {
+    /// The BIP 158 block filter index `-blockfilterindex` builds is synced.
+    pub fn block_filter_index_synced() -> Self {
+        ReadyCondition::IndexSynced("basic block filter index".to_string())
+    }
+
+    /// Checks the condition once, returning why it is not met yet.
+    async fn check(&self, client: &RpcClient) -> Result<(), String> {
+        match self {
+            ReadyCondition::RpcResponding => {
+                call::<Value>(client, "getblockchaininfo").await?;
+            }
+            ReadyCondition::MinBlockHeight(height) => {
+                let count = call::<u64>(client, "getblockcount").await?;
+                if count < *height {
+                    return Err(format!("block height is {} of {}", count, height));
+                }
+            }
+            ReadyCondition::WalletLoaded(wallet) => {
+                let wallets = call::<Vec<String>>(client, "listwallets").await?;
+                if !wallets.contains(wallet) {
+                    return Err(format!("wallet {} is not loaded", wallet));
+                }
+            }
+            ReadyCondition::ZmqBound => {
+                let notifications = call::<Vec<Value>>(client, "getzmqnotifications").await?;
+                if notifications.is_empty() {
+                    return Err("no ZMQ notifications are published".to_string());
+                }
+            }
+            ReadyCondition::InitialBlockDownloadDone => {
+                let info = call::<Value>(client, "getblockchaininfo").await?;
+                if info["initialblockdownload"] != false {
+                    return Err(format!(
+                        "initial block download at block {}, {:.2}% verified",
+                        info["blocks"],
+                        info["verificationprogress"].as_f64().unwrap_or(0.0) * 100.0
+                    ));
+                }
+            }
+            ReadyCondition::Synced => {
+                let info = call::<Value>(client, "getblockchaininfo").await?;
+                if info["blocks"] != info["headers"] {
+                    return Err(format!(
+                        "block {} of {} headers",
+                        info["blocks"], info["headers"]
+                    ));
+                }
+                let indexes = call::<BTreeMap<String, Value>>(client, "getindexinfo").await?;
+                if let Some((name, index)) =
+                    indexes.iter().find(|(_, index)| index["synced"] != true)
+                {
+                    return Err(format!("{} at block {}", name, index["best_block_height"]));
+                }
+            }
+            ReadyCondition::IndexSynced(name) => {
+                let indexes = call::<BTreeMap<String, Value>>(client, "getindexinfo").await?;
+                match indexes.get(name) {
+                    Some(index) if index["synced"] == true => {}
+                    Some(index) => {
+                        return Err(format!("{} at block {}", name, index["best_block_height"]))
+                    }
+                    None => return Err(format!("the node doesn't build {}", name)),
+                }
+            }
+            ReadyCondition::SnapshotValidated => {
+                // The background chainstate is listed first, and dropped once
+                // it reaches the snapshot's base block.
+                let info = call::<Value>(client, "getchainstates").await?;
+                if let [background, snapshot, ..] = info["chainstates"]
+                    .as_array()
+                    .map_or(&[][..], Vec::as_slice)
+                {
+                    return Err(format!(
+                        "background validation at block {} of snapshot {}",
+                        background["blocks"], snapshot["snapshot_blockhash"]
+                    ));
+                }
+            }
+            ReadyCondition::TxConfirmed {
+                txid,
+                confirmations,
+            } => {
+                let tx = match client
+                    .call::<Value>("getrawtransaction", json!([txid, true]))
+                    .await
+                {
+                    Ok(tx) => tx,
+                    Err(RpcError::Server { message, .. }) => client
+                        .call::<Value>("gettransaction", json!([txid]))
+                        .await
+                        .map_err(|_| message)?,
+                    Err(err) => return Err(err.to_string()),
+                };
+                // The wallet counts the confirmations of a conflicting
+                // transaction as negative.
+                match tx["confirmations"].as_i64().unwrap_or(0) {
+                    found if found < 0 => {
+                        return Err("conflicts with a confirmed transaction".to_string())
+                    }
+                    0 => return Err("in the mempool".to_string()),
+                    found if (found as u64) < *confirmations => {
+                        return Err(format!(
+                            "{} confirmations in block {}",
+                            found,
+                            tx["blockhash"].as_str().unwrap_or_default()
+                        ))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Last error of a probe cut short by the deadline.
+pub(crate) const NO_ANSWER: &str = "the node did not answer in time";
+
+/// Awaits `probe` until `deadline` at the latest, so a node that accepts
+/// connections but never answers, e.g. a paused one, can't hold a wait past
+/// its timeout. Returns `None` if the deadline passed first.
+pub(crate) async fn probe_until<T>(deadline: Instant, probe: impl Future<Output = T>) -> Option<T> {
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    tokio::time::timeout(remaining, probe).await.ok()
+}
+
+/// Returns the hex of transaction `txid` if the node has it outside its
+/// mempool, in the transaction index or its wallet.
+async fn raw_transaction(client: &RpcClient, txid: Txid) -> Option<String> {
+    if let Ok(hex) = client
+        .call::<String>("getrawtransaction", json!([txid]))
+        .await
+    {
+        return Some(hex);
+    }
+    let tx = client
+        .call::<Value>("gettransaction", json!([txid]))
+        .await
+        .ok()?;
+    tx["hex"].as_str().map(str::to_string)
+}
+
+async fn call<T: serde::de::DeserializeOwned>(
+    client: &RpcClient,
+    method: &str,
+) -> Result<T, String> {
+    client
+        .call(method, json!([]))
+        .await
+        .map_err(|err| match err {
+            RpcError::Http { status: 401, .. } => {
+                "RPC credentials were rejected by the node".to_string()
+            }
+            err => err.to_string(),
+        })
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Waits until the started node meets every condition, retrying with the
+    /// backoff of the readiness config.
+    ///
+    /// ```ignore
+    /// bitcoind
+    ///     .wait_until_ready(
+    ///         &[ReadyCondition::MinBlockHeight(101), ReadyCondition::WalletLoaded("mywallet".into())],
+    ///         Duration::from_secs(30),
+    ///     )
+    ///     .await?;
+    /// ```
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::ReadinessFailed)` if the node exits while waiting.
+    /// * `Err(BitcoindError::ReadinessTimeout)` if a condition is still unmet
+    ///   after `timeout`.
+    pub async fn wait_until_ready(
+        &self,
+        conditions: &[ReadyCondition],
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        let client = self.rpc_client();
+        let deadline = Instant::now() + timeout;
+        let mut backoff = self.readiness.initial_backoff;
+        let mut pending = conditions.to_vec();
+
+        loop {
+            let mut last_error = None;
+            for condition in std::mem::take(&mut pending) {
+                let checked = probe_until(deadline, condition.check(&client))
+                    .await
+                    .unwrap_or_else(|| Err(NO_ANSWER.to_string()));
+                match checked {
+                    Ok(()) => info!("bitcoind node {}: {}", self.backend.name(), condition),
+                    Err(err) => {
+                        last_error = Some(format!("{}: {}", condition, err));
+                        pending.push(condition);
+                    }
+                }
+            }
+            let Some(last_error) = last_error else {
+                return Ok(());
+            };
+
+            if !self.backend.is_running().await? {
+                return Err(BitcoindError::ReadinessFailed(format!(
+                    "node {} exited while waiting for {}",
+                    self.backend.name(),
+                    last_error
+                )));
+            }
+
+            if Instant::now() + backoff > deadline {
+                return Err(BitcoindError::ReadinessTimeout {
+                    timeout,
+                    last_error,
+                });
+            }
+
+            debug!("bitcoind not ready yet: {}", last_error);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.readiness.max_backoff);
+        }
+    }
+
+    /// Waits until the tip of the node reaches `height`, e.g. blocks mined by
+    /// another node or by the code under test.
+    ///
+    /// See [`ReadyCondition::MinBlockHeight`] and
+    /// [`Bitcoind::wait_until_ready`] for the errors.
+    pub async fn wait_for_block_height(
+        &self,
+        height: u64,
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        self.wait_until_ready(&[ReadyCondition::MinBlockHeight(height)], timeout)
+            .await
+    }
+
+    /// Waits until transaction `txid` has at least `confirmations`
+    /// confirmations.
+    ///
+    /// See [`ReadyCondition::TxConfirmed`] and [`Bitcoind::wait_until_ready`]
+    /// for the errors. The timeout error tells whether the transaction is
+    /// still in the mempool, short of confirmations or unknown to the node.
+    pub async fn wait_for_tx_confirmed(
+        &self,
+        txid: Txid,
+        confirmations: u64,
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        let condition = ReadyCondition::TxConfirmed {
+            txid,
+            confirmations,
+        };
+        self.wait_until_ready(&[condition], timeout).await
+    }
+
+    /// Waits until transaction `txid` is in the node's mempool, e.g. to
+    /// assert it propagated from another node.
+    ///
+    /// When the node can get the transaction itself, from its wallet or the
+    /// transaction index, it is checked with `testmempoolaccept` and the wait
+    /// fails as soon as the node rejects it for good. A transaction whose
+    /// parent hasn't arrived yet is waited for.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::TxRejected)` with the node's reject reason.
+    /// * `Err(BitcoindError::ReadinessTimeout)` if it isn't in the mempool
+    ///   after `timeout`.
+    pub async fn wait_for_mempool_tx(
+        &self,
+        txid: Txid,
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        let client = self.rpc_client();
+        let deadline = Instant::now() + timeout;
+        let mut backoff = self.readiness.initial_backoff;
+        let mut last_error = NO_ANSWER.to_string();
+
+        loop {
+            let probe = async {
+                if client
+                    .call::<Value>("getmempoolentry", json!([txid]))
+                    .await
+                    .is_ok()
+                {
+                    return Ok(None);
+                }
+                let mut last_error = format!("{} is not in the mempool", txid);
+                if let Some(hex) = raw_transaction(&client, txid).await {
+                    let results = client
+                        .call::<Vec<Value>>("testmempoolaccept", json!([[hex]]))
+                        .await?;
+                    let result = results.first().cloned().unwrap_or_default();
+                    if let Some(reason) = result["reject-reason"].as_str() {
+                        if reason != "missing-inputs" {
+                            return Err(BitcoindError::TxRejected {
+                                txid,
+                                reason: reason.to_string(),
+                            });
+                        }
+                        last_error = format!("{}: {}", last_error, reason);
+                    }
+                }
+                Ok(Some(last_error))
+            };
+            // A probe cut short by the deadline keeps the previous error.
+            match probe_until(deadline, probe).await {
+                Some(Ok(None)) => return Ok(()),
+                Some(Ok(Some(error))) => last_error = error,
+                Some(Err(err)) => return Err(err),
+                None => {}
+            }
+
+            if Instant::now() + backoff > deadline {
+                return Err(BitcoindError::ReadinessTimeout {
+                    timeout,
+                    last_error,
+                });
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(self.readiness.max_backoff);
+        }
+    }
+}
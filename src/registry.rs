@@ -0,0 +1,199 @@
+//! Credentials for pulling images from private registries.
+
+use crate::error::BitcoindError;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bollard::auth::DockerCredentials;
+use redact::Secret;
+use serde::Deserialize;
+use std::path::PathBuf;
+
+/// Key Docker uses for Docker Hub in `config.json`.
+const DOCKER_HUB: &str = "https://index.docker.io/v1/";
+
+/// How to authenticate image pulls against the image's registry.
+///
+/// In a config file the variant is selected with `type`, e.g.
+/// `{ type = "credentials", username = "ci", password = "${REGISTRY_PASSWORD}" }`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RegistryAuth {
+    /// Username and password, or an access token used as the password.
+    Credentials {
+        username: String,
+        password: Secret<String>,
+    },
+    /// OAuth identity token, e.g. from `docker login` against a token-based registry.
+    IdentityToken { token: Secret<String> },
+    /// Reads the credentials stored by `docker login` in `config.json`. Defaults
+    /// to `$DOCKER_CONFIG/config.json`, then `~/.docker/config.json`. Credential
+    /// helpers (`credsStore`, `credHelpers`) are not supported.
+    DockerConfig {
+        #[serde(default)]
+        path: Option<PathBuf>,
+    },
+}
+
+impl RegistryAuth {
+    /// Credentials to send when pulling `image`.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::ConfigFile)` if the Docker config file cannot be read.
+    /// * `Err(BitcoindError::InvalidConfig)` if it has no usable entry for the
+    ///   image's registry.
+    pub(crate) fn credentials(&self, image: &str) -> Result<DockerCredentials, BitcoindError> {
+        let registry = registry_host(image);
+        let serveraddress = Some(registry.to_string());
+        match self {
+            RegistryAuth::Credentials { username, password } => Ok(DockerCredentials {
+                username: Some(username.clone()),
+                password: Some(password.expose_secret().clone()),
+                serveraddress,
+                ..Default::default()
+            }),
+            RegistryAuth::IdentityToken { token } => Ok(DockerCredentials {
+                identitytoken: Some(token.expose_secret().clone()),
+                serveraddress,
+                ..Default::default()
+            }),
+            RegistryAuth::DockerConfig { path } => {
+                let path = path.clone().or_else(docker_config_path).ok_or_else(|| {
+                    BitcoindError::InvalidConfig("no Docker config file found".to_string())
+                })?;
+                let contents =
+                    std::fs::read_to_string(&path).map_err(|source| BitcoindError::ConfigFile {
+                        path: path.clone(),
+                        source,
+                    })?;
+                let mut credentials = credentials_from_docker_config(&contents, registry)?;
+                credentials.serveraddress = serveraddress;
+                Ok(credentials)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DockerConfigFile {
+    #[serde(default)]
+    auths: std::collections::HashMap<String, DockerConfigAuth>,
+}
+
+#[derive(Deserialize)]
+struct DockerConfigAuth {
+    auth: Option<String>,
+    identitytoken: Option<String>,
+}
+
+fn credentials_from_docker_config(
+    contents: &str,
+    registry: &str,
+) -> Result<DockerCredentials, BitcoindError> {
+    let config: DockerConfigFile = serde_json::from_str(contents)
+        .map_err(|e| BitcoindError::InvalidConfig(format!("invalid Docker config: {}", e)))?;
+
+    // Entries may be keyed by a bare host or by a url such as `https://host/v1/`.
+    let entry = config
+        .auths
+        .iter()
+        .find(|(key, _)| {
+            let host = key
+                .trim_start_matches("https://")
+                .trim_start_matches("http://")
+                .split('/')
+                .next()
+                .unwrap_or_default();
+            key.as_str() == registry || host == registry
+        })
+        .map(|(_, entry)| entry)
+        .ok_or_else(|| {
+            BitcoindError::InvalidConfig(format!(
+                "no credentials for {} in the Docker config",
+                registry
+            ))
+        })?;
+
+    if let Some(token) = &entry.identitytoken {
+        return Ok(DockerCredentials {
+            identitytoken: Some(token.clone()),
+            ..Default::default()
+        });
+    }
+
+    let decoded = entry
+        .auth
+        .as_ref()
+        .and_then(|auth| STANDARD.decode(auth).ok())
+        .and_then(|auth| String::from_utf8(auth).ok())
+        .ok_or_else(|| {
+            BitcoindError::InvalidConfig(format!(
+                "invalid credentials for {} in the Docker config",
+                registry
+            ))
+        })?;
+    let (username, password) = decoded.split_once(':').ok_or_else(|| {
+        BitcoindError::InvalidConfig(format!(
+            "invalid credentials for {} in the Docker config",
+            registry
+        ))
+    })?;
+
+    Ok(DockerCredentials {
+        username: Some(username.to_string()),
+        password: Some(password.to_string()),
+        ..Default::default()
+    })
+}
+
+fn docker_config_path() -> Option<PathBuf> {
+    std::env::var_os("DOCKER_CONFIG")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".docker")))
+        .map(|dir| dir.join("config.json"))
+}
+
+/// Registry an image is pulled from, following the Docker CLI rule that the
+/// first path component is a registry when it looks like a host name.
+fn registry_host(image: &str) -> &str {
+    match image.split_once('/') {
+        Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => host,
+        _ => DOCKER_HUB,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_credentials_from_docker_config() -> Result<(), BitcoindError> {
+        assert_eq!(registry_host("bitcoin/bitcoin:29.1"), DOCKER_HUB);
+        assert_eq!(
+            registry_host("registry.internal:5000/bitcoin/bitcoin:29.1"),
+            "registry.internal:5000"
+        );
+
+        let config = format!(
+            r#"{{"auths": {{
+                "https://registry.internal:5000/v1/": {{ "auth": "{}" }},
+                "ghcr.io": {{ "identitytoken": "token" }}
+            }}}}"#,
+            STANDARD.encode("ci:secret")
+        );
+
+        let credentials = credentials_from_docker_config(&config, "registry.internal:5000")?;
+        assert_eq!(credentials.username.as_deref(), Some("ci"));
+        assert_eq!(credentials.password.as_deref(), Some("secret"));
+
+        let credentials = credentials_from_docker_config(&config, "ghcr.io")?;
+        assert_eq!(credentials.identitytoken.as_deref(), Some("token"));
+
+        assert!(matches!(
+            credentials_from_docker_config(&config, DOCKER_HUB),
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Client for the REST interface `bitcoind` serves with `-rest`, for
+//! downloading blocks and headers in binary without JSON-RPC overhead.
+
+use crate::bitcoind::Bitcoind;
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use crate::rpc::RpcError;
+use bitcoin::block::Header;
+use bitcoin::consensus::encode::deserialize;
+use bitcoin::{Block, BlockHash};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Result of `/rest/chaininfo`, the fields tests look at.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RestChainInfo {
+    pub chain: String,
+    pub blocks: u64,
+    pub headers: u64,
+    #[serde(rename = "bestblockhash")]
+    pub best_block_hash: String,
+    #[serde(rename = "initialblockdownload")]
+    pub initial_block_download: bool,
+    pub pruned: bool,
+}
+
+/// Minimal client for the REST interface, built by [`Bitcoind::rest_client`]
+/// or from a base url such as `http://localhost:18443/rest`.
+#[derive(Debug, Clone)]
+pub struct RestClient {
+    client: reqwest::Client,
+    base_url: String,
+}
+
+impl RestClient {
+    pub fn new(base_url: &str) -> Self {
+        RestClient {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Returns the state of the chain from `/rest/chaininfo.json`.
+    pub async fn chain_info(&self) -> Result<RestChainInfo, BitcoindError> {
+        let body = self.get("chaininfo.json").await?;
+        Ok(parse_json(&body)?)
+    }
+
+    /// Returns up to `count` headers from `start` on along the active chain,
+    /// from `/rest/headers`. Needs Bitcoin Core 24 or later.
+    pub async fn headers(
+        &self,
+        start: BlockHash,
+        count: u32,
+    ) -> Result<Vec<Header>, BitcoindError> {
+        let body = self
+            .get(&format!("headers/{}.bin?count={}", start, count))
+            .await?;
+        Ok(parse_headers(&body)?)
+    }
+
+    /// Returns the block `hash` from `/rest/block`.
+    pub async fn block(&self, hash: BlockHash) -> Result<Block, BitcoindError> {
+        let body = self.get(&format!("block/{}.bin", hash)).await?;
+        Ok(deserialize(&body).map_err(|e| RpcError::InvalidResponse(e.to_string()))?)
+    }
+
+    async fn get(&self, path: &str) -> Result<Vec<u8>, RpcError> {
+        let response = self
+            .client
+            .get(format!("{}/{}", self.base_url, path))
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.bytes().await?;
+        if !status.is_success() {
+            return Err(RpcError::Http {
+                status: status.as_u16(),
+                body: String::from_utf8_lossy(&body).trim().to_string(),
+            });
+        }
+        Ok(body.to_vec())
+    }
+}
+
+fn parse_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, RpcError> {
+    serde_json::from_slice(body).map_err(|e| RpcError::InvalidResponse(e.to_string()))
+}
+
+/// Parses the concatenated 80-byte headers of `/rest/headers/*.bin`.
+fn parse_headers(body: &[u8]) -> Result<Vec<Header>, RpcError> {
+    if !body.len().is_multiple_of(80) {
+        return Err(RpcError::InvalidResponse(format!(
+            "{} bytes are not a whole number of headers",
+            body.len()
+        )));
+    }
+    body.chunks(80)
+        .map(|header| deserialize(header).map_err(|e| RpcError::InvalidResponse(e.to_string())))
+        .collect()
+}
+
+impl Bitcoind<DockerBackend> {
+    /// Returns the base url of the REST interface, e.g.
+    /// `http://localhost:49153/rest` after a start with ephemeral ports, or
+    /// `None` unless it is enabled with [`BitcoindBuilder::rest`].
+    ///
+    /// [`BitcoindBuilder::rest`]: crate::builder::BitcoindBuilder::rest
+    pub fn rest_url(&self) -> Option<String> {
+        if !self.backend.container.publish.rest {
+            return None;
+        }
+        let mut url = reqwest::Url::parse(self.rpc_config().url.expose_secret()).ok()?;
+        url.set_path("rest");
+        url.set_query(None);
+        Some(url.to_string())
+    }
+
+    /// Returns a client for the REST interface.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if REST isn't enabled.
+    pub fn rest_client(&self) -> Result<RestClient, BitcoindError> {
+        self.rest_url()
+            .map(|url| RestClient::new(&url))
+            .ok_or_else(|| {
+                BitcoindError::InvalidConfig("the REST interface is disabled".to_string())
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bitcoind::ephemeral_builder;
+    use crate::rpc::RpcClient;
+    use crate::snapshot::premined_descriptor;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{CompactTarget, TxMerkleNode};
+    use serde_json::json;
+
+    #[test]
+    fn test_rest_url() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().rest(true).build()?;
+        assert_eq!(
+            bitcoind.rest_url().as_deref(),
+            Some("http://localhost:18443/rest")
+        );
+        assert_eq!(
+            bitcoind.rest_client()?.base_url(),
+            "http://localhost:18443/rest"
+        );
+
+        let bitcoind = ephemeral_builder().build()?;
+        assert_eq!(bitcoind.rest_url(), None);
+        assert!(matches!(
+            bitcoind.rest_client(),
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let header = Header {
+            version: bitcoin::block::Version::TWO,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: 1296688602,
+            bits: CompactTarget::from_consensus(0x207fffff),
+            nonce: 2,
+        };
+        let mut body = bitcoin::consensus::encode::serialize(&header);
+        body.extend(bitcoin::consensus::encode::serialize(&header));
+        assert_eq!(parse_headers(&body).unwrap(), [header, header]);
+        assert!(parse_headers(&body[..100]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_start_rest() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().rest(true).build()?;
+        let rpc_config = bitcoind.start().await?;
+        let fetched = async {
+            let rpc = RpcClient::new(&rpc_config);
+            rpc.call::<serde_json::Value>(
+                "generatetodescriptor",
+                json!([5, premined_descriptor()]),
+            )
+            .await?;
+            let genesis = rpc.call::<BlockHash>("getblockhash", json!([0])).await?;
+            let rest = bitcoind.rest_client()?;
+            let headers = rest.headers(genesis, 10).await?;
+            let tip = rest.block(headers[5].block_hash()).await?;
+            Ok::<_, BitcoindError>((rest.chain_info().await?, headers, tip))
+        };
+        let fetched = fetched.await;
+        bitcoind.stop().await?;
+
+        let (info, headers, tip) = fetched?;
+        assert_eq!(info.blocks, 5);
+        assert_eq!(headers.len(), 6);
+        assert_eq!(tip.txdata.len(), 1);
+        Ok(())
+    }
+}
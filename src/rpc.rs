@@ -0,0 +1,77 @@
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RpcError {
+    #[error("RPC transport error: {0}")]
+    Transport(#[from] reqwest::Error),
+
+    #[error("RPC error {code}: {message}")]
+    Server { code: i64, message: String },
+
+    #[error("Unexpected HTTP {status} response: {body}")]
+    Http { status: u16, body: String },
+
+    #[error("Invalid RPC response: {0}")]
+    InvalidResponse(String),
+}
+
+/// Minimal async JSON-RPC client used to talk to the managed node.
+pub(crate) struct RpcClient {
+    client: reqwest::Client,
+    url: String,
+    username: String,
+    password: String,
+}
+
+impl RpcClient {
+    pub(crate) fn new(rpc_config: &RpcConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: rpc_config.url.expose_secret().to_string(),
+            username: rpc_config.username.expose_secret().to_string(),
+            password: rpc_config.password.expose_secret().to_string(),
+        }
+    }
+
+    pub(crate) async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, RpcError> {
+        let request = json!({
+            "jsonrpc": "1.0",
+            "id": "bitcoind",
+            "method": method,
+            "params": params,
+        });
+
+        let response = self
+            .client
+            .post(&self.url)
+            .basic_auth(&self.username, Some(&self.password))
+            .json(&request)
+            .send()
+            .await?;
+
+        // bitcoind answers RPC errors with a non-2xx status and a JSON body,
+        // so the body is parsed before looking at the status code.
+        let status = response.status();
+        let body = response.text().await?;
+        let mut value: Value = serde_json::from_str(&body).map_err(|_| RpcError::Http {
+            status: status.as_u16(),
+            body,
+        })?;
+
+        if let Some(error) = value.get("error").filter(|error| !error.is_null()) {
+            return Err(RpcError::Server {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+
+        serde_json::from_value(value["result"].take())
+            .map_err(|e| RpcError::InvalidResponse(e.to_string()))
+    }
+}
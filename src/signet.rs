@@ -0,0 +1,721 @@
+//! Private signets: nodes sharing a custom block challenge, and a signer that
+//! mines blocks satisfying it (BIP 325).
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::{ephemeral_builder, Bitcoind};
+use crate::builder::random_hex;
+use crate::error::BitcoindError;
+use crate::rpc::{RpcClient, RpcError};
+use bitcoin::block::{Header, Version as BlockVersion};
+use bitcoin::consensus::encode::{deserialize_hex, serialize, serialize_hex};
+use bitcoin::hashes::Hash;
+use bitcoin::hex::FromHex;
+use bitcoin::opcodes::all::{OP_CHECKMULTISIG, OP_RETURN};
+use bitcoin::opcodes::{OP_0, OP_TRUE};
+use bitcoin::script::{Builder, PushBytesBuf};
+use bitcoin::secp256k1::{Message, Secp256k1};
+use bitcoin::sighash::{EcdsaSighashType, SighashCache};
+use bitcoin::transaction::Version;
+use bitcoin::{
+    absolute, ecdsa, Amount, Block, BlockHash, CompactTarget, CompressedPublicKey, Network,
+    OutPoint, PrivateKey, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Witness,
+};
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use serde::Deserialize;
+use serde_json::json;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// Marks the signet solution among the pushes of the witness commitment.
+const SIGNET_HEADER: [u8; 4] = [0xec, 0xc7, 0xda, 0xa2];
+
+/// How often [`SignetCluster::wait_for_block`] checks the nodes' tips.
+const CLUSTER_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Start of the witness commitment output's script.
+const WITNESS_COMMITMENT_PREFIX: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
+/// A signet whose blocks must satisfy a custom challenge script, for private
+/// networks shared by the nodes of a multi-party test.
+///
+/// Every node gets the challenge with [`BitcoindBuilder::custom_signet`];
+/// the holder of the keys mines with [`Bitcoind::mine_signet_block`].
+///
+/// [`BitcoindBuilder::custom_signet`]: crate::builder::BitcoindBuilder::custom_signet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomSignet {
+    challenge: ScriptBuf,
+    /// Multisig script a federation's challenge commits to.
+    witness_script: Option<ScriptBuf>,
+    /// Signatures a block needs.
+    threshold: usize,
+    /// Keys of the challenge in the order of the script, `None` for those
+    /// held elsewhere.
+    keys: Vec<Option<PrivateKey>>,
+}
+
+impl CustomSignet {
+    /// Generates a key and a challenge requiring its signature. The key is
+    /// not drawn from a secure source, so use it for tests only.
+    pub fn generate() -> Self {
+        CustomSignet::from_key(random_key())
+    }
+
+    /// A signet whose blocks are signed by `key`: the challenge is the P2WPKH
+    /// script of its public key.
+    pub fn from_key(key: PrivateKey) -> Self {
+        let key = PrivateKey {
+            compressed: true,
+            ..key
+        };
+        let public_key =
+            CompressedPublicKey::from_private_key(&Secp256k1::new(), &key).expect("compressed key");
+        CustomSignet {
+            challenge: ScriptBuf::new_p2wpkh(&public_key.wpubkey_hash()),
+            witness_script: None,
+            threshold: 1,
+            keys: vec![Some(key)],
+        }
+    }
+
+    /// Generates `signers` keys and a challenge any `threshold` of them sign,
+    /// like [`CustomSignet::federation`].
+    pub fn generate_federation(threshold: usize, signers: usize) -> Result<Self, BitcoindError> {
+        CustomSignet::federation(threshold, (0..signers).map(|_| random_key()).collect())
+    }
+
+    /// A signet whose blocks are signed by any `threshold` of `keys`: the
+    /// challenge is a P2WSH `threshold`-of-n multisig. Blocks are signed by
+    /// the first keys held; see [`CustomSignet::with_signers`].
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` unless `1 <= threshold <= n <= 20`.
+    pub fn federation(threshold: usize, keys: Vec<PrivateKey>) -> Result<Self, BitcoindError> {
+        if threshold == 0 || threshold > keys.len() || keys.len() > 20 {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "invalid {}-of-{} signet federation",
+                threshold,
+                keys.len()
+            )));
+        }
+        let secp = Secp256k1::new();
+        let keys = keys
+            .into_iter()
+            .map(|key| PrivateKey {
+                compressed: true,
+                ..key
+            })
+            .collect::<Vec<_>>();
+        let witness_script = keys
+            .iter()
+            .fold(Builder::new().push_int(threshold as i64), |script, key| {
+                script.push_key(&key.public_key(&secp))
+            })
+            .push_int(keys.len() as i64)
+            .push_opcode(OP_CHECKMULTISIG)
+            .into_script();
+        Ok(CustomSignet {
+            challenge: ScriptBuf::new_p2wsh(&witness_script.wscript_hash()),
+            witness_script: Some(witness_script),
+            threshold,
+            keys: keys.into_iter().map(Some).collect(),
+        })
+    }
+
+    /// A signet with the challenge another party chose, e.g. to join its
+    /// network. Blocks can only be mined for the challenge `OP_TRUE`.
+    pub fn from_challenge(challenge: ScriptBuf) -> Self {
+        CustomSignet {
+            challenge,
+            witness_script: None,
+            threshold: 1,
+            keys: Vec::new(),
+        }
+    }
+
+    /// The same signet holding only the keys at `signers`, by their position
+    /// in the federation, e.g. to mine while the other signers are down.
+    pub fn with_signers(&self, signers: &[usize]) -> Self {
+        let keys = self
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| key.filter(|_| signers.contains(&i)))
+            .collect();
+        CustomSignet {
+            keys,
+            ..self.clone()
+        }
+    }
+
+    pub fn challenge(&self) -> &Script {
+        &self.challenge
+    }
+
+    /// Keys held for signing blocks.
+    pub fn keys(&self) -> impl Iterator<Item = &PrivateKey> {
+        self.keys.iter().flatten()
+    }
+
+    /// Adds the signet solution to the block's witness commitment and
+    /// updates the merkle root. The proof of work is left to do.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if too few keys are held for
+    ///   the challenge or the coinbase has no witness commitment.
+    fn sign(&self, block: &mut Block) -> Result<(), BitcoindError> {
+        if self.challenge.as_bytes() == [OP_TRUE.to_u8()] {
+            block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+            return Ok(());
+        }
+        let threshold = self.threshold;
+        if self.keys().count() < threshold {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "{} keys are needed to sign blocks for the signet challenge {}, {} are held",
+                threshold,
+                self.challenge.to_hex_string(),
+                self.keys().count()
+            )));
+        }
+        let commitment = block.txdata[0]
+            .output
+            .iter()
+            .rposition(|output| {
+                output
+                    .script_pubkey
+                    .as_bytes()
+                    .starts_with(&WITNESS_COMMITMENT_PREFIX)
+            })
+            .ok_or_else(|| {
+                BitcoindError::InvalidConfig("the coinbase has no witness commitment".to_string())
+            })?;
+
+        // The signature commits to the block with the solution left out of
+        // the commitment but its header kept.
+        let witness_commitment = block.txdata[0].output[commitment].script_pubkey.clone();
+        let mut unsigned = witness_commitment.clone();
+        unsigned.push_slice(SIGNET_HEADER);
+        block.txdata[0].output[commitment].script_pubkey = unsigned;
+        let header = Header {
+            merkle_root: block.compute_merkle_root().expect("block has a coinbase"),
+            ..block.header
+        };
+
+        let secp = Secp256k1::new();
+        let spend = signet_spend(&header, &self.challenge);
+        let mut cache = SighashCache::new(&spend);
+        let sighash = match &self.witness_script {
+            Some(script) => cache
+                .p2wsh_signature_hash(0, script, Amount::ZERO, EcdsaSighashType::All)
+                .expect("one input to sign"),
+            None => cache
+                .p2wpkh_signature_hash(0, &self.challenge, Amount::ZERO, EcdsaSighashType::All)
+                .expect("P2WPKH challenge"),
+        };
+        let mut signatures = self.keys().take(threshold).map(|key| {
+            ecdsa::Signature::sighash_all(
+                secp.sign_ecdsa(&Message::from_digest(sighash.to_byte_array()), &key.inner),
+            )
+        });
+        let witness = match &self.witness_script {
+            Some(script) => {
+                let mut witness = Witness::new();
+                // OP_CHECKMULTISIG pops one item too many.
+                witness.push([]);
+                signatures.for_each(|signature| witness.push(signature.serialize()));
+                witness.push(script.as_bytes());
+                witness
+            }
+            None => {
+                let key = self.keys().next().expect("threshold checked");
+                Witness::p2wpkh(
+                    &signatures.next().expect("threshold checked"),
+                    &key.public_key(&secp).inner,
+                )
+            }
+        };
+
+        let mut solution = SIGNET_HEADER.to_vec();
+        solution.extend(serialize(&ScriptBuf::new()));
+        solution.extend(serialize(&witness));
+        let mut signed = witness_commitment;
+        signed.push_slice(PushBytesBuf::try_from(solution).expect("solution fits a push"));
+        block.txdata[0].output[commitment].script_pubkey = signed;
+        block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+        Ok(())
+    }
+}
+
+/// Generates a key from the randomly seeded hasher of the standard library.
+fn random_key() -> PrivateKey {
+    loop {
+        let bytes = <[u8; 32]>::from_hex(&random_hex(64)).expect("valid hex");
+        if let Ok(key) = PrivateKey::from_slice(&bytes, Network::Signet) {
+            return key;
+        }
+    }
+}
+
+/// Transaction spending the challenge for the block `header`, whose merkle
+/// root leaves the solution out. Its input's witness is the solution.
+fn signet_spend(header: &Header, challenge: &Script) -> Transaction {
+    // Version, previous block, merkle root and time.
+    let block_data = PushBytesBuf::try_from(serialize(header)[..72].to_vec()).expect("72 bytes");
+    let to_spend = Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_opcode(OP_0)
+                .push_slice(block_data)
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: challenge.to_owned(),
+        }],
+    };
+    Transaction {
+        version: Version(0),
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(to_spend.compute_txid(), 0),
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new().push_opcode(OP_RETURN).into_script(),
+        }],
+    }
+}
+
+/// The parts of `getblocktemplate` a block is built from.
+#[derive(Debug, Deserialize)]
+struct BlockTemplate {
+    version: i32,
+    previousblockhash: String,
+    transactions: Vec<TemplateTransaction>,
+    coinbasevalue: u64,
+    bits: String,
+    mintime: u32,
+    height: i64,
+    default_witness_commitment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TemplateTransaction {
+    data: String,
+}
+
+/// Builds the block of `template` paying to `payout`, without the signet
+/// solution and the proof of work.
+fn template_block(template: &BlockTemplate, payout: &Script) -> Result<Block, BitcoindError> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        BitcoindError::Rpc(RpcError::InvalidResponse(format!(
+            "invalid block template: {}",
+            e
+        )))
+    };
+    let witness_commitment = template
+        .default_witness_commitment
+        .as_deref()
+        .ok_or_else(|| invalid(&"no witness commitment"))?;
+    let coinbase = Transaction {
+        version: Version::TWO,
+        lock_time: absolute::LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new()
+                .push_int(template.height)
+                .push_opcode(OP_0)
+                .into_script(),
+            sequence: Sequence::MAX,
+            witness: Witness::from_slice(&[[0u8; 32]]),
+        }],
+        output: vec![
+            TxOut {
+                value: Amount::from_sat(template.coinbasevalue),
+                script_pubkey: payout.to_owned(),
+            },
+            TxOut {
+                value: Amount::ZERO,
+                script_pubkey: ScriptBuf::from_hex(witness_commitment).map_err(|e| invalid(&e))?,
+            },
+        ],
+    };
+    let mut txdata = vec![coinbase];
+    for tx in &template.transactions {
+        txdata.push(deserialize_hex(&tx.data).map_err(|e| invalid(&e))?);
+    }
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs() as u32);
+    let mut block = Block {
+        header: Header {
+            version: BlockVersion::from_consensus(template.version),
+            prev_blockhash: BlockHash::from_str(&template.previousblockhash)
+                .map_err(|e| invalid(&e))?,
+            merkle_root: Hash::all_zeros(),
+            time: now.max(template.mintime),
+            bits: CompactTarget::from_unprefixed_hex(&template.bits).map_err(|e| invalid(&e))?,
+            nonce: 0,
+        },
+        txdata,
+    };
+    block.header.merkle_root = block.compute_merkle_root().expect("block has a coinbase");
+    Ok(block)
+}
+
+/// Finds a nonce, and time if need be, meeting the header's target.
+fn grind(mut header: Header) -> Header {
+    let target = header.target();
+    while !target.is_met_by(header.block_hash()) {
+        header.nonce = header.nonce.wrapping_add(1);
+        if header.nonce == 0 {
+            header.time += 1;
+        }
+    }
+    header
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Mines a block on the custom signet `signet`, paying the subsidy and
+    /// the fees to `payout`: builds it from `getblocktemplate`, signs it
+    /// with the signet's key, grinds the proof of work and submits it.
+    ///
+    /// Grinding takes a few seconds at the signet's minimum difficulty.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the node isn't on signet or
+    ///   `signet` has no key for its challenge.
+    /// * `Err(BitcoindError::BlockRejected)` if the node refuses the block,
+    ///   e.g. `bad-signet-blksig` when it runs another challenge.
+    pub async fn mine_signet_block(
+        &self,
+        signet: &CustomSignet,
+        payout: &Script,
+    ) -> Result<BlockHash, BitcoindError> {
+        if self.rpc_config.network != Network::Signet {
+            return Err(BitcoindError::InvalidConfig(
+                "signet blocks can only be mined on signet".to_string(),
+            ));
+        }
+        let client = self.rpc_client();
+        let template = client
+            .call::<BlockTemplate>(
+                "getblocktemplate",
+                json!([{ "rules": ["segwit", "signet"] }]),
+            )
+            .await?;
+        let mut block = template_block(&template, payout)?;
+        signet.sign(&mut block)?;
+        let header = block.header;
+        block.header = tokio::task::spawn_blocking(move || grind(header))
+            .await
+            .expect("grinding does not panic");
+
+        let hash = block.block_hash();
+        if let Some(reason) = client
+            .call::<Option<String>>("submitblock", json!([serialize_hex(&block)]))
+            .await?
+        {
+            return Err(BitcoindError::BlockRejected(reason));
+        }
+        info!("Mined signet block {} at height {}", hash, template.height);
+        Ok(hash)
+    }
+}
+
+/// Nodes on one custom signet, connected to each other, whose blocks a
+/// federation signs, e.g. the committee of a multi-party protocol. Each node
+/// is isolated like [`Bitcoind::ephemeral`].
+pub struct SignetCluster {
+    signet: CustomSignet,
+    nodes: Vec<Bitcoind>,
+}
+
+impl SignetCluster {
+    /// Builds `size` nodes on `signet`, e.g. a federation from
+    /// [`CustomSignet::generate_federation`], to start with
+    /// [`SignetCluster::start`].
+    pub fn new(size: usize, signet: CustomSignet) -> Result<Self, BitcoindError> {
+        if size == 0 {
+            return Err(BitcoindError::InvalidConfig(
+                "a signet cluster needs at least one node".to_string(),
+            ));
+        }
+        let nodes = (0..size)
+            .map(|_| {
+                let builder = ephemeral_builder().container_name("bitcoin-signet");
+                let rpc_config = RpcConfig {
+                    network: Network::Signet,
+                    ..builder.rpc_config.clone().expect("ephemeral RPC config")
+                };
+                builder
+                    .rpc_config(rpc_config)
+                    .custom_signet(&signet)
+                    .build()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(SignetCluster { signet, nodes })
+    }
+
+    pub fn signet(&self) -> &CustomSignet {
+        &self.signet
+    }
+
+    pub fn nodes(&self) -> &[Bitcoind] {
+        &self.nodes
+    }
+
+    /// Starts the nodes and connects each one to all the others.
+    pub async fn start(&self) -> Result<Vec<RpcConfig>, BitcoindError> {
+        let mut rpc_configs = Vec::new();
+        for node in &self.nodes {
+            rpc_configs.push(node.start().await?);
+        }
+        for (i, node) in self.nodes.iter().enumerate() {
+            for peer in &self.nodes[..i] {
+                node.connect_peer(peer).await?;
+            }
+        }
+        Ok(rpc_configs)
+    }
+
+    /// Stops every node, returning the first error.
+    pub async fn stop(&self) -> Result<(), BitcoindError> {
+        let mut result = Ok(());
+        for node in &self.nodes {
+            let stopped = node.stop().await;
+            result = result.and(stopped);
+        }
+        result
+    }
+
+    /// Mines a block on the first node with the federation's keys and waits
+    /// until every node has it.
+    pub async fn mine_block(
+        &self,
+        payout: &Script,
+        timeout: Duration,
+    ) -> Result<BlockHash, BitcoindError> {
+        let hash = self.nodes[0]
+            .mine_signet_block(&self.signet, payout)
+            .await?;
+        self.wait_for_block(hash, timeout).await?;
+        Ok(hash)
+    }
+
+    /// Waits until the tip of every node is the block `hash`.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::ReadinessTimeout)` if a node doesn't get there
+    ///   within `timeout`.
+    pub async fn wait_for_block(
+        &self,
+        hash: BlockHash,
+        timeout: Duration,
+    ) -> Result<(), BitcoindError> {
+        let deadline = Instant::now() + timeout;
+        for node in &self.nodes {
+            let client = RpcClient::new(&node.rpc_config());
+            loop {
+                let tip = client.call::<String>("getbestblockhash", json!([])).await?;
+                if tip == hash.to_string() {
+                    break;
+                }
+                if Instant::now() >= deadline {
+                    return Err(BitcoindError::ReadinessTimeout {
+                        timeout,
+                        last_error: format!("{} is at block {}", node.backend.name(), tip),
+                    });
+                }
+                tokio::time::sleep(CLUSTER_POLL_INTERVAL).await;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn template() -> BlockTemplate {
+        serde_json::from_value(json!({
+            "version": 536870912,
+            "previousblockhash": "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+            "transactions": [],
+            "coinbasevalue": 5000000000u64,
+            "bits": "1e0377ae",
+            "curtime": 1760000000,
+            "mintime": 1598918401,
+            "height": 1,
+            "default_witness_commitment":
+                "6a24aa21a9ede2f61c3f71d1defd3fa999dfa36953755c690689799962b48bebd836974e8cf9"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_sign_signet_block() -> Result<(), BitcoindError> {
+        let signet = CustomSignet::from_key(
+            PrivateKey::from_slice(&[7; 32], Network::Signet).expect("valid secret key"),
+        );
+        assert!(signet.challenge().is_p2wpkh());
+        assert_ne!(CustomSignet::generate(), CustomSignet::generate());
+
+        let payout = signet.challenge().to_owned();
+        let mut block = template_block(&template(), &payout)?;
+        signet.sign(&mut block)?;
+        assert_eq!(block.compute_merkle_root(), Some(block.header.merkle_root));
+
+        // Strip the solution as the node does and check the signature.
+        let commitment = &block.txdata[0].output[1].script_pubkey;
+        let pushes = commitment
+            .instructions()
+            .filter_map(|instruction| Some(instruction.ok()?.push_bytes()?.as_bytes().to_vec()))
+            .collect::<Vec<_>>();
+        let [witness_commitment, solution] = &pushes[..] else {
+            panic!("unexpected commitment {:?}", commitment);
+        };
+        assert_eq!(solution[..4], SIGNET_HEADER);
+        let witness: Witness = bitcoin::consensus::deserialize(&solution[5..]).unwrap();
+        assert_eq!(solution[4], 0, "empty scriptSig");
+
+        let mut unsigned = block.clone();
+        unsigned.txdata[0].output[1].script_pubkey = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_slice(<&bitcoin::script::PushBytes>::try_from(&witness_commitment[..]).unwrap())
+            .push_slice(SIGNET_HEADER)
+            .into_script();
+        let header = Header {
+            merkle_root: unsigned.compute_merkle_root().unwrap(),
+            ..block.header
+        };
+        let spend = signet_spend(&header, signet.challenge());
+        let sighash = SighashCache::new(&spend)
+            .p2wpkh_signature_hash(0, signet.challenge(), Amount::ZERO, EcdsaSighashType::All)
+            .unwrap();
+        let signature = ecdsa::Signature::from_slice(witness.nth(0).unwrap()).unwrap();
+        let public_key = bitcoin::PublicKey::from_slice(witness.nth(1).unwrap()).unwrap();
+        Secp256k1::new()
+            .verify_ecdsa(
+                &Message::from_digest(sighash.to_byte_array()),
+                &signature.signature,
+                &public_key.inner,
+            )
+            .unwrap();
+
+        let unsignable = CustomSignet::from_challenge(signet.challenge().to_owned());
+        assert!(matches!(
+            unsignable.sign(&mut template_block(&template(), &payout)?),
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_sign_federation_block() -> Result<(), BitcoindError> {
+        let signet = CustomSignet::generate_federation(2, 3)?;
+        assert!(signet.challenge().is_p2wsh());
+        assert_eq!(signet.keys().count(), 3);
+        for (threshold, signers) in [(0, 3), (4, 3), (1, 21)] {
+            assert!(matches!(
+                CustomSignet::generate_federation(threshold, signers),
+                Err(BitcoindError::InvalidConfig(_))
+            ));
+        }
+
+        let mut block = template_block(&template(), Script::new())?;
+        assert!(matches!(
+            signet.with_signers(&[1]).sign(&mut block),
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        signet.with_signers(&[0, 2]).sign(&mut block)?;
+
+        let commitment = &block.txdata[0].output[1].script_pubkey;
+        let solution = commitment.instructions().last().unwrap().unwrap();
+        let solution = solution.push_bytes().unwrap().as_bytes();
+        let witness: Witness = bitcoin::consensus::deserialize(&solution[5..]).unwrap();
+        assert_eq!(witness.len(), 4);
+        assert!(witness.nth(0).unwrap().is_empty());
+        let witness_script = Script::from_bytes(witness.last().unwrap());
+        assert_eq!(
+            ScriptBuf::new_p2wsh(&witness_script.wscript_hash()),
+            *signet.challenge()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_grind() {
+        let mut header = template_block(&template(), Script::new()).unwrap().header;
+        // An easy target keeps the test fast.
+        header.bits = CompactTarget::from_consensus(0x207fffff);
+        let ground = grind(header);
+        assert!(ground.validate_pow(ground.target()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_start_custom_signet() -> Result<(), BitcoindError> {
+        let signet = CustomSignet::generate();
+        let builder = ephemeral_builder();
+        let rpc_config = RpcConfig {
+            network: Network::Signet,
+            ..builder.rpc_config.clone().unwrap()
+        };
+        let bitcoind = builder
+            .rpc_config(rpc_config)
+            .custom_signet(&signet)
+            .build()?;
+        let rpc_config = bitcoind.start().await?;
+        let mined = async {
+            for _ in 0..2 {
+                bitcoind
+                    .mine_signet_block(&signet, signet.challenge())
+                    .await?;
+            }
+            RpcClient::new(&rpc_config)
+                .call::<u64>("getblockcount", json!([]))
+                .await
+                .map_err(BitcoindError::from)
+        };
+        let count = mined.await;
+        bitcoind.stop().await?;
+        assert_eq!(count?, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_signet_cluster() -> Result<(), BitcoindError> {
+        let cluster = SignetCluster::new(3, CustomSignet::generate_federation(2, 3)?)?;
+        cluster.start().await?;
+        let mined = async {
+            let payout = cluster.signet().challenge();
+            cluster.mine_block(payout, Duration::from_secs(30)).await?;
+
+            // Two signers are enough while the third is away.
+            let signet = cluster.signet().with_signers(&[1, 2]);
+            let hash = cluster.nodes()[2]
+                .mine_signet_block(&signet, payout)
+                .await?;
+            cluster.wait_for_block(hash, Duration::from_secs(30)).await
+        };
+        let mined = mined.await;
+        cluster.stop().await?;
+        mined
+    }
+}
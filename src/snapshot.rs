@@ -0,0 +1,209 @@
+//! Snapshots of a node's datadir, so an expensive setup such as a long chain
+//! or funded wallets can be restored in later tests.
+
+use crate::backend::NodeBackend;
+use crate::bitcoind::Bitcoind;
+use crate::builder::random_hex;
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use crate::rpc::RpcClient;
+use bitcoin::{Network, PrivateKey};
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use tracing::info;
+
+/// Overrides the directory snapshots are kept in.
+const SNAPSHOT_DIR_ENV: &str = "BITCOIND_SNAPSHOT_DIR";
+
+/// Height of the pre-mined chain. The coinbases of the first 100 blocks are
+/// mature, 5000 BTC in total.
+pub const PREMINED_BLOCKS: u64 = 200;
+
+/// Descriptor of the key the pre-mined chain pays to, for importing the funds
+/// into a wallet with `importdescriptors`. The key is public knowledge, so
+/// use it on regtest only.
+pub fn premined_descriptor() -> String {
+    let key = PrivateKey::from_slice(&[1; 32], Network::Regtest).expect("valid secret key");
+    format!("wpkh({})", key.to_wif())
+}
+
+/// Directory snapshots are kept in: `BITCOIND_SNAPSHOT_DIR` when set,
+/// otherwise `bitcoind-snapshots` in the system's temporary directory.
+pub fn snapshot_dir() -> PathBuf {
+    std::env::var_os(SNAPSHOT_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("bitcoind-snapshots"))
+}
+
+/// Path of the snapshot `name`.
+pub(crate) fn snapshot_path(name: &str) -> Result<PathBuf, BitcoindError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '-'))
+        && !name.starts_with('.');
+    if !valid {
+        return Err(BitcoindError::InvalidConfig(format!(
+            "invalid snapshot name: {:?}",
+            name
+        )));
+    }
+    Ok(snapshot_dir().join(format!("{}.tar", name)))
+}
+
+/// Snapshot holding the pre-mined chain of `image`. Older Bitcoin Core
+/// versions can't read newer datadirs, so each image has its own.
+fn premined_snapshot_name(image: &str) -> String {
+    let image = image
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '.' | '-' => c,
+            _ => '-',
+        })
+        .collect::<String>();
+    format!("premined-{}-{}", PREMINED_BLOCKS, image)
+}
+
+impl Bitcoind<DockerBackend> {
+    /// Saves the node's datadir as the snapshot `name`, replacing an earlier
+    /// one, and returns the path of the archive.
+    ///
+    /// The chain state is flushed to disk first; wallets are copied as they
+    /// are on disk. The node keeps running.
+    pub async fn snapshot(&self, name: &str) -> Result<PathBuf, BitcoindError> {
+        let path = snapshot_path(name)?;
+        info!("Saving snapshot {} of {}", name, self.backend.name());
+
+        // `gettxoutsetinfo` flushes the chain state before reading it.
+        self.rpc_client()
+            .call::<Value>("gettxoutsetinfo", json!(["none"]))
+            .await?;
+        let archive = self
+            .backend
+            .download(&self.backend.container.datadir())
+            .await?;
+
+        // Written aside and renamed, so concurrent restores never read a
+        // partial snapshot.
+        let partial = path.with_extension(format!("tar.{}", random_hex(8)));
+        std::fs::create_dir_all(snapshot_dir())
+            .and_then(|()| std::fs::write(&partial, archive))
+            .and_then(|()| std::fs::rename(&partial, &path))
+            .map_err(|source| BitcoindError::Artifact {
+                path: path.clone(),
+                source,
+            })?;
+        Ok(path)
+    }
+
+    /// Starts a regtest node on a chain of [`PREMINED_BLOCKS`] blocks paying
+    /// to [`premined_descriptor`], so tests get mature coins without mining
+    /// them.
+    ///
+    /// The chain is mined on the first call and kept as a snapshot per image,
+    /// which later calls restore.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the node isn't on regtest.
+    pub async fn start_with_premined_chain(&self) -> Result<RpcConfig, BitcoindError> {
+        if self.rpc_config.network != Network::Regtest {
+            return Err(BitcoindError::InvalidConfig(
+                "a pre-mined chain is only available on regtest".to_string(),
+            ));
+        }
+        let name = premined_snapshot_name(self.backend.image());
+        if snapshot_path(&name)?.is_file() {
+            return self.restore(&name).await;
+        }
+
+        info!("Mining {} blocks for the pre-mined chain", PREMINED_BLOCKS);
+        let rpc_config = self.start().await?;
+        RpcClient::new(&rpc_config)
+            .call::<Value>(
+                "generatetodescriptor",
+                json!([PREMINED_BLOCKS, premined_descriptor()]),
+            )
+            .await?;
+        self.snapshot(&name).await?;
+        Ok(rpc_config)
+    }
+
+    /// Restarts the node from the snapshot `name`, replacing its chain state
+    /// and wallets, and waits until it answers RPC.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the datadir is on a volume, a
+    ///   host directory or a tmpfs, which the snapshot can't replace.
+    /// * `Err(BitcoindError::Artifact)` if the snapshot can't be read.
+    pub async fn restore(&self, name: &str) -> Result<RpcConfig, BitcoindError> {
+        let path = snapshot_path(name)?;
+        let container = &self.backend.container;
+        let datadir = container.datadir();
+        if container.tmpfs_datadir.is_some()
+            || container
+                .mounts()
+                .iter()
+                .any(|(_, target)| *target == datadir)
+        {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "can't restore a snapshot onto a datadir mounted at {}",
+                datadir
+            )));
+        }
+        let archive = std::fs::read(&path).map_err(|source| BitcoindError::Artifact {
+            path: path.clone(),
+            source,
+        })?;
+        // The archive holds the datadir under its own name.
+        let parent = Path::new(&datadir)
+            .parent()
+            .map_or("/".to_string(), |parent| parent.display().to_string());
+
+        info!("Restoring snapshot {} to {}", name, self.backend.name());
+        let _lifecycle = self.lifecycle.lock().await;
+        let spec = self.node_spec();
+        self.stopped.store(false, Ordering::SeqCst);
+        self.run_on_starting().await;
+        let restored = async {
+            self.backend.stop().await?;
+            self.backend.create(&spec).await?;
+            self.backend.upload(archive, &parent).await?;
+            self.backend.start().await?;
+            self.await_started(&spec).await
+        };
+        self.save_logs_on_failure(restored.await).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_snapshot_path() {
+        let path = snapshot_path("funded-101").unwrap();
+        assert_eq!(path, snapshot_dir().join("funded-101.tar"));
+
+        for name in ["", "../escape", ".hidden", "with space"] {
+            assert!(matches!(
+                snapshot_path(name),
+                Err(BitcoindError::InvalidConfig(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_premined_chain_names() {
+        assert!(premined_descriptor().starts_with("wpkh(c"));
+        assert_eq!(premined_descriptor(), premined_descriptor());
+
+        let name = premined_snapshot_name("bitcoin/bitcoin:29.1@sha256:abab");
+        assert_eq!(name, "premined-200-bitcoin-bitcoin-29.1-sha256-abab");
+        assert!(snapshot_path(&name).is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+//! CPU, memory and IO usage of the container the Docker backend runs.
+
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use bollard::container::{MemoryStatsStats, Stats, StatsOptions};
+use bollard::errors::Error;
+use futures_util::stream::{Stream, StreamExt};
+
+/// Resource usage of the node's container, computed the way `docker stats` does.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ResourceUsage {
+    /// CPU usage since the previous sample, where `100.0` is one full core.
+    pub cpu_percent: f64,
+    /// Memory in use, without the reclaimable page cache.
+    pub memory_bytes: u64,
+    /// Memory limit of the container, or of the host without one.
+    pub memory_limit_bytes: u64,
+    /// Bytes read from block devices since the container started.
+    pub block_read_bytes: u64,
+    /// Bytes written to block devices since the container started.
+    pub block_write_bytes: u64,
+    /// Bytes received over the network since the container started.
+    pub network_rx_bytes: u64,
+    /// Bytes sent over the network since the container started.
+    pub network_tx_bytes: u64,
+    /// Number of processes and threads in the container.
+    pub pids: u64,
+}
+
+impl From<Stats> for ResourceUsage {
+    fn from(stats: Stats) -> Self {
+        let cpu = &stats.cpu_stats;
+        let precpu = &stats.precpu_stats;
+        let cpu_delta = cpu
+            .cpu_usage
+            .total_usage
+            .saturating_sub(precpu.cpu_usage.total_usage);
+        let system_delta = cpu
+            .system_cpu_usage
+            .unwrap_or_default()
+            .saturating_sub(precpu.system_cpu_usage.unwrap_or_default());
+        let online_cpus = cpu.online_cpus.unwrap_or_else(|| {
+            cpu.cpu_usage
+                .percpu_usage
+                .as_ref()
+                .map_or(1, |usage| usage.len() as u64)
+        });
+        let cpu_percent = if system_delta > 0 {
+            cpu_delta as f64 / system_delta as f64 * online_cpus as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        let memory = &stats.memory_stats;
+        let page_cache = match memory.stats {
+            Some(MemoryStatsStats::V1(stats)) => stats.total_inactive_file,
+            Some(MemoryStatsStats::V2(stats)) => stats.inactive_file,
+            None => 0,
+        };
+
+        let (block_read_bytes, block_write_bytes) = stats
+            .blkio_stats
+            .io_service_bytes_recursive
+            .iter()
+            .flatten()
+            .fold((0, 0), |(read, write), entry| {
+                match entry.op.to_ascii_lowercase().as_str() {
+                    "read" => (read + entry.value, write),
+                    "write" => (read, write + entry.value),
+                    _ => (read, write),
+                }
+            });
+
+        let (network_rx_bytes, network_tx_bytes) = stats
+            .networks
+            .iter()
+            .flat_map(|networks| networks.values())
+            .chain(stats.network.as_ref())
+            .fold((0, 0), |(rx, tx), network| {
+                (rx + network.rx_bytes, tx + network.tx_bytes)
+            });
+
+        ResourceUsage {
+            cpu_percent,
+            memory_bytes: memory.usage.unwrap_or_default().saturating_sub(page_cache),
+            memory_limit_bytes: memory.limit.unwrap_or_default(),
+            block_read_bytes,
+            block_write_bytes,
+            network_rx_bytes,
+            network_tx_bytes,
+            pids: stats.pids_stats.current.unwrap_or_default(),
+        }
+    }
+}
+
+impl DockerBackend {
+    /// Returns a single sample of the container's resource usage. Docker
+    /// takes two readings to compute the CPU usage, so this takes about a second.
+    pub async fn stats(&self) -> Result<ResourceUsage, BitcoindError> {
+        let mut stream = self.docker.stats(
+            &self.container_name,
+            Some(StatsOptions {
+                stream: false,
+                one_shot: false,
+            }),
+        );
+        let stats = stream.next().await.unwrap_or_else(|| {
+            Err(Error::DockerStreamError {
+                error: format!("no stats for container {}", self.container_name),
+            })
+        })?;
+        Ok(stats.into())
+    }
+
+    /// Streams the container's resource usage, one sample per second, until
+    /// the container stops.
+    pub fn stats_stream(&self) -> impl Stream<Item = Result<ResourceUsage, BitcoindError>> + '_ {
+        self.docker
+            .stats(
+                &self.container_name,
+                Some(StatsOptions {
+                    stream: true,
+                    one_shot: false,
+                }),
+            )
+            .map(|stats| Ok(stats?.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_resource_usage_from_stats() {
+        let cpu_stats = |total_usage: u64, system_cpu_usage: u64| {
+            json!({
+                "cpu_usage": {
+                    "total_usage": total_usage,
+                    "usage_in_usermode": 0,
+                    "usage_in_kernelmode": 0
+                },
+                "system_cpu_usage": system_cpu_usage,
+                "online_cpus": 4,
+                "throttling_data": { "periods": 0, "throttled_periods": 0, "throttled_time": 0 }
+            })
+        };
+        let stats: Stats = serde_json::from_value(json!({
+            "read": "2024-01-01T00:00:01Z",
+            "preread": "2024-01-01T00:00:00Z",
+            "num_procs": 0,
+            "pids_stats": { "current": 12 },
+            "networks": {
+                "eth0": {
+                    "rx_bytes": 1000, "tx_bytes": 500, "rx_dropped": 0, "rx_errors": 0,
+                    "rx_packets": 0, "tx_dropped": 0, "tx_errors": 0, "tx_packets": 0
+                }
+            },
+            "memory_stats": { "usage": 300, "limit": 1000 },
+            "blkio_stats": {
+                "io_service_bytes_recursive": [
+                    { "major": 8, "minor": 0, "op": "read", "value": 4096 },
+                    { "major": 8, "minor": 0, "op": "write", "value": 8192 }
+                ]
+            },
+            "cpu_stats": cpu_stats(300, 2000),
+            "precpu_stats": cpu_stats(100, 1000),
+            "storage_stats": {}
+        }))
+        .unwrap();
+
+        let usage = ResourceUsage::from(stats);
+        assert_eq!(usage.cpu_percent, 80.0);
+        assert_eq!(usage.memory_bytes, 300);
+        assert_eq!(usage.memory_limit_bytes, 1000);
+        assert_eq!(usage.block_read_bytes, 4096);
+        assert_eq!(usage.block_write_bytes, 8192);
+        assert_eq!(usage.network_rx_bytes, 1000);
+        assert_eq!(usage.network_tx_bytes, 500);
+        assert_eq!(usage.pids, 12);
+    }
+}
@@ -0,0 +1,214 @@
+//! [`testcontainers`] integration. Enabled with the `testcontainers` feature.
+//!
+//! ```ignore
+//! use testcontainers::runners::AsyncRunner;
+//!
+//! let image = BitcoindImage::new("bitcoin/bitcoin:29.1", rpc_config, BitcoindFlags::default())?;
+//! let container = image.clone().start().await?;
+//! let rpc_config = image.rpc_config_for(&container).await?;
+//! ```
+
+use crate::backend::RpcAddress;
+use crate::bitcoind::{rpc_config_at, Bitcoind, BitcoindFlags};
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use crate::ports::RPC_CONTAINER_PORT;
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use std::borrow::Cow;
+use testcontainers::core::{ContainerPort, WaitFor};
+use testcontainers::{ContainerAsync, Image};
+
+/// Line `bitcoind` prints once it has finished loading and answers RPC.
+const READY_MESSAGE: &str = "init message: Done loading";
+
+/// The `bitcoind` container as a [`testcontainers::Image`].
+///
+/// The image runs the same command line, environment and ports as a
+/// [`Bitcoind`] started with the Docker backend. Testcontainers maps the
+/// exposed ports to free host ports and removes the container when it is dropped.
+#[derive(Debug, Clone)]
+pub struct BitcoindImage {
+    name: String,
+    tag: String,
+    env: Vec<(String, String)>,
+    entrypoint: Option<String>,
+    cmd: Vec<String>,
+    ports: Vec<ContainerPort>,
+    rpc_config: RpcConfig,
+}
+
+impl BitcoindImage {
+    /// Creates an image running `image` with the given RPC config and flags.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if the RPC port can't be derived from the url.
+    pub fn new(
+        image: &str,
+        rpc_config: RpcConfig,
+        flags: BitcoindFlags,
+    ) -> Result<Self, BitcoindError> {
+        let bitcoind = Bitcoind::builder()
+            .image(image)
+            .rpc_config(rpc_config)
+            .flags(flags)
+            .build()?;
+        Ok(Self::from(&bitcoind))
+    }
+
+    /// Returns the configured RPC config pointing at the host port
+    /// testcontainers mapped for `container`.
+    pub async fn rpc_config_for(
+        &self,
+        container: &ContainerAsync<BitcoindImage>,
+    ) -> Result<RpcConfig, BitcoindError> {
+        let host = container.get_host().await?.to_string();
+        let port = container
+            .get_host_port_ipv4(ContainerPort::Tcp(RPC_CONTAINER_PORT))
+            .await?;
+
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        rpc_config_at(
+            &self.rpc_config,
+            &RpcAddress {
+                host: Some(host.to_string()),
+                port,
+            },
+        )
+    }
+}
+
+impl From<&Bitcoind<DockerBackend>> for BitcoindImage {
+    fn from(bitcoind: &Bitcoind<DockerBackend>) -> Self {
+        let backend = bitcoind.backend();
+        let spec = bitcoind.node_spec();
+        let (name, tag) = split_image(backend.image());
+
+        // Testcontainers takes a single entrypoint executable; its remaining
+        // arguments go in front of the command, which Docker runs the same way.
+        let mut entrypoint = backend.container.effective_entrypoint().unwrap_or_default();
+        let mut cmd = if entrypoint.len() > 1 {
+            entrypoint.split_off(1)
+        } else {
+            Vec::new()
+        };
+        cmd.extend(backend.container.container_cmd(&spec));
+
+        Self {
+            name,
+            tag,
+            env: backend
+                .container
+                .container_env()
+                .into_iter()
+                .filter_map(|var| {
+                    var.split_once('=')
+                        .map(|(key, value)| (key.to_string(), value.to_string()))
+                })
+                .collect(),
+            entrypoint: entrypoint.pop(),
+            cmd,
+            ports: backend
+                .container
+                .published_ports(&spec)
+                .into_iter()
+                .map(|(container_port, _)| ContainerPort::Tcp(container_port))
+                .collect(),
+            rpc_config: bitcoind.rpc_config(),
+        }
+    }
+}
+
+impl Image for BitcoindImage {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn ready_conditions(&self) -> Vec<WaitFor> {
+        vec![WaitFor::message_on_stdout(READY_MESSAGE)]
+    }
+
+    fn env_vars(
+        &self,
+    ) -> impl IntoIterator<Item = (impl Into<Cow<'_, str>>, impl Into<Cow<'_, str>>)> {
+        self.env
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    fn entrypoint(&self) -> Option<&str> {
+        self.entrypoint.as_deref()
+    }
+
+    fn cmd(&self) -> impl IntoIterator<Item = impl Into<Cow<'_, str>>> {
+        self.cmd.iter().map(String::as_str)
+    }
+
+    fn expose_ports(&self) -> &[ContainerPort] {
+        &self.ports
+    }
+}
+
+/// Splits an image reference into the name and tag testcontainers joins with
+/// a colon. A digest splits into `name@sha256` and the hex digest.
+fn split_image(image: &str) -> (String, String) {
+    match image.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => (name.to_string(), tag.to_string()),
+        _ => (image.to_string(), "latest".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use bitcoin::Network;
+    use redact::Secret;
+
+    #[test]
+    fn test_image_from_bitcoind() -> Result<(), BitcoindError> {
+        let rpc_config = RpcConfig {
+            username: Secret::new("foo".to_string()),
+            password: Secret::new("rpcpassword".to_string()),
+            url: Secret::new("http://localhost:18443".to_string()),
+            wallet: "mywallet".to_string(),
+            network: Network::Regtest,
+        };
+        let bitcoind = Bitcoind::builder()
+            .image("localhost:5000/bitcoin:29.1")
+            .rpc_config(rpc_config)
+            .p2p_port(18444)
+            .entrypoint(Some(vec!["/entrypoint.sh".to_string(), "-x".to_string()]))
+            .arg("-acceptnonstdtxn=1")
+            .build()?;
+        let image = BitcoindImage::from(&bitcoind);
+
+        assert_eq!(image.name(), "localhost:5000/bitcoin");
+        assert_eq!(image.tag(), "29.1");
+        assert_eq!(image.entrypoint(), Some("/entrypoint.sh"));
+        assert_eq!(image.cmd.first().unwrap(), "-x");
+        assert!(image.cmd.contains(&"-rpcuser=foo".to_string()));
+        assert_eq!(image.cmd.last().unwrap(), "-acceptnonstdtxn=1");
+        assert_eq!(
+            image.expose_ports(),
+            &[ContainerPort::Tcp(18443), ContainerPort::Tcp(18444)]
+        );
+        assert!(image
+            .env
+            .contains(&("BITCOIN_DATA".to_string(), "/data".to_string())));
+
+        assert_eq!(
+            split_image("bitcoin/bitcoin@sha256:abc"),
+            ("bitcoin/bitcoin@sha256".to_string(), "abc".to_string())
+        );
+        assert_eq!(
+            split_image("bitcoin/bitcoin"),
+            ("bitcoin/bitcoin".to_string(), "latest".to_string())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,304 @@
+//! Detection of the Bitcoin Core version a node runs, and checks of the
+//! configured flags against it.
+
+use crate::backend::{NodeBackend, NodeSpec};
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use crate::readiness::{probe_until, NO_ANSWER};
+use crate::rpc::RpcClient;
+use bitvmx_bitcoin_rpc::rpc_config::RpcConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::PoisonError;
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+/// A Bitcoin Core release. Releases before 22.0 are numbered by their minor
+/// version, e.g. 0.21.1 as 21.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct NodeVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl NodeVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        NodeVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// Parses the `version` number `getnetworkinfo` reports, e.g. `290100`
+    /// for 29.1.
+    pub fn from_number(version: u64) -> Self {
+        NodeVersion::new(
+            (version / 10_000) as u32,
+            (version / 100 % 100) as u32,
+            (version % 100) as u32,
+        )
+    }
+
+    /// Reads the version from an image tag such as `bitcoin/bitcoin:29.1`,
+    /// `...:0.21.1` or `...:v28.0-alpine`. `None` for tags without one, such
+    /// as `latest`.
+    pub fn from_image(image: &str) -> Option<Self> {
+        let image = image.split('@').next()?;
+        let name = image.rsplit('/').next()?;
+        let tag = name.split_once(':')?.1.trim_start_matches('v');
+        let numbers = tag
+            .split(|c: char| !c.is_ascii_digit() && c != '.')
+            .next()?
+            .split('.')
+            .map(str::parse::<u32>)
+            .collect::<Result<Vec<_>, _>>()
+            .ok()?;
+        match numbers[..] {
+            [0, minor, ref rest @ ..] => Some(NodeVersion::new(
+                minor,
+                rest.first().copied().unwrap_or(0),
+                0,
+            )),
+            [major, minor, ref rest @ ..] => Some(NodeVersion::new(
+                major,
+                minor,
+                rest.first().copied().unwrap_or(0),
+            )),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for NodeVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.major {
+            ..=21 => write!(f, "0.{}.{}", self.major, self.minor)?,
+            _ => write!(f, "{}.{}", self.major, self.minor)?,
+        }
+        if self.patch > 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// Release in which a flag appeared, was deprecated or was removed, by
+/// major version.
+struct FlagChange {
+    flag: &'static str,
+    added: Option<u32>,
+    deprecated: Option<u32>,
+    removed: Option<u32>,
+}
+
+const fn added(flag: &'static str, version: u32) -> FlagChange {
+    FlagChange {
+        flag,
+        added: Some(version),
+        deprecated: None,
+        removed: None,
+    }
+}
+
+const fn deprecated(flag: &'static str, version: u32, removed: Option<u32>) -> FlagChange {
+    FlagChange {
+        flag,
+        added: None,
+        deprecated: Some(version),
+        removed,
+    }
+}
+
+const fn removed(flag: &'static str, version: u32) -> FlagChange {
+    FlagChange {
+        flag,
+        added: None,
+        deprecated: None,
+        removed: Some(version),
+    }
+}
+
+/// Options whose availability depends on the release, as far as tests are
+/// likely to use them.
+const FLAG_CHANGES: &[FlagChange] = &[
+    added("-asmap", 20),
+    added("-blockfilterindex", 19),
+    added("-peerblockfilters", 19),
+    added("-signetchallenge", 21),
+    added("-coinstatsindex", 22),
+    added("-i2psam", 22),
+    added("-natpmp", 22),
+    added("-v2transport", 26),
+    added("-testnet4", 28),
+    added("-blocksxor", 28),
+    removed("-zapwallettxes", 21),
+    removed("-salvagewallet", 21),
+    deprecated("-rpcserialversion", 26, Some(28)),
+    deprecated("-mempoolfullrbf", 28, Some(29)),
+    removed("-upnp", 29),
+    deprecated("-datacarrier", 30, None),
+    deprecated("-datacarriersize", 30, None),
+];
+
+/// Checks `args` against `version`, returning warnings about deprecated
+/// options.
+///
+/// # Returns
+///
+/// * `Err(BitcoindError::InvalidConfig)` if an option is missing from
+///   `version`, which would stop `bitcoind` from starting.
+pub(crate) fn check_flags(
+    args: &[String],
+    version: NodeVersion,
+) -> Result<Vec<String>, BitcoindError> {
+    let mut warnings = Vec::new();
+    for arg in args {
+        let name = arg.split('=').next().unwrap_or(arg);
+        let name = match name.strip_prefix("-no") {
+            Some(negated) => format!("-{}", negated),
+            None => name.to_string(),
+        };
+        let Some(change) = FLAG_CHANGES.iter().find(|change| change.flag == name) else {
+            continue;
+        };
+        if let Some(added) = change.added.filter(|added| version.major < *added) {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "{} needs Bitcoin Core {} or later, the node runs {}",
+                change.flag, added, version
+            )));
+        }
+        if let Some(removed) = change.removed.filter(|removed| version.major >= *removed) {
+            return Err(BitcoindError::InvalidConfig(format!(
+                "{} was removed in Bitcoin Core {}, the node runs {}",
+                change.flag, removed, version
+            )));
+        }
+        if let Some(deprecated) = change
+            .deprecated
+            .filter(|deprecated| version.major >= *deprecated)
+        {
+            warnings.push(format!(
+                "{} is deprecated since Bitcoin Core {}",
+                change.flag, deprecated
+            ));
+        }
+    }
+    Ok(warnings)
+}
+
+impl<B: NodeBackend> Bitcoind<B> {
+    /// Returns the Bitcoin Core version the node reported once it was last
+    /// ready, or `None` before it started.
+    pub fn node_version(&self) -> Option<NodeVersion> {
+        *self
+            .node_version
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Checks the flags of `spec` before the node starts, against the version
+    /// it ran last or the one its backend announces.
+    pub(crate) fn check_flags(&self, spec: &NodeSpec) -> Result<(), BitcoindError> {
+        let Some(version) = self
+            .node_version()
+            .or_else(|| self.backend.expected_version())
+        else {
+            return Ok(());
+        };
+        check_flags(&spec.command_line([]), version).map(|_| ())
+    }
+
+    /// Asks the ready node for its version and warns about deprecated flags.
+    pub(crate) async fn detect_version(&self, spec: &NodeSpec, rpc_config: &RpcConfig) {
+        let client = RpcClient::new(rpc_config);
+        let info = probe_until(
+            Instant::now() + self.readiness.timeout,
+            client.call::<Value>("getnetworkinfo", json!([])),
+        )
+        .await;
+        let version = match info {
+            Some(Ok(info)) => match info["version"].as_u64() {
+                Some(version) => NodeVersion::from_number(version),
+                None => return,
+            },
+            Some(Err(err)) => {
+                debug!("Could not detect the bitcoind version: {}", err);
+                return;
+            }
+            None => {
+                debug!("Could not detect the bitcoind version: {}", NO_ANSWER);
+                return;
+            }
+        };
+        info!(
+            "bitcoind node {} runs Bitcoin Core {}",
+            self.backend.name(),
+            version
+        );
+        *self
+            .node_version
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(version);
+
+        match check_flags(&spec.command_line([]), version) {
+            Ok(warnings) => warnings.iter().for_each(|warning| warn!("{}", warning)),
+            Err(err) => warn!("{}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_node_version() {
+        assert_eq!(NodeVersion::from_number(290100), NodeVersion::new(29, 1, 0));
+        assert_eq!(NodeVersion::from_number(210100).to_string(), "0.21.1");
+        assert_eq!(NodeVersion::new(28, 0, 0).to_string(), "28.0");
+
+        for (image, version) in [
+            ("bitcoin/bitcoin:29.1", Some(NodeVersion::new(29, 1, 0))),
+            (
+                "localhost:5000/bitcoin:v28.0-alpine",
+                Some(NodeVersion::new(28, 0, 0)),
+            ),
+            (
+                "ruimarinho/bitcoin-core:0.21.1",
+                Some(NodeVersion::new(21, 1, 0)),
+            ),
+            ("bitcoin/bitcoin:latest", None),
+            ("localhost:5000/bitcoin", None),
+        ] {
+            assert_eq!(NodeVersion::from_image(image), version, "{}", image);
+        }
+    }
+
+    #[test]
+    fn test_check_flags() -> Result<(), BitcoindError> {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        assert!(check_flags(
+            &args(&["-regtest=1", "-txindex=1"]),
+            NodeVersion::new(29, 0, 0)
+        )?
+        .is_empty());
+        assert!(matches!(
+            check_flags(&args(&["-v2transport=1"]), NodeVersion::new(25, 1, 0)),
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            check_flags(&args(&["-noupnp"]), NodeVersion::new(29, 0, 0)),
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        assert_eq!(
+            check_flags(&args(&["-mempoolfullrbf=1"]), NodeVersion::new(28, 1, 0))?,
+            ["-mempoolfullrbf is deprecated since Bitcoin Core 28"]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! Background task restarting a node that exits unexpectedly, for
+//! long-running soak environments.
+
+use crate::backend::{NodeBackend, NodeStatus};
+use crate::bitcoind::Bitcoind;
+use crate::error::BitcoindError;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// How the watchdog checks the node and restarts it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchdogConfig {
+    /// Delay between checks of the node's status.
+    pub poll_interval: Duration,
+    /// Restarts after which the watchdog gives up, counting failed attempts.
+    pub max_restarts: u32,
+    /// Delay before the first restart. Doubles with every restart.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay before a restart.
+    pub max_backoff: Duration,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            poll_interval: Duration::from_secs(1),
+            max_restarts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Handle of a running watchdog. Dropping it stops the watchdog.
+pub struct Watchdog {
+    task: JoinHandle<()>,
+    restarts: Arc<AtomicU32>,
+}
+
+impl Watchdog {
+    /// Number of restarts attempted so far.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    /// Whether the watchdog gave up restarting the node.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Stops watching the node, leaving it as it is.
+    pub fn stop(self) {}
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl<B: NodeBackend + 'static> Bitcoind<B> {
+    /// Watches the started node in a background task and restarts it when it
+    /// exits without [`Bitcoind::stop`] or [`Bitcoind::kill`], e.g. after an
+    /// out-of-memory kill.
+    ///
+    /// A node whose process exited is restarted in place, keeping its data;
+    /// a Docker container removed on exit is started afresh, so keep its
+    /// datadir on a volume. Crashes and the restarts are reported to the
+    /// lifecycle hooks. Must be called from within a Tokio runtime.
+    pub fn watchdog(self: &Arc<Self>, config: WatchdogConfig) -> Watchdog {
+        let restarts = Arc::new(AtomicU32::new(0));
+        let task = tokio::spawn(watch(Arc::clone(self), config, Arc::clone(&restarts)));
+        Watchdog { task, restarts }
+    }
+}
+
+async fn watch<B: NodeBackend>(
+    bitcoind: Arc<Bitcoind<B>>,
+    config: WatchdogConfig,
+    restarts: Arc<AtomicU32>,
+) {
+    let name = bitcoind.backend.name();
+    let mut backoff = config.initial_backoff;
+    loop {
+        tokio::time::sleep(config.poll_interval).await;
+        // A node being started, stopped or restored passes through the same
+        // states as a crashed one; leave it to the method that holds the lock.
+        let Ok(lifecycle) = bitcoind.lifecycle.try_lock() else {
+            continue;
+        };
+        let status = match down_status(&bitcoind).await {
+            Some(status) => status,
+            None => continue,
+        };
+        drop(lifecycle);
+
+        warn!("bitcoind node {} is down: {:?}", name, status);
+        bitcoind.run_on_crashed(status).await;
+        if let Err(err) = restart(&bitcoind, &config, &restarts, status, &mut backoff).await {
+            warn!(
+                "Watchdog gave up restarting bitcoind node {}: {}",
+                name, err
+            );
+            bitcoind.run_on_gave_up(&err).await;
+            return;
+        }
+    }
+}
+
+/// Restarts the node that went down with `status`, retrying until a restart
+/// succeeds or `max_restarts` is reached.
+async fn restart<B: NodeBackend>(
+    bitcoind: &Bitcoind<B>,
+    config: &WatchdogConfig,
+    restarts: &AtomicU32,
+    status: NodeStatus,
+    backoff: &mut Duration,
+) -> Result<(), BitcoindError> {
+    let name = bitcoind.backend.name();
+    loop {
+        if restarts.load(Ordering::SeqCst) >= config.max_restarts {
+            return Err(BitcoindError::ReadinessFailed(format!(
+                "node {} went down after {} restarts",
+                name, config.max_restarts
+            )));
+        }
+        tokio::time::sleep(*backoff).await;
+        *backoff = (*backoff * 2).min(config.max_backoff);
+        let _lifecycle = bitcoind.lifecycle.lock().await;
+        // The node may have been stopped or brought back in the meantime.
+        if down_status(bitcoind).await.is_none() {
+            return Ok(());
+        }
+
+        let attempt = restarts.fetch_add(1, Ordering::SeqCst) + 1;
+        info!(
+            "Watchdog restarting bitcoind node {} ({}/{})",
+            name, attempt, config.max_restarts
+        );
+        let restarted = match status {
+            NodeStatus::Exited(_) => bitcoind.restart_node(true).await,
+            _ => bitcoind.start_node().await,
+        };
+        match restarted {
+            Ok(_) => return Ok(()),
+            Err(err) if attempt >= config.max_restarts => return Err(err),
+            Err(err) => warn!("Watchdog could not restart {}: {}", name, err),
+        }
+    }
+}
+
+/// Returns the status of a node that went down without being stopped, or
+/// `None` if it is up, stopped on purpose or can't be checked.
+async fn down_status<B: NodeBackend>(bitcoind: &Bitcoind<B>) -> Option<NodeStatus> {
+    if bitcoind.stopped.load(Ordering::SeqCst) {
+        return None;
+    }
+    match bitcoind.status().await {
+        Ok(status @ (NodeStatus::Exited(_) | NodeStatus::NotCreated | NodeStatus::Created)) => {
+            Some(status)
+        }
+        Ok(_) => None,
+        Err(err) => {
+            warn!(
+                "Watchdog could not check bitcoind node {}: {}",
+                bitcoind.backend.name(),
+                err
+            );
+            None
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Async streams of the blocks and transactions `bitcoind` publishes over
+//! ZMQ, enabled with the `zmq` feature.
+
+use crate::bitcoind::Bitcoind;
+use crate::docker::DockerBackend;
+use crate::error::BitcoindError;
+use crate::events::{block_height, BlockEvent, MempoolEvent, MempoolTracker};
+use crate::ports::ZmqTopic;
+use crate::rpc::RpcClient;
+use bitcoin::consensus::encode::{deserialize, Decodable};
+use bitcoin::hashes::Hash;
+use bitcoin::{Block, BlockHash, Transaction, Txid};
+use bollard::container::InspectContainerOptions;
+use bollard::Docker;
+use futures_util::stream::{self, Stream};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::time::Duration;
+use tracing::debug;
+use zeromq::{Socket, SocketRecv, SubSocket};
+
+/// How long a subscription waits for a message before it checks whether the
+/// node restarted. A subscriber isn't told when the node goes away, so
+/// without this it would wait forever after a restart.
+const IDLE_CHECK: Duration = Duration::from_secs(5);
+/// Delay before connecting again after a failed attempt.
+const RECONNECT_DELAY: Duration = Duration::from_millis(500);
+/// How long a removal waits for the transaction replacing it. bitcoind
+/// publishes both while accepting the replacement, removal first.
+const REPLACEMENT_WAIT: Duration = Duration::from_secs(1);
+
+/// Container a subscription watches for restarts, with the time it last
+/// started.
+struct RestartCheck {
+    docker: Docker,
+    container_name: String,
+    started_at: Option<String>,
+}
+
+impl RestartCheck {
+    async fn started_at(&self) -> Option<String> {
+        self.docker
+            .inspect_container(&self.container_name, None::<InspectContainerOptions>)
+            .await
+            .ok()
+            .and_then(|container| container.state)
+            .and_then(|state| state.started_at)
+    }
+}
+
+/// Subscription to one topic that connects on first use and reconnects when
+/// the connection fails or, for a watched container, the node restarts.
+pub(crate) struct ZmqSubscription {
+    address: String,
+    topic: ZmqTopic,
+    socket: Option<SubSocket>,
+    failed: bool,
+    restarts: Option<RestartCheck>,
+}
+
+impl ZmqSubscription {
+    pub(crate) fn new(address: &str, topic: ZmqTopic) -> Self {
+        ZmqSubscription {
+            address: address.to_string(),
+            topic,
+            socket: None,
+            failed: false,
+            restarts: None,
+        }
+    }
+
+    /// Reconnects when `node`'s container is started again, e.g. by
+    /// [`Bitcoind::restart`]. An idle subscription is otherwise kept, so no
+    /// message is missed while the node is quiet.
+    fn watching(mut self, node: &DockerBackend) -> Self {
+        self.restarts = Some(RestartCheck {
+            docker: node.docker().clone(),
+            container_name: node.container_name.clone(),
+            started_at: None,
+        });
+        self
+    }
+
+    /// Returns the body of the next message, the frame between the topic and
+    /// the sequence number.
+    ///
+    /// Messages published while the node restarts are lost, as with any ZMQ
+    /// subscriber.
+    pub(crate) async fn next_body(&mut self) -> Result<Vec<u8>, BitcoindError> {
+        loop {
+            let socket = match self.socket.as_mut() {
+                Some(socket) => socket,
+                None => {
+                    if self.failed {
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                    let socket = self.connect().await;
+                    self.failed = socket.is_err();
+                    self.socket.insert(socket?)
+                }
+            };
+            match tokio::time::timeout(IDLE_CHECK, socket.recv()).await {
+                Ok(Ok(message)) => {
+                    let topic = message.get(0).map(|frame| frame.as_ref());
+                    if topic == Some(self.topic.name().as_bytes()) {
+                        if let Some(body) = message.get(1) {
+                            return Ok(body.to_vec());
+                        }
+                    }
+                }
+                Ok(Err(e)) => {
+                    debug!(
+                        "ZMQ {} subscription failed, reconnecting: {}",
+                        self.topic, e
+                    );
+                    self.socket = None;
+                }
+                Err(_) => {
+                    if self.restarted().await {
+                        debug!("bitcoind restarted, reconnecting ZMQ {}", self.topic);
+                        self.socket = None;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether the watched container started again since the subscription
+    /// connected.
+    async fn restarted(&self) -> bool {
+        match &self.restarts {
+            Some(check) => check.started_at().await != check.started_at,
+            None => false,
+        }
+    }
+
+    async fn connect(&mut self) -> Result<SubSocket, BitcoindError> {
+        if let Some(check) = &mut self.restarts {
+            check.started_at = check.started_at().await;
+        }
+        let zmq_error =
+            |e: zeromq::ZmqError| BitcoindError::Zmq(format!("{}: {}", self.address, e));
+        let mut socket = SubSocket::new();
+        socket
+            .subscribe(self.topic.name())
+            .await
+            .map_err(zmq_error)?;
+        socket.connect(&self.address).await.map_err(zmq_error)?;
+        Ok(socket)
+    }
+}
+
+/// Parses a hash from a `hashblock`, `hashtx` or `sequence` message, which
+/// carry it in the byte order RPC displays.
+pub(crate) fn parse_hash<T: Hash<Bytes = [u8; 32]>>(body: &[u8]) -> Result<T, BitcoindError> {
+    let mut bytes: [u8; 32] = body
+        .get(..32)
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| BitcoindError::Zmq(format!("{} byte message has no hash", body.len())))?;
+    bytes.reverse();
+    Ok(T::from_byte_array(bytes))
+}
+
+/// Subscribes to `topic` at `address`, e.g. `tcp://localhost:28332`, and
+/// decodes each message, e.g. a [`Block`] for `rawblock`.
+///
+/// The stream never ends: failures to connect are yielded as errors and
+/// retried when it is polled again. It reconnects when the connection fails
+/// but isn't told when the node restarts, so subscribe again after that; the
+/// streams of [`Bitcoind`] follow restarts themselves.
+pub fn subscribe<T: Decodable>(
+    address: &str,
+    topic: ZmqTopic,
+) -> impl Stream<Item = Result<T, BitcoindError>> {
+    decode(ZmqSubscription::new(address, topic))
+}
+
+fn decode<T: Decodable>(
+    subscription: ZmqSubscription,
+) -> impl Stream<Item = Result<T, BitcoindError>> {
+    stream::unfold(subscription, |mut subscription| async move {
+        let item = subscription.next_body().await.and_then(|body| {
+            deserialize(&body).map_err(|e| {
+                BitcoindError::Zmq(format!("invalid {} message: {}", subscription.topic, e))
+            })
+        });
+        Some((item, subscription))
+    })
+}
+
+/// Mempool changes read from `sequence` notifications: `A` and `R` for
+/// transactions added and removed, and `C` for connected blocks, whose
+/// transactions leave the mempool without a notification of their own.
+struct SequenceEvents {
+    subscription: ZmqSubscription,
+    client: RpcClient,
+    tracker: MempoolTracker,
+    /// Removals waiting to be matched with a replacement.
+    removed: Vec<Txid>,
+    pending: VecDeque<MempoolEvent>,
+    seeded: bool,
+}
+
+impl SequenceEvents {
+    async fn next_event(&mut self) -> Result<MempoolEvent, BitcoindError> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(event);
+            }
+            if !self.seeded {
+                let txids = self
+                    .client
+                    .call::<Vec<Txid>>("getrawmempool", json!([]))
+                    .await?;
+                for txid in txids {
+                    self.tracker.insert(&self.client, txid).await;
+                }
+                self.seeded = true;
+            }
+            let body = if self.removed.is_empty() {
+                self.subscription.next_body().await?
+            } else {
+                match tokio::time::timeout(REPLACEMENT_WAIT, self.subscription.next_body()).await {
+                    Ok(body) => body?,
+                    Err(_) => {
+                        let removed = std::mem::take(&mut self.removed);
+                        self.pending.extend(self.tracker.changes(&[], &removed));
+                        continue;
+                    }
+                }
+            };
+            match body.get(32) {
+                Some(b'A') => {
+                    let txid = parse_hash(&body)?;
+                    self.tracker.insert(&self.client, txid).await;
+                    let removed = std::mem::take(&mut self.removed);
+                    self.pending.extend(self.tracker.changes(&[txid], &removed));
+                }
+                Some(b'R') => {
+                    let txid = parse_hash(&body)?;
+                    if self.tracker.contains(&txid) {
+                        self.removed.push(txid);
+                    }
+                }
+                Some(b'C') => {
+                    let hash: BlockHash = parse_hash(&body)?;
+                    let block = self
+                        .client
+                        .call::<serde_json::Value>("getblock", json!([hash, 1]))
+                        .await?;
+                    let txids: Vec<Txid> =
+                        serde_json::from_value(block["tx"].clone()).map_err(|e| {
+                            BitcoindError::Zmq(format!("invalid block {}: {}", hash, e))
+                        })?;
+                    let mut removed = std::mem::take(&mut self.removed);
+                    removed.extend(txids.into_iter().filter(|txid| self.tracker.contains(txid)));
+                    self.pending.extend(self.tracker.changes(&[], &removed));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Streams the mempool changes the `sequence` notifications of
+/// `subscription` report, looking up the spent outputs of each transaction
+/// with `client`.
+pub(crate) fn mempool_sequence(
+    subscription: ZmqSubscription,
+    client: RpcClient,
+) -> impl Stream<Item = Result<MempoolEvent, BitcoindError>> {
+    let events = SequenceEvents {
+        subscription,
+        client,
+        tracker: MempoolTracker::default(),
+        removed: Vec::new(),
+        pending: VecDeque::new(),
+        seeded: false,
+    };
+    stream::unfold(events, |mut events| async move {
+        let event = events.next_event().await;
+        Some((event, events))
+    })
+}
+
+impl Bitcoind<DockerBackend> {
+    /// Streams the blocks the node connects, from its ZMQ `rawblock`
+    /// notifications. Reconnects after a restart.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if `rawblock` isn't published,
+    ///   see [`BitcoindBuilder::zmq_port`].
+    ///
+    /// [`BitcoindBuilder::zmq_port`]: crate::builder::BitcoindBuilder::zmq_port
+    pub async fn subscribe_blocks(
+        &self,
+    ) -> Result<impl Stream<Item = Result<Block, BitcoindError>>, BitcoindError> {
+        Ok(decode(self.zmq_subscription(ZmqTopic::RawBlock).await?))
+    }
+
+    /// Streams the blocks the node connects as [`BlockEvent`]s, from its ZMQ
+    /// `hashblock` notifications, like [`Bitcoind::block_events`] does by
+    /// polling.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if `hashblock` isn't published.
+    pub async fn subscribe_block_events(
+        &self,
+    ) -> Result<impl Stream<Item = Result<BlockEvent, BitcoindError>>, BitcoindError> {
+        let subscription = self.zmq_subscription(ZmqTopic::HashBlock).await?;
+        let client = self.rpc_client();
+        Ok(stream::unfold(
+            (subscription, client),
+            |(mut subscription, client)| async move {
+                let event = async {
+                    let hash: BlockHash = parse_hash(&subscription.next_body().await?)?;
+                    let height = block_height(&client, hash).await?;
+                    Ok(BlockEvent { hash, height })
+                };
+                Some((event.await, (subscription, client)))
+            },
+        ))
+    }
+
+    /// Streams the transactions the node accepts to its mempool or sees in
+    /// a block, from its ZMQ `rawtx` notifications.
+    ///
+    /// # Returns
+    ///
+    /// * `Err(BitcoindError::InvalidConfig)` if `rawtx` isn't published.
+    pub async fn subscribe_raw_txs(
+        &self,
+    ) -> Result<impl Stream<Item = Result<Transaction, BitcoindError>>, BitcoindError> {
+        Ok(decode(self.zmq_subscription(ZmqTopic::RawTx).await?))
+    }
+
+    /// Subscription to `topic` that reconnects when the node restarts.
+    pub(crate) async fn zmq_subscription(
+        &self,
+        topic: ZmqTopic,
+    ) -> Result<ZmqSubscription, BitcoindError> {
+        let address = self.zmq_address(topic).await?;
+        Ok(ZmqSubscription::new(&address, topic).watching(&self.backend))
+    }
+
+    /// Address `topic` is published on.
+    pub(crate) async fn zmq_address(&self, topic: ZmqTopic) -> Result<String, BitcoindError> {
+        self.zmq_endpoints()
+            .await?
+            .into_iter()
+            .find(|endpoint| endpoint.topic == topic)
+            .map(|endpoint| endpoint.address)
+            .ok_or_else(|| {
+                BitcoindError::InvalidConfig(format!("ZMQ {} notifications are disabled", topic))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::bitcoind::ephemeral_builder;
+    use crate::events::tests::{fund_wallet, replace_and_mine};
+    use crate::snapshot::premined_descriptor;
+    use bitcoin::hex::FromHex;
+    use futures_util::StreamExt;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_hash() {
+        let hash = "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206";
+        let body = Vec::<u8>::from_hex(hash).unwrap();
+        assert_eq!(parse_hash::<BlockHash>(&body).unwrap().to_string(), hash);
+        assert!(parse_hash::<BlockHash>(&[0; 31]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_subscribe_without_zmq() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder().build()?;
+        assert!(matches!(
+            bitcoind.subscribe_blocks().await,
+            Err(BitcoindError::InvalidConfig(_))
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_mempool_sequence_events() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder()
+            .zmq_topic(ZmqTopic::Sequence, 28338)
+            .build()?;
+        bitcoind.start().await?;
+        let received = async {
+            fund_wallet(&bitcoind).await?;
+            let mut events = bitcoind.mempool_events(Duration::from_secs(60)).await?;
+            let (events, sent) = tokio::join!(
+                tokio::time::timeout(
+                    Duration::from_secs(30),
+                    events.by_ref().take(4).collect::<Vec<_>>()
+                ),
+                async {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    replace_and_mine(&bitcoind).await
+                }
+            );
+            let events = events
+                .expect("no mempool events within 30s")
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok::<_, BitcoindError>((events, sent?))
+        };
+        let received = received.await;
+        bitcoind.stop().await?;
+
+        // Polling once a minute would miss all of them.
+        let (events, (txid, by)) = received?;
+        assert_eq!(
+            events,
+            [
+                MempoolEvent::Added(txid),
+                MempoolEvent::Added(by),
+                MempoolEvent::Replaced { replaced: txid, by },
+                MempoolEvent::Removed(by),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_subscribe_blocks() -> Result<(), BitcoindError> {
+        let bitcoind = ephemeral_builder()
+            .zmq_topic(ZmqTopic::RawBlock, 28336)
+            .build()?;
+        let rpc_config = bitcoind.start().await?;
+        let received = async {
+            let mut blocks = Box::pin(bitcoind.subscribe_blocks().await?);
+            // The first poll connects; give the subscription time to reach
+            // the node before mining.
+            let (block, mined) = tokio::join!(
+                tokio::time::timeout(Duration::from_secs(30), blocks.next()),
+                async {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    RpcClient::new(&rpc_config)
+                        .call::<Vec<BlockHash>>(
+                            "generatetodescriptor",
+                            json!([1, premined_descriptor()]),
+                        )
+                        .await
+                }
+            );
+            let block = block
+                .map_err(|_| BitcoindError::Zmq("no block received".to_string()))?
+                .expect("block stream ended")?;
+            let mined = mined?;
+            Ok::<_, BitcoindError>((mined, block))
+        };
+        let received = received.await;
+        bitcoind.stop().await?;
+
+        let (mined, block) = received?;
+        assert_eq!(block.block_hash(), mined[0]);
+        Ok(())
+    }
+}